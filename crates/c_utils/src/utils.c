@@ -28,10 +28,67 @@ utl_image_load(const unsigned char *buffer, int buffer_size, int *w, int *h, int
     return stbi_load_from_memory(buffer, buffer_size, w, h, channels, 0);
 }
 
+unsigned short *
+utl_image_load_16(const unsigned char *buffer, int buffer_size, int *w, int *h, int *channels)
+{
+    return stbi_load_16_from_memory(buffer, buffer_size, w, h, channels, 0);
+}
+
+float *
+utl_image_load_f32(const unsigned char *buffer, int buffer_size, int *w, int *h, int *channels)
+{
+    return stbi_loadf_from_memory(buffer, buffer_size, w, h, channels, 0);
+}
+
+int
+utl_image_info(const unsigned char *buffer, int buffer_size, int *w, int *h, int *channels, int *is_16_bit, int *is_hdr)
+{
+    if (!stbi_info_from_memory(buffer, buffer_size, w, h, channels)) {
+        return 0;
+    }
+
+    *is_16_bit = stbi_is_16_bit_from_memory(buffer, buffer_size);
+    *is_hdr    = stbi_is_hdr_from_memory(buffer, buffer_size);
+    return 1;
+}
+
+unsigned char *
+utl_image_load_gif(const unsigned char *buffer, int buffer_size, int **delays, int *w, int *h, int *frames)
+{
+    int channels = 0;
+    return stbi_load_gif_from_memory(buffer, buffer_size, delays, w, h, frames, &channels, 4);
+}
+
 int
 utl_image_save_png(const char *filename, unsigned char *buffer, int w, int h, int channels)
 {
-     return stbi_write_png(filename, w, h, channels, buffer, w);
+    return utl_image_save(filename, UTL_IMAGE_FORMAT_PNG, buffer, w, h, channels, 0);
+}
+
+int
+utl_image_save(const char *filename, int format, const void *buffer, int w, int h, int channels, int quality)
+{
+    switch (format) {
+    case UTL_IMAGE_FORMAT_PNG: return stbi_write_png(filename, w, h, channels, buffer, w * channels);
+    case UTL_IMAGE_FORMAT_BMP: return stbi_write_bmp(filename, w, h, channels, buffer);
+    case UTL_IMAGE_FORMAT_TGA: return stbi_write_tga(filename, w, h, channels, buffer);
+    case UTL_IMAGE_FORMAT_JPG: return stbi_write_jpg(filename, w, h, channels, buffer, quality);
+    case UTL_IMAGE_FORMAT_HDR: return stbi_write_hdr(filename, w, h, channels, (const float *)buffer);
+    default:                   return 0;
+    }
+}
+
+int
+utl_image_encode(utl_write_func func, void *context, int format, const void *buffer, int w, int h, int channels, int quality)
+{
+    switch (format) {
+    case UTL_IMAGE_FORMAT_PNG: return stbi_write_png_to_func(func, context, w, h, channels, buffer, w * channels);
+    case UTL_IMAGE_FORMAT_BMP: return stbi_write_bmp_to_func(func, context, w, h, channels, buffer);
+    case UTL_IMAGE_FORMAT_TGA: return stbi_write_tga_to_func(func, context, w, h, channels, buffer);
+    case UTL_IMAGE_FORMAT_JPG: return stbi_write_jpg_to_func(func, context, w, h, channels, buffer, quality);
+    case UTL_IMAGE_FORMAT_HDR: return stbi_write_hdr_to_func(func, context, w, h, channels, (const float *)buffer);
+    default:                   return 0;
+    }
 }
 
 void
@@ -40,51 +97,220 @@ utl_image_free(unsigned char *buffer)
     stbi_image_free(buffer);
 }
 
+static int
+font_init(stbtt_fontinfo *info, const unsigned char *ttf_raw, int font_index)
+{
+    int font_offset = stbtt_GetFontOffsetForIndex(ttf_raw, font_index);
+    return (font_offset >= 0) && stbtt_InitFont(info, ttf_raw, font_offset);
+}
+
+unsigned char *
+utl_zlib_decode(const unsigned char *buffer, int buffer_size, int size_hint, int *out_size)
+{
+    return (unsigned char *)stbi_zlib_decode_malloc_guesssize_headerflag(
+        (const char *)buffer, buffer_size, size_hint > 0 ? size_hint : 16384, out_size, 1);
+}
+
 int
-utl_font_parse(unsigned char *ttf_raw, int font_size, struct Font *font)
+utl_font_count(const unsigned char *ttf_raw)
 {
     assert(ttf_raw && "passing null as raw");
-    assert(font && "pleas pass allocated font before calling util_font_pars(..)!");
-    font->img_width    = 1024;
-    font->img_height   = 1024;
-    font->img_channels = 1;
-    font->font_size  = font_size;
-    font->img_buffer = malloc(font->img_width * font->img_height * font->img_channels);
-    assert(font->img_buffer && "Failed to allocate mem for pixels.");
+    int count = stbtt_GetNumberOfFonts(ttf_raw);
+    return count < 0 ? 0 : count;
+}
+
+int
+utl_font_name(const unsigned char *ttf_raw, int font_index, int name_id, char *out, int out_capacity)
+{
+    assert(ttf_raw && "passing null as raw");
+
+    stbtt_fontinfo info;
+    if (!font_init(&info, ttf_raw, font_index))
+        return -1;
+
+    int length = 0;
+    const char *name = stbtt_GetFontNameString(&info, &length, STBTT_PLATFORM_ID_MICROSOFT,
+            STBTT_MS_EID_UNICODE_BMP, STBTT_MS_LANG_ENGLISH, name_id);
+    if (!name)
+        name = stbtt_GetFontNameString(&info, &length, STBTT_PLATFORM_ID_UNICODE,
+                STBTT_UNICODE_EID_UNICODE_2_0_BMP, 0, name_id);
+    if (!name)
+        return -1;
+
+    if (out)
+        memcpy(out, name, length < out_capacity ? length : out_capacity);
+
+    return length;
+}
+
+int
+utl_font_glyph_coverage(const unsigned char *ttf_raw, int font_index,
+                        const struct FontRange *ranges, int ranges_count,
+                        unsigned char *has_glyph)
+{
+    assert(ttf_raw && "passing null as raw");
+    assert(has_glyph && "passing null as has_glyph");
+
+    stbtt_fontinfo info;
+    if (!font_init(&info, ttf_raw, font_index))
+        return 0;
+
+    for ( int i = 0
+        ; i < ranges_count
+        ; i ++ )
+    {
+        for ( int j = 0
+            ; j < ranges[i].count
+            ; j ++ )
+        {
+            *has_glyph++ = stbtt_FindGlyphIndex(&info, ranges[i].first_codepoint + j) != 0;
+        }
+    }
+
+    return 1;
+}
+
+int
+utl_font_parse(const unsigned char *ttf_raw, const struct FontBakeConfig *config,
+               const struct FontRange *ranges, int ranges_count,
+               unsigned char *pixels, struct stbtt_packedchar *packed_chars)
+{
+    assert(ttf_raw && "passing null as raw");
+    assert(config && "passing null as config");
+    assert(pixels && packed_chars && "pleas pass allocated pixels and packed_chars before calling util_font_pars(..)!");
+
+    stbtt_fontinfo info;
+    if (!font_init(&info, ttf_raw, config->font_index))
+        return -1;
+
+    stbtt_pack_range *pack_ranges = calloc(ranges_count, sizeof(*pack_ranges));
+    assert(pack_ranges && "Failed to allocate mem for pack ranges.");
+
+    int packed_chars_count = 0;
+    for ( int i = 0
+        ; i < ranges_count
+        ; i ++ )
+    {
+        pack_ranges[i].font_size                        = STBTT_POINT_SIZE(config->font_size);
+        pack_ranges[i].first_unicode_codepoint_in_range = ranges[i].first_codepoint;
+        pack_ranges[i].num_chars                        = ranges[i].count;
+        pack_ranges[i].chardata_for_range               = (stbtt_packedchar *)&packed_chars[packed_chars_count];
+        packed_chars_count += ranges[i].count;
+    }
 
     int success = 0;
     stbtt_pack_context spc;
-    success = stbtt_PackBegin(&spc, font->img_buffer, font->img_width, font->img_height, font->img_width, 1, NULL);
-    assert(success);
+    if (stbtt_PackBegin(&spc, pixels, config->atlas_width, config->atlas_height, config->atlas_width, config->padding, NULL))
+    {
+        stbtt_PackSetOversampling(&spc, config->oversample_h, config->oversample_v);
+        success = stbtt_PackFontRanges(&spc, ttf_raw, config->font_index, pack_ranges, ranges_count);
+        stbtt_PackEnd(&spc);
+    }
 
-    stbtt_PackSetOversampling(&spc, 8, 8);
+    free(pack_ranges);
 
-    success = stbtt_PackFontRange(&spc, ttf_raw, 0, STBTT_POINT_SIZE(font->font_size),
-            ' ', NUMBER_OF_PACKED_CHARS, (stbtt_packedchar *)&font->packed_char[0]);
-    assert(success);
+    return success ? 1 : 0;
+}
 
-    stbtt_PackEnd(&spc);
+int
+utl_rect_pack(int width, int height, struct PackRect *rects, int rects_count)
+{
+    assert(rects && "passing null as rects");
 
-    return 1;
+    stbrp_node *nodes = calloc(width, sizeof(*nodes));
+    stbrp_rect *pack_rects = calloc(rects_count, sizeof(*pack_rects));
+    assert(nodes && pack_rects && "Failed to allocate mem for rect packing.");
+
+    for ( int i = 0
+        ; i < rects_count
+        ; i ++ )
+    {
+        pack_rects[i].id = i;
+        pack_rects[i].w  = rects[i].w;
+        pack_rects[i].h  = rects[i].h;
+    }
+
+    stbrp_context context;
+    stbrp_init_target(&context, width, height, nodes, width);
+    int success = stbrp_pack_rects(&context, pack_rects, rects_count);
+
+    for ( int i = 0
+        ; i < rects_count
+        ; i ++ )
+    {
+        rects[i].x          = pack_rects[i].x;
+        rects[i].y          = pack_rects[i].y;
+        rects[i].was_packed = pack_rects[i].was_packed;
+    }
+
+    free(pack_rects);
+    free(nodes);
+
+    return success;
+}
+
+short *
+utl_vorbis_decode(const unsigned char *buffer, int buffer_size, int *channels, int *sample_rate, int *frames)
+{
+    short *samples = NULL;
+    *frames = stb_vorbis_decode_memory(buffer, buffer_size, channels, sample_rate, &samples);
+    if (*frames < 0) {
+        free(samples);
+        return NULL;
+    }
+    return samples;
 }
 
 void
-utl_font_free(struct Font *font)
+utl_vorbis_free(short *samples)
+{
+    free(samples);
+}
+
+static void *
+vorbis_opened(stb_vorbis *vorbis, int *channels, int *sample_rate, unsigned int *frames)
+{
+    if (vorbis) {
+        stb_vorbis_info info = stb_vorbis_get_info(vorbis);
+        *channels    = info.channels;
+        *sample_rate = (int)info.sample_rate;
+        *frames      = stb_vorbis_stream_length_in_samples(vorbis);
+    }
+    return vorbis;
+}
+
+void *
+utl_vorbis_open_memory(const unsigned char *buffer, int buffer_size, int *channels, int *sample_rate, unsigned int *frames)
 {
-    assert(font && "passing invalid font to free");
-    if (font->img_buffer != NULL)
-        free(font->img_buffer);
+    int error = 0;
+    return vorbis_opened(stb_vorbis_open_memory(buffer, buffer_size, &error, NULL), channels, sample_rate, frames);
+}
+
+void *
+utl_vorbis_open_file(const char *filename, int *channels, int *sample_rate, unsigned int *frames)
+{
+    int error = 0;
+    return vorbis_opened(stb_vorbis_open_filename(filename, &error, NULL), channels, sample_rate, frames);
+}
+
+int
+utl_vorbis_read(void *vorbis, short *out, int channels, int frames)
+{
+    return stb_vorbis_get_samples_short_interleaved((stb_vorbis *)vorbis, channels, out, frames * channels);
+}
+
+int
+utl_vorbis_seek(void *vorbis, unsigned int frame)
+{
+    return stb_vorbis_seek((stb_vorbis *)vorbis, frame);
 }
 
 void
-utl_font_get_quad(struct Font *font, char c_to_display, float *xpos, float *ypos, struct stbtt_aligned_quad *quad)
-{
-    assert(font);
-    stbtt_GetPackedQuad((stbtt_packedchar*)font->packed_char, font->img_width, font->img_height,
-                        (c_to_display - ' '),     // character to display
-                        xpos, ypos,               // pointers to current position in screen pixel space
-                        (stbtt_aligned_quad*)quad,// output: quad to draw
-                        1);
+utl_vorbis_close(void *vorbis)
+{
+    if (vorbis) {
+        stb_vorbis_close((stb_vorbis *)vorbis);
+    }
 }
 
 unsigned int
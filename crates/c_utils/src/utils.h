@@ -13,24 +13,100 @@ struct stbtt_aligned_quad {
    float x1,y1,s1,t1; // bottom-right
 };
 
-#define NUMBER_OF_PACKED_CHARS ('~'-' ') // ansi
-/// True Type Font
-struct Font {
-    unsigned char *img_buffer;
-    int img_width, img_height, img_channels;
-
-    int font_size;
+/// a run of `count` unicode codepoints starting at `first_codepoint`.
+struct FontRange {
+    int first_codepoint;
+    int count;
+};
 
-    struct stbtt_packedchar packed_char[NUMBER_OF_PACKED_CHARS];
+/// True Type Font atlas baking options
+struct FontBakeConfig {
+    int font_index; // face inside a font collection (.ttc), 0 for plain .ttf
+    float font_size;
+    int atlas_width, atlas_height; // the atlas is always 1 channel
+    int padding;
+    unsigned int oversample_h, oversample_v;
 };
 
+#define UTL_IMAGE_FORMAT_PNG 0
+#define UTL_IMAGE_FORMAT_BMP 1
+#define UTL_IMAGE_FORMAT_TGA 2
+#define UTL_IMAGE_FORMAT_JPG 3
+#define UTL_IMAGE_FORMAT_HDR 4 // `buffer` holds floats
+
+// same signature as `stbi_write_func`, gets called with the encoded bytes in chunks.
+typedef void (*utl_write_func)(void *context, void *data, int size);
+
 //unsigned char * utl_image_load    (const char *filename, int *w, int *h, int *channels);
 unsigned char * utl_image_load    (const unsigned char *buffer, int buffer_size, int *w, int *h, int *channels);
+unsigned short* utl_image_load_16 (const unsigned char *buffer, int buffer_size, int *w, int *h, int *channels);
+float *         utl_image_load_f32(const unsigned char *buffer, int buffer_size, int *w, int *h, int *channels);
 void            utl_image_free    (unsigned char *buffer);
+
+// reads the header only, enough of the file for the header is enough.
+// returns: 0 if the format is not recognized.
+int             utl_image_info    (const unsigned char *buffer, int buffer_size, int *w, int *h, int *channels, int *is_16_bit, int *is_hdr);
+
+// all the frames of a gif as rgba, one after the other (w * h * 4 * frames bytes),
+// `delays` gets one entry per frame in milliseconds, free both with `utl_image_free`.
+unsigned char * utl_image_load_gif(const unsigned char *buffer, int buffer_size, int **delays, int *w, int *h, int *frames);
 int             utl_image_save_png(const char *filename, unsigned char *buffer, int w, int h, int channels);
 
-int  utl_font_parse(unsigned char *ttf_raw, int font_size, struct Font *font);
-void utl_font_free(struct Font *font);
-void utl_font_get_quad(struct Font *font, char c_to_display, float *xpot, float *ypos, struct stbtt_aligned_quad *quad);
+// writes tightly packed rows (w * channels values) in one of the UTL_IMAGE_FORMAT_*,
+// `quality` (1 .. 100) is only used by jpg.
+// returns: 0 on failure.
+int utl_image_save  (const char *filename, int format, const void *buffer, int w, int h, int channels, int quality);
+int utl_image_encode(utl_write_func func, void *context, int format, const void *buffer, int w, int h, int channels, int quality);
+
+// inflates a zlib stream (with its 2 byte header), `size_hint` is the expected output size.
+// returns: NULL on failure, free it with `utl_image_free`.
+unsigned char * utl_zlib_decode(const unsigned char *buffer, int buffer_size, int size_hint, int *out_size);
+
+// number of faces in a font collection (.ttc), 1 for a plain .ttf, 0 if `ttf_raw` is not a font.
+int utl_font_count(const unsigned char *ttf_raw);
+
+// copies the name record `name_id` (1 family, 2 style, 4 full name) as utf-16 big endian into `out`.
+// returns: the length in bytes (can be bigger than `out_capacity`), or -1 if the face has no such name.
+int utl_font_name(const unsigned char *ttf_raw, int font_index, int name_id, char *out, int out_capacity);
+
+// sets `has_glyph` (one byte per codepoint of `ranges`) to 1 for the codepoints the face has a glyph for.
+// returns: 0 if `ttf_raw` is not a valid font.
+int utl_font_glyph_coverage(const unsigned char *ttf_raw, int font_index,
+                            const struct FontRange *ranges, int ranges_count,
+                            unsigned char *has_glyph);
+
+// bakes `ranges` into `pixels` (atlas_width * atlas_height bytes) and `packed_chars`,
+// `packed_chars` has to hold the sum of all the ranges counts.
+// returns: 1 on success, 0 if the glyphs did not fit in the atlas, -1 if `ttf_raw` is not a valid font.
+int utl_font_parse(const unsigned char *ttf_raw, const struct FontBakeConfig *config,
+                   const struct FontRange *ranges, int ranges_count,
+                   unsigned char *pixels, struct stbtt_packedchar *packed_chars);
+
+/// one rectangle for `utl_rect_pack`, `x`, `y` and `was_packed` are outputs.
+struct PackRect {
+    int w, h;
+    int x, y;
+    int was_packed;
+};
+
+// packs `rects` into a `width` * `height` area with stb_rect_pack (skyline, best fit heuristic).
+// returns: 1 if every rect was packed, 0 if some did not fit (see `was_packed`).
+int utl_rect_pack(int width, int height, struct PackRect *rects, int rects_count);
+
+// decodes a whole ogg vorbis file to interleaved 16 bit samples (`frames` * `channels` values).
+// returns: NULL on failure, free it with `utl_vorbis_free`.
+short * utl_vorbis_decode(const unsigned char *buffer, int buffer_size, int *channels, int *sample_rate, int *frames);
+void    utl_vorbis_free  (short *samples);
+
+// streaming, `buffer` is read in place and has to outlive the stream, `frames` is the length.
+// returns: NULL on failure, close it with `utl_vorbis_close`.
+void * utl_vorbis_open_memory(const unsigned char *buffer, int buffer_size, int *channels, int *sample_rate, unsigned int *frames);
+void * utl_vorbis_open_file  (const char *filename, int *channels, int *sample_rate, unsigned int *frames);
+// reads up to `frames` interleaved frames of `channels` values into `out`.
+// returns: the frames read, 0 at the end of the stream.
+int    utl_vorbis_read (void *vorbis, short *out, int channels, int frames);
+// returns: 0 on failure.
+int    utl_vorbis_seek (void *vorbis, unsigned int frame);
+void   utl_vorbis_close(void *vorbis);
 
 unsigned int utl_hash_one_at_time(const char *key, unsigned long len);
@@ -268,6 +268,48 @@ impl Drop for Shader {
     }
 }
 
+/// internal format of a texture and the pixel data it is uploaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+    /// u8 pixels.
+    R8,
+    Rgb8,
+    Rgba8,
+    /// f32 pixels stored as half floats.
+    R16F,
+    Rgb16F,
+    Rgba16F,
+    /// f32 pixels.
+    R32F,
+    Rgb32F,
+    Rgba32F,
+}
+
+impl TextureFormat {
+    pub fn channels(&self) -> i32 {
+        match self {
+            Self::R8 | Self::R16F | Self::R32F => 1,
+            Self::Rgb8 | Self::Rgb16F | Self::Rgb32F => 3,
+            Self::Rgba8 | Self::Rgba16F | Self::Rgba32F => 4,
+        }
+    }
+
+    /// (internal format, format, type)
+    fn gl(&self) -> (GLenum, GLenum, GLenum) {
+        match self {
+            Self::R8 => (GL_R8, GL_RED, GL_UNSIGNED_BYTE),
+            Self::Rgb8 => (GL_RGB8, GL_RGB, GL_UNSIGNED_BYTE),
+            Self::Rgba8 => (GL_RGBA8, GL_RGBA, GL_UNSIGNED_BYTE),
+            Self::R16F => (GL_R16F, GL_RED, GL_FLOAT),
+            Self::Rgb16F => (GL_RGB16F, GL_RGB, GL_FLOAT),
+            Self::Rgba16F => (GL_RGBA16F, GL_RGBA, GL_FLOAT),
+            Self::R32F => (GL_R32F, GL_RED, GL_FLOAT),
+            Self::Rgb32F => (GL_RGB32F, GL_RGB, GL_FLOAT),
+            Self::Rgba32F => (GL_RGBA32F, GL_RGBA, GL_FLOAT),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Texture {
     pub handle: gl::GLuint,
@@ -279,11 +321,35 @@ impl Texture {
         width: i32,
         height: i32,
         channels: i32,
+    ) -> Result<Self, &'a str> {
+        let format = match channels {
+            1 => TextureFormat::R8,
+            3 => TextureFormat::Rgb8,
+            4 => TextureFormat::Rgba8,
+            _ => return Err("Passing image with unsupported number of channels."),
+        };
+
+        Self::init_with_format(pixels as *const GLvoid, width, height, format)
+    }
+
+    /// `pixels` are u8 for the 8 bit formats and f32 for the float ones,
+    /// float textures can not be filtered linearly on every GLES3 device so they stay nearest.
+    pub fn init_with_format<'a>(
+        pixels: *const GLvoid,
+        width: i32,
+        height: i32,
+        format: TextureFormat,
     ) -> Result<Self, &'a str> {
         if pixels.is_null() {
             return Err("Passing Null to Texture");
         }
 
+        let (internal_format, pixel_format, pixel_type) = format.gl();
+        let row_size = match pixel_type {
+            GL_FLOAT => width * format.channels() * size_of::<f32>() as i32,
+            _ => width * format.channels(),
+        };
+
         unsafe {
             let mut texture = 0;
 
@@ -295,57 +361,73 @@ impl Texture {
 
             glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_NEAREST as i32);
             glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST as i32);
-            match channels {
-                1 => {
-                    glPixelStorei(GL_UNPACK_ALIGNMENT, 1);
-                    glTexImage2D(
-                        GL_TEXTURE_2D,
-                        0,
-                        GL_RED as i32,
-                        width,
-                        height,
-                        0,
-                        GL_RED,
-                        GL_UNSIGNED_BYTE,
-                        pixels as *mut std::os::raw::c_void,
-                    );
-                }
-                3 => {
-                    glPixelStorei(GL_UNPACK_ALIGNMENT, 1);
-                    glTexImage2D(
-                        GL_TEXTURE_2D,
-                        0,
-                        GL_RGB as i32,
-                        width,
-                        height,
-                        0,
-                        GL_RGB,
-                        GL_UNSIGNED_BYTE,
-                        pixels as *mut std::os::raw::c_void,
-                    );
-                }
-                4 => {
-                    glPixelStorei(GL_UNPACK_ALIGNMENT, 4);
-                    glTexImage2D(
-                        GL_TEXTURE_2D,
-                        0,
-                        GL_RGBA as i32,
-                        width,
-                        height,
-                        0,
-                        GL_RGBA,
-                        GL_UNSIGNED_BYTE,
-                        pixels as *mut std::os::raw::c_void,
-                    );
-                }
 
-                _ => {
-                    return Err("Passing image with unsupported number of channels.");
-                }
+            glPixelStorei(GL_UNPACK_ALIGNMENT, if row_size % 4 == 0 { 4 } else { 1 });
+            glTexImage2D(
+                GL_TEXTURE_2D,
+                0,
+                internal_format as i32,
+                width,
+                height,
+                0,
+                pixel_format,
+                pixel_type,
+                pixels as *mut std::os::raw::c_void,
+            );
+
+            glBindTexture(GL_TEXTURE_2D, 0);
+
+            Ok(Self { handle: texture })
+        }
+    }
+
+    /// block compressed mip levels (width, height, data), biggest first.
+    /// `internal_format` is one of the GL_COMPRESSED_* formats, ETC2 / EAC are always there on GLES3.
+    pub fn init_compressed<'a>(
+        internal_format: GLenum,
+        levels: &[(i32, i32, &[u8])],
+    ) -> Result<Self, &'a str> {
+        if levels.is_empty() {
+            return Err("Passing no mip levels to Texture");
+        }
+
+        unsafe {
+            let mut texture = 0;
+
+            glGenTextures(1, &mut texture);
+            glBindTexture(GL_TEXTURE_2D, texture);
+
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as i32);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as i32);
+
+            glTexParameteri(
+                GL_TEXTURE_2D,
+                GL_TEXTURE_MIN_FILTER,
+                min_filter(levels.len()),
+            );
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_NEAREST as i32);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAX_LEVEL, levels.len() as i32 - 1);
+
+            for (level, (width, height, data)) in levels.iter().enumerate() {
+                glCompressedTexImage2D(
+                    GL_TEXTURE_2D,
+                    level as i32,
+                    internal_format,
+                    *width,
+                    *height,
+                    0,
+                    data.len() as i32,
+                    data.as_ptr() as *const GLvoid,
+                );
             }
 
             glBindTexture(GL_TEXTURE_2D, 0);
 
+            if let Some(err) = gl_get_error() {
+                glDeleteTextures(1, &texture);
+                return Err(err);
+            }
+
             Ok(Self { handle: texture })
         }
     }
@@ -372,6 +454,180 @@ impl Drop for Texture {
     }
 }
 
+/// GL_TEXTURE_2D_ARRAY, same size layers sampled with `sampler2DArray`.
+#[derive(Debug)]
+pub struct TextureArray {
+    pub handle: gl::GLuint,
+    pub layers: i32,
+}
+
+impl TextureArray {
+    /// `pixels` holds the `layers` images one after the other.
+    pub fn init<'a>(
+        pixels: *const u8,
+        width: i32,
+        height: i32,
+        layers: i32,
+        channels: i32,
+    ) -> Result<Self, &'a str> {
+        if pixels.is_null() {
+            return Err("Passing Null to TextureArray");
+        }
+
+        let (internal_format, pixel_format) = match channels {
+            1 => (GL_R8, GL_RED),
+            3 => (GL_RGB8, GL_RGB),
+            4 => (GL_RGBA8, GL_RGBA),
+            _ => return Err("Passing image with unsupported number of channels."),
+        };
+
+        unsafe {
+            let mut texture = 0;
+
+            glGenTextures(1, &mut texture);
+            glBindTexture(GL_TEXTURE_2D_ARRAY, texture);
+
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_WRAP_S,
+                GL_CLAMP_TO_EDGE as i32,
+            );
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_WRAP_T,
+                GL_CLAMP_TO_EDGE as i32,
+            );
+
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_MIN_FILTER,
+                GL_NEAREST as i32,
+            );
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_MAG_FILTER,
+                GL_NEAREST as i32,
+            );
+
+            glPixelStorei(
+                GL_UNPACK_ALIGNMENT,
+                if (width * channels) % 4 == 0 { 4 } else { 1 },
+            );
+            glTexImage3D(
+                GL_TEXTURE_2D_ARRAY,
+                0,
+                internal_format as i32,
+                width,
+                height,
+                layers,
+                0,
+                pixel_format,
+                GL_UNSIGNED_BYTE,
+                pixels as *mut std::os::raw::c_void,
+            );
+
+            glBindTexture(GL_TEXTURE_2D_ARRAY, 0);
+
+            Ok(Self {
+                handle: texture,
+                layers,
+            })
+        }
+    }
+
+    /// block compressed mip levels (width, height, data of all the layers), biggest first.
+    pub fn init_compressed<'a>(
+        internal_format: GLenum,
+        layers: i32,
+        levels: &[(i32, i32, &[u8])],
+    ) -> Result<Self, &'a str> {
+        if levels.is_empty() {
+            return Err("Passing no mip levels to TextureArray");
+        }
+
+        unsafe {
+            let mut texture = 0;
+
+            glGenTextures(1, &mut texture);
+            glBindTexture(GL_TEXTURE_2D_ARRAY, texture);
+
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_WRAP_S,
+                GL_CLAMP_TO_EDGE as i32,
+            );
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_WRAP_T,
+                GL_CLAMP_TO_EDGE as i32,
+            );
+
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_MIN_FILTER,
+                min_filter(levels.len()),
+            );
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_MAG_FILTER,
+                GL_NEAREST as i32,
+            );
+            glTexParameteri(
+                GL_TEXTURE_2D_ARRAY,
+                GL_TEXTURE_MAX_LEVEL,
+                levels.len() as i32 - 1,
+            );
+
+            for (level, (width, height, data)) in levels.iter().enumerate() {
+                glCompressedTexImage3D(
+                    GL_TEXTURE_2D_ARRAY,
+                    level as i32,
+                    internal_format,
+                    *width,
+                    *height,
+                    layers,
+                    0,
+                    data.len() as i32,
+                    data.as_ptr() as *const GLvoid,
+                );
+            }
+
+            glBindTexture(GL_TEXTURE_2D_ARRAY, 0);
+
+            if let Some(err) = gl_get_error() {
+                glDeleteTextures(1, &texture);
+                return Err(err);
+            }
+
+            Ok(Self {
+                handle: texture,
+                layers,
+            })
+        }
+    }
+
+    pub fn bind(&self, index: u32) {
+        unsafe {
+            assert!(
+                index < GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS,
+                "GLES, Texture index out of bounds, please pick an index between 0 and {}.",
+                GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS
+            );
+
+            glActiveTexture(GL_TEXTURE0 + index);
+            glBindTexture(GL_TEXTURE_2D_ARRAY, self.handle);
+        }
+    }
+}
+
+impl Drop for TextureArray {
+    fn drop(&mut self) {
+        unsafe {
+            glDeleteTextures(1, &self.handle);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum VertexFormat {
     StaticF32_3_2_3,
@@ -390,7 +646,7 @@ impl Vbo {
         Self::init(Some(vertices), VertexFormat::StaticF32_3_2_3)
     }
 
-    /// expecting to use Vbo::update() after creation.
+    /// expecting to use Vbo::update() after creation, the buffer takes any number of vertices.
     pub fn init_f32_2_2() -> Self {
         Self::init(None, VertexFormat::DynamicF32_2_2)
     }
@@ -454,8 +710,8 @@ impl Vbo {
         match self.vertex_format {
             VertexFormat::DynamicF32_2_2 => {
                 assert_eq!(
-                    (6 * 4),
-                    vertices.len(),
+                    0,
+                    vertices.len() % 4,
                     "Passing invalid format/size of vertices to update Vbo.",
                 );
 
@@ -578,6 +834,19 @@ impl Vao {
             glBindVertexArray(0);
         };
     }
+
+    /// draws `count` vertices starting at `first`, for dynamic buffers where the vertices count changes.
+    pub fn draw_triangles_range(&self, first: gl::GLint, count: gl::GLsizei) {
+        if count <= 0 {
+            return;
+        }
+
+        unsafe {
+            glBindVertexArray(self.handle);
+            glDrawArrays(GL_TRIANGLES, first, count);
+            glBindVertexArray(0);
+        };
+    }
 }
 
 impl Drop for Vao {
@@ -595,6 +864,15 @@ impl FrameBuffer {
     // TODO
 }
 
+/// nearest, or nearest between the nearest mip levels when there are some.
+fn min_filter(levels_count: usize) -> i32 {
+    if levels_count > 1 {
+        GL_NEAREST_MIPMAP_NEAREST as i32
+    } else {
+        GL_NEAREST as i32
+    }
+}
+
 fn gl_get_error<'a>() -> Option<&'a str> {
     unsafe {
         match glGetError() {
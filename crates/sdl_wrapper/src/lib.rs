@@ -24,6 +24,32 @@ pub fn poll_events() -> bool {
     unsafe { sys::plt_poll_events() != 0 }
 }
 
+/// starts audio on its own, it also works without a window. false without an audio driver.
+pub fn audio_init() -> bool {
+    unsafe { sys::plt_audio_init() != 0 }
+}
+
+pub fn audio_quit() {
+    unsafe {
+        sys::plt_audio_quit();
+    }
+}
+
+/// names of the output devices, for `plt_audio_open`.
+pub fn audio_devices() -> Vec<String> {
+    let count = unsafe { sys::plt_audio_device_count() };
+    (0..count)
+        .filter_map(|i| {
+            let name = unsafe { sys::plt_audio_device_name(i) };
+            if name.is_null() {
+                return None;
+            }
+            let name = unsafe { std::ffi::CStr::from_ptr(name) };
+            Some(name.to_string_lossy().into_owned())
+        })
+        .collect()
+}
+
 pub fn gl_swap_buffers() {
     unsafe {
         sys::plt_gl_swap_buffers();
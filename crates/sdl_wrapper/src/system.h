@@ -440,9 +440,6 @@ typedef struct {
   f64 delta_time; // the last increment between the last frame and currnet frame in seconds.
 }Clock;
 
-typedef void Chunk;
-typedef void Music;
-
 // statically linked gles2 lib
 PLT_DEF void plt_init_gles2_static(const char *window_name, i32 width, i32 height);
 PLT_DEF void plt_quit             (void);
@@ -467,18 +464,23 @@ PLT_DEF const Keyboard* plt_keyboard(void);
 PLT_DEF const Clock*    plt_clock   (void);
 
 
-PLT_DEF Chunk * plt_chunk_load(const u8 *data, size_t size);
-PLT_DEF void    plt_chunk_free(Chunk *chunk);
-// If the specified channel is -1, play on the first free channel (and return  -1 without playing anything new if no free channel was available).
-// If `loops` is greater than zero, loop the sound that many times. If `loops` is -1, loop "infinitely" (~65000 times).
-// returns:  which channel was used to play the sound, or -1 if sound could not be played.
-PLT_DEF i32     plt_channel_play(i32 channel, Chunk *chunk, i32 loops);
-// If the specified channel is -1, all channels are checked, and this function returns the number of channels currently playing.
-PLT_DEF i32     plt_channel_is_playing(i32 channel);
-
-//PLT_DEF i32 plt_music_play();
-//PLT_DEF Music * plt_music_load(const u8 *data, size_t size);
-//PLT_DEF void    plt_music_free(Music *music);
+// called from the audio thread with `len` bytes of interleaved f32 frames to fill.
+typedef void (*plt_audio_callback)(void *udata, u8 *stream, i32 len);
+// starts the audio subsystem, works without a window. returns: 0 if there is no audio driver.
+PLT_DEF b32     plt_audio_init(void);
+// closes the output device and stops the audio subsystem.
+PLT_DEF void    plt_audio_quit(void);
+// output devices by index, the names are valid until the next call.
+PLT_DEF i32         plt_audio_device_count(void);
+PLT_DEF const char* plt_audio_device_name (i32 index);
+// opens `device_name` (NULL or "" for the default) for f32 frames at `frequency` with `channels`, SDL converts to whatever the device uses.
+// `samples` is the device buffer in frames, `callback` starts being called right away. a device that fails to open falls back to the default.
+// there is one output at a time, opening closes the previous one. plt_poll_events reopens it when its device is unplugged (on the default)
+// or plugged back in (the named one).
+// returns: a token for plt_audio_close that stays valid across reopening, 0 if there is no audio (no driver, no device, headless), never fatal.
+PLT_DEF u32     plt_audio_open(const char *device_name, i32 frequency, i32 channels, i32 samples, plt_audio_callback callback, void *udata);
+// stops the callback and closes the output opened with `token`, 0 and old tokens are ignored.
+PLT_DEF void    plt_audio_close(u32 token);
 
 #define UNUSED(x) ((void)(x))
 
@@ -501,7 +503,6 @@ PLT_DEF i32     plt_channel_is_playing(i32 channel);
 #ifdef PLT_IMPLEMENTATION
 
 #include <SDL2/SDL.h>
-#include <SDL2/SDL_mixer.h>
 
 static struct System {
   SDL_Window    *window;
@@ -512,14 +513,25 @@ static struct System {
   Clock    clock;
 } *g_sys;
 
+// the output device, separate from g_sys so audio works without a window.
+static struct Audio {
+  SDL_AudioDeviceID device;   // 0 while closed or lost.
+  u32               token;    // 0 while closed.
+  u32               next_token;
+  b32               fallback; // playing on the default instead of `name`.
+  SDL_AudioSpec     want;
+  char              name[256];
+} g_audio;
+
 internal void clock_update (Clock *clock);
 internal void mouse_tick   (Mouse *mouse);
 internal void keyboard_tick(Keyboard *keyboard);
+internal void audio_hotplug(b32 removed, u32 which);
 
 PLT_DEF void
 plt_init_gles2_static(const char *window_name, i32 width, i32 height)
 {
-  Assert(SDL_Init(SDL_INIT_VIDEO|SDL_INIT_AUDIO) == 0);
+  Assert(SDL_Init(SDL_INIT_VIDEO) == 0);
 
   g_sys = malloc(sizeof(*g_sys));
   memset(g_sys, 0, sizeof(*g_sys));
@@ -555,32 +567,6 @@ plt_init_gles2_static(const char *window_name, i32 width, i32 height)
   g_sys->gl_context = SDL_GL_CreateContext(g_sys->window);
   Assert(g_sys->gl_context);
   SDL_GL_SetSwapInterval(1); // Enable vsync
-
-  ////////////////
-  ///// init audio
-  ////////////////
-
-#ifdef DEBUG
-  i32 number_of_devices = SDL_GetNumAudioDevices(0);
-  for ( i32 i = 0
-      ; i < number_of_devices
-      ; i ++ )
-  {
-    plt_log_info("audio device [%s]", SDL_GetAudioDeviceName(i, 0));
-  }
-#endif
-
-  i32 audio_device_success;
-
-  i32 frequency = 48000;
-  u16 format    = AUDIO_S16SYS;
-  i32 nchannels = 2;            // Stereo
-  i32 chunksize = 1024;         // 2048, 4096
-
-  audio_device_success = Mix_OpenAudioDevice(frequency, format, nchannels, chunksize, NULL,
-      SDL_AUDIO_ALLOW_FREQUENCY_CHANGE | SDL_AUDIO_ALLOW_CHANNELS_CHANGE);
-
-  Assert((audio_device_success == 0) && "failed to open audio device");
 }
 
 PLT_DEF void
@@ -590,8 +576,7 @@ plt_quit(void)
   SDL_GL_DeleteContext(g_sys->gl_context);
   SDL_DestroyWindow(g_sys->window);
 
-  Mix_CloseAudio();
-
+  plt_audio_quit();
   SDL_Quit();
   free(g_sys);
 }
@@ -637,6 +622,15 @@ plt_poll_events(void)
         }
       }break;
 
+      case SDL_AUDIODEVICEADDED:
+      case SDL_AUDIODEVICEREMOVED:
+      {
+        if (!e.adevice.iscapture)
+        {
+          audio_hotplug(e.type == SDL_AUDIODEVICEREMOVED, e.adevice.which);
+        }
+      }break;
+
       default: {} // do noting
     }
   }
@@ -833,29 +827,152 @@ keyboard_tick(Keyboard *keyboard)
   }
 }
 
-PLT_DEF Chunk *
-plt_chunk_load(const u8 *data, size_t size)
+PLT_DEF b32
+plt_audio_init(void)
 {
-  return (Chunk*)Mix_QuickLoad_RAW((u8*)data, size);
+  if (!SDL_WasInit(SDL_INIT_AUDIO) && SDL_InitSubSystem(SDL_INIT_AUDIO) != 0)
+  {
+    plt_log_warn("no audio: %s", SDL_GetError());
+    return 0;
+  }
+  return 1;
 }
 
 PLT_DEF void
-plt_chunk_free(Chunk *chunk)
+plt_audio_quit(void)
 {
-  Mix_FreeChunk((Mix_Chunk*)chunk);
+  plt_audio_close(g_audio.token);
+  if (SDL_WasInit(SDL_INIT_AUDIO))
+  {
+    SDL_QuitSubSystem(SDL_INIT_AUDIO);
+  }
 }
 
 PLT_DEF i32
-plt_channel_play(i32 channel, Chunk *chunk, i32 loops)
+plt_audio_device_count(void)
 {
-  return Mix_PlayChannel(channel, (Mix_Chunk*)chunk, loops);
+  if (!plt_audio_init())
+  {
+    return 0;
+  }
+  i32 count = SDL_GetNumAudioDevices(0);
+  return count < 0 ? 0 : count;
 }
 
-// If the specified channel is -1, all channels are checked, and this function returns the number of channels currently playing.
-PLT_DEF i32
-plt_channel_is_playing(i32 channel)
+PLT_DEF const char*
+plt_audio_device_name(i32 index)
 {
-  return Mix_Playing(channel);
+  return SDL_GetAudioDeviceName(index, 0);
+}
+
+// opens `g_audio.name`, or the default if it is empty or fails to open.
+internal SDL_AudioDeviceID
+audio_reopen(void)
+{
+  SDL_AudioDeviceID device = 0;
+  g_audio.fallback = 0;
+
+  if (g_audio.name[0])
+  {
+    device = SDL_OpenAudioDevice(g_audio.name, 0, &g_audio.want, NULL, 0);
+    if (device == 0)
+    {
+      plt_log_warn("failed to open audio device '%s': %s, using the default", g_audio.name, SDL_GetError());
+      g_audio.fallback = 1;
+    }
+  }
+  if (device == 0)
+  {
+    // no allowed changes, SDL converts so the mixer keeps its own format.
+    device = SDL_OpenAudioDevice(NULL, 0, &g_audio.want, NULL, 0);
+  }
+  if (device == 0)
+  {
+    plt_log_warn("failed to open audio device: %s", SDL_GetError());
+    return 0;
+  }
+
+  SDL_PauseAudioDevice(device, 0);
+  return device;
+}
+
+PLT_DEF u32
+plt_audio_open(const char *device_name, i32 frequency, i32 channels, i32 samples, plt_audio_callback callback, void *udata)
+{
+  plt_audio_close(g_audio.token);
+  if (!plt_audio_init())
+  {
+    return 0;
+  }
+
+  SDL_zero(g_audio.want);
+  g_audio.want.freq     = frequency;
+  g_audio.want.format   = AUDIO_F32SYS;
+  g_audio.want.channels = (u8)channels;
+  g_audio.want.samples  = (u16)samples;
+  g_audio.want.callback = (SDL_AudioCallback)callback;
+  g_audio.want.userdata = udata;
+  SDL_strlcpy(g_audio.name, device_name ? device_name : "", sizeof(g_audio.name));
+
+  g_audio.device = audio_reopen();
+  if (g_audio.device == 0)
+  {
+    return 0;
+  }
+
+  g_audio.token = ++g_audio.next_token;
+  return g_audio.token;
+}
+
+PLT_DEF void
+plt_audio_close(u32 token)
+{
+  if (token == 0 || token != g_audio.token)
+  {
+    return;
+  }
+  if (g_audio.device != 0)
+  {
+    SDL_CloseAudioDevice(g_audio.device);
+  }
+  g_audio.device = 0;
+  g_audio.token  = 0;
+}
+
+// `which` is the device id when removed and the device index when added.
+internal void
+audio_hotplug(b32 removed, u32 which)
+{
+  if (g_audio.token == 0)
+  {
+    return;
+  }
+
+  if (removed)
+  {
+    if (which != g_audio.device)
+    {
+      return;
+    }
+    plt_log_info("audio device lost, reopening");
+    SDL_CloseAudioDevice(g_audio.device);
+    // a named device that is gone falls back, plugging it back in switches back to it.
+    g_audio.device = audio_reopen();
+    return;
+  }
+
+  const char *name = SDL_GetAudioDeviceName((i32)which, 0);
+  b32 lost     = g_audio.device == 0;
+  b32 returned = g_audio.fallback && name && SDL_strcmp(name, g_audio.name) == 0;
+  if (lost || returned)
+  {
+    plt_log_info("audio device added: %s", name ? name : "?");
+    if (g_audio.device != 0)
+    {
+      SDL_CloseAudioDevice(g_audio.device);
+    }
+    g_audio.device = audio_reopen();
+  }
 }
 
 #endif // PLT_IMPLEMENTATION
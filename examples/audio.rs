@@ -7,7 +7,7 @@ fn main() -> Result<(), &'static str> {
 
     let raw_chunk = read_res("mixkit-retro-game-notification-212-edit.wav")?;
 
-    let chunk = audio::Audio::init(raw_chunk.as_slice());
+    let chunk = audio::Audio::init(raw_chunk.as_slice())?;
 
     'main_loop: while sdl_wrapper::poll_events() {
         if keyboard_key_clicked(sdl_wrapper::sys::KEY_ESCAPE) {
@@ -0,0 +1,212 @@
+//! buses group voices under one volume and effect chain: every bus is mixed into its parent
+//! up to master, and ducking rules lower a bus while another one has sounds playing.
+
+use super::effects::EffectChain;
+
+/// a bus of the mixer, the five defaults always exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BusId(pub(super) usize);
+
+impl BusId {
+    pub const MASTER: Self = Self(0);
+    pub const MUSIC: Self = Self(1);
+    pub const SFX: Self = Self(2);
+    pub const VOICE: Self = Self(3);
+    pub const UI: Self = Self(4);
+}
+
+/// lowers `target` to `gain` while anything plays on `trigger` (or a bus under it),
+/// ramping down over `attack` seconds and back up over `release` seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DuckRule {
+    pub trigger: BusId,
+    pub target: BusId,
+    pub gain: f32,
+    pub attack: f32,
+    pub release: f32,
+}
+
+struct Bus {
+    name: String,
+    /// always a bus added before this one, so gains resolve in order.
+    parent: Option<usize>,
+    volume: f32,
+    effects: EffectChain,
+}
+
+struct Ducking {
+    rule: DuckRule,
+    /// 1.0 released, `rule.gain` fully ducked.
+    level: f32,
+}
+
+/// the buses of a mixer and the per frame gains of the block being mixed.
+pub(super) struct Routing {
+    buses: Vec<Bus>,
+    ducking: Vec<Ducking>,
+    /// buses x frames, volume times ducking of the bus alone.
+    gains: Vec<f32>,
+    frames: usize,
+    active: Vec<bool>,
+}
+
+impl Routing {
+    pub(super) fn init() -> Self {
+        let mut routing = Self {
+            buses: Vec::new(),
+            ducking: Vec::new(),
+            gains: Vec::new(),
+            frames: 0,
+            active: Vec::new(),
+        };
+        routing.buses.push(Bus {
+            name: "master".to_string(),
+            parent: None,
+            volume: 1.0,
+            effects: EffectChain::init(),
+        });
+        for name in ["music", "sfx", "voice", "ui"] {
+            routing.add(name, BusId::MASTER);
+        }
+        routing
+    }
+
+    /// an unknown parent puts the bus under master.
+    pub(super) fn add(&mut self, name: &str, parent: BusId) -> BusId {
+        let parent = parent.0.min(self.buses.len() - 1);
+        self.buses.push(Bus {
+            name: name.to_string(),
+            parent: Some(parent),
+            volume: 1.0,
+            effects: EffectChain::init(),
+        });
+        BusId(self.buses.len() - 1)
+    }
+
+    pub(super) fn find(&self, name: &str) -> Option<BusId> {
+        self.buses.iter().position(|b| b.name == name).map(BusId)
+    }
+
+    pub(super) fn contains(&self, bus: BusId) -> bool {
+        bus.0 < self.buses.len()
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.buses.len()
+    }
+
+    pub(super) fn parent(&self, bus: usize) -> Option<usize> {
+        self.buses.get(bus).and_then(|b| b.parent)
+    }
+
+    pub(super) fn effects(&mut self, bus: BusId) -> Option<&mut EffectChain> {
+        self.buses.get_mut(bus.0).map(|b| &mut b.effects)
+    }
+
+    pub(super) fn set_volume(&mut self, bus: BusId, volume: f32) {
+        if let Some(bus) = self.buses.get_mut(bus.0) {
+            bus.volume = volume.max(0.0);
+        }
+    }
+
+    pub(super) fn volume(&self, bus: BusId) -> f32 {
+        self.buses.get(bus.0).map_or(0.0, |b| b.volume)
+    }
+
+    /// true if `bus` is `ancestor` or somewhere under it.
+    pub(super) fn is_under(&self, bus: usize, ancestor: usize) -> bool {
+        let mut at = Some(bus);
+        while let Some(b) = at {
+            if b == ancestor {
+                return true;
+            }
+            at = self.buses.get(b).and_then(|b| b.parent);
+        }
+        false
+    }
+
+    pub(super) fn add_ducking(&mut self, rule: DuckRule) {
+        let rule = DuckRule {
+            gain: rule.gain.clamp(0.0, 1.0),
+            ..rule
+        };
+        self.ducking.push(Ducking { rule, level: 1.0 });
+    }
+
+    pub(super) fn clear_ducking(&mut self) {
+        self.ducking.clear();
+    }
+
+    /// volume times ducking of `bus` and its parents, as of the last frame mixed.
+    pub(super) fn gain(&self, bus: BusId) -> f32 {
+        let mut gain = 1.0;
+        let mut at = Some(bus.0);
+        while let Some(b) = at {
+            let Some(node) = self.buses.get(b) else {
+                return 0.0;
+            };
+            gain *= node.volume * self.duck_level(b);
+            at = node.parent;
+        }
+        gain
+    }
+
+    fn duck_level(&self, bus: usize) -> f32 {
+        self.ducking
+            .iter()
+            .filter(|d| d.rule.target.0 == bus)
+            .map(|d| d.level)
+            .product()
+    }
+
+    /// marks the buses that have something playing, reset by `update`.
+    pub(super) fn set_active(&mut self, bus: BusId) {
+        self.active.resize(self.buses.len(), false);
+        if let Some(active) = self.active.get_mut(bus.0) {
+            *active = true;
+        }
+    }
+
+    /// steps the ducking and fills the gain of every bus for the next `frames` frames.
+    pub(super) fn update(&mut self, frames: usize, sample_rate: u32) {
+        self.active.resize(self.buses.len(), false);
+        let buses = self.buses.len();
+
+        let triggered: Vec<bool> = self
+            .ducking
+            .iter()
+            .map(|d| (0..buses).any(|b| self.active[b] && self.is_under(b, d.rule.trigger.0)))
+            .collect();
+        self.active.iter_mut().for_each(|a| *a = false);
+
+        self.frames = frames;
+        self.gains.resize(buses * frames, 1.0);
+        let rate = sample_rate.max(1) as f32;
+
+        for frame in 0..frames {
+            for (duck, &on) in self.ducking.iter_mut().zip(&triggered) {
+                let depth = 1.0 - duck.rule.gain;
+                if on {
+                    let step = depth / (duck.rule.attack * rate).max(1.0);
+                    duck.level = (duck.level - step).max(duck.rule.gain);
+                } else {
+                    let step = depth / (duck.rule.release * rate).max(1.0);
+                    duck.level = (duck.level + step).min(1.0);
+                }
+            }
+
+            for b in 0..buses {
+                self.gains[b * frames + frame] = self.buses[b].volume * self.duck_level(b);
+            }
+        }
+    }
+
+    /// gain of `bus` alone at `frame` of the block given to `update`, the parents apply
+    /// theirs when it is mixed into them.
+    pub(super) fn frame_gain(&self, bus: BusId, frame: usize) -> f32 {
+        self.gains
+            .get(bus.0 * self.frames + frame)
+            .copied()
+            .unwrap_or(0.0)
+    }
+}
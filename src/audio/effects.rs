@@ -0,0 +1,686 @@
+//! effects for the chains of buses and voices: biquad filters, a freeverb style reverb, delay,
+//! bitcrusher and a compressor / limiter. parameters are set and automated by name.
+
+use std::f32::consts::PI;
+
+/// processes interleaved frames in place.
+pub trait Effect: Send {
+    fn process(&mut self, buffer: &mut [f32], channels: usize, sample_rate: u32);
+    /// false if the effect has no parameter called `name`.
+    fn set_param(&mut self, name: &str, value: f32) -> bool;
+    fn param(&self, name: &str) -> Option<f32>;
+    /// clears filter state and tails.
+    fn reset(&mut self) {}
+}
+
+/// parameters change at most this often while automated, in frames.
+const AUTOMATION_BLOCK: usize = 32;
+
+struct Automation {
+    effect: usize,
+    param: String,
+    from: f32,
+    to: f32,
+    seconds: f32,
+    /// frames processed since it started.
+    done: usize,
+}
+
+/// effects run one after the other, with linear parameter ramps.
+#[derive(Default)]
+pub struct EffectChain {
+    effects: Vec<Box<dyn Effect>>,
+    automations: Vec<Automation>,
+}
+
+impl EffectChain {
+    pub fn init() -> Self {
+        Self::default()
+    }
+
+    /// appends `effect`, returns its index in the chain.
+    pub fn push(&mut self, effect: Box<dyn Effect>) -> usize {
+        self.effects.push(effect);
+        self.effects.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+        self.automations.clear();
+    }
+
+    pub fn set_param(&mut self, effect: usize, name: &str, value: f32) -> bool {
+        // a parameter set by hand stops being automated.
+        self.automations
+            .retain(|a| a.effect != effect || a.param != name);
+        self.effects
+            .get_mut(effect)
+            .is_some_and(|e| e.set_param(name, value))
+    }
+
+    pub fn param(&self, effect: usize, name: &str) -> Option<f32> {
+        self.effects.get(effect)?.param(name)
+    }
+
+    /// ramps `name` of `effect` from its current value to `to` over `seconds` of processing,
+    /// false if there is no such parameter.
+    pub fn automate(&mut self, effect: usize, name: &str, to: f32, seconds: f32) -> bool {
+        let Some(from) = self.param(effect, name) else {
+            return false;
+        };
+        self.automations
+            .retain(|a| a.effect != effect || a.param != name);
+        self.automations.push(Automation {
+            effect,
+            param: name.to_string(),
+            from,
+            to,
+            seconds,
+            done: 0,
+        });
+        true
+    }
+
+    pub fn reset(&mut self) {
+        self.effects.iter_mut().for_each(|e| e.reset());
+    }
+
+    pub fn process(&mut self, buffer: &mut [f32], channels: usize, sample_rate: u32) {
+        if self.effects.is_empty() {
+            return;
+        }
+        if self.automations.is_empty() {
+            for effect in self.effects.iter_mut() {
+                effect.process(buffer, channels, sample_rate);
+            }
+            return;
+        }
+
+        for block in buffer.chunks_mut(AUTOMATION_BLOCK * channels) {
+            let frames = block.len() / channels;
+            let effects = &mut self.effects;
+            self.automations.retain_mut(|a| {
+                let length = a.seconds * sample_rate as f32;
+                let t = match length > 0.0 {
+                    true => (a.done as f32 / length).min(1.0),
+                    false => 1.0,
+                };
+                if let Some(effect) = effects.get_mut(a.effect) {
+                    effect.set_param(&a.param, a.from + (a.to - a.from) * t);
+                }
+                a.done += frames;
+                t < 1.0
+            });
+
+            for effect in self.effects.iter_mut() {
+                effect.process(block, channels, sample_rate);
+            }
+        }
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-9).log10()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    LowPass,
+    HighPass,
+    /// 0 dB at the center frequency.
+    BandPass,
+    LowShelf,
+    HighShelf,
+}
+
+/// the RBJ cookbook filters.
+/// parameters: "frequency" (Hz), "q", "gain_db" (shelves only).
+pub struct Biquad {
+    kind: FilterKind,
+    frequency: f32,
+    q: f32,
+    gain_db: f32,
+    /// b0, b1, b2, a1, a2 normalized by a0, for `coefficients_rate`.
+    coefficients: [f32; 5],
+    coefficients_rate: u32,
+    /// transposed direct form II state per channel.
+    state: Vec<[f32; 2]>,
+}
+
+impl Biquad {
+    pub fn init(kind: FilterKind, frequency: f32, q: f32) -> Self {
+        Self {
+            kind,
+            frequency,
+            q,
+            gain_db: 0.0,
+            coefficients: [1.0, 0.0, 0.0, 0.0, 0.0],
+            coefficients_rate: 0,
+            state: Vec::new(),
+        }
+    }
+
+    pub fn low_pass(frequency: f32) -> Self {
+        Self::init(
+            FilterKind::LowPass,
+            frequency,
+            std::f32::consts::FRAC_1_SQRT_2,
+        )
+    }
+
+    pub fn high_pass(frequency: f32) -> Self {
+        Self::init(
+            FilterKind::HighPass,
+            frequency,
+            std::f32::consts::FRAC_1_SQRT_2,
+        )
+    }
+
+    pub fn band_pass(frequency: f32, q: f32) -> Self {
+        Self::init(FilterKind::BandPass, frequency, q)
+    }
+
+    pub fn low_shelf(frequency: f32, gain_db: f32) -> Self {
+        Self {
+            gain_db,
+            ..Self::init(
+                FilterKind::LowShelf,
+                frequency,
+                std::f32::consts::FRAC_1_SQRT_2,
+            )
+        }
+    }
+
+    pub fn high_shelf(frequency: f32, gain_db: f32) -> Self {
+        Self {
+            gain_db,
+            ..Self::init(
+                FilterKind::HighShelf,
+                frequency,
+                std::f32::consts::FRAC_1_SQRT_2,
+            )
+        }
+    }
+
+    fn update(&mut self, sample_rate: u32) {
+        let nyquist = sample_rate as f32 * 0.5;
+        let w0 = 2.0 * PI * self.frequency.clamp(1.0, nyquist * 0.999) / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * self.q.max(0.01));
+        let a = 10.0f32.powf(self.gain_db / 40.0);
+        let sq = 2.0 * a.sqrt() * alpha;
+
+        let [b0, b1, b2, a0, a1, a2] = match self.kind {
+            FilterKind::LowPass => [
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ],
+            FilterKind::HighPass => [
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ],
+            FilterKind::BandPass => [alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            FilterKind::LowShelf => [
+                a * ((a + 1.0) - (a - 1.0) * cos + sq),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sq),
+                (a + 1.0) + (a - 1.0) * cos + sq,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sq,
+            ],
+            FilterKind::HighShelf => [
+                a * ((a + 1.0) + (a - 1.0) * cos + sq),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sq),
+                (a + 1.0) - (a - 1.0) * cos + sq,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sq,
+            ],
+        };
+
+        self.coefficients = [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0];
+        self.coefficients_rate = sample_rate;
+    }
+}
+
+impl Effect for Biquad {
+    fn process(&mut self, buffer: &mut [f32], channels: usize, sample_rate: u32) {
+        if self.coefficients_rate != sample_rate {
+            self.update(sample_rate);
+        }
+        self.state.resize(channels, [0.0; 2]);
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+
+        for frame in buffer.chunks_exact_mut(channels) {
+            for (x, z) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let y = b0 * *x + z[0];
+                z[0] = b1 * *x - a1 * y + z[1];
+                z[1] = b2 * *x - a2 * y;
+                *x = y;
+            }
+        }
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "frequency" => self.frequency = value,
+            "q" => self.q = value,
+            "gain_db" => self.gain_db = value,
+            _ => return false,
+        }
+        // recomputed on the next process.
+        self.coefficients_rate = 0;
+        true
+    }
+
+    fn param(&self, name: &str) -> Option<f32> {
+        match name {
+            "frequency" => Some(self.frequency),
+            "q" => Some(self.q),
+            "gain_db" => Some(self.gain_db),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.clear();
+    }
+}
+
+/// feedback comb with a one pole low pass in the loop.
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    store: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damp: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.store = output * (1.0 - damp) + self.store * damp;
+        self.buffer[self.index] = input + self.store * feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.index];
+        self.buffer[self.index] = input + delayed * 0.5;
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+/// Jezar's freeverb tunings, in samples at 44100 Hz.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+
+/// freeverb: 8 parallel combs into 4 allpasses, per side.
+/// parameters: "room_size", "damping", "wet", "dry", "width", all 0.0 to 1.0.
+pub struct Reverb {
+    room_size: f32,
+    damping: f32,
+    wet: f32,
+    dry: f32,
+    width: f32,
+    sides: Vec<(Vec<Comb>, Vec<Allpass>)>,
+    sides_rate: u32,
+}
+
+impl Reverb {
+    pub fn init(room_size: f32, damping: f32, wet: f32) -> Self {
+        Self {
+            room_size,
+            damping,
+            wet,
+            dry: 1.0,
+            width: 1.0,
+            sides: Vec::new(),
+            sides_rate: 0,
+        }
+    }
+
+    fn build(&mut self, sample_rate: u32) {
+        let scale = |n: usize| ((n * sample_rate as usize) / 44100).max(1);
+        self.sides = (0..2)
+            .map(|side| {
+                let spread = side * STEREO_SPREAD;
+                let combs = COMB_TUNING
+                    .iter()
+                    .map(|&n| Comb {
+                        buffer: vec![0.0; scale(n + spread)],
+                        index: 0,
+                        store: 0.0,
+                    })
+                    .collect();
+                let allpasses = ALLPASS_TUNING
+                    .iter()
+                    .map(|&n| Allpass {
+                        buffer: vec![0.0; scale(n + spread)],
+                        index: 0,
+                    })
+                    .collect();
+                (combs, allpasses)
+            })
+            .collect();
+        self.sides_rate = sample_rate;
+    }
+}
+
+impl Effect for Reverb {
+    fn process(&mut self, buffer: &mut [f32], channels: usize, sample_rate: u32) {
+        if self.sides_rate != sample_rate {
+            self.build(sample_rate);
+        }
+        let feedback = self.room_size.clamp(0.0, 1.0) * 0.28 + 0.7;
+        let damp = self.damping.clamp(0.0, 1.0) * 0.4;
+        let wet = self.wet * 3.0;
+        let wet1 = wet * (self.width / 2.0 + 0.5);
+        let wet2 = wet * ((1.0 - self.width) / 2.0);
+        let dry = self.dry * 2.0;
+
+        for frame in buffer.chunks_exact_mut(channels) {
+            let left = frame[0];
+            let right = frame.get(1).copied().unwrap_or(left);
+            let input = (left + right) * 0.015;
+
+            let mut out = [0.0f32; 2];
+            for (o, (combs, allpasses)) in out.iter_mut().zip(self.sides.iter_mut()) {
+                let mut v: f32 = combs
+                    .iter_mut()
+                    .map(|c| c.process(input, feedback, damp))
+                    .sum();
+                for allpass in allpasses.iter_mut() {
+                    v = allpass.process(v);
+                }
+                *o = v;
+            }
+
+            if channels == 1 {
+                frame[0] = out[0] * (wet1 + wet2) + left * dry / 2.0;
+            } else {
+                frame[0] = out[0] * wet1 + out[1] * wet2 + left * dry / 2.0;
+                frame[1] = out[1] * wet1 + out[0] * wet2 + right * dry / 2.0;
+            }
+        }
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "room_size" => self.room_size = value,
+            "damping" => self.damping = value,
+            "wet" => self.wet = value,
+            "dry" => self.dry = value,
+            "width" => self.width = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn param(&self, name: &str) -> Option<f32> {
+        match name {
+            "room_size" => Some(self.room_size),
+            "damping" => Some(self.damping),
+            "wet" => Some(self.wet),
+            "dry" => Some(self.dry),
+            "width" => Some(self.width),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sides_rate = 0;
+    }
+}
+
+/// the longest "time" of a `Delay` in seconds.
+pub const MAX_DELAY: f32 = 2.0;
+
+/// echo: the signal comes back after "time" seconds (up to `MAX_DELAY`), "feedback" of it
+/// again and again, "mix" is the level of the echoes against the dry signal.
+pub struct Delay {
+    time: f32,
+    feedback: f32,
+    mix: f32,
+    /// interleaved ring buffer of `MAX_DELAY`, written at `index`.
+    buffer: Vec<f32>,
+    index: usize,
+    /// channels and sample rate the buffer was made for.
+    format: (usize, u32),
+}
+
+impl Delay {
+    pub fn init(time: f32, feedback: f32, mix: f32) -> Self {
+        Self {
+            time,
+            feedback,
+            mix,
+            buffer: Vec::new(),
+            index: 0,
+            format: (0, 0),
+        }
+    }
+}
+
+impl Effect for Delay {
+    fn process(&mut self, buffer: &mut [f32], channels: usize, sample_rate: u32) {
+        let len = ((MAX_DELAY * sample_rate as f32).ceil() as usize + 1).max(2);
+        if self.format != (channels, sample_rate) {
+            self.buffer = vec![0.0; len * channels];
+            self.index = 0;
+            self.format = (channels, sample_rate);
+        }
+        // a new "time" only moves the read tap, the echoes already in the buffer stay.
+        let delay = (self.time * sample_rate as f32).clamp(1.0, (len - 1) as f32);
+        let behind = delay.ceil() as usize;
+        // weight of the newer of the two frames around the tap.
+        let t = behind as f32 - delay;
+        let feedback = self.feedback.clamp(0.0, 0.99);
+
+        for frame in buffer.chunks_exact_mut(channels) {
+            let older = (self.index + len - behind) % len * channels;
+            let newer = (self.index + len - behind + 1) % len * channels;
+            for (c, x) in frame.iter_mut().enumerate() {
+                let echo = self.buffer[older + c] * (1.0 - t) + self.buffer[newer + c] * t;
+                self.buffer[self.index * channels + c] = *x + echo * feedback;
+                *x += echo * self.mix;
+            }
+            self.index = (self.index + 1) % len;
+        }
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "time" => self.time = value,
+            "feedback" => self.feedback = value,
+            "mix" => self.mix = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn param(&self, name: &str) -> Option<f32> {
+        match name {
+            "time" => Some(self.time),
+            "feedback" => Some(self.feedback),
+            "mix" => Some(self.mix),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.iter_mut().for_each(|v| *v = 0.0);
+    }
+}
+
+/// lo-fi: samples rounded to "bits" of resolution and held for "downsample" frames.
+pub struct Bitcrusher {
+    bits: f32,
+    downsample: f32,
+    held: Vec<f32>,
+    counter: f32,
+}
+
+impl Bitcrusher {
+    pub fn init(bits: f32, downsample: f32) -> Self {
+        Self {
+            bits,
+            downsample,
+            held: Vec::new(),
+            counter: 0.0,
+        }
+    }
+}
+
+impl Effect for Bitcrusher {
+    fn process(&mut self, buffer: &mut [f32], channels: usize, _sample_rate: u32) {
+        self.held.resize(channels, 0.0);
+        let step = 2.0 / 2.0f32.powf(self.bits.clamp(1.0, 24.0));
+        let hold = self.downsample.max(1.0);
+
+        for frame in buffer.chunks_exact_mut(channels) {
+            if self.counter <= 0.0 {
+                for (h, x) in self.held.iter_mut().zip(frame.iter()) {
+                    *h = (*x / step).round() * step;
+                }
+                self.counter += hold;
+            }
+            self.counter -= 1.0;
+            frame.copy_from_slice(&self.held);
+        }
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "bits" => self.bits = value,
+            "downsample" => self.downsample = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn param(&self, name: &str) -> Option<f32> {
+        match name {
+            "bits" => Some(self.bits),
+            "downsample" => Some(self.downsample),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.counter = 0.0;
+    }
+}
+
+/// peak compressor with the channels linked, a limiter is an infinite ratio and no attack.
+/// parameters: "threshold_db", "ratio", "attack" and "release" (seconds), "makeup_db".
+pub struct Compressor {
+    threshold_db: f32,
+    ratio: f32,
+    attack: f32,
+    release: f32,
+    makeup_db: f32,
+    envelope: f32,
+}
+
+impl Compressor {
+    pub fn init(threshold_db: f32, ratio: f32, attack: f32, release: f32) -> Self {
+        Self {
+            threshold_db,
+            ratio,
+            attack,
+            release,
+            makeup_db: 0.0,
+            envelope: 0.0,
+        }
+    }
+
+    /// nothing goes over `ceiling_db`, for the master bus.
+    pub fn limiter(ceiling_db: f32) -> Self {
+        Self::init(ceiling_db, f32::INFINITY, 0.0, 0.05)
+    }
+}
+
+impl Effect for Compressor {
+    fn process(&mut self, buffer: &mut [f32], channels: usize, sample_rate: u32) {
+        let coefficient = |time: f32| match time <= 0.0 {
+            true => 0.0,
+            false => (-1.0 / (time * sample_rate as f32)).exp(),
+        };
+        let (attack, release) = (coefficient(self.attack), coefficient(self.release));
+        let slope = 1.0 - 1.0 / self.ratio.max(1.0);
+        let makeup = db_to_gain(self.makeup_db);
+
+        for frame in buffer.chunks_exact_mut(channels) {
+            let peak = frame.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+            let c = if peak > self.envelope {
+                attack
+            } else {
+                release
+            };
+            self.envelope = peak + c * (self.envelope - peak);
+
+            let over = gain_to_db(self.envelope) - self.threshold_db;
+            let gain = match over > 0.0 {
+                true => db_to_gain(-over * slope),
+                false => 1.0,
+            };
+            frame.iter_mut().for_each(|v| *v *= gain * makeup);
+        }
+    }
+
+    fn set_param(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "threshold_db" => self.threshold_db = value,
+            "ratio" => self.ratio = value,
+            "attack" => self.attack = value,
+            "release" => self.release = value,
+            "makeup_db" => self.makeup_db = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn param(&self, name: &str) -> Option<f32> {
+        match name {
+            "threshold_db" => Some(self.threshold_db),
+            "ratio" => Some(self.ratio),
+            "attack" => Some(self.attack),
+            "release" => Some(self.release),
+            "makeup_db" => Some(self.makeup_db),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+}
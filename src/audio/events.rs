@@ -0,0 +1,357 @@
+//! sound events: a name that plays one of several sounds with a random pitch and volume,
+//! with a cooldown and a limit on how many play at once. banks of them come from json.
+
+use super::bus::BusId;
+use super::mixer::{with_mixer, SoundHandle};
+use super::spatial::Spatial;
+use super::Audio;
+use crate::parsers::Json;
+use crate::{one_at_a_time_hash, Hash};
+use std::collections::HashMap;
+use vector_math::*;
+
+/// a stolen voice fades out this fast instead of clicking.
+const STEAL_FADE: f32 = 0.005;
+
+/// what an event at its instance limit does with a new trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StealPolicy {
+    /// stops the instance that started first.
+    Oldest,
+    /// stops the instance played at the lowest volume.
+    Quietest,
+    /// the new one is not played.
+    None,
+}
+
+pub struct SoundEvent {
+    pub name: String,
+    /// one is picked at random every time the event plays.
+    pub sounds: Vec<Audio>,
+    /// (min, max) picked uniformly.
+    pub volume: (f32, f32),
+    pub pitch: (f32, f32),
+    /// seconds after a play during which the event does nothing.
+    pub cooldown: f32,
+    /// 0 is no limit.
+    pub max_instances: usize,
+    pub steal: StealPolicy,
+    pub bus: BusId,
+    /// used by `SoundBank::play_at`.
+    pub spatial: Spatial,
+}
+
+impl SoundEvent {
+    pub fn init(name: &str, sounds: Vec<Audio>) -> Self {
+        Self {
+            name: name.to_string(),
+            sounds,
+            volume: (1.0, 1.0),
+            pitch: (1.0, 1.0),
+            cooldown: 0.0,
+            max_instances: 0,
+            steal: StealPolicy::Oldest,
+            bus: BusId::SFX,
+            spatial: Spatial::default(),
+        }
+    }
+}
+
+/// xorshift32, the same sequence for the same seed.
+struct XorShift(u32);
+
+impl XorShift {
+    fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// uniform in min..=max.
+    fn range(&mut self, (min, max): (f32, f32)) -> f32 {
+        let t = (self.next() >> 8) as f32 / (1u32 << 24) as f32;
+        min + (max - min) * t
+    }
+}
+
+struct Instance {
+    handle: SoundHandle,
+    volume: f32,
+    /// order of the triggers, the lowest is the oldest.
+    serial: u64,
+}
+
+struct EventState {
+    /// the bank time of the last play.
+    last_played: Option<f64>,
+    instances: Vec<Instance>,
+}
+
+/// events by name, with the clock of their cooldowns.
+pub struct SoundBank {
+    pub events: Vec<SoundEvent>,
+    states: Vec<EventState>,
+    lookup: HashMap<Hash, usize>,
+    rng: XorShift,
+    time: f64,
+    serial: u64,
+}
+
+impl Default for SoundBank {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            states: Vec::new(),
+            lookup: HashMap::new(),
+            rng: XorShift(0x9E37_79B9),
+            time: 0.0,
+            serial: 0,
+        }
+    }
+}
+
+impl SoundBank {
+    pub fn init() -> Self {
+        Self::default()
+    }
+
+    /// a json bank, `load` gets every sound name once and returns it decoded.
+    /// ```json
+    /// { "footstep": { "sounds": ["step1.wav", "step2.wav"], "volume": [0.8, 1.0],
+    ///   "pitch": [0.9, 1.1], "cooldown": 0.05, "max_instances": 4,
+    ///   "steal": "oldest", "bus": "sfx" } }
+    /// ```
+    /// everything but "sounds" is optional, "steal" is "oldest", "quietest" or "none" and
+    /// "bus" the name of a mixer bus.
+    pub fn init_json<'a>(
+        buffer: &[u8],
+        mut load: impl FnMut(&str) -> Result<Audio, &'a str>,
+    ) -> Result<Self, &'a str> {
+        let text =
+            std::str::from_utf8(buffer).map_err(|_| "Failed to parse sound bank, invalid utf-8")?;
+        let json = Json::parse(text)?;
+        let events = json
+            .as_object()
+            .ok_or("Failed to parse sound bank, expected an object of events")?;
+
+        let mut cache: HashMap<String, Audio> = HashMap::new();
+        let mut bank = Self::init();
+        for (name, definition) in events {
+            let mut sounds = Vec::new();
+            for sound in definition
+                .get("sounds")
+                .and_then(Json::as_array)
+                .ok_or("Failed to parse sound bank, event without sounds")?
+            {
+                let sound = sound
+                    .as_str()
+                    .ok_or("Failed to parse sound bank, sound is not a string")?;
+                if !cache.contains_key(sound) {
+                    cache.insert(sound.to_string(), load(sound)?);
+                }
+                sounds.push(cache[sound].clone());
+            }
+
+            let mut event = SoundEvent::init(name, sounds);
+            if let Some(volume) = range(definition.get("volume"))? {
+                event.volume = volume;
+            }
+            if let Some(pitch) = range(definition.get("pitch"))? {
+                event.pitch = pitch;
+            }
+            if let Some(cooldown) = definition.get("cooldown").and_then(Json::as_f64) {
+                event.cooldown = cooldown as f32;
+            }
+            if let Some(max) = definition.get("max_instances").and_then(Json::as_i32) {
+                event.max_instances = max.max(0) as usize;
+            }
+            event.steal = match definition.get("steal").and_then(Json::as_str) {
+                None | Some("oldest") => StealPolicy::Oldest,
+                Some("quietest") => StealPolicy::Quietest,
+                Some("none") => StealPolicy::None,
+                Some(_) => return Err("Failed to parse sound bank, unknown steal policy"),
+            };
+            if let Some(bus) = definition.get("bus").and_then(Json::as_str) {
+                event.bus = with_mixer(|m| m.find_bus(bus))
+                    .ok_or("Failed to parse sound bank, unknown bus")?;
+            }
+            bank.add(event);
+        }
+        Ok(bank)
+    }
+
+    /// `init_json` with the sounds read next to the bank file.
+    pub fn init_file<'a>(filename: &str) -> Result<Self, &'a str> {
+        let buffer = std::fs::read(filename).map_err(|_| "Failed to read sound bank")?;
+        let directory = std::path::Path::new(filename)
+            .parent()
+            .unwrap_or(std::path::Path::new(""));
+        Self::init_json(&buffer, |sound| {
+            let data =
+                std::fs::read(directory.join(sound)).map_err(|_| "Failed to read bank sound")?;
+            Audio::init(&data)
+        })
+    }
+
+    /// the first event added with a name wins the lookup.
+    pub fn add(&mut self, event: SoundEvent) {
+        self.lookup
+            .entry(one_at_a_time_hash(&event.name))
+            .or_insert(self.events.len());
+        self.events.push(event);
+        self.states.push(EventState {
+            last_played: None,
+            instances: Vec::new(),
+        });
+    }
+
+    pub fn event(&self, name: &str) -> Option<&SoundEvent> {
+        self.event_by_hash(one_at_a_time_hash(name))
+    }
+
+    pub fn event_by_hash(&self, hash: Hash) -> Option<&SoundEvent> {
+        self.lookup.get(&hash).map(|&i| &self.events[i])
+    }
+
+    pub fn event_mut(&mut self, name: &str) -> Option<&mut SoundEvent> {
+        let i = *self.lookup.get(&one_at_a_time_hash(name))?;
+        Some(&mut self.events[i])
+    }
+
+    /// the same picks and pitches every run, for replays and tests.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.rng = XorShift(seed.max(1));
+    }
+
+    /// advances the cooldown clock, call it every frame with `clock_delta_time`.
+    pub fn update(&mut self, delta_seconds: f64) {
+        self.time += delta_seconds;
+    }
+
+    /// `SoundHandle::NONE` if the event is unknown, cooling down or at its limit.
+    pub fn play(&mut self, name: &str) -> SoundHandle {
+        self.play_by_hash(one_at_a_time_hash(name))
+    }
+
+    pub fn play_by_hash(&mut self, hash: Hash) -> SoundHandle {
+        self.trigger(hash, None)
+    }
+
+    /// `play` at `position` with the spatial settings of the event.
+    pub fn play_at(&mut self, name: &str, position: Vec3) -> SoundHandle {
+        self.play_at_by_hash(one_at_a_time_hash(name), position)
+    }
+
+    pub fn play_at_by_hash(&mut self, hash: Hash, position: Vec3) -> SoundHandle {
+        self.trigger(hash, Some(position))
+    }
+
+    /// instances of the event still playing.
+    pub fn instances(&self, name: &str) -> usize {
+        let Some(&i) = self.lookup.get(&one_at_a_time_hash(name)) else {
+            return 0;
+        };
+        let instances = &self.states[i].instances;
+        with_mixer(|m| {
+            instances
+                .iter()
+                .filter(|instance| m.is_playing(instance.handle))
+                .count()
+        })
+    }
+
+    fn trigger(&mut self, hash: Hash, position: Option<Vec3>) -> SoundHandle {
+        let Some(&i) = self.lookup.get(&hash) else {
+            return SoundHandle::NONE;
+        };
+        let event = &self.events[i];
+        let state = &mut self.states[i];
+        if event.sounds.is_empty() {
+            return SoundHandle::NONE;
+        }
+        if let Some(last) = state.last_played {
+            if ((self.time - last) as f32) < event.cooldown {
+                return SoundHandle::NONE;
+            }
+        }
+
+        let sound = &event.sounds[self.rng.next() as usize % event.sounds.len()];
+        let volume = self.rng.range(event.volume).max(0.0);
+        let pitch = self.rng.range(event.pitch).max(0.0);
+        self.serial += 1;
+        let serial = self.serial;
+
+        let handle = with_mixer(|m| {
+            state
+                .instances
+                .retain(|instance| m.is_playing(instance.handle));
+            let mut victim = None;
+            if event.max_instances > 0 && state.instances.len() >= event.max_instances {
+                let oldest = match event.steal {
+                    StealPolicy::Oldest => state
+                        .instances
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, v)| v.serial),
+                    StealPolicy::Quietest => state
+                        .instances
+                        .iter()
+                        .enumerate()
+                        .min_by(|(_, a), (_, b)| a.volume.total_cmp(&b.volume)),
+                    StealPolicy::None => None,
+                };
+                let Some((oldest, _)) = oldest else {
+                    return SoundHandle::NONE;
+                };
+                victim = Some(oldest);
+            }
+
+            let handle = match position {
+                Some(position) => {
+                    m.play_at(event.bus, sound.samples.clone(), position, event.spatial, 0)
+                }
+                None => m.play_on(event.bus, sound.samples.clone(), -1, 0),
+            };
+            // without a free voice the victim keeps playing.
+            if handle == SoundHandle::NONE {
+                return handle;
+            }
+            if let Some(victim) = victim {
+                m.fade_out(state.instances.remove(victim).handle, STEAL_FADE);
+            }
+            m.set_volume(handle, volume);
+            m.set_pitch(handle, pitch);
+            handle
+        });
+
+        if handle != SoundHandle::NONE {
+            state.last_played = Some(self.time);
+            state.instances.push(Instance {
+                handle,
+                volume,
+                serial,
+            });
+        }
+        handle
+    }
+}
+
+/// [min, max] or a single number for both.
+fn range<'a>(json: Option<&Json>) -> Result<Option<(f32, f32)>, &'a str> {
+    let Some(json) = json else {
+        return Ok(None);
+    };
+    if let Some(value) = json.as_f64() {
+        return Ok(Some((value as f32, value as f32)));
+    }
+    match json.as_array() {
+        Some([min, max]) => match (min.as_f64(), max.as_f64()) {
+            (Some(min), Some(max)) => Ok(Some((min as f32, max as f32))),
+            _ => Err("Failed to parse sound bank, range is not numbers"),
+        },
+        _ => Err("Failed to parse sound bank, range is not [min, max]"),
+    }
+}
@@ -0,0 +1,704 @@
+//! the engine mixer behind `Audio::play`: voices and streams are mixed in f32 on their buses
+//! and handed to an `Output`, so every playing voice can still be changed, faded or stopped.
+
+use super::bus::{BusId, DuckRule, Routing};
+use super::effects::EffectChain;
+use super::output::{AudioConfig, NullOutput, Output, SdlOutput};
+use super::spatial::{Listener, Spatial};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use vector_math::*;
+
+/// voices mixed at once, channel -1 takes the first free one.
+pub const VOICES: usize = 32;
+
+/// a played sound, does nothing once it ended or its channel was reused by another sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SoundHandle {
+    channel: i32,
+    generation: u32,
+}
+
+impl SoundHandle {
+    /// the handle of a sound that could not be played.
+    pub const NONE: Self = Self {
+        channel: -1,
+        generation: 0,
+    };
+
+    /// the channel the sound plays on, -1 if it could not be played.
+    pub fn channel(&self) -> i32 {
+        self.channel
+    }
+
+    /// true while the sound plays or is paused.
+    pub fn is_playing(&self) -> bool {
+        with_mixer(|m| m.is_playing(*self))
+    }
+
+    /// 0.0 silent, 1.0 as loaded.
+    pub fn set_volume(&self, volume: f32) {
+        with_mixer(|m| m.set_volume(*self, volume));
+    }
+
+    /// -1.0 left, 0.0 center, 1.0 right.
+    pub fn set_pan(&self, pan: f32) {
+        with_mixer(|m| m.set_pan(*self, pan));
+    }
+
+    /// playback speed, 2.0 is an octave up and twice as fast.
+    pub fn set_pitch(&self, pitch: f32) {
+        with_mixer(|m| m.set_pitch(*self, pitch));
+    }
+
+    pub fn pause(&self) {
+        with_mixer(|m| m.pause(*self));
+    }
+
+    pub fn resume(&self) {
+        with_mixer(|m| m.resume(*self));
+    }
+
+    pub fn stop(&self) {
+        with_mixer(|m| m.stop(*self));
+    }
+
+    /// fades to silence over `seconds`, then stops.
+    pub fn fade_out(&self, seconds: f32) {
+        with_mixer(|m| m.fade_out(*self, seconds));
+    }
+
+    /// moves a positional sound, call it every frame for moving sources.
+    pub fn set_position(&self, position: Vec3) {
+        with_mixer(|m| m.set_position(*self, position));
+    }
+
+    pub fn set_spatial(&self, spatial: Spatial) {
+        with_mixer(|m| m.set_spatial(*self, spatial));
+    }
+
+    /// runs `f` on the effects of the sound, `None` once it ended. `f` runs under the mixer
+    /// lock and holds up the device, keep it short and do not call audio functions from it.
+    pub fn effects<T>(&self, f: impl FnOnce(&mut EffectChain) -> T) -> Option<T> {
+        with_mixer(|m| m.voice_effects(*self).map(f))
+    }
+}
+
+/// a source mixed on a bus next to the voices, `Music` registers its player as one.
+pub trait AudioStream: Send {
+    /// adds the next frames to `out` (interleaved, in the mixer format).
+    fn mix(&mut self, out: &mut [f32]);
+    /// true while it makes sound, for the ducking rules.
+    fn is_active(&self) -> bool;
+}
+
+struct Voice {
+    samples: Arc<[f32]>,
+    bus: BusId,
+    /// in frames, fractional while the pitch is not 1.0.
+    position: f64,
+    /// plays left after this one, -1 forever.
+    loops: i32,
+    volume: f32,
+    pan: f32,
+    pitch: f32,
+    paused: bool,
+    /// fade gain and its change per frame, the voice stops once it reaches silence.
+    fade: f32,
+    fade_step: f32,
+    /// positional sounds are panned and attenuated from the listener.
+    location: Option<(Vec3, Spatial)>,
+    /// left and right gains at the end of the last mix, changes ramp from there.
+    gains: Option<[f32; 2]>,
+    /// applied before the bus, the tail is cut when the voice ends.
+    effects: Option<EffectChain>,
+}
+
+/// mixes up to `VOICES` sounds and the streams into interleaved frames of the format given
+/// to `init`, through the buses.
+pub struct Mixer {
+    sample_rate: u32,
+    channels: u16,
+    voices: Vec<Option<Voice>>,
+    generations: Vec<u32>,
+    paused: bool,
+    listener: Listener,
+    routing: Routing,
+    /// dropped streams are removed on the next mix.
+    streams: Vec<(BusId, Weak<Mutex<dyn AudioStream>>)>,
+    stream_buffer: Vec<f32>,
+    /// one block per bus, mixed into the parent after its effects.
+    bus_buffers: Vec<Vec<f32>>,
+    voice_buffer: Vec<f32>,
+    output: Box<dyn Output>,
+    /// the output set aside while an `OfflineRender` runs.
+    offline: Option<Box<dyn Output>>,
+    scratch: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn init(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels: channels.max(1),
+            voices: (0..VOICES).map(|_| None).collect(),
+            generations: vec![0; VOICES],
+            paused: false,
+            listener: Listener::default(),
+            routing: Routing::init(),
+            streams: Vec::new(),
+            stream_buffer: Vec::new(),
+            bus_buffers: Vec::new(),
+            voice_buffer: Vec::new(),
+            output: Box::new(NullOutput),
+            offline: None,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// the mix goes to `output` from now on, it gets the mixer format. returns the previous
+    /// output, a device has to be dropped after the engine mixer is unlocked.
+    pub fn set_output(&mut self, output: Box<dyn Output>) -> Box<dyn Output> {
+        std::mem::replace(&mut self.output, output)
+    }
+
+    /// mixes the next `frames` frames into the output, devices pull the mix themselves.
+    pub fn render(&mut self, frames: usize) {
+        if self.output.is_device() {
+            return;
+        }
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+        scratch.resize(frames * self.channels as usize, 0.0);
+        self.mix(&mut scratch);
+        self.output.write(&scratch);
+        self.scratch = scratch;
+    }
+
+    /// `samples` are interleaved in the mixer format, `channel` and `loops` work like
+    /// `Audio::play`, on the sfx bus.
+    pub fn play(&mut self, samples: Arc<[f32]>, channel: i32, loops: i32) -> SoundHandle {
+        self.play_on(BusId::SFX, samples, channel, loops)
+    }
+
+    /// `play` on `bus`, unknown buses play on sfx.
+    pub fn play_on(
+        &mut self,
+        bus: BusId,
+        samples: Arc<[f32]>,
+        channel: i32,
+        loops: i32,
+    ) -> SoundHandle {
+        let bus = match self.routing.contains(bus) {
+            true => bus,
+            false => BusId::SFX,
+        };
+        let slot = match channel {
+            -1 => self.voices.iter().position(Option::is_none),
+            c if (0..VOICES as i32).contains(&c) => Some(c as usize),
+            _ => None,
+        };
+        let Some(slot) = slot else {
+            return SoundHandle::NONE;
+        };
+
+        self.generations[slot] = self.generations[slot].wrapping_add(1);
+        self.voices[slot] = Some(Voice {
+            samples,
+            bus,
+            position: 0.0,
+            loops,
+            volume: 1.0,
+            pan: 0.0,
+            pitch: 1.0,
+            paused: false,
+            fade: 1.0,
+            fade_step: 0.0,
+            location: None,
+            gains: None,
+            effects: None,
+        });
+
+        SoundHandle {
+            channel: slot as i32,
+            generation: self.generations[slot],
+        }
+    }
+
+    fn voice(&mut self, handle: SoundHandle) -> Option<&mut Voice> {
+        let slot = usize::try_from(handle.channel).ok()?;
+        if self.generations.get(slot) != Some(&handle.generation) {
+            return None;
+        }
+        self.voices[slot].as_mut()
+    }
+
+    pub fn is_playing(&mut self, handle: SoundHandle) -> bool {
+        self.voice(handle).is_some()
+    }
+
+    /// -1 checks every channel.
+    pub fn is_channel_playing(&self, channel: i32) -> bool {
+        match channel {
+            -1 => self.voices.iter().any(Option::is_some),
+            c => usize::try_from(c)
+                .ok()
+                .and_then(|c| self.voices.get(c))
+                .is_some_and(Option::is_some),
+        }
+    }
+
+    pub fn set_volume(&mut self, handle: SoundHandle, volume: f32) {
+        if let Some(voice) = self.voice(handle) {
+            voice.volume = volume.max(0.0);
+        }
+    }
+
+    pub fn set_pan(&mut self, handle: SoundHandle, pan: f32) {
+        if let Some(voice) = self.voice(handle) {
+            voice.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    pub fn set_pitch(&mut self, handle: SoundHandle, pitch: f32) {
+        if let Some(voice) = self.voice(handle) {
+            voice.pitch = pitch.max(0.0);
+        }
+    }
+
+    pub fn pause(&mut self, handle: SoundHandle) {
+        if let Some(voice) = self.voice(handle) {
+            voice.paused = true;
+        }
+    }
+
+    pub fn resume(&mut self, handle: SoundHandle) {
+        if let Some(voice) = self.voice(handle) {
+            voice.paused = false;
+        }
+    }
+
+    pub fn stop(&mut self, handle: SoundHandle) {
+        if self.voice(handle).is_some() {
+            self.voices[handle.channel as usize] = None;
+        }
+    }
+
+    pub fn fade_out(&mut self, handle: SoundHandle, seconds: f32) {
+        let frames = (seconds * self.sample_rate as f32).max(1.0);
+        if let Some(voice) = self.voice(handle) {
+            voice.fade_step = -voice.fade / frames;
+        }
+    }
+
+    /// makes the sound positional, it follows `position` and the listener from the next mix.
+    pub fn set_position(&mut self, handle: SoundHandle, position: Vec3) {
+        if let Some(voice) = self.voice(handle) {
+            let spatial = voice.location.map_or(Spatial::default(), |(_, s)| s);
+            voice.location = Some((position, spatial));
+        }
+    }
+
+    pub fn set_spatial(&mut self, handle: SoundHandle, spatial: Spatial) {
+        if let Some(voice) = self.voice(handle) {
+            let position = voice.location.map_or(Vec3::default(), |(p, _)| p);
+            voice.location = Some((position, spatial));
+        }
+    }
+
+    /// the effects of a playing sound, an empty chain the first time.
+    pub fn voice_effects(&mut self, handle: SoundHandle) -> Option<&mut EffectChain> {
+        Some(
+            self.voice(handle)?
+                .effects
+                .get_or_insert_with(EffectChain::init),
+        )
+    }
+
+    /// `play_on` at `position`, attenuated by `spatial`.
+    pub fn play_at(
+        &mut self,
+        bus: BusId,
+        samples: Arc<[f32]>,
+        position: Vec3,
+        spatial: Spatial,
+        loops: i32,
+    ) -> SoundHandle {
+        let handle = self.play_on(bus, samples, -1, loops);
+        if let Some(voice) = self.voice(handle) {
+            voice.location = Some((position, spatial));
+        }
+        handle
+    }
+
+    pub fn set_listener(&mut self, listener: Listener) {
+        self.listener = listener;
+    }
+
+    pub fn listener(&self) -> Listener {
+        self.listener
+    }
+
+    pub fn stop_all(&mut self) {
+        self.voices.iter_mut().for_each(|v| *v = None);
+    }
+
+    /// holds every voice where it is, on top of the pause of each sound.
+    pub fn pause_all(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume_all(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.routing.set_volume(BusId::MASTER, volume);
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.routing.volume(BusId::MASTER)
+    }
+
+    /// a new bus under `parent`, unknown parents put it under master.
+    pub fn add_bus(&mut self, name: &str, parent: BusId) -> BusId {
+        self.routing.add(name, parent)
+    }
+
+    pub fn find_bus(&self, name: &str) -> Option<BusId> {
+        self.routing.find(name)
+    }
+
+    pub fn set_bus_volume(&mut self, bus: BusId, volume: f32) {
+        self.routing.set_volume(bus, volume);
+    }
+
+    pub fn bus_volume(&self, bus: BusId) -> f32 {
+        self.routing.volume(bus)
+    }
+
+    /// what `bus` is heard at: its volume and the parents', with the ducking.
+    pub fn bus_gain(&self, bus: BusId) -> f32 {
+        self.routing.gain(bus)
+    }
+
+    pub fn add_ducking(&mut self, rule: DuckRule) {
+        self.routing.add_ducking(rule);
+    }
+
+    pub fn clear_ducking(&mut self) {
+        self.routing.clear_ducking();
+    }
+
+    /// the effects of `bus`, run on everything mixed into it before its volume.
+    pub fn bus_effects(&mut self, bus: BusId) -> Option<&mut EffectChain> {
+        self.routing.effects(bus)
+    }
+
+    /// mixes `stream` on `bus` until it is dropped.
+    pub fn add_stream(&mut self, bus: BusId, stream: Weak<Mutex<dyn AudioStream>>) {
+        self.streams.push((bus, stream));
+    }
+
+    /// adds the playing voices and streams to `out` (interleaved frames).
+    pub fn mix(&mut self, out: &mut [f32]) {
+        let c = self.channels as usize;
+        let frames = out.len() / c;
+
+        for voice in self.voices.iter().flatten() {
+            if !voice.paused && !self.paused {
+                self.routing.set_active(voice.bus);
+            }
+        }
+        self.streams.retain(|(_, stream)| stream.strong_count() > 0);
+        for (bus, stream) in &self.streams {
+            let active = stream
+                .upgrade()
+                .is_some_and(|s| s.lock().is_ok_and(|s| s.is_active()));
+            if active {
+                self.routing.set_active(*bus);
+            }
+        }
+        self.routing.update(frames, self.sample_rate);
+
+        self.bus_buffers.resize_with(self.routing.len(), Vec::new);
+        for buffer in self.bus_buffers.iter_mut() {
+            buffer.clear();
+            buffer.resize(frames * c, 0.0);
+        }
+
+        if !self.paused {
+            self.mix_voices();
+        }
+
+        for (bus, stream) in &self.streams {
+            let Some(stream) = stream.upgrade() else {
+                continue;
+            };
+            self.stream_buffer.clear();
+            self.stream_buffer.resize(frames * c, 0.0);
+            if let Ok(mut stream) = stream.lock() {
+                stream.mix(&mut self.stream_buffer);
+            }
+            if let Some(buffer) = self.bus_buffers.get_mut(bus.0) {
+                buffer
+                    .iter_mut()
+                    .zip(&self.stream_buffer)
+                    .for_each(|(o, s)| *o += s);
+            }
+        }
+
+        // children always come after their parent, so every bus is complete when reached.
+        for b in (0..self.bus_buffers.len()).rev() {
+            let mut buffer = std::mem::take(&mut self.bus_buffers[b]);
+            if let Some(effects) = self.routing.effects(BusId(b)) {
+                effects.process(&mut buffer, c, self.sample_rate);
+            }
+            let target = match self.routing.parent(b) {
+                Some(parent) => &mut self.bus_buffers[parent][..],
+                None => &mut out[..frames * c],
+            };
+            for (f, (frame, src)) in target
+                .chunks_exact_mut(c)
+                .zip(buffer.chunks_exact(c))
+                .enumerate()
+            {
+                let gain = self.routing.frame_gain(BusId(b), f);
+                for (o, s) in frame.iter_mut().zip(src) {
+                    *o += s * gain;
+                }
+            }
+            self.bus_buffers[b] = buffer;
+        }
+    }
+
+    fn mix_voices(&mut self) {
+        let c = self.channels as usize;
+        for slot in self.voices.iter_mut() {
+            let Some(voice) = slot else {
+                continue;
+            };
+            if voice.paused {
+                continue;
+            }
+            let Some(out) = self.bus_buffers.get_mut(voice.bus.0) else {
+                continue;
+            };
+
+            let finished = match voice.effects.take() {
+                None => voice.mix(&self.listener, out, c),
+                Some(mut effects) => {
+                    self.voice_buffer.clear();
+                    self.voice_buffer.resize(out.len(), 0.0);
+                    let finished = voice.mix(&self.listener, &mut self.voice_buffer, c);
+                    effects.process(&mut self.voice_buffer, c, self.sample_rate);
+                    out.iter_mut()
+                        .zip(&self.voice_buffer)
+                        .for_each(|(o, s)| *o += s);
+                    voice.effects = Some(effects);
+                    finished
+                }
+            };
+            if finished {
+                *slot = None;
+            }
+        }
+    }
+}
+
+impl Voice {
+    /// adds the next frames to `out`, true once the voice ended.
+    fn mix(&mut self, listener: &Listener, out: &mut [f32], c: usize) -> bool {
+        let (spatial_gain, spatial_pan) = match &self.location {
+            Some((position, spatial)) => listener.locate(*position, spatial),
+            None => (1.0, 0.0),
+        };
+        let pan = (self.pan + spatial_pan).clamp(-1.0, 1.0);
+        let target = match c {
+            2 => [(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)].map(|g| g * spatial_gain),
+            _ => [spatial_gain; 2],
+        };
+        let start = self.gains.unwrap_or(target);
+        self.gains = Some(target);
+        let block = (out.len() / c).max(1) as f32;
+
+        let frames = self.samples.len() / c;
+        let mut finished = frames == 0;
+
+        for (f, frame) in out.chunks_exact_mut(c).enumerate() {
+            if finished {
+                break;
+            }
+            while self.position >= frames as f64 {
+                if self.loops == 0 {
+                    finished = true;
+                    break;
+                }
+                if self.loops > 0 {
+                    self.loops -= 1;
+                }
+                self.position -= frames as f64;
+            }
+            if finished {
+                break;
+            }
+
+            let i = self.position as usize;
+            let t = (self.position - i as f64) as f32;
+            // the next frame wraps around while looping, holds at the end otherwise.
+            let next = match i + 1 {
+                n if n < frames => n,
+                _ if self.loops != 0 => 0,
+                _ => i,
+            };
+            let gain = self.volume * self.fade;
+            let ramp = (f + 1) as f32 / block;
+
+            for (ch, o) in frame.iter_mut().enumerate() {
+                let a = self.samples[i * c + ch];
+                let b = self.samples[next * c + ch];
+                let side = ch.min(1);
+                let pan = start[side] + (target[side] - start[side]) * ramp;
+                *o += (a + (b - a) * t) * gain * pan;
+            }
+
+            self.position += self.pitch as f64;
+            self.fade += self.fade_step;
+            if self.fade <= 0.0 {
+                finished = true;
+            }
+        }
+
+        finished || (self.loops == 0 && self.position >= frames as f64)
+    }
+}
+
+static MIXER: Mutex<Option<Mixer>> = Mutex::new(None);
+
+/// the device callback, adds the engine mixer to `out`.
+pub(super) fn mix_device(out: &mut [f32]) {
+    // a poisoned lock plays silence instead of unwinding into C.
+    if let Ok(mut mixer) = MIXER.lock() {
+        // a device set aside by an offline render plays silence as well.
+        if let Some(mixer) = mixer.as_mut().filter(|m| m.output.is_device()) {
+            mixer.mix(out);
+        }
+    }
+}
+
+/// the device and format the engine mixer starts with.
+static CONFIG: Mutex<AudioConfig> = Mutex::new(AudioConfig::DEFAULT);
+
+fn lock() -> MutexGuard<'static, Option<Mixer>> {
+    MIXER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(super) fn config() -> AudioConfig {
+    CONFIG.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn open_output(config: &AudioConfig) -> Box<dyn Output> {
+    match SdlOutput::open(config) {
+        Some(device) => Box::new(device),
+        None => Box::new(NullOutput),
+    }
+}
+
+/// starts the engine mixer with `config`, false if it runs already.
+pub(super) fn start(config: AudioConfig) -> bool {
+    if lock().is_some() {
+        return false;
+    }
+    // opened outside of the lock, the device callback takes it.
+    let output = open_output(&config);
+    let mut mixer = Mixer::init(config.sample_rate, config.channels);
+    mixer.set_output(output);
+    *CONFIG.lock().unwrap_or_else(|e| e.into_inner()) = config;
+
+    let mut engine = lock();
+    if engine.is_some() {
+        // unlocked before the unused device closes.
+        drop(engine);
+        return false;
+    }
+    *engine = Some(mixer);
+    true
+}
+
+/// drops the engine mixer and everything playing, the next use starts it again.
+pub(super) fn stop() {
+    let mixer = lock().take();
+    drop(mixer);
+}
+
+/// runs `f` on the engine mixer, started on first use with the last config (the default
+/// device and format unless `audio::init` said otherwise).
+pub(super) fn with_mixer<T>(f: impl FnOnce(&mut Mixer) -> T) -> T {
+    if lock().is_none() {
+        start(config());
+    }
+    let mut mixer = lock();
+    let mixer = mixer.get_or_insert_with(|| {
+        let config = config();
+        Mixer::init(config.sample_rate, config.channels)
+    });
+    f(mixer)
+}
+
+/// replaces the output of the engine mixer and returns the old one, out of the lock so
+/// that closing it cannot wait on the device thread.
+pub(super) fn set_output(output: Box<dyn Output>) -> Box<dyn Output> {
+    with_mixer(|m| m.set_output(output))
+}
+
+/// plays on `device` from now on, `None` is the default one.
+pub(super) fn select_device(device: Option<&str>) {
+    CONFIG.lock().unwrap_or_else(|e| e.into_inner()).device = device.map(str::to_string);
+    open_device();
+}
+
+/// back to the configured device, or a `NullOutput` without one. during an offline render
+/// it takes over when the render ends.
+pub(super) fn open_device() {
+    let (sample_rate, channels) = with_mixer(|m| (m.sample_rate, m.channels));
+    let config = AudioConfig {
+        sample_rate,
+        channels,
+        ..config()
+    };
+    let output = open_output(&config);
+    let previous = with_mixer(|m| match &mut m.offline {
+        Some(offline) => std::mem::replace(offline, output),
+        None => m.set_output(output),
+    });
+    drop(previous);
+}
+
+/// sets the output aside for an offline render, the mix goes nowhere and a device plays
+/// silence until `end_offline`. false if a render runs already.
+pub(super) fn begin_offline() -> bool {
+    with_mixer(|m| {
+        if m.offline.is_some() {
+            return false;
+        }
+        m.offline = Some(m.set_output(Box::new(NullOutput)));
+        true
+    })
+}
+
+/// puts the output from before `begin_offline` back.
+pub(super) fn end_offline() {
+    let render = with_mixer(|m| m.offline.take().map(|previous| m.set_output(previous)));
+    drop(render);
+}
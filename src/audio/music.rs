@@ -0,0 +1,647 @@
+//! streamed music: ogg vorbis (stb_vorbis) and wav decoded a few thousand frames at a time,
+//! from memory or straight from the file, instead of a whole `Sound` in memory.
+//!
+//! `MusicPlayer` does the work without touching the device, `Music` streams one on a bus.
+
+use super::bus::BusId;
+use super::mixer::{with_mixer, AudioStream};
+use super::sound::{convert_frame, wav_layout, WavFormat};
+use super::tracker::{Module, ModulePlayer};
+use c_utils::sys;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// frames decoded per read.
+const CHUNK_FRAMES: usize = 4096;
+
+/// an incremental decoder, `MusicTrack` picks one for ogg and wav.
+pub trait MusicDecoder: Send {
+    fn channels(&self) -> u16;
+    fn sample_rate(&self) -> u32;
+    /// length in frames.
+    fn frames(&self) -> u64;
+    /// fills `out` with whole interleaved frames, returns the frames read, 0 at the end.
+    fn read(&mut self, out: &mut [f32]) -> usize;
+    fn seek<'a>(&mut self, frame: u64) -> Result<(), &'a str>;
+}
+
+struct VorbisDecoder {
+    handle: *mut std::os::raw::c_void,
+    channels: u16,
+    sample_rate: u32,
+    frames: u64,
+    /// stb_vorbis reads the memory in place.
+    _buffer: Vec<u8>,
+    scratch: Vec<i16>,
+}
+
+// the handle is only used through `&mut self`.
+unsafe impl Send for VorbisDecoder {}
+
+impl VorbisDecoder {
+    fn init<'a>(buffer: Vec<u8>, filename: Option<&str>) -> Result<Self, &'a str> {
+        let mut channels: i32 = 0;
+        let mut sample_rate: i32 = 0;
+        let mut frames: u32 = 0;
+
+        let handle = match filename {
+            Some(filename) => {
+                let filename = std::ffi::CString::new(filename)
+                    .map_err(|_| "Failed to open music, invalid filename")?;
+                unsafe {
+                    sys::utl_vorbis_open_file(
+                        filename.as_ptr(),
+                        &mut channels,
+                        &mut sample_rate,
+                        &mut frames,
+                    )
+                }
+            }
+            None => unsafe {
+                sys::utl_vorbis_open_memory(
+                    buffer.as_ptr(),
+                    buffer.len() as i32,
+                    &mut channels,
+                    &mut sample_rate,
+                    &mut frames,
+                )
+            },
+        };
+
+        if handle.is_null() || channels <= 0 || sample_rate <= 0 {
+            unsafe { sys::utl_vorbis_close(handle) };
+            return Err("Failed to open music, invalid ogg vorbis");
+        }
+
+        Ok(Self {
+            handle,
+            channels: channels as u16,
+            sample_rate: sample_rate as u32,
+            frames: frames as u64,
+            _buffer: buffer,
+            scratch: Vec::new(),
+        })
+    }
+}
+
+impl MusicDecoder for VorbisDecoder {
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        let channels = self.channels as usize;
+        let frames = out.len() / channels;
+        self.scratch.resize(frames * channels, 0);
+
+        let read = unsafe {
+            sys::utl_vorbis_read(
+                self.handle,
+                self.scratch.as_mut_ptr(),
+                channels as i32,
+                frames as i32,
+            )
+        }
+        .max(0) as usize;
+
+        for (o, &v) in out.iter_mut().zip(&self.scratch[..read * channels]) {
+            *o = v as f32 / 32768.0;
+        }
+        read
+    }
+
+    fn seek<'a>(&mut self, frame: u64) -> Result<(), &'a str> {
+        if unsafe { sys::utl_vorbis_seek(self.handle, frame as u32) } == 0 {
+            return Err("Failed to seek music");
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VorbisDecoder {
+    fn drop(&mut self) {
+        unsafe { sys::utl_vorbis_close(self.handle) };
+    }
+}
+
+struct WavDecoder<R> {
+    reader: R,
+    format: WavFormat,
+    data_offset: u64,
+    frames: u64,
+    position: u64,
+    scratch: Vec<u8>,
+}
+
+impl<R: Read + Seek + Send> WavDecoder<R> {
+    fn init<'a>(mut reader: R) -> Result<Self, &'a str> {
+        reader
+            .seek(SeekFrom::Start(0))
+            .map_err(|_| "Failed to open music file")?;
+        let (format, data_offset, data_len) = wav_layout(&mut reader)?;
+        let frames = data_len / format.frame_size() as u64;
+
+        let mut decoder = Self {
+            reader,
+            format,
+            data_offset,
+            frames,
+            position: 0,
+            scratch: Vec::new(),
+        };
+        decoder.seek(0)?;
+        Ok(decoder)
+    }
+}
+
+impl<R: Read + Seek + Send> MusicDecoder for WavDecoder<R> {
+    fn channels(&self) -> u16 {
+        self.format.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.format.sample_rate
+    }
+
+    fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        let channels = self.format.channels as usize;
+        let frames = ((out.len() / channels) as u64).min(self.frames - self.position) as usize;
+        self.scratch.resize(frames * self.format.frame_size(), 0);
+
+        if self.reader.read_exact(&mut self.scratch).is_err() {
+            return 0;
+        }
+        let samples = self.format.decode(&self.scratch);
+        out[..samples.len()].copy_from_slice(&samples);
+
+        self.position += frames as u64;
+        frames
+    }
+
+    fn seek<'a>(&mut self, frame: u64) -> Result<(), &'a str> {
+        let frame = frame.min(self.frames);
+        self.reader
+            .seek(SeekFrom::Start(
+                self.data_offset + frame * self.format.frame_size() as u64,
+            ))
+            .map_err(|_| "Failed to seek music")?;
+        self.position = frame;
+        Ok(())
+    }
+}
+
+/// a song ready to be streamed, with optional loop points.
+pub struct MusicTrack {
+    decoder: Box<dyn MusicDecoder>,
+    /// the part played again and again (frames of the track, end excluded) when `looping`.
+    pub loop_start: u64,
+    pub loop_end: u64,
+    pub looping: bool,
+}
+
+fn is_ogg(magic: &[u8]) -> bool {
+    magic.starts_with(b"OggS")
+}
+
+impl MusicTrack {
+    /// ogg vorbis, wav or a tracker module in memory, decoded while it plays.
+    pub fn init<'a>(buffer: Vec<u8>) -> Result<Self, &'a str> {
+        if Module::detect(&buffer).is_some() {
+            return Self::init_module(&buffer);
+        }
+        if is_ogg(&buffer) {
+            return Ok(Self::init_decoder(Box::new(VorbisDecoder::init(
+                buffer, None,
+            )?)));
+        }
+        Ok(Self::init_decoder(Box::new(WavDecoder::init(
+            std::io::Cursor::new(buffer),
+        )?)))
+    }
+
+    /// ogg vorbis or wav read from the disk while it plays, tracker modules are read whole.
+    pub fn init_file<'a>(filename: &str) -> Result<Self, &'a str> {
+        let mut file = std::fs::File::open(filename).map_err(|_| "Failed to open music file")?;
+        // a MOD tag is at 1080.
+        let mut magic = Vec::new();
+        file.by_ref()
+            .take(1084)
+            .read_to_end(&mut magic)
+            .map_err(|_| "Failed to open music file")?;
+        if magic.len() < 4 {
+            return Err("Failed to open music file");
+        }
+
+        if Module::detect(&magic).is_some() {
+            let buffer = std::fs::read(filename).map_err(|_| "Failed to open music file")?;
+            return Self::init_module(&buffer);
+        }
+
+        if is_ogg(&magic) {
+            return Ok(Self::init_decoder(Box::new(VorbisDecoder::init(
+                Vec::new(),
+                Some(filename),
+            )?)));
+        }
+        Ok(Self::init_decoder(Box::new(WavDecoder::init(
+            std::io::BufReader::new(file),
+        )?)))
+    }
+
+    /// a MOD, XM or S3M rendered at `DEFAULT_FREQUENCY`, looping where the song jumps back.
+    pub fn init_module<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let player = ModulePlayer::init(Module::init(buffer)?, super::DEFAULT_FREQUENCY);
+        let (loop_start, looping) = (player.loop_start(), player.is_looping());
+        let mut track = Self::init_decoder(Box::new(player));
+        if looping {
+            track.set_loop(loop_start, track.frames());
+        }
+        Ok(track)
+    }
+
+    pub fn init_decoder(decoder: Box<dyn MusicDecoder>) -> Self {
+        let loop_end = decoder.frames();
+        Self {
+            decoder,
+            loop_start: 0,
+            loop_end,
+            looping: false,
+        }
+    }
+
+    /// loops `start .. end` (frames, end excluded) forever, the intro before `start` plays once.
+    pub fn set_loop(&mut self, start: u64, end: u64) {
+        self.loop_end = end.min(self.frames()).max(1);
+        self.loop_start = start.min(self.loop_end - 1);
+        self.looping = true;
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.decoder.channels()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate()
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.decoder.frames()
+    }
+}
+
+/// a track being played: decoded chunk, loop handling and resampling to the device.
+struct MusicVoice {
+    track: MusicTrack,
+    chunk: Vec<f32>,
+    chunk_frames: usize,
+    chunk_cursor: usize,
+    /// track frame the next decoded frame comes from.
+    decode_position: u64,
+    /// track frame of `window[1]`, the one being played.
+    position: u64,
+    positions: [u64; 4],
+    /// 4 frames around the play position (device channels) for the cubic interpolation,
+    /// `real` is false for the padding after the end.
+    window: Vec<f32>,
+    real: [bool; 4],
+    frac: f64,
+    step: f64,
+    channels: usize,
+}
+
+impl MusicVoice {
+    fn init(track: MusicTrack, sample_rate: u32, channels: u16) -> Self {
+        let step = track.sample_rate() as f64 / sample_rate as f64;
+        let mut voice = Self {
+            chunk: vec![0.0; CHUNK_FRAMES * track.channels() as usize],
+            track,
+            chunk_frames: 0,
+            chunk_cursor: 0,
+            decode_position: 0,
+            position: 0,
+            positions: [0; 4],
+            window: vec![0.0; 4 * channels as usize],
+            real: [false; 4],
+            frac: 0.0,
+            step,
+            channels: channels as usize,
+        };
+        voice.prime();
+        voice
+    }
+
+    fn finished(&self) -> bool {
+        !self.real[1]
+    }
+
+    /// decodes the next track frame into `window[slot]`, false at the end.
+    fn pull(&mut self, slot: usize) -> bool {
+        if self.chunk_cursor == self.chunk_frames {
+            let track = &mut self.track;
+            if track.looping && self.decode_position >= track.loop_end {
+                if track.decoder.seek(track.loop_start).is_err() {
+                    return false;
+                }
+                self.decode_position = track.loop_start;
+            }
+
+            let mut frames = CHUNK_FRAMES as u64;
+            if track.looping {
+                frames = frames.min(track.loop_end - self.decode_position);
+            }
+            let src_channels = track.channels() as usize;
+            self.chunk_frames = track
+                .decoder
+                .read(&mut self.chunk[..frames as usize * src_channels]);
+            self.chunk_cursor = 0;
+
+            if self.chunk_frames == 0 {
+                return false;
+            }
+        }
+
+        let src_channels = self.track.channels() as usize;
+        let src = &self.chunk[self.chunk_cursor * src_channels..][..src_channels];
+        convert_frame(
+            src,
+            &mut self.window[slot * self.channels..(slot + 1) * self.channels],
+        );
+        self.positions[slot] = self.decode_position;
+        self.chunk_cursor += 1;
+        self.decode_position += 1;
+        true
+    }
+
+    /// fills the window from the decode position, the frame before it is the first one again.
+    fn prime(&mut self) {
+        self.frac = 0.0;
+        self.real = [false; 4];
+        for slot in 1..4 {
+            if self.pull(slot) {
+                self.real[slot] = true;
+            } else {
+                self.copy_slot(slot - 1, slot);
+            }
+        }
+        self.copy_slot(1, 0);
+        self.real[0] = self.real[1];
+        self.position = self.positions[1];
+    }
+
+    fn copy_slot(&mut self, from: usize, to: usize) {
+        let c = self.channels;
+        self.window.copy_within(from * c..(from + 1) * c, to * c);
+        self.positions[to] = self.positions[from];
+    }
+
+    fn seek<'a>(&mut self, frame: u64) -> Result<(), &'a str> {
+        let frame = frame.min(self.track.frames());
+        self.track.decoder.seek(frame)?;
+        self.decode_position = frame;
+        self.chunk_frames = 0;
+        self.chunk_cursor = 0;
+        self.prime();
+        Ok(())
+    }
+
+    /// adds `gain(frame index)` * the next frames to `out`, returns the frames written.
+    fn mix(&mut self, out: &mut [f32], mut gain: impl FnMut(usize) -> f32) -> usize {
+        let c = self.channels;
+        for (i, frame) in out.chunks_exact_mut(c).enumerate() {
+            while self.frac >= 1.0 {
+                self.window.copy_within(c.., 0);
+                self.positions.copy_within(1.., 0);
+                self.real.copy_within(1.., 0);
+                self.real[3] = self.pull(3);
+                if !self.real[3] {
+                    self.copy_slot(2, 3);
+                }
+                self.frac -= 1.0;
+            }
+            if self.finished() {
+                return i;
+            }
+            self.position = self.positions[1];
+
+            let t = self.frac as f32;
+            let g = gain(i);
+            for (ch, o) in frame.iter_mut().enumerate() {
+                let [p0, p1, p2, p3] = [0, 1, 2, 3].map(|s| self.window[s * c + ch]);
+                let v = p1
+                    + 0.5
+                        * t
+                        * (p2 - p0
+                            + t * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3
+                                + t * (3.0 * (p1 - p2) + p3 - p0)));
+                *o += v * g;
+            }
+            self.frac += self.step;
+        }
+        out.len() / c
+    }
+}
+
+/// plays one track at a time (two during a crossfade), without a device: `mix` adds the
+/// music to interleaved frames of the format given to `init`.
+pub struct MusicPlayer {
+    sample_rate: u32,
+    channels: u16,
+    current: Option<MusicVoice>,
+    /// the track fading out during a crossfade.
+    outgoing: Option<MusicVoice>,
+    fade_frames: usize,
+    fade_done: usize,
+    paused: bool,
+    pub volume: f32,
+}
+
+impl MusicPlayer {
+    pub fn init(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            current: None,
+            outgoing: None,
+            fade_frames: 0,
+            fade_done: 0,
+            paused: false,
+            volume: 1.0,
+        }
+    }
+
+    /// replaces whatever is playing, at full volume.
+    pub fn play(&mut self, track: MusicTrack) {
+        self.current = Some(MusicVoice::init(track, self.sample_rate, self.channels));
+        self.outgoing = None;
+        self.fade_frames = 0;
+        self.fade_done = 0;
+        self.paused = false;
+    }
+
+    /// fades the playing track out while `track` fades in (equal power).
+    pub fn crossfade(&mut self, track: MusicTrack, seconds: f32) {
+        let outgoing = self.current.take();
+        self.play(track);
+        self.outgoing = outgoing;
+        self.fade_frames = (seconds.max(0.0) * self.sample_rate as f32) as usize;
+        self.fade_done = 0;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.outgoing = None;
+        self.paused = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.current.is_some() && !self.paused
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.current.is_some() && self.paused
+    }
+
+    /// moves the current track to `seconds`, a crossfade in progress ends.
+    pub fn seek<'a>(&mut self, seconds: f64) -> Result<(), &'a str> {
+        let voice = self
+            .current
+            .as_mut()
+            .ok_or("Failed to seek music, nothing is playing")?;
+        let frame = (seconds.max(0.0) * voice.track.sample_rate() as f64) as u64;
+        self.outgoing = None;
+        self.fade_frames = 0;
+        self.fade_done = 0;
+        voice.seek(frame)
+    }
+
+    /// play position of the current track in seconds.
+    pub fn position(&self) -> f64 {
+        self.current
+            .as_ref()
+            .map_or(0.0, |v| v.position as f64 / v.track.sample_rate() as f64)
+    }
+
+    /// adds the music to `out` (interleaved frames).
+    pub fn mix(&mut self, out: &mut [f32]) {
+        if self.paused {
+            return;
+        }
+        let volume = self.volume;
+        let (fade_frames, fade_done) = (self.fade_frames, self.fade_done);
+        let fade = move |i: usize| {
+            ((fade_done + i) as f32 / fade_frames.max(1) as f32).min(1.0)
+                * std::f32::consts::FRAC_PI_2
+        };
+
+        if let Some(voice) = &mut self.outgoing {
+            let written = voice.mix(out, |i| volume * fade(i).cos());
+            if written * (self.channels as usize) < out.len() || fade_done + written >= fade_frames
+            {
+                self.outgoing = None;
+            }
+        }
+
+        let crossfading = self.outgoing.is_some() || fade_done < fade_frames;
+        if let Some(voice) = &mut self.current {
+            let written = if crossfading {
+                voice.mix(out, |i| volume * fade(i).sin())
+            } else {
+                voice.mix(out, |_| volume)
+            };
+            if written * (self.channels as usize) < out.len() {
+                self.current = None;
+            }
+        }
+
+        self.fade_done = (fade_done + out.len() / self.channels as usize).min(fade_frames);
+    }
+}
+
+impl AudioStream for MusicPlayer {
+    fn mix(&mut self, out: &mut [f32]) {
+        MusicPlayer::mix(self, out);
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_playing()
+    }
+}
+
+/// music streamed on a bus of the mixer, as many at once as needed.
+pub struct Music {
+    player: Arc<Mutex<MusicPlayer>>,
+}
+
+impl Music {
+    /// a player on the music bus.
+    pub fn init() -> Self {
+        Self::init_on(BusId::MUSIC)
+    }
+
+    pub fn init_on(bus: BusId) -> Self {
+        let player = with_mixer(|m| {
+            let player = Arc::new(Mutex::new(MusicPlayer::init(m.sample_rate(), m.channels())));
+            let stream: Arc<Mutex<dyn AudioStream>> = player.clone();
+            m.add_stream(bus, Arc::downgrade(&stream));
+            player
+        });
+        Self { player }
+    }
+
+    /// the player, locked: the audio thread waits while it is held.
+    pub fn player(&self) -> MutexGuard<'_, MusicPlayer> {
+        self.player.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn play(&self, track: MusicTrack) {
+        self.player().play(track);
+    }
+
+    pub fn crossfade(&self, track: MusicTrack, seconds: f32) {
+        self.player().crossfade(track, seconds);
+    }
+
+    pub fn pause(&self) {
+        self.player().pause();
+    }
+
+    pub fn resume(&self) {
+        self.player().resume();
+    }
+
+    pub fn stop(&self) {
+        self.player().stop();
+    }
+
+    pub fn seek<'a>(&self, seconds: f64) -> Result<(), &'a str> {
+        self.player().seek(seconds)
+    }
+
+    pub fn position(&self) -> f64 {
+        self.player().position()
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.player().volume = volume;
+    }
+}
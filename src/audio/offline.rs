@@ -0,0 +1,107 @@
+//! renders the engine mixer into a wav file without a device or real time: every game tick
+//! advances the mix by the same number of frames, so runs are deterministic.
+
+use super::mixer::{begin_offline, end_offline, with_mixer};
+use super::sound::{WavEncoding, WavWriter};
+use std::io::{BufWriter, Seek, Write};
+
+/// puts the output from before the render back when dropped, with or without `finish`.
+struct Offline;
+
+impl Drop for Offline {
+    fn drop(&mut self) {
+        end_offline();
+    }
+}
+
+pub struct OfflineRender<W: Write + Seek> {
+    writer: WavWriter<W>,
+    sample_rate: u32,
+    ticks_per_second: u32,
+    ticks: u64,
+    scratch: Vec<f32>,
+    offline: Offline,
+}
+
+impl OfflineRender<BufWriter<std::fs::File>> {
+    /// renders to `filename` at `ticks_per_second` game ticks.
+    pub fn create<'a>(
+        filename: &str,
+        ticks_per_second: u32,
+        encoding: WavEncoding,
+    ) -> Result<Self, &'a str> {
+        let file = std::fs::File::create(filename).map_err(|_| "Failed to create wav file")?;
+        Self::init(BufWriter::new(file), ticks_per_second, encoding)
+    }
+}
+
+impl<W: Write + Seek> OfflineRender<W> {
+    /// the device plays silence and only `tick` advances the mix, until `finish` or the
+    /// render is dropped. one render at a time.
+    pub fn init<'a>(
+        writer: W,
+        ticks_per_second: u32,
+        encoding: WavEncoding,
+    ) -> Result<Self, &'a str> {
+        if ticks_per_second == 0 {
+            return Err("Failed to start offline render, zero ticks per second");
+        }
+        if !begin_offline() {
+            return Err("Failed to start offline render, another one is running");
+        }
+        let offline = Offline;
+        let (sample_rate, channels) = with_mixer(|m| (m.sample_rate(), m.channels()));
+        let writer = WavWriter::init(writer, sample_rate, channels, encoding)?;
+
+        Ok(Self {
+            writer,
+            sample_rate,
+            ticks_per_second,
+            ticks: 0,
+            scratch: Vec::new(),
+            offline,
+        })
+    }
+
+    /// frames of tick `tick`, spread so that every second has exactly `sample_rate` frames.
+    fn tick_frames(&self, tick: u64) -> usize {
+        let at = |t: u64| t * self.sample_rate as u64 / self.ticks_per_second as u64;
+        (at(tick + 1) - at(tick)) as usize
+    }
+
+    /// mixes one game tick, call it once per update.
+    pub fn tick<'a>(&mut self) -> Result<(), &'a str> {
+        let frames = self.tick_frames(self.ticks);
+        self.ticks += 1;
+
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+        with_mixer(|m| {
+            scratch.resize(frames * m.channels() as usize, 0.0);
+            m.mix(&mut scratch);
+        });
+        let written = self.writer.write(&scratch);
+        self.scratch = scratch;
+        written
+    }
+
+    /// ticks until `seconds` more are rendered, for runs without a game loop.
+    pub fn render_seconds<'a>(&mut self, seconds: f64) -> Result<(), &'a str> {
+        let ticks = (seconds * self.ticks_per_second as f64).round() as u64;
+        for _ in 0..ticks {
+            self.tick()?;
+        }
+        Ok(())
+    }
+
+    /// seconds rendered so far.
+    pub fn time(&self) -> f64 {
+        self.writer.frames() as f64 / self.sample_rate as f64
+    }
+
+    /// completes the wav and puts the output from before the render back.
+    pub fn finish<'a>(self) -> Result<W, &'a str> {
+        drop(self.offline);
+        self.writer.finish()
+    }
+}
@@ -0,0 +1,131 @@
+//! where the mix goes: the audio device pulls it from its own thread, the other outputs get
+//! whatever `Mixer::render` produces.
+
+use std::sync::{Arc, Mutex};
+
+/// the device and format of the engine mixer, given to `audio::init`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioConfig {
+    /// a name from `audio::devices`, `None` for the default device.
+    pub device: Option<String>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// device buffer in frames, 1024 is about 21ms at 48000 Hz.
+    pub buffer_frames: u16,
+}
+
+impl AudioConfig {
+    pub const DEFAULT: Self = Self {
+        device: None,
+        sample_rate: super::DEFAULT_FREQUENCY,
+        channels: super::DEFAULT_CHANNELS,
+        buffer_frames: 1024,
+    };
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub trait Output: Send {
+    /// true if a device pulls the mix on its own, `Mixer::render` then does nothing.
+    fn is_device(&self) -> bool {
+        false
+    }
+
+    /// interleaved frames rendered with `Mixer::render`.
+    fn write(&mut self, samples: &[f32]);
+}
+
+/// drops the mix, for servers and machines without audio.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullOutput;
+
+impl Output for NullOutput {
+    fn write(&mut self, _samples: &[f32]) {}
+}
+
+/// keeps everything rendered, clones share the samples.
+#[derive(Debug, Default, Clone)]
+pub struct BufferOutput {
+    samples: Arc<Mutex<Vec<f32>>>,
+}
+
+impl BufferOutput {
+    pub fn init() -> Self {
+        Self::default()
+    }
+
+    /// a copy of the samples so far.
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// the samples so far, the buffer starts over.
+    pub fn take(&self) -> Vec<f32> {
+        self.samples
+            .lock()
+            .map(|mut s| std::mem::take(&mut *s))
+            .unwrap_or_default()
+    }
+}
+
+impl Output for BufferOutput {
+    fn write(&mut self, samples: &[f32]) {
+        if let Ok(mut buffer) = self.samples.lock() {
+            buffer.extend_from_slice(samples);
+        }
+    }
+}
+
+/// an audio device playing the engine mixer. there is one at a time, opening another closes
+/// it. unplugging the device moves it to the default and plugging it back in returns to it,
+/// from `sdl_wrapper::poll_events`.
+pub struct SdlOutput {
+    token: u32,
+}
+
+impl SdlOutput {
+    /// `None` if there is no audio device, SDL converts from the format of `config`. a device
+    /// that fails to open falls back to the default.
+    pub fn open(config: &AudioConfig) -> Option<Self> {
+        let name = config
+            .device
+            .as_deref()
+            .and_then(|name| std::ffi::CString::new(name).ok());
+        let token = unsafe {
+            sdl_wrapper::sys::plt_audio_open(
+                name.as_ref().map_or(std::ptr::null(), |n| n.as_ptr()),
+                config.sample_rate as i32,
+                config.channels as i32,
+                config.buffer_frames as i32,
+                Some(device_callback),
+                std::ptr::null_mut(),
+            )
+        };
+        (token != 0).then_some(Self { token })
+    }
+}
+
+impl Output for SdlOutput {
+    fn is_device(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, _samples: &[f32]) {}
+}
+
+impl Drop for SdlOutput {
+    fn drop(&mut self) {
+        // waits for the callback, never drop it while the mixer is locked.
+        unsafe { sdl_wrapper::sys::plt_audio_close(self.token) };
+    }
+}
+
+unsafe extern "C" fn device_callback(_udata: *mut std::os::raw::c_void, stream: *mut u8, len: i32) {
+    let out = std::slice::from_raw_parts_mut(stream as *mut f32, len as usize / 4);
+    out.fill(0.0);
+    super::mixer::mix_device(out);
+}
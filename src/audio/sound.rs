@@ -0,0 +1,456 @@
+//! decoded audio, independent of the device: RIFF/WAV (integer and float pcm) and ogg vorbis
+//! (stb_vorbis), with the resampling and channel conversion needed before mixing.
+
+use crate::parsers::ByteReader;
+use c_utils::sys;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// sample layout of the data chunk of a wav file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct WavFormat {
+    tag: u16,
+    pub(super) channels: u16,
+    pub(super) sample_rate: u32,
+    bits: u16,
+}
+
+impl WavFormat {
+    fn supported(&self) -> bool {
+        matches!(
+            (self.tag, self.bits),
+            (WAVE_FORMAT_PCM, 8 | 16 | 24 | 32) | (WAVE_FORMAT_IEEE_FLOAT, 32 | 64)
+        )
+    }
+
+    /// bytes per frame (one sample of every channel).
+    pub(super) fn frame_size(&self) -> usize {
+        (self.bits / 8) as usize * self.channels as usize
+    }
+
+    /// `data` converted to -1.0 -> 1.0 floats.
+    pub(super) fn decode(&self, data: &[u8]) -> Vec<f32> {
+        match (self.tag, self.bits) {
+            (WAVE_FORMAT_PCM, 8) => data.iter().map(|&v| (v as f32 - 128.0) / 128.0).collect(),
+            (WAVE_FORMAT_PCM, 16) => data
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+                .collect(),
+            (WAVE_FORMAT_PCM, 24) => data
+                .chunks_exact(3)
+                .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8388608.0)
+                .collect(),
+            (WAVE_FORMAT_PCM, 32) => data
+                .chunks_exact(4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0)
+                .collect(),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => data
+                .chunks_exact(8)
+                .map(|b| {
+                    f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// one frame from `src.len()` to `dst.len()` channels: mono is copied to every channel,
+/// going down to mono averages the channels, anything else keeps the first channels
+/// (front left, front right ..) and fills with silence.
+pub(super) fn convert_frame(src: &[f32], dst: &mut [f32]) {
+    if src.len() == 1 {
+        dst.fill(src[0]);
+    } else if dst.len() == 1 {
+        dst[0] = src.iter().sum::<f32>() / src.len() as f32;
+    } else {
+        for (c, out) in dst.iter_mut().enumerate() {
+            *out = src.get(c).copied().unwrap_or(0.0);
+        }
+    }
+}
+
+fn read_exact<'a, R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<(), &'a str> {
+    reader
+        .read_exact(buffer)
+        .map_err(|_| "Failed to parse wav, unexpected end of file")
+}
+
+/// walks the RIFF chunks up to the samples: (format, data offset, data length in bytes).
+pub(super) fn wav_layout<'a, R: Read + Seek>(
+    reader: &mut R,
+) -> Result<(WavFormat, u64, u64), &'a str> {
+    let mut header = [0u8; 12];
+    read_exact(reader, &mut header)?;
+    if &header[..4] != b"RIFF" {
+        return Err("Failed to parse wav, missing RIFF header");
+    }
+    if &header[8..] != b"WAVE" {
+        return Err("Failed to parse wav, not a WAVE file");
+    }
+
+    let file_size = reader
+        .seek(SeekFrom::End(0))
+        .map_err(|_| "Failed to parse wav, unexpected end of file")?;
+    let mut offset = 12u64;
+    let mut format: Option<WavFormat> = None;
+    let mut data: Option<(u64, u64)> = None;
+
+    while offset + 8 <= file_size && (format.is_none() || data.is_none()) {
+        reader
+            .seek(SeekFrom::Start(offset))
+            .map_err(|_| "Failed to parse wav, unexpected end of file")?;
+        let mut chunk = [0u8; 8];
+        read_exact(reader, &mut chunk)?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+        // some writers put a wrong size on the last chunk.
+        let body_size = size.min(file_size - offset - 8);
+
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut body = vec![0u8; body_size.min(64) as usize];
+                read_exact(reader, &mut body)?;
+                let mut fmt = ByteReader::init(&body);
+                let mut tag = fmt.u16_le()?;
+                let channels = fmt.u16_le()?;
+                let sample_rate = fmt.u32_le()?;
+                let _byte_rate = fmt.u32_le()?;
+                let _block_align = fmt.u16_le()?;
+                let bits = fmt.u16_le()?;
+
+                if tag == WAVE_FORMAT_EXTENSIBLE {
+                    let _extra_size = fmt.u16_le()?;
+                    let _valid_bits = fmt.u16_le()?;
+                    let _channel_mask = fmt.u32_le()?;
+                    // the sub format guid starts with the plain format tag.
+                    tag = fmt.u16_le()?;
+                }
+                format = Some(WavFormat {
+                    tag,
+                    channels,
+                    sample_rate,
+                    bits,
+                });
+            }
+            b"data" => data = Some((offset + 8, body_size)),
+            _ => {}
+        }
+
+        // chunks are padded to an even size.
+        offset += 8 + size + size % 2;
+    }
+
+    let format = format.ok_or("Failed to parse wav, missing fmt chunk")?;
+    let (data_offset, data_len) = data.ok_or("Failed to parse wav, missing data chunk")?;
+    if format.channels == 0 || format.sample_rate == 0 {
+        return Err("Failed to parse wav, invalid channels or sample rate");
+    }
+    if !format.supported() {
+        return Err("Failed to parse wav, unsupported sample format");
+    }
+
+    Ok((format, data_offset, data_len))
+}
+
+/// sample format of written wav files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavEncoding {
+    Pcm16,
+    Float32,
+}
+
+/// writes a wav file a block at a time, the sizes in the header are filled by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    encoding: WavEncoding,
+    channels: u16,
+    /// where the header sizes go.
+    fact_offset: Option<u64>,
+    data_offset: u64,
+    data_len: u64,
+}
+
+fn write_all<'a, W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), &'a str> {
+    writer.write_all(bytes).map_err(|_| "Failed to write wav")
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn init<'a>(
+        mut writer: W,
+        sample_rate: u32,
+        channels: u16,
+        encoding: WavEncoding,
+    ) -> Result<Self, &'a str> {
+        let (tag, bits) = match encoding {
+            WavEncoding::Pcm16 => (WAVE_FORMAT_PCM, 16u16),
+            WavEncoding::Float32 => (WAVE_FORMAT_IEEE_FLOAT, 32u16),
+        };
+        let block_align = channels * bits / 8;
+
+        let mut header = Vec::with_capacity(58);
+        header.extend(b"RIFF\0\0\0\0WAVE");
+        header.extend(b"fmt ");
+        // float files carry the extension size (0) and a fact chunk.
+        let float = encoding == WavEncoding::Float32;
+        header.extend((if float { 18u32 } else { 16 }).to_le_bytes());
+        header.extend(tag.to_le_bytes());
+        header.extend(channels.to_le_bytes());
+        header.extend(sample_rate.to_le_bytes());
+        header.extend((sample_rate * block_align as u32).to_le_bytes());
+        header.extend(block_align.to_le_bytes());
+        header.extend(bits.to_le_bytes());
+
+        let mut fact_offset = None;
+        if float {
+            header.extend(0u16.to_le_bytes());
+            header.extend(b"fact");
+            header.extend(4u32.to_le_bytes());
+            fact_offset = Some(header.len() as u64);
+            header.extend(0u32.to_le_bytes());
+        }
+        header.extend(b"data\0\0\0\0");
+
+        write_all(&mut writer, &header)?;
+        Ok(Self {
+            writer,
+            encoding,
+            channels,
+            fact_offset,
+            data_offset: header.len() as u64,
+            data_len: 0,
+        })
+    }
+
+    /// interleaved frames from -1.0 to 1.0, clipped.
+    pub fn write<'a>(&mut self, samples: &[f32]) -> Result<(), &'a str> {
+        let bytes: Vec<u8> = match self.encoding {
+            WavEncoding::Pcm16 => samples
+                .iter()
+                .flat_map(|&v| {
+                    ((v * 32767.0).round().clamp(-32768.0, 32767.0) as i16).to_le_bytes()
+                })
+                .collect(),
+            WavEncoding::Float32 => samples
+                .iter()
+                .flat_map(|&v| v.clamp(-1.0, 1.0).to_le_bytes())
+                .collect(),
+        };
+        write_all(&mut self.writer, &bytes)?;
+        self.data_len += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// frames written so far.
+    pub fn frames(&self) -> u64 {
+        let sample_size = match self.encoding {
+            WavEncoding::Pcm16 => 2,
+            WavEncoding::Float32 => 4,
+        };
+        self.data_len / (sample_size * self.channels.max(1) as u64)
+    }
+
+    /// fills in the sizes and hands the writer back.
+    pub fn finish<'a>(mut self) -> Result<W, &'a str> {
+        let frames = self.frames() as u32;
+        let mut patch = |offset: u64, value: u32| -> Result<(), &'a str> {
+            self.writer
+                .seek(SeekFrom::Start(offset))
+                .map_err(|_| "Failed to write wav")?;
+            write_all(&mut self.writer, &value.to_le_bytes())
+        };
+
+        // odd sized data is padded to keep the chunks aligned.
+        let padding = self.data_len % 2;
+        let riff_size = self.data_offset - 8 + self.data_len + padding;
+        patch(4, riff_size as u32)?;
+        patch(self.data_offset - 4, self.data_len as u32)?;
+        if let Some(offset) = self.fact_offset {
+            patch(offset, frames)?;
+        }
+
+        self.writer
+            .seek(SeekFrom::End(0))
+            .map_err(|_| "Failed to write wav")?;
+        if padding == 1 {
+            write_all(&mut self.writer, &[0])?;
+        }
+        self.writer.flush().map_err(|_| "Failed to write wav")?;
+        Ok(self.writer)
+    }
+}
+
+/// interleaved samples from -1.0 to 1.0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sound {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl Sound {
+    /// wav or ogg vorbis, picked by the magic bytes.
+    pub fn init<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        match buffer {
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => {
+                Self::init_wav(buffer)
+            }
+            [b'O', b'g', b'g', b'S', ..] => Self::init_ogg(buffer),
+            _ => Err("Failed to parse sound, unknown format"),
+        }
+    }
+
+    pub fn init_wav<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let (format, offset, len) = wav_layout(&mut std::io::Cursor::new(buffer))?;
+        let data = &buffer[offset as usize..(offset + len) as usize];
+        // drops a trailing partial frame.
+        let data = &data[..data.len() - data.len() % format.frame_size()];
+
+        Ok(Self {
+            samples: format.decode(data),
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+        })
+    }
+
+    pub fn init_ogg<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let mut channels: i32 = 0;
+        let mut sample_rate: i32 = 0;
+        let mut frames: i32 = 0;
+
+        let raw_ptr = unsafe {
+            sys::utl_vorbis_decode(
+                buffer.as_ptr(),
+                buffer.len() as i32,
+                &mut channels,
+                &mut sample_rate,
+                &mut frames,
+            )
+        };
+        if raw_ptr.is_null() || channels <= 0 || sample_rate <= 0 {
+            unsafe { sys::utl_vorbis_free(raw_ptr) };
+            return Err("Failed to parse ogg vorbis");
+        }
+
+        let raw = unsafe { std::slice::from_raw_parts(raw_ptr, (frames * channels) as usize) };
+        let samples = raw.iter().map(|&v| v as f32 / 32768.0).collect();
+        unsafe { sys::utl_vorbis_free(raw_ptr) };
+
+        Ok(Self {
+            samples,
+            sample_rate: sample_rate as u32,
+            channels: channels as u16,
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    /// length in seconds.
+    pub fn duration(&self) -> f64 {
+        self.frames() as f64 / self.sample_rate as f64
+    }
+
+    /// same sound at `sample_rate`, with cubic (catmull-rom) interpolation.
+    pub fn resample(&self, sample_rate: u32) -> Self {
+        if sample_rate == self.sample_rate || self.frames() == 0 {
+            return Self {
+                sample_rate,
+                ..self.clone()
+            };
+        }
+
+        let channels = self.channels as usize;
+        let frames = self.frames();
+        let out_frames =
+            ((frames as u64 * sample_rate as u64).div_ceil(self.sample_rate as u64)) as usize;
+        let step = self.sample_rate as f64 / sample_rate as f64;
+        let at = |frame: isize, c: usize| {
+            self.samples[frame.clamp(0, frames as isize - 1) as usize * channels + c]
+        };
+
+        let mut samples = Vec::with_capacity(out_frames * channels);
+        for i in 0..out_frames {
+            let pos = i as f64 * step;
+            let frame = pos.floor() as isize;
+            let t = (pos - pos.floor()) as f32;
+            for c in 0..channels {
+                let (p0, p1, p2, p3) = (
+                    at(frame - 1, c),
+                    at(frame, c),
+                    at(frame + 1, c),
+                    at(frame + 2, c),
+                );
+                let v = p1
+                    + 0.5
+                        * t
+                        * (p2 - p0
+                            + t * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3
+                                + t * (3.0 * (p1 - p2) + p3 - p0)));
+                samples.push(v.clamp(-1.0, 1.0));
+            }
+        }
+
+        Self {
+            samples,
+            sample_rate,
+            channels: self.channels,
+        }
+    }
+
+    /// see `convert_frame`.
+    pub fn convert_channels(&self, channels: u16) -> Self {
+        if channels == self.channels || channels == 0 {
+            return self.clone();
+        }
+
+        let to = channels as usize;
+        let mut samples = vec![0.0; self.frames() * to];
+        for (src, dst) in self
+            .samples
+            .chunks_exact(self.channels as usize)
+            .zip(samples.chunks_exact_mut(to))
+        {
+            convert_frame(src, dst);
+        }
+
+        Self {
+            samples,
+            sample_rate: self.sample_rate,
+            channels,
+        }
+    }
+
+    /// a wav file of the sound.
+    pub fn encode_wav<'a>(&self, encoding: WavEncoding) -> Result<Vec<u8>, &'a str> {
+        let cursor = std::io::Cursor::new(Vec::new());
+        let mut writer = WavWriter::init(cursor, self.sample_rate, self.channels, encoding)?;
+        writer.write(&self.samples)?;
+        Ok(writer.finish()?.into_inner())
+    }
+
+    pub fn save_wav<'a>(&self, filename: &str, encoding: WavEncoding) -> Result<(), &'a str> {
+        let bytes = self.encode_wav(encoding)?;
+        std::fs::write(filename, bytes).map_err(|_| "Failed to write wav file")
+    }
+
+    /// signed 16 bit samples, as in 16 bit wav files.
+    pub fn to_i16(&self) -> Vec<i16> {
+        self.samples
+            .iter()
+            .map(|&v| (v * 32767.0).round().clamp(-32768.0, 32767.0) as i16)
+            .collect()
+    }
+
+    /// resampled and converted for a device running at `sample_rate` with `channels`.
+    pub fn to_device(&self, sample_rate: u32, channels: u16) -> Self {
+        self.convert_channels(channels).resample(sample_rate)
+    }
+}
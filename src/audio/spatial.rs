@@ -0,0 +1,121 @@
+//! positional sounds: gain from the distance to the listener and pan from the side the
+//! sound is on, recomputed every mix so moving sources and listeners follow along.
+
+use vector_math::*;
+
+/// how the gain falls off between `min_distance` and `max_distance`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attenuation {
+    /// 1.0 at min distance down to silence at max distance (with a rolloff of 1.0).
+    Linear,
+    /// min / (min + rolloff * (distance - min)), halves every min distance with a rolloff of 1.0.
+    Inverse,
+    /// (distance / min) ^ -rolloff.
+    Exponential,
+}
+
+/// attenuation settings of a positional sound, silent past `max_distance`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spatial {
+    pub attenuation: Attenuation,
+    /// full volume up to this distance.
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub rolloff: f32,
+}
+
+impl Default for Spatial {
+    fn default() -> Self {
+        Self {
+            attenuation: Attenuation::Inverse,
+            min_distance: 1.0,
+            max_distance: 100.0,
+            rolloff: 1.0,
+        }
+    }
+}
+
+impl Spatial {
+    pub fn init(attenuation: Attenuation, min_distance: f32, max_distance: f32) -> Self {
+        Self {
+            attenuation,
+            min_distance,
+            max_distance,
+            rolloff: 1.0,
+        }
+    }
+
+    /// gain of a sound `distance` away.
+    pub fn gain(&self, distance: f32) -> f32 {
+        if distance > self.max_distance {
+            return 0.0;
+        }
+        let min = self.min_distance.max(f32::EPSILON);
+        if distance <= min {
+            return 1.0;
+        }
+
+        let gain = match self.attenuation {
+            Attenuation::Linear => {
+                let range = (self.max_distance - min).max(f32::EPSILON);
+                1.0 - self.rolloff * (distance - min) / range
+            }
+            Attenuation::Inverse => min / (min + self.rolloff * (distance - min)),
+            Attenuation::Exponential => (distance / min).powf(-self.rolloff),
+        };
+        gain.clamp(0.0, 1.0)
+    }
+}
+
+/// where the sounds are heard from, the same eye and orientation as the view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listener {
+    pub position: Vec3,
+    pub forward: Vec3,
+    pub up: Vec3,
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Self::init_2d(Vec2::default())
+    }
+}
+
+impl Listener {
+    pub fn init(position: Vec3, forward: Vec3, up: Vec3) -> Self {
+        Self {
+            position,
+            forward,
+            up,
+        }
+    }
+
+    /// the arguments of `Mat4::look_at` for the view.
+    pub fn init_look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        Self::init(eye, target - eye, up)
+    }
+
+    /// at `center` of a 2d scene, +x is to the right, sources are at z 0.
+    pub fn init_2d(center: Vec2) -> Self {
+        Self::init(
+            vec3(center.x, center.y, 0.0),
+            vec3(0.0, 0.0, -1.0),
+            vec3(0.0, 1.0, 0.0),
+        )
+    }
+
+    pub fn right(&self) -> Vec3 {
+        Vec3::norm(Vec3::cross(Vec3::norm(self.forward), Vec3::norm(self.up)))
+    }
+
+    /// (gain, pan) of a sound at `position`, pan from -1.0 (left) to 1.0 (right).
+    pub fn locate(&self, position: Vec3, spatial: &Spatial) -> (f32, f32) {
+        let offset = position - self.position;
+        let distance = Vec3::mag(offset);
+        let pan = match distance <= f32::EPSILON {
+            true => 0.0,
+            false => Vec3::dot(Vec3::norm(offset), self.right()).clamp(-1.0, 1.0),
+        };
+        (spatial.gain(distance), pan)
+    }
+}
@@ -0,0 +1,1650 @@
+//! tracker modules: ProTracker MOD, FastTracker 2 XM and Scream Tracker 3 S3M. the three load
+//! into one representation with the XM effects, `ModulePlayer` renders it tick by tick as a
+//! `MusicDecoder` so modules stream, loop and seek like any other music.
+
+use super::music::MusicDecoder;
+
+/// the longest song measured when loading, in seconds.
+const MAX_SONG_SECONDS: u64 = 3600;
+const KEY_OFF: u8 = 97;
+/// amiga periods of C-4 at its sample rate, 4 times the ProTracker periods.
+const C4_PERIOD: f32 = 1712.0;
+const PAL_CLOCK: f32 = 3546894.6 * 4.0;
+const NTSC_CLOCK: f32 = 8363.0 * C4_PERIOD;
+/// half a sine, the vibrato and tremolo of ProTracker.
+const SINE: [i32; 32] = [
+    0, 24, 49, 74, 97, 120, 141, 161, 180, 197, 212, 224, 235, 244, 250, 253, 255, 253, 250, 244,
+    235, 224, 212, 197, 180, 161, 141, 120, 97, 74, 49, 24,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleFormat {
+    Mod,
+    Xm,
+    S3m,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopKind {
+    None,
+    Forward,
+    PingPong,
+}
+
+struct Sample {
+    /// mono, -1.0 -> 1.0.
+    data: Vec<f32>,
+    loop_kind: LoopKind,
+    loop_start: usize,
+    loop_end: usize,
+    /// 0 -> 64.
+    volume: u8,
+    /// 0 -> 255, only XM samples pan.
+    panning: Option<u8>,
+    /// linear periods: 1/128 semitones and semitones from the note.
+    finetune: i8,
+    relative_note: i8,
+    /// amiga periods: the rate C-4 plays at.
+    c4_speed: f32,
+}
+
+impl Sample {
+    fn empty() -> Self {
+        Self {
+            data: Vec::new(),
+            loop_kind: LoopKind::None,
+            loop_start: 0,
+            loop_end: 0,
+            volume: 0,
+            panning: None,
+            finetune: 0,
+            relative_note: 0,
+            c4_speed: 8363.0,
+        }
+    }
+
+    /// a loop of `start .. start + length` if it fits in the data.
+    fn set_loop(&mut self, kind: LoopKind, start: usize, length: usize) {
+        let end = (start + length).min(self.data.len());
+        if kind == LoopKind::None || start >= end {
+            return;
+        }
+        self.loop_kind = kind;
+        self.loop_start = start;
+        self.loop_end = end;
+    }
+}
+
+/// (tick, 0 -> 64) points, empty when the envelope is off.
+#[derive(Default)]
+struct Envelope {
+    points: Vec<(u16, u8)>,
+    sustain: Option<usize>,
+    looping: Option<(usize, usize)>,
+}
+
+impl Envelope {
+    fn enabled(&self) -> bool {
+        !self.points.is_empty()
+    }
+
+    fn value(&self, tick: u16) -> f32 {
+        let Some(&(last_tick, last)) = self.points.last() else {
+            return 64.0;
+        };
+        if tick >= last_tick {
+            return last as f32;
+        }
+        for pair in self.points.windows(2) {
+            let [(t0, v0), (t1, v1)] = [pair[0], pair[1]];
+            if tick < t1 {
+                let t = match t1 > t0 {
+                    true => (tick.saturating_sub(t0)) as f32 / (t1 - t0) as f32,
+                    false => 0.0,
+                };
+                return v0 as f32 + (v1 as f32 - v0 as f32) * t;
+            }
+        }
+        last as f32
+    }
+
+    /// the tick after `tick`, held at the sustain point while the key is down.
+    fn advance(&self, tick: u16, key_on: bool) -> u16 {
+        if let Some(sustain) = self.sustain.and_then(|s| self.points.get(s)) {
+            if key_on && tick == sustain.0 {
+                return tick;
+            }
+        }
+        if let Some((start, end)) = self.looping {
+            if let (Some(start), Some(end)) = (self.points.get(start), self.points.get(end)) {
+                if tick >= end.0 {
+                    return start.0;
+                }
+            }
+        }
+        tick.saturating_add(1)
+    }
+}
+
+/// the XM auto vibrato of an instrument.
+#[derive(Default, Clone, Copy)]
+struct AutoVibrato {
+    wave: u8,
+    sweep: u8,
+    depth: u8,
+    rate: u8,
+}
+
+struct Instrument {
+    /// index in `Module::samples` per note.
+    sample_map: [Option<usize>; 96],
+    volume_envelope: Envelope,
+    panning_envelope: Envelope,
+    fadeout: u16,
+    vibrato: AutoVibrato,
+}
+
+impl Instrument {
+    /// MOD and S3M instruments are a sample.
+    fn of_sample(sample: usize) -> Self {
+        Self {
+            sample_map: [Some(sample); 96],
+            volume_envelope: Envelope::default(),
+            panning_envelope: Envelope::default(),
+            fadeout: 0,
+            vibrato: AutoVibrato::default(),
+        }
+    }
+}
+
+/// a note of a pattern, with the XM meaning of every field.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    /// 1 -> 96 from C-0, `KEY_OFF`, 0 none.
+    note: u8,
+    /// 1 based, 0 none.
+    instrument: u8,
+    /// the XM volume column, 0x10 -> 0x50 sets the volume.
+    volume: u8,
+    effect: u8,
+    param: u8,
+}
+
+struct Pattern {
+    rows: usize,
+    /// rows x channels.
+    cells: Vec<Cell>,
+}
+
+/// a tracker song: patterns in order, played by channels from instruments.
+pub struct Module {
+    pub name: String,
+    pub format: ModuleFormat,
+    channels: usize,
+    /// pattern index per position.
+    orders: Vec<usize>,
+    restart: usize,
+    patterns: Vec<Pattern>,
+    instruments: Vec<Instrument>,
+    samples: Vec<Sample>,
+    speed: usize,
+    tempo: usize,
+    global_volume: i32,
+    /// XM linear frequencies instead of amiga periods.
+    linear: bool,
+    clock: f32,
+    panning: Vec<u8>,
+}
+
+fn bytes<'a>(b: &[u8], at: usize, len: usize) -> Result<&[u8], &'a str> {
+    b.get(at..at + len)
+        .ok_or("Failed to load module, truncated file")
+}
+
+fn u8_at<'a>(b: &[u8], at: usize) -> Result<u8, &'a str> {
+    Ok(bytes(b, at, 1)?[0])
+}
+
+fn u16le<'a>(b: &[u8], at: usize) -> Result<u16, &'a str> {
+    let b = bytes(b, at, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u16be<'a>(b: &[u8], at: usize) -> Result<u16, &'a str> {
+    let b = bytes(b, at, 2)?;
+    Ok(u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn u32le<'a>(b: &[u8], at: usize) -> Result<u32, &'a str> {
+    let b = bytes(b, at, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// the byte at `at` of packed pattern data, 0 past the end.
+fn take(data: &[u8], at: &mut usize) -> u8 {
+    let v = data.get(*at).copied().unwrap_or(0);
+    *at += 1;
+    v
+}
+
+fn text(b: &[u8]) -> String {
+    let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+    String::from_utf8_lossy(&b[..end]).trim_end().to_string()
+}
+
+/// channels of a MOD from its tag at 1080.
+fn mod_channels(tag: &[u8]) -> Option<usize> {
+    match tag {
+        b"M.K." | b"M!K!" | b"M&K!" | b"FLT4" | b"4CHN" | b"N.T." => Some(4),
+        [n, b'C', b'H', b'N'] if n.is_ascii_digit() => Some((n - b'0') as usize),
+        [a, b, b'C', b'H' | b'N'] if a.is_ascii_digit() && b.is_ascii_digit() => {
+            Some(((a - b'0') * 10 + (b - b'0')) as usize)
+        }
+        _ => None,
+    }
+    .filter(|&c| (1..=32).contains(&c))
+}
+
+impl Module {
+    /// the format of a module in `buffer`, `None` if it is not one.
+    pub fn detect(buffer: &[u8]) -> Option<ModuleFormat> {
+        if buffer.starts_with(b"Extended Module: ") {
+            return Some(ModuleFormat::Xm);
+        }
+        if buffer.get(44..48) == Some(b"SCRM") {
+            return Some(ModuleFormat::S3m);
+        }
+        buffer
+            .get(1080..1084)
+            .and_then(mod_channels)
+            .map(|_| ModuleFormat::Mod)
+    }
+
+    /// a MOD, XM or S3M file.
+    pub fn init<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let module = match Self::detect(buffer) {
+            Some(ModuleFormat::Mod) => Self::init_mod(buffer)?,
+            Some(ModuleFormat::Xm) => Self::init_xm(buffer)?,
+            Some(ModuleFormat::S3m) => Self::init_s3m(buffer)?,
+            None => return Err("Failed to load module, unknown format"),
+        };
+        if module.orders.is_empty() {
+            return Err("Failed to load module, no patterns to play");
+        }
+        Ok(module)
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// positions in the order list.
+    pub fn song_length(&self) -> usize {
+        self.orders.len()
+    }
+
+    fn init_mod<'a>(b: &[u8]) -> Result<Self, &'a str> {
+        let channels = mod_channels(bytes(b, 1080, 4)?).ok_or("Failed to load mod, unknown tag")?;
+        let length = (u8_at(b, 950)? as usize).clamp(1, 128);
+        let table = bytes(b, 952, 128)?;
+        let patterns = table.iter().map(|&p| p as usize + 1).max().unwrap_or(1);
+        let restart = match u8_at(b, 951)? as usize {
+            r if r < length => r,
+            _ => 0,
+        };
+
+        let pattern_size = 64 * channels * 4;
+        let mut data_at = 1084 + patterns * pattern_size;
+        let mut samples = Vec::new();
+        for i in 0..31 {
+            let header = bytes(b, 20 + i * 30, 30)?;
+            let length = u16::from_be_bytes([header[22], header[23]]) as usize * 2;
+            let finetune = ((header[24] & 0x0F) << 4) as i8 >> 4;
+            let loop_start = u16::from_be_bytes([header[26], header[27]]) as usize * 2;
+            let loop_length = u16::from_be_bytes([header[28], header[29]]) as usize * 2;
+
+            let raw = b
+                .get(data_at..(data_at + length).min(b.len()))
+                .unwrap_or(&[]);
+            data_at += length;
+            let mut sample = Sample {
+                data: raw.iter().map(|&v| v as i8 as f32 / 128.0).collect(),
+                volume: header[25].min(64),
+                finetune: finetune * 16,
+                c4_speed: PAL_CLOCK / C4_PERIOD * 2.0f32.powf(finetune as f32 / 96.0),
+                ..Sample::empty()
+            };
+            if loop_length > 2 {
+                sample.set_loop(LoopKind::Forward, loop_start, loop_length);
+            }
+            samples.push(sample);
+        }
+
+        let mut pattern_list = Vec::new();
+        for p in 0..patterns {
+            let raw = bytes(b, 1084 + p * pattern_size, pattern_size)?;
+            let cells = raw
+                .chunks_exact(4)
+                .map(|c| {
+                    let period = ((c[0] as u16 & 0x0F) << 8) | c[1] as u16;
+                    let note = match period {
+                        0 => 0,
+                        p => {
+                            (48.0 + 12.0 * (428.0 / p as f32).log2())
+                                .round()
+                                .clamp(0.0, 95.0) as u8
+                                + 1
+                        }
+                    };
+                    Cell {
+                        note,
+                        instrument: (c[0] & 0xF0) | (c[2] >> 4),
+                        volume: 0,
+                        effect: c[2] & 0x0F,
+                        param: c[3],
+                    }
+                })
+                .collect();
+            pattern_list.push(Pattern { rows: 64, cells });
+        }
+
+        Ok(Self {
+            name: text(bytes(b, 0, 20)?),
+            format: ModuleFormat::Mod,
+            channels,
+            orders: table[..length].iter().map(|&p| p as usize).collect(),
+            restart,
+            patterns: pattern_list,
+            instruments: (0..31).map(Instrument::of_sample).collect(),
+            samples,
+            speed: 6,
+            tempo: 125,
+            global_volume: 64,
+            linear: false,
+            clock: PAL_CLOCK,
+            // amiga LRRL.
+            panning: (0..channels)
+                .map(|c| match c % 4 {
+                    0 | 3 => 64,
+                    _ => 192,
+                })
+                .collect(),
+        })
+    }
+
+    fn init_xm<'a>(b: &[u8]) -> Result<Self, &'a str> {
+        let header_size = u32le(b, 60)? as usize;
+        let length = u16le(b, 64)? as usize;
+        let restart = u16le(b, 66)? as usize;
+        let channels = u16le(b, 68)? as usize;
+        let pattern_count = u16le(b, 70)? as usize;
+        let instrument_count = u16le(b, 72)? as usize;
+        let flags = u16le(b, 74)?;
+        if !(1..=32).contains(&channels) {
+            return Err("Failed to load xm, bad channel count");
+        }
+        let table = bytes(b, 80, length.min(256))?;
+
+        let mut at = 60 + header_size;
+        let mut patterns = Vec::new();
+        for _ in 0..pattern_count {
+            let pattern_header = u32le(b, at)? as usize;
+            let rows = (u16le(b, at + 5)? as usize).clamp(1, 256);
+            let packed = u16le(b, at + 7)? as usize;
+            let data = bytes(b, at + pattern_header, packed)?;
+            at += pattern_header + packed;
+
+            let mut cells = vec![Cell::default(); rows * channels];
+            let mut i = 0;
+            for cell in cells.iter_mut() {
+                if i >= data.len() {
+                    break;
+                }
+                let first = take(data, &mut i);
+                let fields = match first & 0x80 {
+                    0 => {
+                        cell.note = first;
+                        0x1E
+                    }
+                    _ => first,
+                };
+                if fields & 0x01 != 0 {
+                    cell.note = take(data, &mut i);
+                }
+                if fields & 0x02 != 0 {
+                    cell.instrument = take(data, &mut i);
+                }
+                if fields & 0x04 != 0 {
+                    cell.volume = take(data, &mut i);
+                }
+                if fields & 0x08 != 0 {
+                    cell.effect = take(data, &mut i);
+                }
+                if fields & 0x10 != 0 {
+                    cell.param = take(data, &mut i);
+                }
+                if cell.note > KEY_OFF {
+                    cell.note = 0;
+                }
+            }
+            patterns.push(Pattern { rows, cells });
+        }
+
+        let mut instruments = Vec::new();
+        let mut samples = Vec::new();
+        for _ in 0..instrument_count {
+            let size = u32le(b, at)? as usize;
+            let sample_count = u16le(b, at + 27)? as usize;
+            let mut instrument = Instrument::of_sample(usize::MAX);
+            instrument.sample_map = [None; 96];
+            if sample_count == 0 {
+                at += size.max(29);
+                instruments.push(instrument);
+                continue;
+            }
+
+            let sample_header = u32le(b, at + 29)? as usize;
+            let map = bytes(b, at + 33, 96)?;
+            let envelope = |points_at: usize, count_at: usize, flags_at: usize| {
+                let flags = u8_at(b, at + flags_at)?;
+                let count = (u8_at(b, at + count_at)? as usize).min(12);
+                if flags & 1 == 0 || count == 0 {
+                    return Ok(Envelope::default());
+                }
+                let points = (0..count)
+                    .map(|p| {
+                        let x = u16le(b, at + points_at + p * 4)?;
+                        let y = u16le(b, at + points_at + p * 4 + 2)?;
+                        Ok((x, y.min(64) as u8))
+                    })
+                    .collect::<Result<Vec<_>, &'a str>>()?;
+                let sustain = u8_at(b, at + count_at + 2)? as usize;
+                let loop_start = u8_at(b, at + count_at + 3)? as usize;
+                let loop_end = u8_at(b, at + count_at + 4)? as usize;
+                Ok(Envelope {
+                    points,
+                    sustain: (flags & 2 != 0 && sustain < count).then_some(sustain),
+                    looping: (flags & 4 != 0 && loop_start <= loop_end && loop_end < count)
+                        .then_some((loop_start, loop_end)),
+                })
+            };
+            // the sustain and loop points follow the two point counts.
+            instrument.volume_envelope = envelope(129, 225, 233)?;
+            let mut panning_envelope = envelope(177, 226, 234)?;
+            // the panning sustain and loop are 3 bytes after the volume ones.
+            if panning_envelope.enabled() {
+                let count = panning_envelope.points.len();
+                let flags = u8_at(b, at + 234)?;
+                let sustain = u8_at(b, at + 230)? as usize;
+                let (loop_start, loop_end) =
+                    (u8_at(b, at + 231)? as usize, u8_at(b, at + 232)? as usize);
+                panning_envelope.sustain = (flags & 2 != 0 && sustain < count).then_some(sustain);
+                panning_envelope.looping =
+                    (flags & 4 != 0 && loop_start <= loop_end && loop_end < count)
+                        .then_some((loop_start, loop_end));
+            }
+            instrument.panning_envelope = panning_envelope;
+            instrument.vibrato = AutoVibrato {
+                wave: u8_at(b, at + 235)?,
+                sweep: u8_at(b, at + 236)?,
+                depth: u8_at(b, at + 237)?,
+                rate: u8_at(b, at + 238)?,
+            };
+            instrument.fadeout = u16le(b, at + 239)?;
+            at += size;
+
+            let first = samples.len();
+            let mut headers = Vec::new();
+            for s in 0..sample_count {
+                let h = bytes(b, at + s * sample_header, 18)?;
+                headers.push(h);
+            }
+            at += sample_count * sample_header;
+
+            for h in headers {
+                let bytes_length = u32::from_le_bytes([h[0], h[1], h[2], h[3]]) as usize;
+                let loop_start = u32::from_le_bytes([h[4], h[5], h[6], h[7]]) as usize;
+                let loop_length = u32::from_le_bytes([h[8], h[9], h[10], h[11]]) as usize;
+                let kind = h[14];
+                let wide = kind & 0x10 != 0;
+                let raw = b.get(at..(at + bytes_length).min(b.len())).unwrap_or(&[]);
+                at += bytes_length;
+
+                // delta encoded.
+                let data: Vec<f32> = match wide {
+                    true => {
+                        let mut old = 0i16;
+                        raw.chunks_exact(2)
+                            .map(|v| {
+                                old = old.wrapping_add(i16::from_le_bytes([v[0], v[1]]));
+                                old as f32 / 32768.0
+                            })
+                            .collect()
+                    }
+                    false => {
+                        let mut old = 0i8;
+                        raw.iter()
+                            .map(|&v| {
+                                old = old.wrapping_add(v as i8);
+                                old as f32 / 128.0
+                            })
+                            .collect()
+                    }
+                };
+                let unit = if wide { 2 } else { 1 };
+                let finetune = h[13] as i8;
+                let relative_note = h[16] as i8;
+                let mut sample = Sample {
+                    data,
+                    volume: h[12].min(64),
+                    panning: Some(h[15]),
+                    finetune,
+                    relative_note,
+                    c4_speed: 8363.0
+                        * 2.0f32.powf((relative_note as f32 * 128.0 + finetune as f32) / 1536.0),
+                    ..Sample::empty()
+                };
+                let loop_kind = match kind & 3 {
+                    1 => LoopKind::Forward,
+                    2 => LoopKind::PingPong,
+                    _ => LoopKind::None,
+                };
+                sample.set_loop(loop_kind, loop_start / unit, loop_length / unit);
+                samples.push(sample);
+            }
+
+            for (note, &s) in map.iter().enumerate() {
+                if (s as usize) < sample_count {
+                    instrument.sample_map[note] = Some(first + s as usize);
+                }
+            }
+            instruments.push(instrument);
+        }
+
+        // positions past the patterns play an empty one.
+        let empty = patterns.len();
+        patterns.push(Pattern {
+            rows: 64,
+            cells: vec![Cell::default(); 64 * channels],
+        });
+        let orders = table
+            .iter()
+            .map(|&p| match (p as usize) < empty {
+                true => p as usize,
+                false => empty,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            name: text(bytes(b, 17, 20)?),
+            format: ModuleFormat::Xm,
+            channels,
+            restart: if restart < orders.len() { restart } else { 0 },
+            orders,
+            patterns,
+            instruments,
+            samples,
+            speed: (u16le(b, 76)? as usize).clamp(1, 31),
+            tempo: (u16le(b, 78)? as usize).clamp(32, 255),
+            global_volume: 64,
+            linear: flags & 1 != 0,
+            clock: NTSC_CLOCK,
+            panning: vec![128; channels],
+        })
+    }
+
+    fn init_s3m<'a>(b: &[u8]) -> Result<Self, &'a str> {
+        let order_count = u16le(b, 32)? as usize;
+        let instrument_count = u16le(b, 34)? as usize;
+        let pattern_count = u16le(b, 36)? as usize;
+        let signed = u16le(b, 42)? == 1;
+        let stereo = u8_at(b, 51)? & 0x80 != 0;
+        let settings = bytes(b, 64, 32)?;
+
+        // enabled channels in file order.
+        let mut channel_map = [None; 32];
+        let mut panning = Vec::new();
+        for (c, &setting) in settings.iter().enumerate() {
+            if setting < 16 {
+                channel_map[c] = Some(panning.len());
+                panning.push(match (stereo, setting < 8) {
+                    (false, _) => 128,
+                    (true, true) => 0x33,
+                    (true, false) => 0xCC,
+                });
+            }
+        }
+        if panning.is_empty() {
+            return Err("Failed to load s3m, no channels");
+        }
+        let channels = panning.len();
+
+        let orders_at = 96;
+        let instruments_at = orders_at + order_count;
+        let patterns_at = instruments_at + instrument_count * 2;
+        if u8_at(b, 53)? == 252 {
+            let table = bytes(b, patterns_at + pattern_count * 2, 32)?;
+            for (c, &pan) in table.iter().enumerate() {
+                if let Some(channel) = channel_map[c] {
+                    if pan & 0x20 != 0 {
+                        panning[channel] = (pan & 0x0F) * 17;
+                    }
+                }
+            }
+        }
+
+        let mut samples = Vec::new();
+        for i in 0..instrument_count {
+            let at = u16le(b, instruments_at + i * 2)? as usize * 16;
+            let h = bytes(b, at, 80)?;
+            if h[0] != 1 {
+                samples.push(Sample::empty());
+                continue;
+            }
+            let data_at = (((h[13] as usize) << 16) | u16le(h, 14)? as usize) * 16;
+            let length = u32le(h, 16)? as usize;
+            let flags = h[31];
+            let wide = flags & 4 != 0;
+            let raw = match wide {
+                true => b.get(data_at..(data_at + length * 2).min(b.len())),
+                false => b.get(data_at..(data_at + length).min(b.len())),
+            }
+            .unwrap_or(&[]);
+            let data = match (wide, signed) {
+                (true, true) => raw
+                    .chunks_exact(2)
+                    .map(|v| i16::from_le_bytes([v[0], v[1]]) as f32 / 32768.0)
+                    .collect(),
+                (true, false) => raw
+                    .chunks_exact(2)
+                    .map(|v| (u16::from_le_bytes([v[0], v[1]]) as f32 - 32768.0) / 32768.0)
+                    .collect(),
+                (false, true) => raw.iter().map(|&v| v as i8 as f32 / 128.0).collect(),
+                (false, false) => raw.iter().map(|&v| (v as f32 - 128.0) / 128.0).collect(),
+            };
+            let c2spd = u32le(h, 32)?;
+            let mut sample = Sample {
+                data,
+                volume: h[28].min(64),
+                c4_speed: if c2spd == 0 { 8363.0 } else { c2spd as f32 },
+                ..Sample::empty()
+            };
+            if flags & 1 != 0 {
+                let start = u32le(h, 20)? as usize;
+                let end = u32le(h, 24)? as usize;
+                sample.set_loop(LoopKind::Forward, start, end.saturating_sub(start));
+            }
+            samples.push(sample);
+        }
+
+        let mut patterns = Vec::new();
+        for i in 0..pattern_count {
+            let mut cells = vec![Cell::default(); 64 * channels];
+            let at = u16le(b, patterns_at + i * 2)? as usize * 16;
+            if at != 0 {
+                let length = u16le(b, at)? as usize;
+                let data = b.get(at + 2..(at + length).min(b.len())).unwrap_or(&[]);
+                let mut i = 0;
+                let mut row = 0;
+                while row < 64 {
+                    let what = take(data, &mut i);
+                    if what == 0 {
+                        row += 1;
+                        if i >= data.len() {
+                            break;
+                        }
+                        continue;
+                    }
+                    let mut cell = Cell::default();
+                    if what & 0x20 != 0 {
+                        cell.note = match take(data, &mut i) {
+                            255 => 0,
+                            254 => KEY_OFF,
+                            n => ((n >> 4) * 12 + (n & 0x0F)).min(95) + 1,
+                        };
+                        cell.instrument = take(data, &mut i);
+                    }
+                    if what & 0x40 != 0 {
+                        cell.volume = 0x10 + take(data, &mut i).min(64);
+                    }
+                    if what & 0x80 != 0 {
+                        let (effect, param) = (take(data, &mut i), take(data, &mut i));
+                        (cell.effect, cell.param) = s3m_effect(effect, param);
+                    }
+                    if let Some(channel) = channel_map[(what & 31) as usize] {
+                        cells[row * channels + channel] = cell;
+                    }
+                }
+            }
+            patterns.push(Pattern { rows: 64, cells });
+        }
+
+        let orders = bytes(b, orders_at, order_count)?
+            .iter()
+            .take_while(|&&o| o != 255)
+            .filter(|&&o| o != 254 && (o as usize) < pattern_count)
+            .map(|&o| o as usize)
+            .collect();
+
+        Ok(Self {
+            name: text(bytes(b, 0, 28)?),
+            format: ModuleFormat::S3m,
+            channels,
+            orders,
+            restart: 0,
+            patterns,
+            instruments: (0..instrument_count).map(Instrument::of_sample).collect(),
+            samples,
+            speed: (u8_at(b, 49)? as usize).clamp(1, 255),
+            tempo: (u8_at(b, 50)? as usize).clamp(32, 255),
+            global_volume: u8_at(b, 48)?.min(64) as i32,
+            linear: false,
+            clock: NTSC_CLOCK,
+            panning,
+        })
+    }
+
+    fn cell(&self, order: usize, row: usize, channel: usize) -> Cell {
+        let pattern = &self.patterns[self.orders[order]];
+        pattern
+            .cells
+            .get(row * self.channels + channel)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn rows(&self, order: usize) -> usize {
+        self.patterns[self.orders[order]].rows
+    }
+
+    fn period(&self, sample: &Sample, note: usize) -> f32 {
+        match self.linear {
+            true => {
+                7680.0
+                    - (note as f32 + sample.relative_note as f32) * 64.0
+                    - sample.finetune as f32 / 2.0
+            }
+            false => self.clock / (sample.c4_speed * 2.0f32.powf((note as f32 - 48.0) / 12.0)),
+        }
+    }
+
+    fn frequency(&self, period: f32, semitones: i32) -> f32 {
+        match self.linear {
+            true => 8363.0 * 2.0f32.powf((4608.0 - period + semitones as f32 * 64.0) / 768.0),
+            false => self.clock / period.max(1.0) * 2.0f32.powf(semitones as f32 / 12.0),
+        }
+    }
+}
+
+/// an S3M effect letter (1 is A) as the XM effect doing the same.
+fn s3m_effect(effect: u8, param: u8) -> (u8, u8) {
+    let (hi, lo) = (param >> 4, param & 0x0F);
+    match effect {
+        // A: speed only.
+        1 if param > 0 => (0x0F, param.min(0x1F)),
+        2 => (0x0B, param),
+        3 => (0x0D, param),
+        4 => match (hi, lo) {
+            (0x0F, lo) if lo > 0 => (0x0E, 0xB0 | lo),
+            (hi, 0x0F) if hi > 0 => (0x0E, 0xA0 | hi),
+            _ => (0x0A, param),
+        },
+        5 => match hi {
+            0x0F => (0x0E, 0x20 | lo),
+            0x0E => (33, 0x20 | lo),
+            _ => (0x02, param),
+        },
+        6 => match hi {
+            0x0F => (0x0E, 0x10 | lo),
+            0x0E => (33, 0x10 | lo),
+            _ => (0x01, param),
+        },
+        7 => (0x03, param),
+        8 => (0x04, param),
+        10 => (0x00, param),
+        11 => (0x06, param),
+        12 => (0x05, param),
+        15 => (0x09, param),
+        17 => (27, param),
+        18 => (0x07, param),
+        19 => match hi {
+            0x3 => (0x0E, 0x40 | lo),
+            0x4 => (0x0E, 0x70 | lo),
+            0x8 => (0x08, lo * 17),
+            0xB => (0x0E, 0x60 | lo),
+            0xC => (0x0E, 0xC0 | lo),
+            0xD => (0x0E, 0xD0 | lo),
+            0xE => (0x0E, 0xE0 | lo),
+            _ => (0, 0),
+        },
+        20 => (0x0F, param.max(0x20)),
+        // U: fine vibrato, a quarter of the depth.
+        21 => (0x04, (param & 0xF0) | lo.div_ceil(4)),
+        22 => (16, param),
+        24 => (0x08, (param.min(0x80) as u16 * 2).min(255) as u8),
+        _ => (0, 0),
+    }
+}
+
+/// -255 -> 255 at `position` (0 -> 63) of a vibrato or tremolo wave.
+fn wave(kind: u8, position: u8) -> i32 {
+    let position = position & 63;
+    match kind & 3 {
+        1 => 255 - position as i32 * 8,
+        2 => match position < 32 {
+            true => 255,
+            false => -255,
+        },
+        _ => match position < 32 {
+            true => SINE[position as usize & 31],
+            false => -SINE[position as usize & 31],
+        },
+    }
+}
+
+#[derive(Clone, Default)]
+struct Channel {
+    /// 1 based, 0 none.
+    instrument: usize,
+    sample: Option<usize>,
+    position: f64,
+    backwards: bool,
+    playing: bool,
+    period: f32,
+    porta_target: f32,
+    /// 0 -> 64.
+    volume: i32,
+    /// 0 -> 255.
+    panning: i32,
+    key_on: bool,
+    /// 65536 full, falls after the key off.
+    fadeout: i32,
+    volume_tick: u16,
+    panning_tick: u16,
+    auto_vibrato_position: u8,
+    auto_vibrato_ticks: u32,
+    vibrato_position: u8,
+    vibrato_speed: u8,
+    vibrato_depth: u8,
+    vibrato_wave: u8,
+    tremolo_position: u8,
+    tremolo_speed: u8,
+    tremolo_depth: u8,
+    tremolo_wave: u8,
+    /// effect memories.
+    porta_up: u8,
+    porta_down: u8,
+    porta_speed: u8,
+    volume_slide: u8,
+    fine_up: u8,
+    fine_down: u8,
+    offset: u8,
+    global_slide: u8,
+    panning_slide: u8,
+    retrigger: u8,
+    loop_row: usize,
+    loop_count: usize,
+    cell: Cell,
+    /// this tick only.
+    period_offset: f32,
+    volume_offset: i32,
+    semitones: i32,
+    /// output of the tick: frames advanced per output frame and left, right gains.
+    step: f64,
+    gains: [f32; 2],
+}
+
+impl Channel {
+    /// starts the sample over, an empty sample (a blank slot) stays silent.
+    fn trigger(&mut self, m: &Module) {
+        self.position = 0.0;
+        self.backwards = false;
+        self.playing = self.sample.is_some_and(|s| !m.samples[s].data.is_empty());
+    }
+
+    fn key_off(&mut self, instrument: Option<&Instrument>) {
+        self.key_on = false;
+        if !instrument.is_some_and(|i| i.volume_envelope.enabled()) {
+            self.volume = 0;
+        }
+    }
+
+    fn volume_slide(&mut self, param: u8) {
+        match (param >> 4, param & 0x0F) {
+            (0, down) => self.volume -= down as i32,
+            (up, _) => self.volume += up as i32,
+        }
+        self.volume = self.volume.clamp(0, 64);
+    }
+
+    fn tone_porta(&mut self) {
+        let speed = self.porta_speed as f32 * 4.0;
+        if self.period > self.porta_target {
+            self.period = (self.period - speed).max(self.porta_target);
+        } else {
+            self.period = (self.period + speed).min(self.porta_target);
+        }
+    }
+
+    fn vibrato(&mut self) {
+        self.period_offset = (wave(self.vibrato_wave, self.vibrato_position)
+            * self.vibrato_depth as i32) as f32
+            / 32.0;
+        self.vibrato_position = self.vibrato_position.wrapping_add(self.vibrato_speed) & 63;
+    }
+
+    fn sample_at(&self, sample: &Sample) -> f32 {
+        let len = sample.data.len();
+        if len == 0 {
+            return 0.0;
+        }
+        let i = (self.position as usize).min(len - 1);
+        let next = match (sample.loop_kind, i + 1) {
+            (LoopKind::Forward, n) if n >= sample.loop_end => sample.loop_start,
+            (_, n) if n >= len => i,
+            (_, n) => n,
+        };
+        let t = (self.position - i as f64) as f32;
+        sample.data[i] + (sample.data[next] - sample.data[i]) * t
+    }
+
+    /// moves `delta` frames through `sample`, around its loop.
+    fn advance(&mut self, sample: &Sample, delta: f64) {
+        if sample.data.is_empty() {
+            self.playing = false;
+            return;
+        }
+        match self.backwards {
+            true => self.position -= delta,
+            false => self.position += delta,
+        }
+        let (start, end) = (sample.loop_start as f64, sample.loop_end as f64);
+        let length = end - start;
+        match sample.loop_kind {
+            LoopKind::None => {
+                if self.position >= sample.data.len() as f64 {
+                    self.playing = false;
+                }
+            }
+            LoopKind::Forward => {
+                if self.position >= end {
+                    self.position = start + (self.position - start) % length;
+                }
+            }
+            LoopKind::PingPong => {
+                let over = match self.backwards {
+                    false if self.position >= end => Some(self.position - end),
+                    true if self.position < start => Some(start - self.position),
+                    _ => None,
+                };
+                if let Some(over) = over {
+                    // bounced an odd or even number of times.
+                    let over = over % (2.0 * length);
+                    let from_end = self.backwards == (over >= length);
+                    let over = over % length;
+                    (self.position, self.backwards) = match from_end {
+                        true => ((end - over).min(end - 1.0), true),
+                        false => (start + over, false),
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    order: usize,
+    row: usize,
+    tick: usize,
+    speed: usize,
+    tempo: usize,
+    global_volume: i32,
+    pattern_delay: usize,
+    /// the row plays again for the pattern delay.
+    repeat: bool,
+    /// (order, row) after this row.
+    jump: Option<(usize, usize)>,
+    /// the current row was reached by a pattern loop.
+    loop_jump: bool,
+    ended: bool,
+    channels: Vec<Channel>,
+    /// frames of the current tick still to render.
+    tick_left: usize,
+    carry: usize,
+    /// frames rendered from the start of the song.
+    frame: u64,
+}
+
+impl State {
+    fn init(m: &Module) -> Self {
+        Self {
+            order: 0,
+            row: 0,
+            tick: 0,
+            speed: m.speed,
+            tempo: m.tempo,
+            global_volume: m.global_volume,
+            pattern_delay: 0,
+            repeat: false,
+            jump: None,
+            loop_jump: false,
+            ended: false,
+            channels: m
+                .panning
+                .iter()
+                .map(|&p| Channel {
+                    panning: p as i32,
+                    fadeout: 65536,
+                    ..Channel::default()
+                })
+                .collect(),
+            tick_left: 0,
+            carry: 0,
+            frame: 0,
+        }
+    }
+
+    /// before the first tick of a row that was not played yet.
+    fn at_row_start(&self) -> bool {
+        self.tick == 0 && !self.repeat && !self.ended && self.tick_left == 0
+    }
+
+    /// runs the next tick, false once the song ended.
+    fn process_tick(&mut self, m: &Module, sample_rate: u32) -> bool {
+        if self.ended {
+            return false;
+        }
+        for ch in self.channels.iter_mut() {
+            ch.period_offset = 0.0;
+            ch.volume_offset = 0;
+            ch.semitones = 0;
+        }
+
+        if self.tick == 0 && !self.repeat {
+            for c in 0..m.channels {
+                let cell = m.cell(self.order, self.row, c);
+                self.channels[c].cell = cell;
+                let delayed =
+                    cell.effect == 0x0E && cell.param >> 4 == 0x0D && cell.param & 0x0F > 0;
+                if !delayed {
+                    self.note(m, c, cell);
+                }
+                self.row_effect(m, c, cell);
+            }
+        } else {
+            for c in 0..m.channels {
+                self.tick_effect(m, c);
+            }
+        }
+
+        self.output(m, sample_rate);
+
+        let total = sample_rate as usize * 5 + self.carry;
+        self.tick_left = total / (2 * self.tempo);
+        self.carry = total % (2 * self.tempo);
+
+        self.tick += 1;
+        if self.tick >= self.speed {
+            self.tick = 0;
+            self.next_row(m);
+        }
+        true
+    }
+
+    fn next_row(&mut self, m: &Module) {
+        if self.pattern_delay > 0 {
+            self.pattern_delay -= 1;
+            self.repeat = true;
+            return;
+        }
+        self.repeat = false;
+
+        let (order, row) = match self.jump.take() {
+            Some(jump) => jump,
+            None if self.row + 1 < m.rows(self.order) => (self.order, self.row + 1),
+            None => (self.order + 1, 0),
+        };
+        if order >= m.orders.len() {
+            self.ended = true;
+            return;
+        }
+        if order != self.order {
+            self.channels.iter_mut().for_each(|ch| ch.loop_row = 0);
+        }
+        self.loop_jump = order == self.order && row <= self.row && self.loop_jump;
+        self.order = order;
+        self.row = if row < m.rows(order) { row } else { 0 };
+    }
+
+    /// the note and instrument of `cell` on `c`.
+    fn note(&mut self, m: &Module, c: usize, cell: Cell) {
+        let ch = &mut self.channels[c];
+        let porta = matches!(cell.effect, 0x03 | 0x05) || cell.volume >= 0xF0;
+        if cell.instrument > 0 && (cell.instrument as usize) <= m.instruments.len() {
+            ch.instrument = cell.instrument as usize;
+        }
+        let instrument = ch
+            .instrument
+            .checked_sub(1)
+            .and_then(|i| m.instruments.get(i));
+
+        if (1..=96).contains(&cell.note) {
+            let note = cell.note as usize - 1;
+            let mapped = instrument.and_then(|i| i.sample_map[note]);
+            match porta {
+                true => {
+                    if let Some(sample) = ch.sample.or(mapped).map(|s| &m.samples[s]) {
+                        ch.porta_target = m.period(sample, note);
+                        if !ch.playing {
+                            ch.period = ch.porta_target;
+                        }
+                    }
+                }
+                false => {
+                    if let Some(s) = mapped.filter(|&s| s < m.samples.len()) {
+                        ch.sample = Some(s);
+                        ch.period = m.period(&m.samples[s], note);
+                        ch.porta_target = ch.period;
+                        ch.trigger(m);
+                        if ch.vibrato_wave & 4 == 0 {
+                            ch.vibrato_position = 0;
+                        }
+                        if ch.tremolo_wave & 4 == 0 {
+                            ch.tremolo_position = 0;
+                        }
+                    }
+                }
+            }
+        }
+
+        if cell.instrument > 0 && instrument.is_some() {
+            if let Some(sample) = ch.sample.map(|s| &m.samples[s]) {
+                ch.volume = sample.volume as i32;
+                if let Some(panning) = sample.panning {
+                    ch.panning = panning as i32;
+                }
+            }
+            ch.key_on = true;
+            ch.fadeout = 65536;
+            ch.volume_tick = 0;
+            ch.panning_tick = 0;
+            ch.auto_vibrato_position = 0;
+            ch.auto_vibrato_ticks = 0;
+        }
+
+        if cell.note == KEY_OFF {
+            ch.key_off(instrument);
+        }
+
+        // the volume column sets the volume over the instrument default.
+        if (0x10..=0x50).contains(&cell.volume) {
+            ch.volume = cell.volume as i32 - 0x10;
+        }
+    }
+
+    /// the first tick of a row.
+    fn row_effect(&mut self, m: &Module, c: usize, cell: Cell) {
+        let memory = m.format != ModuleFormat::Mod;
+        let ch = &mut self.channels[c];
+        let v = cell.volume & 0x0F;
+        match cell.volume >> 4 {
+            0x8 => ch.volume = (ch.volume - v as i32).max(0),
+            0x9 => ch.volume = (ch.volume + v as i32).min(64),
+            0xA => ch.vibrato_speed = v,
+            0xB if v > 0 => ch.vibrato_depth = v,
+            0xC => ch.panning = v as i32 * 17,
+            0xF if v > 0 => ch.porta_speed = v * 16,
+            _ => {}
+        }
+
+        let p = cell.param;
+        let (hi, lo) = (p >> 4, p & 0x0F);
+        match cell.effect {
+            0x01 if p > 0 || !memory => ch.porta_up = p,
+            0x02 if p > 0 || !memory => ch.porta_down = p,
+            0x03 if p > 0 => ch.porta_speed = p,
+            0x04 | 0x06 => {
+                if cell.effect == 0x04 {
+                    if hi > 0 {
+                        ch.vibrato_speed = hi;
+                    }
+                    if lo > 0 {
+                        ch.vibrato_depth = lo;
+                    }
+                } else if p > 0 || !memory {
+                    ch.volume_slide = p;
+                }
+            }
+            0x05 | 0x0A if p > 0 || !memory => ch.volume_slide = p,
+            0x07 => {
+                if hi > 0 {
+                    ch.tremolo_speed = hi;
+                }
+                if lo > 0 {
+                    ch.tremolo_depth = lo;
+                }
+            }
+            0x08 => ch.panning = p as i32,
+            0x09 => {
+                if p > 0 {
+                    ch.offset = p;
+                }
+                let porta = cell.volume >= 0xF0;
+                if (1..=96).contains(&cell.note) && !porta {
+                    ch.position = ch.offset as f64 * 256.0;
+                    let length = ch.sample.map_or(0, |s| m.samples[s].data.len());
+                    if ch.position >= length as f64 {
+                        ch.playing = false;
+                    }
+                }
+            }
+            0x0B => {
+                let row = self.jump.map_or(0, |(_, row)| row);
+                self.jump = Some((p as usize, row));
+                self.loop_jump = false;
+            }
+            0x0C => ch.volume = p.min(64) as i32,
+            0x0D => {
+                let order = self.jump.map_or(self.order + 1, |(order, _)| order);
+                self.jump = Some((order, (hi * 10 + lo) as usize));
+                self.loop_jump = false;
+            }
+            0x0E => match hi {
+                0x1 => {
+                    if lo > 0 || !memory {
+                        ch.fine_up = lo;
+                    }
+                    ch.period -= ch.fine_up as f32 * 4.0;
+                }
+                0x2 => {
+                    if lo > 0 || !memory {
+                        ch.fine_down = lo;
+                    }
+                    ch.period += ch.fine_down as f32 * 4.0;
+                }
+                0x4 => ch.vibrato_wave = lo,
+                0x6 => {
+                    if lo == 0 {
+                        ch.loop_row = self.row;
+                    } else {
+                        match ch.loop_count {
+                            0 => ch.loop_count = lo as usize,
+                            _ => ch.loop_count -= 1,
+                        }
+                        if ch.loop_count > 0 {
+                            self.jump = Some((self.order, ch.loop_row));
+                            self.loop_jump = true;
+                        }
+                    }
+                }
+                0x7 => ch.tremolo_wave = lo,
+                0x8 => ch.panning = lo as i32 * 17,
+                0xA => ch.volume = (ch.volume + lo as i32).min(64),
+                0xB => ch.volume = (ch.volume - lo as i32).max(0),
+                0xC if lo == 0 => ch.volume = 0,
+                0xE if self.pattern_delay == 0 => self.pattern_delay = lo as usize,
+                _ => {}
+            },
+            0x0F => match p {
+                0 => {}
+                1..=0x1F => self.speed = p as usize,
+                _ => self.tempo = p as usize,
+            },
+            16 => self.global_volume = p.min(64) as i32,
+            17 if p > 0 => ch.global_slide = p,
+            20 if p == 0 => {
+                let instrument = ch
+                    .instrument
+                    .checked_sub(1)
+                    .and_then(|i| m.instruments.get(i));
+                ch.key_off(instrument);
+            }
+            25 if p > 0 => ch.panning_slide = p,
+            27 if p > 0 => ch.retrigger = p,
+            33 => match hi {
+                1 => ch.period -= lo as f32,
+                2 => ch.period += lo as f32,
+                _ => {}
+            },
+            _ => {}
+        }
+        ch.volume = ch.volume.clamp(0, 64);
+        ch.panning = ch.panning.clamp(0, 255);
+    }
+
+    /// the ticks after the first of a row.
+    fn tick_effect(&mut self, m: &Module, c: usize) {
+        let tick = self.tick;
+        let ch = &mut self.channels[c];
+        let cell = ch.cell;
+        let v = cell.volume & 0x0F;
+        match cell.volume >> 4 {
+            0x6 => ch.volume = (ch.volume - v as i32).max(0),
+            0x7 => ch.volume = (ch.volume + v as i32).min(64),
+            0xB => ch.vibrato(),
+            0xD => ch.panning = (ch.panning - v as i32).max(0),
+            0xE => ch.panning = (ch.panning + v as i32).min(255),
+            0xF => ch.tone_porta(),
+            _ => {}
+        }
+
+        let p = cell.param;
+        let (hi, lo) = (p >> 4, p & 0x0F);
+        match cell.effect {
+            0x00 if p > 0 => ch.semitones = [0, hi as i32, lo as i32][tick % 3],
+            0x01 => ch.period -= ch.porta_up as f32 * 4.0,
+            0x02 => ch.period += ch.porta_down as f32 * 4.0,
+            0x03 => ch.tone_porta(),
+            0x04 => ch.vibrato(),
+            0x05 => {
+                ch.tone_porta();
+                ch.volume_slide(ch.volume_slide);
+            }
+            0x06 => {
+                ch.vibrato();
+                ch.volume_slide(ch.volume_slide);
+            }
+            0x07 => {
+                ch.volume_offset =
+                    wave(ch.tremolo_wave, ch.tremolo_position) * ch.tremolo_depth as i32 / 64;
+                ch.tremolo_position = ch.tremolo_position.wrapping_add(ch.tremolo_speed) & 63;
+            }
+            0x0A => ch.volume_slide(ch.volume_slide),
+            0x0E => match hi {
+                0x9 if lo > 0 && tick.is_multiple_of(lo as usize) => ch.trigger(m),
+                0xC if tick == lo as usize => ch.volume = 0,
+                0xD if tick == lo as usize => {
+                    self.note(m, c, cell);
+                }
+                _ => {}
+            },
+            17 => {
+                let (up, down) = (ch.global_slide >> 4, ch.global_slide & 0x0F);
+                self.global_volume = match up {
+                    0 => self.global_volume - down as i32,
+                    up => self.global_volume + up as i32,
+                }
+                .clamp(0, 64);
+            }
+            20 if tick == p as usize => {
+                let instrument = ch
+                    .instrument
+                    .checked_sub(1)
+                    .and_then(|i| m.instruments.get(i));
+                ch.key_off(instrument);
+            }
+            25 => {
+                let (right, left) = (ch.panning_slide >> 4, ch.panning_slide & 0x0F);
+                ch.panning = match right {
+                    0 => ch.panning - left as i32,
+                    right => ch.panning + right as i32,
+                }
+                .clamp(0, 255);
+            }
+            27 => {
+                let (change, interval) = (ch.retrigger >> 4, ch.retrigger & 0x0F);
+                if interval > 0 && tick.is_multiple_of(interval as usize) {
+                    ch.volume = match change {
+                        1..=5 => ch.volume - (1 << (change - 1)),
+                        6 => ch.volume * 2 / 3,
+                        7 => ch.volume / 2,
+                        9..=13 => ch.volume + (1 << (change - 9)),
+                        14 => ch.volume * 3 / 2,
+                        15 => ch.volume * 2,
+                        _ => ch.volume,
+                    }
+                    .clamp(0, 64);
+                    ch.trigger(m);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// steps, gains and envelopes of every channel for the tick.
+    fn output(&mut self, m: &Module, sample_rate: u32) {
+        let amplification = 0.5 / (m.channels.max(4) as f32).sqrt();
+        for ch in self.channels.iter_mut() {
+            if !ch.playing || ch.sample.is_none() {
+                ch.gains = [0.0; 2];
+                continue;
+            }
+            let instrument = ch
+                .instrument
+                .checked_sub(1)
+                .and_then(|i| m.instruments.get(i));
+
+            let mut envelope = 64.0;
+            let mut panning = ch.panning as f32;
+            let mut vibrato = 0.0;
+            if let Some(instrument) = instrument {
+                let volume = &instrument.volume_envelope;
+                if volume.enabled() {
+                    envelope = volume.value(ch.volume_tick);
+                    ch.volume_tick = volume.advance(ch.volume_tick, ch.key_on);
+                    if !ch.key_on {
+                        ch.fadeout = (ch.fadeout - instrument.fadeout as i32 * 2).max(0);
+                    }
+                }
+                let pan = &instrument.panning_envelope;
+                if pan.enabled() {
+                    let range = 128.0 - (panning - 128.0).abs();
+                    panning += (pan.value(ch.panning_tick) - 32.0) * range / 32.0;
+                    ch.panning_tick = pan.advance(ch.panning_tick, ch.key_on);
+                }
+
+                let auto = instrument.vibrato;
+                if auto.depth > 0 {
+                    let sweep = match auto.sweep {
+                        0 => 1.0,
+                        sweep => (ch.auto_vibrato_ticks as f32 / sweep as f32).min(1.0),
+                    };
+                    let kind = match auto.wave {
+                        1 => 2,
+                        w => w,
+                    };
+                    vibrato = wave(kind, ch.auto_vibrato_position >> 2) as f32
+                        * auto.depth as f32
+                        * sweep
+                        / 64.0;
+                    ch.auto_vibrato_position = ch.auto_vibrato_position.wrapping_add(auto.rate);
+                    ch.auto_vibrato_ticks += 1;
+                }
+            }
+
+            let volume = (ch.volume + ch.volume_offset).clamp(0, 64) as f32 / 64.0 * envelope
+                / 64.0
+                * ch.fadeout as f32
+                / 65536.0
+                * self.global_volume as f32
+                / 64.0
+                * amplification;
+            let angle = panning.clamp(0.0, 255.0) / 255.0 * std::f32::consts::FRAC_PI_2;
+            ch.gains = [angle.cos() * volume, angle.sin() * volume];
+
+            let frequency = m.frequency(ch.period + ch.period_offset + vibrato, ch.semitones);
+            ch.step = frequency.max(0.0) as f64 / sample_rate as f64;
+        }
+    }
+
+    /// renders `frames` of the current tick into `out` (stereo), or only moves on without it.
+    fn render(&mut self, m: &Module, mut out: Option<&mut [f32]>, frames: usize) {
+        for ch in self.channels.iter_mut() {
+            let Some(sample) = ch.sample.map(|s| &m.samples[s]) else {
+                continue;
+            };
+            if !ch.playing {
+                continue;
+            }
+            match out.as_deref_mut() {
+                Some(out) => {
+                    for frame in out.chunks_exact_mut(2).take(frames) {
+                        let v = ch.sample_at(sample);
+                        frame[0] += v * ch.gains[0];
+                        frame[1] += v * ch.gains[1];
+                        ch.advance(sample, ch.step);
+                        if !ch.playing {
+                            break;
+                        }
+                    }
+                }
+                None => ch.advance(sample, ch.step * frames as f64),
+            }
+        }
+        self.tick_left -= frames;
+        self.frame += frames as u64;
+    }
+}
+
+/// plays a `Module` into stereo frames, once from the start to where it ends or loops.
+pub struct ModulePlayer {
+    module: Module,
+    state: State,
+    sample_rate: u32,
+    frames: u64,
+    loop_start: u64,
+    looping: bool,
+    /// the state where every order starts, for seeking.
+    snapshots: Vec<State>,
+}
+
+impl ModulePlayer {
+    /// plays the song through once without mixing to find its length and loop.
+    pub fn init(module: Module, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate.max(1);
+        let mut state = State::init(&module);
+        let mut entered: Vec<Vec<Option<u64>>> = (0..module.orders.len())
+            .map(|o| vec![None; module.rows(o)])
+            .collect();
+        let mut snapshots = Vec::new();
+        let mut last: Option<(usize, usize)> = None;
+        let mut target = (module.restart, 0);
+        let mut looping = false;
+        let max_frames = MAX_SONG_SECONDS * sample_rate as u64;
+
+        while state.frame < max_frames {
+            if state.at_row_start() {
+                let (order, row) = (state.order, state.row);
+                if state.loop_jump {
+                    if let Some((last_order, last_row)) = last.filter(|l| l.0 == order) {
+                        entered[last_order][row..=last_row.max(row)].fill(None);
+                    }
+                }
+                if entered[order][row].is_some() {
+                    target = (order, row);
+                    looping = true;
+                    break;
+                }
+                if last.is_none_or(|(last_order, _)| last_order != order) {
+                    snapshots.push(state.clone());
+                }
+                entered[order][row] = Some(state.frame);
+                last = Some((order, row));
+            }
+            if state.tick_left == 0 && !state.process_tick(&module, sample_rate) {
+                break;
+            }
+            let frames = state.tick_left;
+            state.render(&module, None, frames);
+        }
+
+        let loop_start = entered
+            .get(target.0)
+            .and_then(|rows| rows.get(target.1).copied().flatten())
+            .unwrap_or(0);
+        let frames = state.frame;
+        let state = snapshots
+            .first()
+            .cloned()
+            .unwrap_or_else(|| State::init(&module));
+
+        Self {
+            module,
+            state,
+            sample_rate,
+            frames,
+            loop_start,
+            looping,
+            snapshots,
+        }
+    }
+
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// the frame a looping song goes back to after `frames`.
+    pub fn loop_start(&self) -> u64 {
+        self.loop_start
+    }
+
+    /// true if the song jumps back instead of running off the end of its order list.
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// (order, row) playing.
+    pub fn position(&self) -> (usize, usize) {
+        (self.state.order, self.state.row)
+    }
+}
+
+impl MusicDecoder for ModulePlayer {
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn read(&mut self, out: &mut [f32]) -> usize {
+        let wanted = out.len() / 2;
+        let mut written = 0;
+        while written < wanted && self.state.frame < self.frames {
+            if self.state.tick_left == 0 && !self.state.process_tick(&self.module, self.sample_rate)
+            {
+                break;
+            }
+            let frames = self
+                .state
+                .tick_left
+                .min(wanted - written)
+                .min((self.frames - self.state.frame) as usize);
+            let block = &mut out[written * 2..(written + frames) * 2];
+            block.fill(0.0);
+            self.state.render(&self.module, Some(block), frames);
+            written += frames;
+        }
+        written
+    }
+
+    fn seek<'a>(&mut self, frame: u64) -> Result<(), &'a str> {
+        let frame = frame.min(self.frames);
+        let i = self
+            .snapshots
+            .partition_point(|s| s.frame <= frame)
+            .saturating_sub(1);
+        self.state = match self.snapshots.get(i) {
+            Some(snapshot) => snapshot.clone(),
+            None => State::init(&self.module),
+        };
+        while self.state.frame < frame {
+            if self.state.tick_left == 0 && !self.state.process_tick(&self.module, self.sample_rate)
+            {
+                break;
+            }
+            let frames = self
+                .state
+                .tick_left
+                .min((frame - self.state.frame) as usize);
+            self.state.render(&self.module, None, frames);
+        }
+        Ok(())
+    }
+}
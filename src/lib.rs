@@ -33,44 +33,129 @@ pub mod parsers {
     use crate::*;
     use c_utils::sys;
 
-    #[derive(Debug)]
-    pub struct Image {
-        pub raw: Vec<u8>,
+    mod aseprite;
+    pub mod etc2;
+    mod image_ops;
+    mod json;
+    mod ktx;
+    mod qoi;
+    mod sprite_atlas;
+    pub use aseprite::{
+        AnimationDirection, Aseprite, AsepriteCel, AsepriteLayer, AsepriteSlice, AsepriteTag,
+        BlendMode, SliceKey,
+    };
+    pub use image_ops::ResizeFilter;
+    pub use json::Json;
+    pub use ktx::{CompressedFormat, KtxImage, KtxLevel};
+    pub use sprite_atlas::{SpriteAtlasFile, SpriteAtlasPage};
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ImageFormat {
+        Png,
+        Bmp,
+        Tga,
+        /// quality from 1 to 100.
+        Jpg(i32),
+        /// radiance rgbe, the 8 bit values are converted back to linear floats.
+        Hdr,
+        /// lossless and fast, gray images are stored as rgb (gray alpha as rgba).
+        Qoi,
+    }
+
+    unsafe extern "C" fn write_to_vec(
+        context: *mut std::os::raw::c_void,
+        data: *mut std::os::raw::c_void,
+        size: std::os::raw::c_int,
+    ) {
+        let out = &mut *(context as *mut Vec<u8>);
+        out.extend_from_slice(std::slice::from_raw_parts(data as *const u8, size as usize));
+    }
+
+    /// storage of one channel of an `Image`: u8, u16 (16 bit png) or f32 (hdr).
+    pub trait Channel: Copy + Default + PartialEq + std::fmt::Debug {
+        /// decodes with the stb_image loader of this type, the result is freed with `utl_image_free`.
+        fn load(buffer: &[u8], width: &mut i32, height: &mut i32, channels: &mut i32) -> *mut Self;
+
+        /// 0.0 -> 1.0 for the integer types.
+        fn to_f32(self) -> f32;
+        fn from_f32(value: f32) -> Self;
+    }
+
+    impl Channel for u8 {
+        fn load(buffer: &[u8], w: &mut i32, h: &mut i32, c: &mut i32) -> *mut Self {
+            unsafe { sys::utl_image_load(buffer.as_ptr(), buffer.len() as i32, w, h, c) }
+        }
+
+        fn to_f32(self) -> f32 {
+            self as f32 / 255.0
+        }
+
+        fn from_f32(value: f32) -> Self {
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        }
+    }
+
+    impl Channel for u16 {
+        fn load(buffer: &[u8], w: &mut i32, h: &mut i32, c: &mut i32) -> *mut Self {
+            unsafe { sys::utl_image_load_16(buffer.as_ptr(), buffer.len() as i32, w, h, c) }
+        }
+
+        fn to_f32(self) -> f32 {
+            self as f32 / 65535.0
+        }
+
+        fn from_f32(value: f32) -> Self {
+            (value.clamp(0.0, 1.0) * 65535.0).round() as u16
+        }
+    }
+
+    impl Channel for f32 {
+        fn load(buffer: &[u8], w: &mut i32, h: &mut i32, c: &mut i32) -> *mut Self {
+            unsafe { sys::utl_image_load_f32(buffer.as_ptr(), buffer.len() as i32, w, h, c) }
+        }
+
+        fn to_f32(self) -> f32 {
+            self
+        }
+
+        fn from_f32(value: f32) -> Self {
+            value
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Image<T = u8> {
+        pub raw: Vec<T>,
         pub width: i32,
         pub height: i32,
         pub channels: i32,
     }
 
-    impl Image {
-        /// takes in png, jpg .. etc buffer.
-        pub fn init<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+    impl<T: Channel> Image<T> {
+        fn decode<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+            if buffer.starts_with(qoi::MAGIC) {
+                return Ok(qoi::decode(buffer)?.convert());
+            }
+
             let mut width: i32 = 0;
             let mut height: i32 = 0;
             let mut channels: i32 = 0;
 
-            let raw_ptr = unsafe {
-                sys::utl_image_load(
-                    buffer.as_ptr(),
-                    buffer.len() as i32,
-                    &mut width,
-                    &mut height,
-                    &mut channels,
-                )
-            };
+            let raw_ptr = T::load(buffer, &mut width, &mut height, &mut channels);
 
             if raw_ptr.is_null() || (width == 0) || (height == 0) || (channels == 0) {
                 return Err("Failed to parse image");
             }
 
             let size = (width * height * channels) as usize;
-            let mut raw: Vec<u8> = Vec::with_capacity(size);
+            let mut raw: Vec<T> = Vec::with_capacity(size);
 
             unsafe {
                 std::ptr::copy_nonoverlapping(raw_ptr, raw.as_mut_ptr(), size);
                 raw.set_len(size);
             };
 
-            unsafe { sys::utl_image_free(raw_ptr) };
+            unsafe { sys::utl_image_free(raw_ptr as *mut u8) };
 
             Ok(Self {
                 raw,
@@ -79,72 +164,1102 @@ pub mod parsers {
                 channels,
             })
         }
+
+        /// same image with another channel type, integers are treated as 0.0 -> 1.0.
+        pub fn convert<U: Channel>(&self) -> Image<U> {
+            Image {
+                raw: self.raw.iter().map(|&v| U::from_f32(v.to_f32())).collect(),
+                width: self.width,
+                height: self.height,
+                channels: self.channels,
+            }
+        }
     }
 
+    impl Image {
+        /// takes in png, jpg, qoi .. etc buffer, 16 bit and hdr images are converted to 8 bit.
+        pub fn init<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+            if buffer.starts_with(qoi::MAGIC) {
+                return qoi::decode(buffer);
+            }
+            Self::decode(buffer)
+        }
+
+        /// rows (top to bottom) ready for the writers, flipped if `flip_vertically`.
+        fn rows_for_write(&self, format: ImageFormat, flip_vertically: bool) -> Vec<u8> {
+            let stride = (self.width * self.channels) as usize;
+            let rows: Vec<&[u8]> = if flip_vertically {
+                self.raw.chunks_exact(stride).rev().collect()
+            } else {
+                self.raw.chunks_exact(stride).collect()
+            };
+
+            if format != ImageFormat::Hdr {
+                return rows.concat();
+            }
+
+            // inverse of what stb_image does to load 8 bit images as floats: gamma 2.2 for the colors.
+            let has_alpha = self.channels == 2 || self.channels == 4;
+            let mut out = Vec::with_capacity(self.raw.len() * 4);
+            for row in rows {
+                for (i, &v) in row.iter().enumerate() {
+                    let v = v as f32 / 255.0;
+                    let is_alpha = has_alpha && (i as i32 % self.channels) == self.channels - 1;
+                    let v = if is_alpha { v } else { v.powf(2.2) };
+                    out.extend_from_slice(&v.to_ne_bytes());
+                }
+            }
+            out
+        }
+
+        fn check_for_write<'a>(&self, format: ImageFormat) -> Result<(), &'a str> {
+            if self.width <= 0 || self.height <= 0 || !(1..=4).contains(&self.channels) {
+                return Err("Failed to write image, invalid size or channels");
+            }
+            if self.raw.len() != (self.width * self.height * self.channels) as usize {
+                return Err(
+                    "Failed to write image, raw size does not match width * height * channels",
+                );
+            }
+            if let ImageFormat::Jpg(quality) = format {
+                if !(1..=100).contains(&quality) {
+                    return Err("Failed to write image, jpg quality out of 1..=100");
+                }
+            }
+
+            Ok(())
+        }
+
+        fn sys_format(format: ImageFormat) -> (i32, i32) {
+            match format {
+                ImageFormat::Png => (sys::UTL_IMAGE_FORMAT_PNG as i32, 0),
+                ImageFormat::Bmp => (sys::UTL_IMAGE_FORMAT_BMP as i32, 0),
+                ImageFormat::Tga => (sys::UTL_IMAGE_FORMAT_TGA as i32, 0),
+                ImageFormat::Jpg(quality) => (sys::UTL_IMAGE_FORMAT_JPG as i32, quality),
+                ImageFormat::Hdr => (sys::UTL_IMAGE_FORMAT_HDR as i32, 0),
+                ImageFormat::Qoi => unreachable!("qoi is encoded in rust"),
+            }
+        }
+
+        fn encode_qoi_rows(&self, flip_vertically: bool) -> Vec<u8> {
+            let image = match self.channels {
+                1 => self.convert_channels(3).unwrap(),
+                2 => self.convert_channels(4).unwrap(),
+                _ => self.clone(),
+            };
+            let rows = image.rows_for_write(ImageFormat::Qoi, flip_vertically);
+            qoi::encode(&rows, image.width, image.height, image.channels)
+        }
+
+        pub fn save<'a>(
+            &self,
+            filename: &str,
+            format: ImageFormat,
+            flip_vertically: bool,
+        ) -> Result<(), &'a str> {
+            self.check_for_write(format)?;
+
+            if format == ImageFormat::Qoi {
+                return std::fs::write(filename, self.encode_qoi_rows(flip_vertically))
+                    .map_err(|_| "Failed to write image");
+            }
+
+            let filename = std::ffi::CString::new(filename)
+                .map_err(|_| "Failed to write image, invalid filename")?;
+            let rows = self.rows_for_write(format, flip_vertically);
+            let (sys_format, quality) = Self::sys_format(format);
+
+            let success = unsafe {
+                sys::utl_image_save(
+                    filename.as_ptr(),
+                    sys_format,
+                    rows.as_ptr() as *const std::os::raw::c_void,
+                    self.width,
+                    self.height,
+                    self.channels,
+                    quality,
+                )
+            };
+            if success == 0 {
+                return Err("Failed to write image");
+            }
+
+            Ok(())
+        }
+
+        /// same as `Image::save` but into memory.
+        pub fn encode<'a>(
+            &self,
+            format: ImageFormat,
+            flip_vertically: bool,
+        ) -> Result<Vec<u8>, &'a str> {
+            self.check_for_write(format)?;
+
+            if format == ImageFormat::Qoi {
+                return Ok(self.encode_qoi_rows(flip_vertically));
+            }
+
+            let rows = self.rows_for_write(format, flip_vertically);
+            let (sys_format, quality) = Self::sys_format(format);
+
+            let mut out: Vec<u8> = Vec::new();
+            let success = unsafe {
+                sys::utl_image_encode(
+                    Some(write_to_vec),
+                    &mut out as *mut Vec<u8> as *mut std::os::raw::c_void,
+                    sys_format,
+                    rows.as_ptr() as *const std::os::raw::c_void,
+                    self.width,
+                    self.height,
+                    self.channels,
+                    quality,
+                )
+            };
+            if success == 0 {
+                return Err("Failed to encode image");
+            }
+
+            Ok(out)
+        }
+
+        pub fn save_png<'a>(&self, filename: &str) -> Result<(), &'a str> {
+            self.save(filename, ImageFormat::Png, false)
+        }
+
+        pub fn save_bmp<'a>(&self, filename: &str) -> Result<(), &'a str> {
+            self.save(filename, ImageFormat::Bmp, false)
+        }
+
+        pub fn save_tga<'a>(&self, filename: &str) -> Result<(), &'a str> {
+            self.save(filename, ImageFormat::Tga, false)
+        }
+
+        pub fn save_jpg<'a>(&self, filename: &str, quality: i32) -> Result<(), &'a str> {
+            self.save(filename, ImageFormat::Jpg(quality), false)
+        }
+
+        pub fn save_hdr<'a>(&self, filename: &str) -> Result<(), &'a str> {
+            self.save(filename, ImageFormat::Hdr, false)
+        }
+
+        pub fn save_qoi<'a>(&self, filename: &str) -> Result<(), &'a str> {
+            self.save(filename, ImageFormat::Qoi, false)
+        }
+
+        pub fn encode_png<'a>(&self) -> Result<Vec<u8>, &'a str> {
+            self.encode(ImageFormat::Png, false)
+        }
+
+        pub fn encode_bmp<'a>(&self) -> Result<Vec<u8>, &'a str> {
+            self.encode(ImageFormat::Bmp, false)
+        }
+
+        pub fn encode_tga<'a>(&self) -> Result<Vec<u8>, &'a str> {
+            self.encode(ImageFormat::Tga, false)
+        }
+
+        pub fn encode_jpg<'a>(&self, quality: i32) -> Result<Vec<u8>, &'a str> {
+            self.encode(ImageFormat::Jpg(quality), false)
+        }
+
+        pub fn encode_hdr<'a>(&self) -> Result<Vec<u8>, &'a str> {
+            self.encode(ImageFormat::Hdr, false)
+        }
+
+        pub fn encode_qoi<'a>(&self) -> Result<Vec<u8>, &'a str> {
+            self.encode(ImageFormat::Qoi, false)
+        }
+    }
+
+    impl Image<u16> {
+        /// keeps the full 16 bits of png heightmaps, 8 bit images are scaled up.
+        pub fn init_16<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+            Self::decode(buffer)
+        }
+    }
+
+    impl Image<f32> {
+        /// linear floats from .hdr files, 8 and 16 bit images go through stb_image gamma 2.2 conversion.
+        pub fn init_hdr<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+            Self::decode(buffer)
+        }
+
+        /// float format matching the channels, None for 2 channels.
+        pub fn texture_format(&self, half_float: bool) -> Option<TextureFormat> {
+            match (self.channels, half_float) {
+                (1, true) => Some(TextureFormat::R16F),
+                (3, true) => Some(TextureFormat::Rgb16F),
+                (4, true) => Some(TextureFormat::Rgba16F),
+                (1, false) => Some(TextureFormat::R32F),
+                (3, false) => Some(TextureFormat::Rgb32F),
+                (4, false) => Some(TextureFormat::Rgba32F),
+                _ => None,
+            }
+        }
+
+        pub fn encode_hdr<'a>(&self) -> Result<Vec<u8>, &'a str> {
+            if self.raw.len() != (self.width * self.height * self.channels) as usize {
+                return Err(
+                    "Failed to write image, raw size does not match width * height * channels",
+                );
+            }
+
+            let mut out: Vec<u8> = Vec::new();
+            let success = unsafe {
+                sys::utl_image_encode(
+                    Some(write_to_vec),
+                    &mut out as *mut Vec<u8> as *mut std::os::raw::c_void,
+                    sys::UTL_IMAGE_FORMAT_HDR as i32,
+                    self.raw.as_ptr() as *const std::os::raw::c_void,
+                    self.width,
+                    self.height,
+                    self.channels,
+                    0,
+                )
+            };
+            if success == 0 {
+                return Err("Failed to encode image");
+            }
+
+            Ok(out)
+        }
+    }
+
+    /// container an image file was detected as, from its magic bytes.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ImageFileFormat {
+        Png,
+        Jpg,
+        Bmp,
+        Gif,
+        Psd,
+        Hdr,
+        Pic,
+        Pnm,
+        Qoi,
+        /// tga has no magic, it is what is left when stb_image still reads the header.
+        Tga,
+    }
+
+    impl ImageFileFormat {
+        fn detect(buffer: &[u8]) -> Self {
+            match buffer {
+                [0x89, b'P', b'N', b'G', ..] => Self::Png,
+                [0xFF, 0xD8, ..] => Self::Jpg,
+                [b'B', b'M', ..] => Self::Bmp,
+                [b'G', b'I', b'F', b'8', ..] => Self::Gif,
+                [b'8', b'B', b'P', b'S', ..] => Self::Psd,
+                [b'#', b'?', ..] => Self::Hdr,
+                [0x53, 0x80, 0xF6, 0x34, ..] => Self::Pic,
+                [b'P', b'5' | b'6', ..] => Self::Pnm,
+                [b'q', b'o', b'i', b'f', ..] => Self::Qoi,
+                _ => Self::Tga,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ImageInfo {
+        pub width: i32,
+        pub height: i32,
+        pub channels: i32,
+        /// bits per channel: 8, 16 or 32 (hdr floats).
+        pub bit_depth: i32,
+        pub format: ImageFileFormat,
+    }
+
+    impl Image {
+        /// reads the size and format from the header without decoding any pixel,
+        /// `buffer` only needs to hold the start of the file for most formats.
+        pub fn probe<'a>(buffer: &[u8]) -> Result<ImageInfo, &'a str> {
+            if buffer.starts_with(qoi::MAGIC) {
+                let (width, height, channels) = qoi::header(buffer)?;
+                return Ok(ImageInfo {
+                    width,
+                    height,
+                    channels,
+                    bit_depth: 8,
+                    format: ImageFileFormat::Qoi,
+                });
+            }
+
+            let mut width: i32 = 0;
+            let mut height: i32 = 0;
+            let mut channels: i32 = 0;
+            let mut is_16_bit: i32 = 0;
+            let mut is_hdr: i32 = 0;
+
+            let success = unsafe {
+                sys::utl_image_info(
+                    buffer.as_ptr(),
+                    buffer.len() as i32,
+                    &mut width,
+                    &mut height,
+                    &mut channels,
+                    &mut is_16_bit,
+                    &mut is_hdr,
+                )
+            };
+            if success == 0 || width <= 0 || height <= 0 {
+                return Err("Failed to probe image");
+            }
+
+            let bit_depth = if is_hdr != 0 {
+                32
+            } else if is_16_bit != 0 {
+                16
+            } else {
+                8
+            };
+
+            Ok(ImageInfo {
+                width,
+                height,
+                channels,
+                bit_depth,
+                format: ImageFileFormat::detect(buffer),
+            })
+        }
+    }
+
+    /// frames of an animation (gif), all the same size and rgba.
+    #[derive(Debug, Clone)]
+    pub struct AnimatedImage {
+        pub frames: Vec<Image>,
+        /// milliseconds each frame stays on screen.
+        pub delays: Vec<u32>,
+    }
+
+    impl AnimatedImage {
+        /// like browsers do, frames asking for 10ms or less are shown for 100ms.
+        pub const DEFAULT_DELAY: u32 = 100;
+
+        pub fn init<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+            let mut delays_ptr: *mut i32 = std::ptr::null_mut();
+            let mut width: i32 = 0;
+            let mut height: i32 = 0;
+            let mut frames_count: i32 = 0;
+
+            let raw_ptr = unsafe {
+                sys::utl_image_load_gif(
+                    buffer.as_ptr(),
+                    buffer.len() as i32,
+                    &mut delays_ptr,
+                    &mut width,
+                    &mut height,
+                    &mut frames_count,
+                )
+            };
+
+            if raw_ptr.is_null() || width == 0 || height == 0 || frames_count == 0 {
+                unsafe { sys::utl_image_free(delays_ptr as *mut u8) };
+                return Err("Failed to parse gif");
+            }
+
+            let frame_size = (width * height * 4) as usize;
+            let raw =
+                unsafe { std::slice::from_raw_parts(raw_ptr, frame_size * frames_count as usize) };
+            let frames = raw
+                .chunks_exact(frame_size)
+                .map(|frame| Image {
+                    raw: frame.to_vec(),
+                    width,
+                    height,
+                    channels: 4,
+                })
+                .collect();
+
+            let delays = if delays_ptr.is_null() {
+                vec![Self::DEFAULT_DELAY; frames_count as usize]
+            } else {
+                unsafe { std::slice::from_raw_parts(delays_ptr, frames_count as usize) }
+                    .iter()
+                    .map(|&d| {
+                        if d <= 10 {
+                            Self::DEFAULT_DELAY
+                        } else {
+                            d as u32
+                        }
+                    })
+                    .collect()
+            };
+
+            unsafe {
+                sys::utl_image_free(raw_ptr);
+                sys::utl_image_free(delays_ptr as *mut u8);
+            }
+
+            Ok(Self { frames, delays })
+        }
+
+        pub fn width(&self) -> i32 {
+            self.frames[0].width
+        }
+
+        pub fn height(&self) -> i32 {
+            self.frames[0].height
+        }
+
+        /// length of one loop in milliseconds.
+        pub fn duration(&self) -> u64 {
+            self.delays.iter().map(|&d| d as u64).sum()
+        }
+
+        /// frame to show `elapsed` milliseconds after the start,
+        /// without `looping` the last frame stays once the animation is over.
+        pub fn frame_at(&self, elapsed: u64, looping: bool) -> usize {
+            frame_at(&self.delays, elapsed, looping)
+        }
+
+        /// the frames side by side, left to right.
+        pub fn to_strip(&self) -> Image {
+            let (width, height) = (self.width(), self.height());
+            let mut strip = Image::init_blank(width * self.frames.len() as i32, height, 4);
+            for (i, frame) in self.frames.iter().enumerate() {
+                for y in 0..height {
+                    let offset = ((y * strip.width + i as i32 * width) * 4) as usize;
+                    let row = (y * width * 4) as usize..((y + 1) * width * 4) as usize;
+                    strip.raw[offset..offset + row.len()].copy_from_slice(&frame.raw[row]);
+                }
+            }
+            strip
+        }
+
+        /// the frames one after the other, the layout `TextureArray::init` expects.
+        pub fn to_layers(&self) -> Vec<u8> {
+            self.frames
+                .iter()
+                .flat_map(|f| f.raw.iter().copied())
+                .collect()
+        }
+    }
+
+    /// index into `delays` (milliseconds per frame) `elapsed` milliseconds after the start,
+    /// without `looping` the last frame stays once the animation is over.
+    pub(crate) fn frame_at(delays: &[u32], elapsed: u64, looping: bool) -> usize {
+        let duration: u64 = delays.iter().map(|&d| d as u64).sum();
+        if duration == 0 {
+            return 0;
+        }
+
+        let mut time = if looping {
+            elapsed % duration
+        } else if elapsed >= duration {
+            return delays.len() - 1;
+        } else {
+            elapsed
+        };
+
+        for (i, &delay) in delays.iter().enumerate() {
+            if time < delay as u64 {
+                return i;
+            }
+            time -= delay as u64;
+        }
+
+        delays.len() - 1
+    }
+
+    /// little endian cursor over a byte buffer, for the binary formats.
+    pub(crate) struct ByteReader<'b> {
+        buffer: &'b [u8],
+        cursor: usize,
+    }
+
+    impl<'b> ByteReader<'b> {
+        pub(crate) fn init(buffer: &'b [u8]) -> Self {
+            Self { buffer, cursor: 0 }
+        }
+
+        pub(crate) fn remaining(&self) -> usize {
+            self.buffer.len() - self.cursor
+        }
+
+        pub(crate) fn position(&self) -> usize {
+            self.cursor
+        }
+
+        /// moves to `offset` from the start of the buffer.
+        pub(crate) fn seek<'a>(&mut self, offset: usize) -> Result<(), &'a str> {
+            if offset > self.buffer.len() {
+                return Err("Unexpected end of buffer");
+            }
+            self.cursor = offset;
+            Ok(())
+        }
+
+        pub(crate) fn bytes<'a>(&mut self, count: usize) -> Result<&'b [u8], &'a str> {
+            if count > self.remaining() {
+                return Err("Unexpected end of buffer");
+            }
+            let r = &self.buffer[self.cursor..self.cursor + count];
+            self.cursor += count;
+            Ok(r)
+        }
+
+        pub(crate) fn u8<'a>(&mut self) -> Result<u8, &'a str> {
+            Ok(self.bytes(1)?[0])
+        }
+
+        pub(crate) fn u16_le<'a>(&mut self) -> Result<u16, &'a str> {
+            let b = self.bytes(2)?;
+            Ok(u16::from_le_bytes([b[0], b[1]]))
+        }
+
+        pub(crate) fn u32_le<'a>(&mut self) -> Result<u32, &'a str> {
+            let b = self.bytes(4)?;
+            Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        }
+
+        pub(crate) fn u64_le<'a>(&mut self) -> Result<u64, &'a str> {
+            let low = self.u32_le()? as u64;
+            let high = self.u32_le()? as u64;
+            Ok(low | (high << 32))
+        }
+
+        pub(crate) fn i32_le<'a>(&mut self) -> Result<i32, &'a str> {
+            Ok(self.u32_le()? as i32)
+        }
+
+        pub(crate) fn f32_le<'a>(&mut self) -> Result<f32, &'a str> {
+            Ok(f32::from_bits(self.u32_le()?))
+        }
+    }
+
+    /// a run of `count` unicode codepoints starting at `first`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FontRange {
+        pub first: u32,
+        pub count: u32,
+    }
+
+    impl FontRange {
+        /// printable ascii, from ' ' to '~'.
+        pub const ASCII: FontRange = FontRange {
+            first: ' ' as u32,
+            count: ('~' as u32 - ' ' as u32) + 1,
+        };
+
+        /// both `first` and `last` are included.
+        pub fn init(first: char, last: char) -> Self {
+            debug_assert!(first <= last, "FontRange with first > last");
+            Self {
+                first: first as u32,
+                count: last as u32 - first as u32 + 1,
+            }
+        }
+
+        pub fn contains(&self, c: char) -> bool {
+            let c = c as u32;
+            c >= self.first && c - self.first < self.count
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct FontBakeConfig {
+        /// face to bake from a font collection (.ttc), 0 for plain .ttf files.
+        pub font_index: i32,
+        pub font_size: f32,
+        /// the atlas starts at this size and doubles (up to `max_atlas_size`) until all the glyphs fit.
+        pub atlas_width: i32,
+        pub atlas_height: i32,
+        pub max_atlas_size: i32,
+        pub oversample_h: u32,
+        pub oversample_v: u32,
+        /// empty pixels between glyphs in the atlas.
+        pub padding: i32,
+        pub ranges: Vec<FontRange>,
+    }
+
+    impl Default for FontBakeConfig {
+        fn default() -> Self {
+            Self {
+                font_index: 0,
+                font_size: 32.0,
+                atlas_width: 1024,
+                atlas_height: 1024,
+                max_atlas_size: 4096,
+                oversample_h: 8,
+                oversample_v: 8,
+                padding: 1,
+                ranges: vec![FontRange::ASCII],
+            }
+        }
+    }
+
+    /// names from the font `name` table.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct FontInfo {
+        pub family: String,
+        /// "Regular", "Bold Italic" .. etc.
+        pub style: String,
+        pub full_name: String,
+    }
+
+    const BAKED_FONT_MAGIC: &[u8; 4] = b"GGFB";
+    /// 1: atlas and glyphs, 2: + glyph coverage and font info.
+    const BAKED_FONT_VERSION: u32 = 2;
+
     #[derive(Debug)]
     pub struct TrueTypeFont {
-        pub sys_font: sys::Font,
+        pub font_size: f32,
+        /// 1 channel atlas.
         pub pixels: Vec<u8>,
+        pub width: i32,
+        pub height: i32,
+        pub ranges: Vec<FontRange>,
+        /// the glyphs of all the `ranges` one after the other.
+        pub packed_chars: Vec<sys::stbtt_packedchar>,
+        /// same length as `packed_chars`, false where the face has no glyph for the codepoint.
+        pub has_glyph: Vec<bool>,
+        pub info: FontInfo,
     }
 
     impl TrueTypeFont {
+        /// bakes printable ascii with the default config.
         pub fn init<'a>(buffer: &[u8], font_size: i32) -> Result<Self, &'a str> {
-            let mut font: sys::Font = unsafe { std::mem::MaybeUninit::zeroed().assume_init() };
-            let ttf_parsing_success =
-                unsafe { sys::utl_font_parse(buffer.as_ptr() as *mut u8, font_size, &mut font) };
+            let config = FontBakeConfig {
+                font_size: font_size as f32,
+                ..Default::default()
+            };
+
+            Self::init_with_config(buffer, &config)
+        }
+
+        pub fn init_with_config<'a>(
+            buffer: &[u8],
+            config: &FontBakeConfig,
+        ) -> Result<Self, &'a str> {
+            if config.ranges.is_empty() {
+                return Err("Failed to parse TTF, no font ranges to bake");
+            }
+
+            if config.oversample_h == 0 || config.oversample_v == 0 {
+                return Err("Failed to parse TTF, oversampling has to be at least 1");
+            }
+
+            if config.atlas_width <= 0 || config.atlas_height <= 0 {
+                return Err("Failed to parse TTF, atlas size has to be at least 1");
+            }
 
-            if ttf_parsing_success == 0 {
+            let sys_ranges: Vec<sys::FontRange> = config
+                .ranges
+                .iter()
+                .map(|r| sys::FontRange {
+                    first_codepoint: r.first as i32,
+                    count: r.count as i32,
+                })
+                .collect();
+
+            let packed_chars_count: u32 = config.ranges.iter().map(|r| r.count).sum();
+            let mut packed_chars: Vec<sys::stbtt_packedchar> =
+                vec![
+                    unsafe { std::mem::MaybeUninit::zeroed().assume_init() };
+                    packed_chars_count as usize
+                ];
+
+            let mut has_glyph = vec![0u8; packed_chars_count as usize];
+            let coverage_success = unsafe {
+                sys::utl_font_glyph_coverage(
+                    buffer.as_ptr(),
+                    config.font_index,
+                    sys_ranges.as_ptr(),
+                    sys_ranges.len() as i32,
+                    has_glyph.as_mut_ptr(),
+                )
+            };
+            if coverage_success == 0 {
                 return Err("Failed to parse TTF");
             }
 
-            let size = (font.img_width * font.img_height * font.img_channels) as usize;
-            let mut pixels: Vec<u8> = Vec::with_capacity(size);
+            let info = Self::read_info(buffer, config.font_index)?;
 
-            unsafe {
-                std::ptr::copy_nonoverlapping(font.img_buffer, pixels.as_mut_ptr(), size);
-                pixels.set_len(size);
+            let mut width = config.atlas_width;
+            let mut height = config.atlas_height;
+
+            loop {
+                if width > config.max_atlas_size || height > config.max_atlas_size {
+                    return Err("Failed to parse TTF, glyphs do not fit in the max atlas size");
+                }
+
+                let size = (width as usize)
+                    .checked_mul(height as usize)
+                    .ok_or("Failed to parse TTF, invalid atlas size")?;
+                let mut pixels: Vec<u8> = vec![0; size];
+                let sys_config = sys::FontBakeConfig {
+                    font_index: config.font_index,
+                    font_size: config.font_size,
+                    atlas_width: width,
+                    atlas_height: height,
+                    padding: config.padding,
+                    oversample_h: config.oversample_h,
+                    oversample_v: config.oversample_v,
+                };
+
+                let result = unsafe {
+                    sys::utl_font_parse(
+                        buffer.as_ptr(),
+                        &sys_config,
+                        sys_ranges.as_ptr(),
+                        sys_ranges.len() as i32,
+                        pixels.as_mut_ptr(),
+                        packed_chars.as_mut_ptr(),
+                    )
+                };
+
+                match result {
+                    1 => {
+                        return Ok(Self {
+                            font_size: config.font_size,
+                            pixels,
+                            width,
+                            height,
+                            ranges: config.ranges.clone(),
+                            packed_chars,
+                            has_glyph: has_glyph.iter().map(|&h| h != 0).collect(),
+                            info,
+                        })
+                    }
+
+                    // grow the smaller side and try again.
+                    0 => {
+                        let side = if width <= height {
+                            &mut width
+                        } else {
+                            &mut height
+                        };
+                        *side = side.checked_mul(2).ok_or(
+                            "Failed to parse TTF, glyphs do not fit in the max atlas size",
+                        )?;
+                    }
+
+                    _ => return Err("Failed to parse TTF"),
+                }
+            }
+        }
+
+        /// number of faces in a font collection (.ttc), 1 for a .ttf and 0 if `buffer` is not a font.
+        pub fn face_count(buffer: &[u8]) -> i32 {
+            if buffer.len() < 12 {
+                return 0;
+            }
+            unsafe { sys::utl_font_count(buffer.as_ptr()) }
+        }
+
+        /// reads the names of face `font_index` without baking anything.
+        pub fn read_info<'a>(buffer: &[u8], font_index: i32) -> Result<FontInfo, &'a str> {
+            if font_index < 0 || font_index >= Self::face_count(buffer) {
+                return Err("Failed to parse TTF, font index out of range");
+            }
+
+            let name = |name_id: i32| -> String {
+                // the length first, names have no limit.
+                let length = unsafe {
+                    sys::utl_font_name(
+                        buffer.as_ptr(),
+                        font_index,
+                        name_id,
+                        std::ptr::null_mut(),
+                        0,
+                    )
+                };
+                if length <= 0 {
+                    return String::new();
+                }
+                let mut raw = vec![0u8; length as usize];
+                unsafe {
+                    sys::utl_font_name(
+                        buffer.as_ptr(),
+                        font_index,
+                        name_id,
+                        raw.as_mut_ptr() as *mut std::os::raw::c_char,
+                        raw.len() as i32,
+                    )
+                };
+
+                // utf-16 big endian.
+                let units: Vec<u16> = raw
+                    .chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
             };
 
-            unsafe { sys::utl_font_free(&mut font) };
+            Ok(FontInfo {
+                family: name(1),
+                style: name(2),
+                full_name: name(4),
+            })
+        }
+
+        /// loads a font that was baked with `TrueTypeFont::to_baked`.
+        pub fn init_baked<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+            let mut reader = ByteReader::init(buffer);
+
+            if reader.bytes(4)? != BAKED_FONT_MAGIC {
+                return Err("Failed to parse baked font, wrong magic");
+            }
+            let version = reader.u32_le()?;
+            if version == 0 || version > BAKED_FONT_VERSION {
+                return Err("Failed to parse baked font, unsupported version");
+            }
+
+            let font_size = reader.f32_le()?;
+            let width = reader.i32_le()?;
+            let height = reader.i32_le()?;
+            if width <= 0 || height <= 0 {
+                return Err("Failed to parse baked font, invalid atlas size");
+            }
+
+            let ranges_count = reader.u32_le()?;
+            let mut ranges = Vec::new();
+            for _ in 0..ranges_count {
+                let first = reader.u32_le()?;
+                let count = reader.u32_le()?;
+                if first
+                    .checked_add(count)
+                    .is_none_or(|end| end > char::MAX as u32 + 1)
+                {
+                    return Err("Failed to parse baked font, invalid range");
+                }
+                ranges.push(FontRange { first, count });
+            }
+
+            let packed_chars_count: u64 = ranges.iter().map(|r| r.count as u64).sum();
+            if packed_chars_count * 28 > reader.remaining() as u64 {
+                return Err("Failed to parse baked font, truncated glyphs");
+            }
+
+            let mut packed_chars = Vec::with_capacity(packed_chars_count as usize);
+            for _ in 0..packed_chars_count {
+                packed_chars.push(sys::stbtt_packedchar {
+                    x0: reader.u16_le()?,
+                    y0: reader.u16_le()?,
+                    x1: reader.u16_le()?,
+                    y1: reader.u16_le()?,
+                    xoff: reader.f32_le()?,
+                    yoff: reader.f32_le()?,
+                    xadvance: reader.f32_le()?,
+                    xoff2: reader.f32_le()?,
+                    yoff2: reader.f32_le()?,
+                });
+            }
+
+            let mut has_glyph = vec![true; packed_chars.len()];
+            let mut info = FontInfo::default();
+            if version >= 2 {
+                for (h, &b) in has_glyph.iter_mut().zip(reader.bytes(packed_chars.len())?) {
+                    *h = b != 0;
+                }
+
+                let mut string = || -> Result<String, &'a str> {
+                    let length = reader.u32_le()? as usize;
+                    Ok(String::from_utf8_lossy(reader.bytes(length)?).into_owned())
+                };
+                info.family = string()?;
+                info.style = string()?;
+                info.full_name = string()?;
+            }
+
+            let size = (width as usize)
+                .checked_mul(height as usize)
+                .ok_or("Failed to parse baked font, invalid atlas size")?;
+            let pixels = reader.bytes(size)?.to_vec();
 
             Ok(Self {
-                sys_font: font,
+                font_size,
                 pixels,
+                width,
+                height,
+                ranges,
+                packed_chars,
+                has_glyph,
+                info,
             })
         }
 
+        /// serializes the atlas and the glyph metrics, load it back with `TrueTypeFont::init_baked`.
+        pub fn to_baked(&self) -> Vec<u8> {
+            let mut out = Vec::with_capacity(self.pixels.len() + self.packed_chars.len() * 28 + 64);
+
+            out.extend_from_slice(BAKED_FONT_MAGIC);
+            out.extend_from_slice(&BAKED_FONT_VERSION.to_le_bytes());
+            out.extend_from_slice(&self.font_size.to_le_bytes());
+            out.extend_from_slice(&self.width.to_le_bytes());
+            out.extend_from_slice(&self.height.to_le_bytes());
+
+            out.extend_from_slice(&(self.ranges.len() as u32).to_le_bytes());
+            for r in self.ranges.iter() {
+                out.extend_from_slice(&r.first.to_le_bytes());
+                out.extend_from_slice(&r.count.to_le_bytes());
+            }
+
+            for p in self.packed_chars.iter() {
+                out.extend_from_slice(&p.x0.to_le_bytes());
+                out.extend_from_slice(&p.y0.to_le_bytes());
+                out.extend_from_slice(&p.x1.to_le_bytes());
+                out.extend_from_slice(&p.y1.to_le_bytes());
+                out.extend_from_slice(&p.xoff.to_le_bytes());
+                out.extend_from_slice(&p.yoff.to_le_bytes());
+                out.extend_from_slice(&p.xadvance.to_le_bytes());
+                out.extend_from_slice(&p.xoff2.to_le_bytes());
+                out.extend_from_slice(&p.yoff2.to_le_bytes());
+            }
+
+            for &h in self.has_glyph.iter() {
+                out.push(h as u8);
+            }
+
+            for string in [&self.info.family, &self.info.style, &self.info.full_name] {
+                out.extend_from_slice(&(string.len() as u32).to_le_bytes());
+                out.extend_from_slice(string.as_bytes());
+            }
+
+            out.extend_from_slice(&self.pixels);
+
+            out
+        }
+
+        pub fn save_baked<'a>(&self, filename: &str) -> Result<(), &'a str> {
+            match std::fs::write(filename, self.to_baked()) {
+                Ok(_) => Ok(()),
+                Err(_) => Err("Failed to save baked font"),
+            }
+        }
+
         /// returns (pixels, width, height, channels)
         pub fn get_image(&self) -> (&[u8], i32, i32, i32) {
-            (
-                self.pixels.as_slice(),
-                self.sys_font.img_width,
-                self.sys_font.img_height,
-                self.sys_font.img_channels,
-            )
+            (self.pixels.as_slice(), self.width, self.height, 1)
+        }
+
+        /// index of `c` in `packed_chars`, None if `c` was not baked or the face has no glyph for it.
+        pub fn glyph_index(&self, c: char) -> Option<usize> {
+            let mut offset = 0;
+            for r in self.ranges.iter() {
+                if r.contains(c) {
+                    let index = offset + (c as u32 - r.first) as usize;
+                    return match self.has_glyph.get(index) {
+                        Some(false) => None,
+                        _ => Some(index),
+                    };
+                }
+                offset += r.count as usize;
+            }
+
+            None
         }
 
-        /// returns (quad, nextXpos, nextYpos)
+        /// returns (quad, nextXpos, nextYpos), characters that were not baked return an empty quad
+        /// and don't advance.
         pub fn get_quad_and_next_position(
             &self,
-            c: char, // maybe will change this to u8 insted
+            c: char,
             x: f32,
             y: f32,
         ) -> (sys::stbtt_aligned_quad, f32, f32) {
-            let mut xpos = x;
-            let mut ypos = y;
-            let mut quad: sys::stbtt_aligned_quad;
-            unsafe {
-                quad = std::mem::MaybeUninit::zeroed().assume_init();
-                sys::utl_font_get_quad(
-                    &self.sys_font as *const sys::Font as *mut sys::Font,
-                    c as std::os::raw::c_char,
-                    &mut xpos,
-                    &mut ypos,
-                    &mut quad,
-                );
+            match self.glyph_index(c) {
+                Some(index) => self.get_glyph_quad(index, x, y),
+                None => (
+                    sys::stbtt_aligned_quad {
+                        x0: x,
+                        y0: y,
+                        s0: 0.0,
+                        t0: 0.0,
+                        x1: x,
+                        y1: y,
+                        s1: 0.0,
+                        t1: 0.0,
+                    },
+                    x,
+                    y,
+                ),
+            }
+        }
+
+        /// same as `stbtt_GetPackedQuad` with `align_to_integer`, `index` is into `packed_chars`.
+        pub fn get_glyph_quad(
+            &self,
+            index: usize,
+            x: f32,
+            y: f32,
+        ) -> (sys::stbtt_aligned_quad, f32, f32) {
+            let b = &self.packed_chars[index];
+            let ipw = 1.0 / self.width as f32;
+            let iph = 1.0 / self.height as f32;
+
+            let x0 = (x + b.xoff + 0.5).floor();
+            let y0 = (y + b.yoff + 0.5).floor();
+
+            let quad = sys::stbtt_aligned_quad {
+                x0,
+                y0,
+                s0: b.x0 as f32 * ipw,
+                t0: b.y0 as f32 * iph,
+                x1: x0 + b.xoff2 - b.xoff,
+                y1: y0 + b.yoff2 - b.yoff,
+                s1: b.x1 as f32 * ipw,
+                t1: b.y1 as f32 * iph,
+            };
+
+            (quad, x + b.xadvance, y)
+        }
+    }
+
+    /// faces in priority order, a glyph missing from a face is looked up in the next one.
+    #[derive(Debug)]
+    pub struct FontFamily {
+        pub faces: Vec<TrueTypeFont>,
+    }
+
+    impl FontFamily {
+        pub fn init<'a>(faces: Vec<TrueTypeFont>) -> Result<Self, &'a str> {
+            if faces.is_empty() {
+                return Err("Failed to create font family, no faces");
+            }
+            Ok(Self { faces })
+        }
+
+        /// bakes every face of a font collection (.ttc) with the same config.
+        pub fn init_collection<'a>(
+            buffer: &[u8],
+            config: &FontBakeConfig,
+        ) -> Result<Self, &'a str> {
+            let count = TrueTypeFont::face_count(buffer);
+            if count == 0 {
+                return Err("Failed to parse TTF collection");
+            }
+
+            let mut faces = Vec::with_capacity(count as usize);
+            for font_index in 0..count {
+                let config = FontBakeConfig {
+                    font_index,
+                    ..config.clone()
+                };
+                faces.push(TrueTypeFont::init_with_config(buffer, &config)?);
             }
 
-            (quad, xpos, ypos)
+            Ok(Self { faces })
+        }
+
+        /// the first face is the one that decides the line height.
+        pub fn primary(&self) -> &TrueTypeFont {
+            &self.faces[0]
+        }
+
+        /// (face, glyph index) of the first face that has `c`.
+        pub fn glyph(&self, c: char) -> Option<(usize, usize)> {
+            self.faces
+                .iter()
+                .enumerate()
+                .find_map(|(face, font)| font.glyph_index(c).map(|glyph| (face, glyph)))
+        }
+
+        /// first face whose family and style match, ignoring case.
+        pub fn find_face(&self, family: &str, style: &str) -> Option<usize> {
+            self.faces.iter().position(|f| {
+                f.info.family.eq_ignore_ascii_case(family)
+                    && f.info.style.eq_ignore_ascii_case(style)
+            })
         }
     }
 
@@ -293,11 +1408,95 @@ pub fn clock_milliseconds_from_start() -> u64 {
     clock.milliseconds
 }
 
+/// a sprite inside a sheet texture, possibly trimmed and packed rotated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteRegion {
+    pub name: String,
+    /// top left of the region in the texture, in pixels.
+    pub x: i32,
+    pub y: i32,
+    /// upright size of the trimmed sprite, the texture area is height x width when rotated.
+    pub width: i32,
+    pub height: i32,
+    /// clockwise degrees (0, 90 or 270) the sprite was turned when it was packed.
+    pub rotation: i32,
+    /// where the trimmed rect starts inside the original sprite.
+    pub trim_x: i32,
+    pub trim_y: i32,
+    /// size before trimming.
+    pub source_width: i32,
+    pub source_height: i32,
+    /// 0 -> 1 inside the original sprite, the point drawn at the position.
+    pub pivot: Vec2,
+    /// animation frame number (libGDX), -1 when it has none.
+    pub index: i32,
+}
+
+impl SpriteRegion {
+    /// untrimmed, unrotated region pivoting around its center.
+    pub fn init(name: &str, x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            x,
+            y,
+            width,
+            height,
+            rotation: 0,
+            trim_x: 0,
+            trim_y: 0,
+            source_width: width,
+            source_height: height,
+            pivot: vec2(0.5, 0.5),
+            index: -1,
+        }
+    }
+
+    /// two triangles (x, y, s, t) in the `SpriteRenderer` vertex order, positions relative to the pivot.
+    pub fn quad(&self, texture_width: i32, texture_height: i32) -> [f32; 24] {
+        let left = self.trim_x as f32 - self.pivot.x * self.source_width as f32;
+        let top = self.trim_y as f32 - self.pivot.y * self.source_height as f32;
+        let (x0, y0, x1, y1) = (
+            left,
+            top,
+            left + self.width as f32,
+            top + self.height as f32,
+        );
+
+        let (area_w, area_h) = match self.rotation {
+            90 | 270 => (self.height, self.width),
+            _ => (self.width, self.height),
+        };
+        let s0 = self.x as f32 / texture_width as f32;
+        let t0 = self.y as f32 / texture_height as f32;
+        let s1 = (self.x + area_w) as f32 / texture_width as f32;
+        let t1 = (self.y + area_h) as f32 / texture_height as f32;
+
+        // texture coordinates of the upright top left, top right, bottom right and bottom left.
+        let [tl, tr, br, bl] = match self.rotation {
+            90 => [(s1, t0), (s1, t1), (s0, t1), (s0, t0)],
+            270 => [(s0, t1), (s0, t0), (s1, t0), (s1, t1)],
+            _ => [(s0, t0), (s1, t0), (s1, t1), (s0, t1)],
+        };
+
+        [
+            x1, y1, br.0, br.1, // 0
+            x1, y0, tr.0, tr.1, // 1
+            x0, y1, bl.0, bl.1, // 2
+            x1, y0, tr.0, tr.1, // 3
+            x0, y0, tl.0, tl.1, // 4
+            x0, y1, bl.0, bl.1, // 5
+        ]
+    }
+}
+
 pub struct SpriteSheet {
     pub texture: Texture,
     pub texture_width: i32,
     pub texture_height: i32,
+    /// 0 for sheets made of regions.
     pub cell_size: i32,
+    pub regions: Vec<SpriteRegion>,
+    lookup: std::collections::HashMap<Hash, usize>,
 }
 
 impl SpriteSheet {
@@ -317,8 +1516,132 @@ impl SpriteSheet {
             texture_width,
             texture_height,
             cell_size,
+            regions: Vec::new(),
+            lookup: std::collections::HashMap::new(),
         })
     }
+
+    pub fn init_with_regions<'a>(
+        pixels: &[u8],
+        width: i32,
+        height: i32,
+        channels: i32,
+        regions: Vec<SpriteRegion>,
+    ) -> Result<Self, &'a str> {
+        let mut sheet = Self::init(pixels, width, height, channels, 0)?;
+        for region in regions {
+            sheet.add_region(region);
+        }
+        Ok(sheet)
+    }
+
+    /// sheet for one page of an atlas file, `image` is the decoded page image.
+    pub fn init_atlas_page<'a>(
+        page: &parsers::SpriteAtlasPage,
+        image: &parsers::Image,
+    ) -> Result<Self, &'a str> {
+        Self::init_with_regions(
+            &image.raw,
+            image.width,
+            image.height,
+            image.channels,
+            page.regions.clone(),
+        )
+    }
+
+    /// the first region added with a name wins the lookup.
+    pub fn add_region(&mut self, region: SpriteRegion) {
+        self.lookup
+            .entry(one_at_a_time_hash(&region.name))
+            .or_insert(self.regions.len());
+        self.regions.push(region);
+    }
+
+    pub fn region(&self, name: &str) -> Option<&SpriteRegion> {
+        self.region_by_hash(one_at_a_time_hash(name))
+    }
+
+    pub fn region_by_hash(&self, hash: Hash) -> Option<&SpriteRegion> {
+        self.lookup.get(&hash).map(|&i| &self.regions[i])
+    }
+
+    /// every region called `name` sorted by `index`, the frames of a libGDX animation.
+    pub fn regions_named(&self, name: &str) -> Vec<&SpriteRegion> {
+        let mut regions: Vec<&SpriteRegion> =
+            self.regions.iter().filter(|r| r.name == name).collect();
+        regions.sort_by_key(|r| r.index);
+        regions
+    }
+}
+
+/// a named sequence of sheet cells (column, row) with a duration each.
+#[derive(Debug, Clone)]
+pub struct SpriteAnimation {
+    pub name: String,
+    pub cells: Vec<(i32, i32)>,
+    /// milliseconds per cell.
+    pub durations: Vec<u32>,
+    pub looping: bool,
+}
+
+impl SpriteAnimation {
+    /// the cell to draw `elapsed` milliseconds after the animation started.
+    pub fn cell_at(&self, elapsed: u64) -> (i32, i32) {
+        self.cells[parsers::frame_at(&self.durations, elapsed, self.looping)]
+    }
+}
+
+impl SpriteSheet {
+    /// the frames of an aseprite file in a grid of square cells (the bigger side of the sprite),
+    /// one animation per tag or a single looping one named "" without tags.
+    pub fn init_aseprite<'a>(buffer: &[u8]) -> Result<(Self, Vec<SpriteAnimation>), &'a str> {
+        let ase = parsers::Aseprite::init(buffer)?;
+        let (pixels, width, height, cell_size, columns) = aseprite_grid(&ase);
+        let sheet = Self::init(&pixels, width, height, 4, cell_size)?;
+        Ok((sheet, aseprite_animations(&ase, columns)))
+    }
+}
+
+fn aseprite_grid(ase: &parsers::Aseprite) -> (Vec<u8>, i32, i32, i32, i32) {
+    let cell_size = ase.width.max(ase.height);
+    let count = ase.frames.len() as i32;
+    let columns = (count as f32).sqrt().ceil() as i32;
+    let rows = (count + columns - 1) / columns;
+
+    let mut grid = parsers::Image::init_blank(columns * cell_size, rows * cell_size, 4);
+    for (i, frame) in ase.frames.iter().enumerate() {
+        let i = i as i32;
+        grid.blit(frame, (i % columns) * cell_size, (i / columns) * cell_size);
+    }
+    (grid.raw, grid.width, grid.height, cell_size, columns)
+}
+
+fn aseprite_animations(ase: &parsers::Aseprite, columns: i32) -> Vec<SpriteAnimation> {
+    let animation = |name: &str, frames: Vec<usize>, looping: bool| SpriteAnimation {
+        name: name.to_string(),
+        cells: frames
+            .iter()
+            .map(|&f| (f as i32 % columns, f as i32 / columns))
+            .collect(),
+        durations: frames.iter().map(|&f| ase.durations[f]).collect(),
+        looping,
+    };
+
+    if ase.tags.is_empty() {
+        return vec![animation("", (0..ase.frames.len()).collect(), true)];
+    }
+
+    ase.tags
+        .iter()
+        .map(|tag| {
+            let frames = tag.frames();
+            if tag.repeat == 0 {
+                animation(&tag.name, frames, true)
+            } else {
+                animation(&tag.name, frames.repeat(tag.repeat as usize), false)
+            }
+        })
+        .collect()
 }
 
 pub struct SpriteRenderer {
@@ -478,14 +1801,75 @@ main()
             "asking for non existing sprite row in sprite sheet"
         );
 
-        let quads = [
-            x1, y1, s1, t1, // 0
-            x1, y0, s1, t0, // 1
-            x0, y1, s0, t1, // 2
-            x1, y0, s1, t0, // 3
-            x0, y0, s0, t0, // 4
-            x0, y1, s0, t1, // 5
-        ];
+        let quads = [
+            x1, y1, s1, t1, // 0
+            x1, y0, s1, t0, // 1
+            x0, y1, s0, t1, // 2
+            x1, y0, s1, t0, // 3
+            x0, y0, s0, t0, // 4
+            x0, y1, s0, t1, // 5
+        ];
+
+        self.vbo.update(quads.as_slice());
+        self.vao.draw_triangles();
+
+        unsafe {
+            glBindVertexArray(0);
+            glDisable(GL_BLEND);
+        }
+    }
+
+    /// draws `region` with its pivot at `pos`, rotating around the pivot.
+    pub fn draw_region(
+        &self,
+        sheet: &SpriteSheet,
+        region: &SpriteRegion,
+        pos: Vec2,
+        rotation: f32,
+        taint: u32,
+    ) {
+        use gles_wrapper::gl::*;
+
+        let (window_width, window_height) = sdl_wrapper::window_size();
+
+        let u_space_matrix = self.shader.uniform("u_space_matrix", UnifomType::Matrix4x4);
+        let u_model = self.shader.uniform("u_model", UnifomType::Matrix4x4);
+        let u_tex0 = self.shader.uniform("u_tex0", UnifomType::I32);
+        let u_use_texture = self.shader.uniform("u_use_texture", UnifomType::I32);
+        let u_taint = self.shader.uniform("u_taint", UnifomType::Vec4);
+
+        let space_matrix = Mat4::ortho(
+            0.0,
+            window_width as f32,
+            window_height as f32,
+            0.0,
+            -1.0,
+            1.0,
+        );
+
+        let rot = Mat4::rotation_deg(rotation, 0.0, 0.0);
+        let transform = Mat4::identity().translate(Vec3 {
+            x: pos.x,
+            y: pos.y,
+            z: 0.0,
+        });
+        let model = transform * rot;
+
+        self.shader.use_();
+        sheet.texture.bind(0);
+
+        u_space_matrix.update_value(&space_matrix).unwrap();
+        u_model.update_value(&model).unwrap();
+        u_tex0.update_value(0).unwrap();
+        u_use_texture.update_value(1).unwrap();
+        u_taint.update_value(rgba(taint)).unwrap();
+
+        unsafe {
+            glEnable(GL_BLEND);
+            glBlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
+        }
+
+        let quads = region.quad(sheet.texture_width, sheet.texture_height);
 
         self.vbo.update(quads.as_slice());
         self.vao.draw_triangles();
@@ -558,20 +1942,193 @@ main()
     }
 }
 
+pub mod text {
+    //! text layout: bidi reordering and arabic shaping before the glyph lookup.
+
+    use crate::*;
+
+    pub mod arabic;
+    pub mod bidi;
+
+    /// shapes and reorders one line of logical text, the result is in visual order (left to right).
+    pub fn visual_line(line: &str) -> Vec<char> {
+        let chars: Vec<char> = line.chars().collect();
+        let (_, levels) = bidi::resolve_levels(&chars, None);
+        let shaped = arabic::shape(&chars);
+
+        bidi::reorder(&levels)
+            .into_iter()
+            .filter_map(|i| {
+                shaped[i].map(|c| {
+                    if levels[i] & 1 == 1 {
+                        bidi::mirror(c)
+                    } else {
+                        c
+                    }
+                })
+            })
+            .collect()
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct PositionedGlyph {
+        /// index into `FontFamily::faces`.
+        pub face: usize,
+        /// index into the face `packed_chars`.
+        pub glyph: usize,
+        /// pen position the glyph was placed at.
+        pub x: f32,
+        pub y: f32,
+        pub quad: c_utils::sys::stbtt_aligned_quad,
+    }
+
+    /// lays `txt` out from `pos` (baseline of the first line), '\n' starts a new line.
+    /// every character comes from the first face that has it, the ones no face has are skipped.
+    pub fn layout(family: &parsers::FontFamily, txt: &str, pos: Vec2) -> Vec<PositionedGlyph> {
+        let mut glyphs = Vec::with_capacity(txt.len());
+
+        let mut ypos = pos.y;
+        for line in txt.split('\n') {
+            let mut xpos = pos.x;
+            for c in visual_line(line) {
+                if let Some((face, glyph)) = family.glyph(c) {
+                    let (quad, next_x, _) = family.faces[face].get_glyph_quad(glyph, xpos, ypos);
+                    glyphs.push(PositionedGlyph {
+                        face,
+                        glyph,
+                        x: xpos,
+                        y: ypos,
+                        quad,
+                    });
+                    xpos = next_x;
+                }
+            }
+            ypos += family.primary().font_size;
+        }
+
+        glyphs
+    }
+
+    /// vertices of one face inside a text mesh.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FaceRange {
+        pub face: usize,
+        /// first vertex.
+        pub first: i32,
+        pub count: i32,
+    }
+
+    /// builds the triangles (pos uv, 6 vertices per glyph) of `glyphs` grouped by face,
+    /// so a whole string takes one draw call per face it uses.
+    pub fn mesh_vertices(glyphs: &[PositionedGlyph]) -> (Vec<f32>, Vec<FaceRange>) {
+        let mut sorted: Vec<&PositionedGlyph> = glyphs.iter().collect();
+        sorted.sort_by_key(|g| g.face);
+
+        let mut vertices = Vec::with_capacity(glyphs.len() * 6 * 4);
+        let mut ranges: Vec<FaceRange> = Vec::new();
+        for g in sorted {
+            let q = g.quad;
+            #[rustfmt::skip]
+            vertices.extend_from_slice(&[
+                q.x1, q.y1, q.s1, q.t1, // 0
+                q.x1, q.y0, q.s1, q.t0, // 1
+                q.x0, q.y1, q.s0, q.t1, // 2
+                q.x1, q.y0, q.s1, q.t0, // 3
+                q.x0, q.y0, q.s0, q.t0, // 4
+                q.x0, q.y1, q.s0, q.t1, // 5
+            ]);
+
+            match ranges.last_mut() {
+                Some(r) if r.face == g.face => r.count += 6,
+                _ => ranges.push(FaceRange {
+                    face: g.face,
+                    first: (vertices.len() / 4) as i32 - 6,
+                    count: 6,
+                }),
+            }
+        }
+
+        (vertices, ranges)
+    }
+}
+
+/// laid out text kept on the gpu, only rebuilt when the text or the position changes.
+pub struct TextMesh {
+    txt: String,
+    pos: Vec2,
+    ranges: Vec<text::FaceRange>,
+    vao: Vao,
+    vbo: Vbo,
+}
+
+impl TextMesh {
+    pub fn init() -> Self {
+        let mut vao = Vao::init();
+        let vbo = Vbo::init_f32_2_2();
+        vao.bind_vbo(&vbo);
+
+        Self {
+            txt: String::new(),
+            pos: vec2(0.0, 0.0),
+            ranges: Vec::new(),
+            vao,
+            vbo,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.txt
+    }
+
+    /// lays the text out again and uploads it, does nothing if neither `txt` nor `pos` changed.
+    pub fn set_text(&mut self, renderer: &TextRenderer, txt: &str, pos: Vec2) {
+        if self.txt == txt && self.pos.x == pos.x && self.pos.y == pos.y {
+            return;
+        }
+
+        self.rebuild(renderer, txt, pos);
+    }
+
+    fn rebuild(&mut self, renderer: &TextRenderer, txt: &str, pos: Vec2) {
+        let origin = vec2(pos.x, pos.y + (renderer.font_size * 0.5));
+        let glyphs = text::layout(&renderer.family, txt, origin);
+        let (vertices, ranges) = text::mesh_vertices(&glyphs);
+
+        self.vbo.update(vertices.as_slice());
+        self.ranges = ranges;
+        self.txt.clear();
+        self.txt.push_str(txt);
+        self.pos = pos;
+    }
+}
+
 pub struct TextRenderer {
-    font: parsers::TrueTypeFont,
+    family: parsers::FontFamily,
     shader: Shader,
-    texture: Texture,
-    font_size: i32,
+    /// one atlas per face.
+    textures: Vec<Texture>,
+    font_size: f32,
+    /// reused by `TextRenderer::draw` for text that changes every frame.
     vao: Vao,
     vbo: Vbo,
 }
 
 impl TextRenderer {
     pub fn init(ttf: parsers::TrueTypeFont) -> Self {
-        let (pixels, width, height, channels) = ttf.get_image();
-        let texture = Texture::init(pixels.as_ptr(), width, height, channels)
-            .expect("Failed to create GL Texture");
+        Self::init_family(parsers::FontFamily { faces: vec![ttf] })
+    }
+
+    /// characters missing from the first face fall back to the next faces of `family`.
+    pub fn init_family(family: parsers::FontFamily) -> Self {
+        let textures = family
+            .faces
+            .iter()
+            .map(|ttf| {
+                let (pixels, width, height, channels) = ttf.get_image();
+                Texture::init(pixels.as_ptr(), width, height, channels)
+                    .expect("Failed to create GL Texture")
+            })
+            .collect();
 
         let mut vao = Vao::init();
         let vbo = Vbo::init_f32_2_2();
@@ -627,25 +2184,47 @@ main()
         "#;
         let shader = Shader::init(shader_src).expect("Failed to compile the builtin shader");
 
-        let font_size = ttf.sys_font.font_size;
+        let font_size = family.primary().font_size;
         Self {
-            font: ttf,
+            family,
             shader,
-            texture,
+            textures,
             font_size,
             vao,
             vbo,
         }
     }
 
+    /// lays `txt` out and draws it with one draw call per face, for text that changes every frame.
+    /// static text is cheaper drawn through a `TextMesh`.
     pub fn draw(&self, txt: &str, pos: Vec2, taint: u32) {
+        let origin = vec2(pos.x, pos.y + (self.font_size * 0.5));
+        let glyphs = text::layout(&self.family, txt, origin);
+        let (vertices, ranges) = text::mesh_vertices(&glyphs);
+
+        self.vbo.update(vertices.as_slice());
+        self.draw_ranges(&self.vao, &ranges, taint);
+    }
+
+    /// a mesh holding `txt`, update it with `TextMesh::set_text`.
+    pub fn mesh(&self, txt: &str, pos: Vec2) -> TextMesh {
+        let mut mesh = TextMesh::init();
+        mesh.rebuild(self, txt, pos);
+        mesh
+    }
+
+    pub fn draw_mesh(&self, mesh: &TextMesh, taint: u32) {
+        self.draw_ranges(&mesh.vao, &mesh.ranges, taint);
+    }
+
+    fn draw_ranges(&self, vao: &Vao, ranges: &[text::FaceRange], taint: u32) {
         use gles_wrapper::gl::*;
 
-        let (window_width, window_height) = sdl_wrapper::window_size();
+        if ranges.is_empty() {
+            return;
+        }
 
-        let u_space_matrix = self.shader.uniform("u_space_matrix", UnifomType::Matrix4x4);
-        let u_tex0 = self.shader.uniform("u_tex0", UnifomType::I32);
-        let u_taint = self.shader.uniform("u_taint", UnifomType::Vec4);
+        let (window_width, window_height) = sdl_wrapper::window_size();
 
         let space_matrix = Mat4::ortho(
             0.0,
@@ -656,6 +2235,13 @@ main()
             1.0,
         );
 
+        // uniforms go to the bound program.
+        self.shader.use_();
+
+        let u_space_matrix = self.shader.uniform("u_space_matrix", UnifomType::Matrix4x4);
+        let u_tex0 = self.shader.uniform("u_tex0", UnifomType::I32);
+        let u_taint = self.shader.uniform("u_taint", UnifomType::Vec4);
+
         u_space_matrix.update_value(&space_matrix).unwrap();
         u_tex0.update_value(0).unwrap();
         u_taint.update_value(rgba(taint)).unwrap();
@@ -665,26 +2251,9 @@ main()
             glBlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
         }
 
-        self.shader.use_();
-        self.texture.bind(0);
-
-        let mut xpos = pos.x;
-        let mut ypos = pos.y + (self.font_size as f32 * 0.5);
-        let mut q;
-        for c in txt.chars() {
-            (q, xpos, ypos) = self.font.get_quad_and_next_position(c, xpos, ypos);
-
-            let quads = [
-                q.x1, q.y1, q.s1, q.t1, // 0
-                q.x1, q.y0, q.s1, q.t0, // 1
-                q.x0, q.y1, q.s0, q.t1, // 2
-                q.x1, q.y0, q.s1, q.t0, // 3
-                q.x0, q.y0, q.s0, q.t0, // 4
-                q.x0, q.y1, q.s0, q.t1, // 5
-            ];
-
-            self.vbo.update(quads.as_slice());
-            self.vao.draw_triangles();
+        for r in ranges {
+            self.textures[r.face].bind(0);
+            vao.draw_triangles_range(r.first, r.count);
         }
 
         unsafe {
@@ -701,45 +2270,514 @@ pub fn read_res<'a>(name: &str) -> Result<Vec<u8>, &'a str> {
     }
 }
 
+pub mod atlas {
+    //! packs many images into a few atlas pages so they can be drawn from one texture.
+
+    use crate::*;
+    use c_utils::sys;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone)]
+    pub struct AtlasConfig {
+        pub page_width: i32,
+        pub page_height: i32,
+        /// empty pixels between images and around the page border.
+        pub padding: i32,
+        /// edge pixels repeated around every image, stops neighbours bleeding in with linear filtering.
+        pub extrude: i32,
+        /// `AtlasBuilder::build` fails instead of adding more pages.
+        pub max_pages: usize,
+    }
+
+    impl Default for AtlasConfig {
+        fn default() -> Self {
+            Self {
+                page_width: 2048,
+                page_height: 2048,
+                padding: 1,
+                extrude: 1,
+                max_pages: 8,
+            }
+        }
+    }
+
+    /// where an image ended up, the rect does not include the extruded pixels.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct AtlasRegion {
+        pub page: usize,
+        pub x: i32,
+        pub y: i32,
+        pub width: i32,
+        pub height: i32,
+        /// top left uv.
+        pub u0: f32,
+        pub v0: f32,
+        /// bottom right uv.
+        pub u1: f32,
+        pub v1: f32,
+    }
+
+    #[derive(Debug)]
+    pub struct Atlas {
+        /// rgba pages.
+        pub pages: Vec<parsers::Image>,
+        pub regions: HashMap<String, AtlasRegion>,
+    }
+
+    impl Atlas {
+        pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+            self.regions.get(name)
+        }
+
+        /// uploads every page, the index of a texture is `AtlasRegion::page`.
+        pub fn to_textures<'a>(&self) -> Result<Vec<Texture>, &'a str> {
+            self.pages
+                .iter()
+                .map(|page| Texture::init(page.raw.as_ptr(), page.width, page.height, 4))
+                .collect()
+        }
+    }
+
+    pub struct AtlasBuilder {
+        config: AtlasConfig,
+        images: Vec<(String, parsers::Image)>,
+    }
+
+    impl AtlasBuilder {
+        pub fn init(config: AtlasConfig) -> Self {
+            Self {
+                config,
+                images: Vec::new(),
+            }
+        }
+
+        /// `image` is converted to rgba, the name has to be unique.
+        pub fn add<'a>(&mut self, name: &str, image: &parsers::Image) -> Result<(), &'a str> {
+            if self.images.iter().any(|(n, _)| n == name) {
+                return Err("Atlas image name already added");
+            }
+
+            if image.width <= 0 || image.height <= 0 {
+                return Err("Atlas image is empty");
+            }
+
+            let border = self.config.padding * 2 + self.config.extrude * 2;
+            if image.width + border > self.config.page_width
+                || image.height + border > self.config.page_height
+            {
+                return Err("Atlas image bigger than a page");
+            }
+
+            self.images
+                .push((name.to_string(), image.convert_channels(4)?));
+            Ok(())
+        }
+
+        pub fn build<'a>(self) -> Result<Atlas, &'a str> {
+            let config = &self.config;
+            let border = config.extrude * 2 + config.padding;
+
+            let mut pages = Vec::new();
+            let mut regions = HashMap::new();
+            let mut pending: Vec<usize> = (0..self.images.len()).collect();
+
+            while !pending.is_empty() {
+                if pages.len() == config.max_pages {
+                    return Err("Atlas images do not fit in max_pages");
+                }
+
+                // the padding of the top and left border comes from the offset,
+                // every rect carries the padding on its right and bottom.
+                let mut rects: Vec<sys::PackRect> = pending
+                    .iter()
+                    .map(|&i| sys::PackRect {
+                        w: self.images[i].1.width + border,
+                        h: self.images[i].1.height + border,
+                        x: 0,
+                        y: 0,
+                        was_packed: 0,
+                    })
+                    .collect();
+
+                unsafe {
+                    sys::utl_rect_pack(
+                        config.page_width - config.padding,
+                        config.page_height - config.padding,
+                        rects.as_mut_ptr(),
+                        rects.len() as i32,
+                    )
+                };
+
+                let page_index = pages.len();
+                let mut page = parsers::Image::init_blank(config.page_width, config.page_height, 4);
+                let mut left = Vec::new();
+
+                for (&i, rect) in pending.iter().zip(rects.iter()) {
+                    if rect.was_packed == 0 {
+                        left.push(i);
+                        continue;
+                    }
+
+                    let (name, image) = &self.images[i];
+                    let x = rect.x + config.padding + config.extrude;
+                    let y = rect.y + config.padding + config.extrude;
+                    copy_extruded(&mut page, image, x, y, config.extrude);
+
+                    regions.insert(
+                        name.clone(),
+                        AtlasRegion {
+                            page: page_index,
+                            x,
+                            y,
+                            width: image.width,
+                            height: image.height,
+                            u0: x as f32 / config.page_width as f32,
+                            v0: y as f32 / config.page_height as f32,
+                            u1: (x + image.width) as f32 / config.page_width as f32,
+                            v1: (y + image.height) as f32 / config.page_height as f32,
+                        },
+                    );
+                }
+
+                if left.len() == pending.len() {
+                    return Err("Atlas failed to pack any image in an empty page");
+                }
+
+                pages.push(page);
+                pending = left;
+            }
+
+            Ok(Atlas { pages, regions })
+        }
+    }
+
+    /// copies `image` to (x, y) and repeats its border `extrude` times around it.
+    fn copy_extruded(
+        page: &mut parsers::Image,
+        image: &parsers::Image,
+        x: i32,
+        y: i32,
+        extrude: i32,
+    ) {
+        for dy in -extrude..image.height + extrude {
+            let sy = dy.clamp(0, image.height - 1);
+            for dx in -extrude..image.width + extrude {
+                let sx = dx.clamp(0, image.width - 1);
+                page.pixel_mut(x + dx, y + dy)
+                    .copy_from_slice(image.pixel(sx, sy));
+            }
+        }
+    }
+}
+
+pub mod assets {
+    //! images found on disk are probed first, so sizes are known before anything is decoded.
+
+    use crate::*;
+    use std::io::Read;
+
+    /// bytes read from the start of a file to probe it, enough for the header of every format but
+    /// jpgs with big metadata, those are read whole.
+    const PROBE_PREFIX_SIZE: u64 = 64 * 1024;
+
+    #[derive(Debug, Clone)]
+    pub struct ImageEntry {
+        /// path under `res/`.
+        pub name: String,
+        pub info: parsers::ImageInfo,
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct ImageCatalog {
+        pub entries: Vec<ImageEntry>,
+    }
+
+    impl ImageCatalog {
+        /// probes every image in `names` (paths under `res/`), fails on the first unreadable one.
+        pub fn init<'a>(names: &[&str]) -> Result<Self, &'a str> {
+            let mut catalog = Self::default();
+            for name in names {
+                let prefix = read_res_prefix(name, PROBE_PREFIX_SIZE)?;
+                let info = match parsers::Image::probe(&prefix) {
+                    Ok(info) => info,
+                    Err(_) => parsers::Image::probe(&read_res(name)?)?,
+                };
+                catalog.entries.push(ImageEntry {
+                    name: name.to_string(),
+                    info,
+                });
+            }
+
+            Ok(catalog)
+        }
+
+        /// adds an image already in memory, `buffer` can be just the start of the file.
+        pub fn add<'a>(&mut self, name: &str, buffer: &[u8]) -> Result<&ImageEntry, &'a str> {
+            let info = parsers::Image::probe(buffer)?;
+            self.entries.push(ImageEntry {
+                name: name.to_string(),
+                info,
+            });
+
+            Ok(self.entries.last().unwrap())
+        }
+
+        pub fn find(&self, name: &str) -> Option<&ImageEntry> {
+            self.entries.iter().find(|e| e.name == name)
+        }
+
+        /// pixels of all the images with `padding` around each one.
+        pub fn total_area(&self, padding: i32) -> i64 {
+            self.entries
+                .iter()
+                .map(|e| (e.info.width + padding * 2) as i64 * (e.info.height + padding * 2) as i64)
+                .sum()
+        }
+
+        /// smallest power of two square that could hold every image, the packing decides if it is
+        /// really enough. None if a single image is bigger than `max_size`.
+        pub fn atlas_size_estimate(&self, padding: i32, max_size: i32) -> Option<i32> {
+            let largest = self
+                .entries
+                .iter()
+                .map(|e| e.info.width.max(e.info.height) + padding * 2)
+                .max()
+                .unwrap_or(1);
+
+            let area = self.total_area(padding);
+            let mut size = 1;
+            while size < largest || (size as i64 * size as i64) < area {
+                if size > max_size / 2 {
+                    return None;
+                }
+                size *= 2;
+            }
+
+            (size <= max_size).then_some(size)
+        }
+
+        /// decodes the image of `entry`.
+        pub fn load<'a>(&self, entry: &ImageEntry) -> Result<parsers::Image, &'a str> {
+            parsers::Image::init(&read_res(&entry.name)?)
+        }
+    }
+
+    fn read_res_prefix<'a>(name: &str, size: u64) -> Result<Vec<u8>, &'a str> {
+        let file = std::fs::File::open(format!("res/{}", name).as_str())
+            .map_err(|_| "Failed to read_res")?;
+
+        let mut prefix = Vec::new();
+        file.take(size)
+            .read_to_end(&mut prefix)
+            .map_err(|_| "Failed to read_res")?;
+
+        Ok(prefix)
+    }
+}
+
 pub mod audio {
-    use crate::sdl_wrapper::*;
+    use std::sync::Arc;
+    use vector_math::*;
+
+    mod bus;
+    mod effects;
+    mod events;
+    mod mixer;
+    mod music;
+    mod offline;
+    mod output;
+    mod sound;
+    mod spatial;
+    mod tracker;
+    pub use bus::{BusId, DuckRule};
+    pub use effects::{
+        Biquad, Bitcrusher, Compressor, Delay, Effect, EffectChain, FilterKind, Reverb, MAX_DELAY,
+    };
+    pub use events::{SoundBank, SoundEvent, StealPolicy};
+    use mixer::with_mixer;
+    pub use mixer::{AudioStream, Mixer, SoundHandle, VOICES};
+    pub use music::{Music, MusicDecoder, MusicPlayer, MusicTrack};
+    pub use offline::OfflineRender;
+    pub use output::{AudioConfig, BufferOutput, NullOutput, Output, SdlOutput};
+    pub use sound::{Sound, WavEncoding, WavWriter};
+    pub use spatial::{Attenuation, Listener, Spatial};
+    pub use tracker::{Module, ModuleFormat, ModulePlayer};
+
+    /// the format of the engine mixer, the device converts from it.
+    pub const DEFAULT_FREQUENCY: u32 = 48000;
+    pub const DEFAULT_CHANNELS: u16 = 2;
+
+    /// (frequency, channels) sounds have to be converted to before they are mixed.
+    pub fn device_format() -> (u32, u16) {
+        with_mixer(|m| (m.sample_rate(), m.channels()))
+    }
+
+    /// starts the engine mixer on the device and in the format of `config`, before any sound
+    /// is loaded. without it the mixer starts on first use with `AudioConfig::default()`.
+    /// no audio device is not an error, the mix then goes to a `NullOutput`.
+    pub fn init<'a>(config: AudioConfig) -> Result<(), &'a str> {
+        match mixer::start(config) {
+            true => Ok(()),
+            false => Err("Failed to init audio, the mixer is running, shutdown first"),
+        }
+    }
+
+    /// stops every sound, closes the device and the audio subsystem. sounds loaded before are
+    /// in the old format, `Music` has to be created again.
+    pub fn shutdown() {
+        mixer::stop();
+        sdl_wrapper::audio_quit();
+    }
+
+    /// names of the output devices for `AudioConfig::device` and `select_device`.
+    pub fn devices() -> Vec<String> {
+        sdl_wrapper::audio_devices()
+    }
 
+    /// plays on `device` from now on, `None` for the default. everything keeps playing, an
+    /// `OfflineRender` keeps the mix until it ends.
+    pub fn select_device(device: Option<&str>) {
+        mixer::select_device(device);
+    }
+
+    /// the config the engine mixer runs with, the format is fixed until `shutdown`.
+    pub fn config() -> AudioConfig {
+        mixer::config()
+    }
+
+    #[derive(Clone)]
     pub struct Audio {
-        chunk: *mut sys::Chunk,
+        /// interleaved in the mixer format, shared with the voices playing it.
+        samples: Arc<[f32]>,
     }
 
     impl Audio {
-        pub fn init(raw_data: &[u8]) -> Self {
-            let chunk = unsafe { sys::plt_chunk_load(raw_data.as_ptr(), raw_data.len()) };
-            Self { chunk }
+        /// takes in a wav or ogg vorbis buffer, decoded and converted to the device format.
+        pub fn init<'a>(raw_data: &[u8]) -> Result<Self, &'a str> {
+            Ok(Self::init_sound(&Sound::init(raw_data)?))
         }
 
-        /// - If the specified `channel` is -1, play on the first free channel (and return -1 without playing anything new if no free channel was available).
-        /// - If `loops` is greater than zero, loop the sound that many times. If `loops` is -1, loop "infinitely" (~65000 times).
-        /// - `returns`  which channel was used to play the sound, or -1 if sound could not be played.
-        pub fn play(&self, channel: i32, loops: i32) -> i32 {
-            unsafe { sys::plt_channel_play(channel, self.chunk, loops) }
+        pub fn init_sound(sound: &Sound) -> Self {
+            let (frequency, channels) = device_format();
+            Self {
+                samples: sound.to_device(frequency, channels).samples.into(),
+            }
         }
-    }
 
-    impl Drop for Audio {
-        fn drop(&mut self) {
-            unsafe {
-                sys::plt_chunk_free(self.chunk);
-            }
+        /// - If the specified `channel` is -1, play on the first free channel (and return `SoundHandle::NONE` without playing anything new if no free channel was available).
+        /// - If `loops` is greater than zero, loop the sound that many times. If `loops` is -1, loop forever.
+        /// - `returns` a handle to change or stop the sound while it plays.
+        pub fn play(&self, channel: i32, loops: i32) -> SoundHandle {
+            with_mixer(|m| m.play(self.samples.clone(), channel, loops))
+        }
+
+        /// `play` on `bus` instead of sfx.
+        pub fn play_on(&self, bus: BusId, channel: i32, loops: i32) -> SoundHandle {
+            with_mixer(|m| m.play_on(bus, self.samples.clone(), channel, loops))
+        }
+
+        /// `play` on the first free channel, panned and attenuated from the listener.
+        pub fn play_at(&self, position: Vec3, spatial: Spatial, loops: i32) -> SoundHandle {
+            with_mixer(|m| m.play_at(BusId::SFX, self.samples.clone(), position, spatial, loops))
         }
     }
 
+    /// every sound played with `Audio::play`, `Music` has its own volume and pause.
     pub struct Channels();
     impl Channels {
         pub fn is_playing(channel: i32) -> bool {
-            let r = unsafe { sys::plt_channel_is_playing(channel) };
-            r != 0
+            with_mixer(|m| m.is_channel_playing(channel))
         }
 
         pub fn any_playing() -> bool {
-            let r = unsafe { sys::plt_channel_is_playing(-1) };
-            r != 0
+            with_mixer(|m| m.is_channel_playing(-1))
+        }
+
+        /// holds every sound, for the pause menu.
+        pub fn pause_all() {
+            with_mixer(|m| m.pause_all());
+        }
+
+        pub fn resume_all() {
+            with_mixer(|m| m.resume_all());
+        }
+
+        pub fn stop_all() {
+            with_mixer(|m| m.stop_all());
+        }
+
+        pub fn set_master_volume(volume: f32) {
+            with_mixer(|m| m.set_master_volume(volume));
+        }
+
+        pub fn master_volume() -> f32 {
+            with_mixer(|m| m.master_volume())
+        }
+
+        /// where positional sounds are heard from, follow the view every frame.
+        pub fn set_listener(listener: Listener) {
+            with_mixer(|m| m.set_listener(listener));
+        }
+
+        pub fn listener() -> Listener {
+            with_mixer(|m| m.listener())
+        }
+
+        /// where the mix goes, e.g. a `NullOutput` on servers, returns the output it replaces.
+        /// the default device is opened on first use and a `NullOutput` takes its place if
+        /// there is none.
+        pub fn set_output(output: Box<dyn Output>) -> Box<dyn Output> {
+            mixer::set_output(output)
+        }
+
+        /// back to the configured device, a `NullOutput` if there is none. during an
+        /// `OfflineRender` the device takes over when the render ends.
+        pub fn open_device() {
+            mixer::open_device();
+        }
+
+        /// advances the mix by `frames` into an output that is not a device.
+        pub fn render(frames: usize) {
+            with_mixer(|m| m.render(frames));
+        }
+    }
+
+    /// the buses of the mixer: master with music, sfx, voice and ui under it, and any added.
+    pub struct Buses();
+    impl Buses {
+        pub fn add(name: &str, parent: BusId) -> BusId {
+            with_mixer(|m| m.add_bus(name, parent))
+        }
+
+        pub fn find(name: &str) -> Option<BusId> {
+            with_mixer(|m| m.find_bus(name))
+        }
+
+        pub fn set_volume(bus: BusId, volume: f32) {
+            with_mixer(|m| m.set_bus_volume(bus, volume));
+        }
+
+        pub fn volume(bus: BusId) -> f32 {
+            with_mixer(|m| m.bus_volume(bus))
+        }
+
+        /// e.g. music down to 0.3 while the voice bus plays.
+        pub fn add_ducking(rule: DuckRule) {
+            with_mixer(|m| m.add_ducking(rule));
+        }
+
+        pub fn clear_ducking() {
+            with_mixer(|m| m.clear_ducking());
+        }
+
+        /// runs `f` on the effects of `bus`, `None` for unknown buses. `f` runs under the
+        /// mixer lock and holds up the device, keep it short and do not call audio functions
+        /// from it.
+        pub fn effects<T>(bus: BusId, f: impl FnOnce(&mut EffectChain) -> T) -> Option<T> {
+            with_mixer(|m| m.bus_effects(bus).map(f))
         }
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,713 @@
+//! Aseprite (.ase / .aseprite) files, frames flattened to rgba with the layers blend modes,
+//! plus the tags, layers and slices.
+//!
+//! tilemap layers are not supported, their cels are skipped.
+
+use super::{sys, ByteReader, Image};
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_OLD_PALETTE_64: u16 = 0x0011;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+const CHUNK_SLICE: u16 = 0x2022;
+
+const LAYER_VISIBLE: u16 = 1;
+const LAYER_BACKGROUND: u16 = 8;
+const LAYER_REFERENCE: u16 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+    Addition,
+    Subtract,
+    Divide,
+}
+
+impl BlendMode {
+    fn from_u16(mode: u16) -> Self {
+        use BlendMode::*;
+        [
+            Normal, Multiply, Screen, Overlay, Darken, Lighten, ColorDodge, ColorBurn, HardLight,
+            SoftLight, Difference, Exclusion, Hue, Saturation, Color, Luminosity, Addition,
+            Subtract, Divide,
+        ]
+        .get(mode as usize)
+        .copied()
+        .unwrap_or(Normal)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationDirection {
+    Forward,
+    Reverse,
+    PingPong,
+    PingPongReverse,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsepriteLayer {
+    pub name: String,
+    pub visible: bool,
+    pub is_group: bool,
+    /// nesting depth, a layer belongs to the closest group above it with a lower level.
+    pub child_level: u16,
+    pub blend_mode: BlendMode,
+    pub opacity: u8,
+    flags: u16,
+}
+
+/// one layer of one frame, linked cels are already resolved.
+#[derive(Debug, Clone)]
+pub struct AsepriteCel {
+    pub layer: usize,
+    pub frame: usize,
+    pub x: i32,
+    pub y: i32,
+    pub opacity: u8,
+    pub image: Image,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsepriteTag {
+    pub name: String,
+    pub from: usize,
+    /// inclusive.
+    pub to: usize,
+    pub direction: AnimationDirection,
+    /// times to play the tag, 0 for forever.
+    pub repeat: u16,
+}
+
+impl AsepriteTag {
+    /// frame indices of one play through, ping pong does not repeat the ends.
+    pub fn frames(&self) -> Vec<usize> {
+        let forward: Vec<usize> = (self.from..=self.to).collect();
+        let backward: Vec<usize> = forward.iter().rev().copied().collect();
+        let inner = |v: &[usize]| v[1..v.len().saturating_sub(1).max(1)].to_vec();
+
+        match self.direction {
+            AnimationDirection::Forward => forward,
+            AnimationDirection::Reverse => backward,
+            AnimationDirection::PingPong => [forward.clone(), inner(&backward)].concat(),
+            AnimationDirection::PingPongReverse => [backward.clone(), inner(&forward)].concat(),
+        }
+    }
+}
+
+/// a slice from `frame` on, until the next key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SliceKey {
+    pub frame: usize,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// the center part of a 9-slice (x, y, width, height), relative to the slice.
+    pub center: Option<[i32; 4]>,
+    /// relative to the slice.
+    pub pivot: Option<[i32; 2]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsepriteSlice {
+    pub name: String,
+    pub keys: Vec<SliceKey>,
+}
+
+impl AsepriteSlice {
+    /// the key in effect at `frame`.
+    pub fn key(&self, frame: usize) -> Option<&SliceKey> {
+        self.keys.iter().rev().find(|k| k.frame <= frame)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Aseprite {
+    pub width: i32,
+    pub height: i32,
+    /// every frame flattened to rgba, the hidden and reference layers are left out.
+    pub frames: Vec<Image>,
+    /// milliseconds each frame stays on screen.
+    pub durations: Vec<u32>,
+    pub layers: Vec<AsepriteLayer>,
+    pub cels: Vec<AsepriteCel>,
+    pub tags: Vec<AsepriteTag>,
+    pub slices: Vec<AsepriteSlice>,
+}
+
+fn string<'a>(reader: &mut ByteReader) -> Result<String, &'a str> {
+    let len = reader.u16_le()? as usize;
+    Ok(String::from_utf8_lossy(reader.bytes(len)?).into_owned())
+}
+
+fn inflate<'a>(compressed: &[u8], size: usize) -> Result<Vec<u8>, &'a str> {
+    let mut out_size: i32 = 0;
+    let raw_ptr = unsafe {
+        sys::utl_zlib_decode(
+            compressed.as_ptr(),
+            compressed.len() as i32,
+            size as i32,
+            &mut out_size,
+        )
+    };
+    if raw_ptr.is_null() {
+        return Err("Failed to parse aseprite, bad compressed cel");
+    }
+
+    let raw = unsafe { std::slice::from_raw_parts(raw_ptr, out_size as usize) }.to_vec();
+    unsafe { sys::utl_image_free(raw_ptr) };
+
+    if raw.len() < size {
+        return Err("Failed to parse aseprite, compressed cel too small");
+    }
+    Ok(raw)
+}
+
+impl Aseprite {
+    pub fn init<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let mut reader = ByteReader::init(buffer);
+
+        let _file_size = reader.u32_le()?;
+        if reader.u16_le()? != HEADER_MAGIC {
+            return Err("Failed to parse aseprite, bad magic number");
+        }
+        let frames_count = reader.u16_le()? as usize;
+        let width = reader.u16_le()? as i32;
+        let height = reader.u16_le()? as i32;
+        let depth = reader.u16_le()?;
+        let header_flags = reader.u32_le()?;
+        let speed = reader.u16_le()? as u32;
+        reader.bytes(8)?;
+        let transparent_index = reader.u8()?;
+        reader.bytes(3 + 2 + 2 + 8 + 84)?;
+
+        if width == 0 || height == 0 || frames_count == 0 {
+            return Err("Failed to parse aseprite, empty sprite");
+        }
+        if !matches!(depth, 8 | 16 | 32) {
+            return Err("Failed to parse aseprite, unknown color depth");
+        }
+        let layer_opacity_valid = header_flags & 1 != 0;
+
+        let mut palette = vec![[0u8; 4]; 256];
+        let mut layers: Vec<AsepriteLayer> = Vec::new();
+        let mut cels: Vec<AsepriteCel> = Vec::new();
+        let mut tags = Vec::new();
+        let mut slices = Vec::new();
+        let mut durations = Vec::with_capacity(frames_count);
+        // raw cel pixels, converted once the whole palette is known.
+        let mut raw_cels: Vec<(usize, Vec<u8>)> = Vec::new();
+        // (cel, the cel it links to), copied after the conversion.
+        let mut links: Vec<(usize, usize)> = Vec::new();
+
+        for frame in 0..frames_count {
+            let frame_start = reader.position();
+            let frame_size = reader.u32_le()? as usize;
+            if reader.u16_le()? != FRAME_MAGIC {
+                return Err("Failed to parse aseprite, bad frame magic number");
+            }
+            let old_chunks = reader.u16_le()? as usize;
+            let duration = reader.u16_le()? as u32;
+            reader.bytes(2)?;
+            let new_chunks = reader.u32_le()? as usize;
+            let chunks = if new_chunks == 0 {
+                old_chunks
+            } else {
+                new_chunks
+            };
+
+            durations.push(match (duration, speed) {
+                (0, 0) => 100,
+                (0, speed) => speed,
+                (duration, _) => duration,
+            });
+
+            for _ in 0..chunks {
+                let chunk_start = reader.position();
+                let chunk_size = reader.u32_le()? as usize;
+                let chunk_type = reader.u16_le()?;
+                if chunk_size < 6 {
+                    return Err("Failed to parse aseprite, bad chunk size");
+                }
+                let chunk_end = chunk_start + chunk_size;
+
+                match chunk_type {
+                    CHUNK_OLD_PALETTE | CHUNK_OLD_PALETTE_64 => {
+                        let packets = reader.u16_le()?;
+                        let mut index = 0usize;
+                        for _ in 0..packets {
+                            index += reader.u8()? as usize;
+                            let count = match reader.u8()? {
+                                0 => 256,
+                                count => count as usize,
+                            };
+                            for _ in 0..count {
+                                let rgb = reader.bytes(3)?;
+                                let scale = |v: u8| {
+                                    if chunk_type == CHUNK_OLD_PALETTE_64 {
+                                        ((v as u32 * 255) / 63) as u8
+                                    } else {
+                                        v
+                                    }
+                                };
+                                if index < 256 {
+                                    palette[index] =
+                                        [scale(rgb[0]), scale(rgb[1]), scale(rgb[2]), 255];
+                                }
+                                index += 1;
+                            }
+                        }
+                    }
+
+                    CHUNK_PALETTE => {
+                        let _size = reader.u32_le()?;
+                        let first = reader.u32_le()? as usize;
+                        let last = reader.u32_le()? as usize;
+                        reader.bytes(8)?;
+                        for index in first..=last {
+                            let flags = reader.u16_le()?;
+                            let rgba = reader.bytes(4)?;
+                            if flags & 1 != 0 {
+                                string(&mut reader)?;
+                            }
+                            if let Some(entry) = palette.get_mut(index) {
+                                *entry = [rgba[0], rgba[1], rgba[2], rgba[3]];
+                            }
+                        }
+                    }
+
+                    CHUNK_LAYER => {
+                        let flags = reader.u16_le()?;
+                        let layer_type = reader.u16_le()?;
+                        let child_level = reader.u16_le()?;
+                        reader.bytes(4)?;
+                        let blend_mode = BlendMode::from_u16(reader.u16_le()?);
+                        let opacity = reader.u8()?;
+                        reader.bytes(3)?;
+                        let name = string(&mut reader)?;
+
+                        layers.push(AsepriteLayer {
+                            name,
+                            visible: flags & LAYER_VISIBLE != 0,
+                            is_group: layer_type == 1,
+                            child_level,
+                            blend_mode,
+                            opacity: if layer_opacity_valid { opacity } else { 255 },
+                            flags,
+                        });
+                    }
+
+                    CHUNK_CEL => {
+                        let layer = reader.u16_le()? as usize;
+                        let x = reader.u16_le()? as i16 as i32;
+                        let y = reader.u16_le()? as i16 as i32;
+                        let opacity = reader.u8()?;
+                        let cel_type = reader.u16_le()?;
+                        reader.bytes(7)?;
+
+                        match cel_type {
+                            0 | 2 => {
+                                let cel_width = reader.u16_le()? as usize;
+                                let cel_height = reader.u16_le()? as usize;
+                                // the zlib decoder takes an i32 size.
+                                let size = cel_width
+                                    .checked_mul(cel_height)
+                                    .and_then(|pixels| pixels.checked_mul(depth as usize / 8))
+                                    .filter(|&size| size <= i32::MAX as usize)
+                                    .ok_or("Failed to parse aseprite, cel too big")?;
+                                let data = if cel_type == 0 {
+                                    reader.bytes(size)?.to_vec()
+                                } else {
+                                    let compressed = reader
+                                        .bytes(chunk_end.saturating_sub(reader.position()))?;
+                                    inflate(compressed, size)?
+                                };
+
+                                raw_cels.push((cels.len(), data));
+                                cels.push(AsepriteCel {
+                                    layer,
+                                    frame,
+                                    x,
+                                    y,
+                                    opacity,
+                                    image: Image::init_blank(
+                                        cel_width as i32,
+                                        cel_height as i32,
+                                        4,
+                                    ),
+                                });
+                            }
+
+                            1 => {
+                                let linked = reader.u16_le()? as usize;
+                                let source = cels
+                                    .iter()
+                                    .position(|c| c.layer == layer && c.frame == linked)
+                                    .ok_or("Failed to parse aseprite, linked cel not found")?;
+                                links.push((cels.len(), source));
+                                cels.push(AsepriteCel {
+                                    layer,
+                                    frame,
+                                    x,
+                                    y,
+                                    opacity,
+                                    image: Image::init_blank(0, 0, 4),
+                                });
+                            }
+
+                            // tilemaps.
+                            _ => {}
+                        }
+                    }
+
+                    CHUNK_TAGS => {
+                        let count = reader.u16_le()?;
+                        reader.bytes(8)?;
+                        for _ in 0..count {
+                            let from = reader.u16_le()? as usize;
+                            let to = reader.u16_le()? as usize;
+                            let direction = match reader.u8()? {
+                                1 => AnimationDirection::Reverse,
+                                2 => AnimationDirection::PingPong,
+                                3 => AnimationDirection::PingPongReverse,
+                                _ => AnimationDirection::Forward,
+                            };
+                            let repeat = reader.u16_le()?;
+                            reader.bytes(6 + 4)?;
+                            let name = string(&mut reader)?;
+
+                            if from > to || to >= frames_count {
+                                return Err("Failed to parse aseprite, tag out of range");
+                            }
+                            tags.push(AsepriteTag {
+                                name,
+                                from,
+                                to,
+                                direction,
+                                repeat,
+                            });
+                        }
+                    }
+
+                    CHUNK_SLICE => {
+                        let keys_count = reader.u32_le()?;
+                        let flags = reader.u32_le()?;
+                        reader.bytes(4)?;
+                        let name = string(&mut reader)?;
+
+                        let mut keys = Vec::new();
+                        for _ in 0..keys_count {
+                            let frame = reader.u32_le()? as usize;
+                            let x = reader.i32_le()?;
+                            let y = reader.i32_le()?;
+                            let width = reader.u32_le()? as i32;
+                            let height = reader.u32_le()? as i32;
+                            let center = if flags & 1 != 0 {
+                                Some([
+                                    reader.i32_le()?,
+                                    reader.i32_le()?,
+                                    reader.u32_le()? as i32,
+                                    reader.u32_le()? as i32,
+                                ])
+                            } else {
+                                None
+                            };
+                            let pivot = if flags & 2 != 0 {
+                                Some([reader.i32_le()?, reader.i32_le()?])
+                            } else {
+                                None
+                            };
+                            keys.push(SliceKey {
+                                frame,
+                                x,
+                                y,
+                                width,
+                                height,
+                                center,
+                                pivot,
+                            });
+                        }
+                        slices.push(AsepriteSlice { name, keys });
+                    }
+
+                    _ => {}
+                }
+
+                reader.seek(chunk_end)?;
+            }
+
+            reader.seek(frame_start + frame_size)?;
+        }
+
+        for (index, data) in raw_cels {
+            let background = layers
+                .get(cels[index].layer)
+                .is_some_and(|l| l.flags & LAYER_BACKGROUND != 0);
+            let image = &mut cels[index].image;
+            for (i, px) in image.raw.chunks_exact_mut(4).enumerate() {
+                let rgba = match depth {
+                    32 => [
+                        data[i * 4],
+                        data[i * 4 + 1],
+                        data[i * 4 + 2],
+                        data[i * 4 + 3],
+                    ],
+                    16 => [data[i * 2], data[i * 2], data[i * 2], data[i * 2 + 1]],
+                    _ if data[i] == transparent_index && !background => [0, 0, 0, 0],
+                    _ => palette[data[i] as usize],
+                };
+                px.copy_from_slice(&rgba);
+            }
+        }
+
+        // sources always come first, links to links are resolved in order.
+        for (index, source) in links {
+            cels[index].image = cels[source].image.clone();
+        }
+
+        let mut ase = Self {
+            width,
+            height,
+            frames: Vec::new(),
+            durations,
+            layers,
+            cels,
+            tags,
+            slices,
+        };
+        ase.frames = (0..frames_count).map(|f| ase.flatten(f)).collect();
+        Ok(ase)
+    }
+
+    /// false when the layer or one of the groups it is in is hidden.
+    pub fn layer_visible(&self, layer: usize) -> bool {
+        let Some(l) = self.layers.get(layer) else {
+            return false;
+        };
+        if !l.visible || l.flags & LAYER_REFERENCE != 0 {
+            return false;
+        }
+
+        self.groups(layer).all(|group| group.visible)
+    }
+
+    /// the opacity of `layer` times the opacity of the groups it is in. the groups are not
+    /// blended on their own first, overlapping layers of a translucent group show through
+    /// each other.
+    pub fn layer_opacity(&self, layer: usize) -> u8 {
+        let Some(l) = self.layers.get(layer) else {
+            return 0;
+        };
+        self.groups(layer).fold(l.opacity as u32, |opacity, group| {
+            opacity * group.opacity as u32 / 255
+        }) as u8
+    }
+
+    /// the groups `layer` is in, the closest first.
+    fn groups(&self, layer: usize) -> impl Iterator<Item = &AsepriteLayer> {
+        let mut level = self.layers[layer].child_level;
+        self.layers[..layer].iter().rev().filter(move |parent| {
+            let group = level > 0 && parent.child_level < level;
+            if group {
+                level = parent.child_level;
+            }
+            group
+        })
+    }
+
+    /// blends the visible cels of `frame` from the bottom layer up.
+    pub fn flatten(&self, frame: usize) -> Image {
+        let mut out = Image::init_blank(self.width, self.height, 4);
+
+        let mut cels: Vec<&AsepriteCel> = self
+            .cels
+            .iter()
+            .filter(|c| c.frame == frame && self.layer_visible(c.layer))
+            .collect();
+        cels.sort_by_key(|c| c.layer);
+
+        for cel in cels {
+            let layer = &self.layers[cel.layer];
+            let opacity = cel.opacity as u32 * self.layer_opacity(cel.layer) as u32;
+
+            for cy in 0..cel.image.height {
+                for cx in 0..cel.image.width {
+                    let (x, y) = (cel.x + cx, cel.y + cy);
+                    if x < 0 || y < 0 || x >= self.width || y >= self.height {
+                        continue;
+                    }
+
+                    let src = cel.image.pixel(cx, cy);
+                    let alpha = (src[3] as u32 * opacity / (255 * 255)) as u8;
+                    if alpha == 0 {
+                        continue;
+                    }
+                    let px = out.pixel_mut(x, y);
+                    let blended = blend(
+                        layer.blend_mode,
+                        [px[0], px[1], px[2], px[3]],
+                        [src[0], src[1], src[2], alpha],
+                    );
+                    px.copy_from_slice(&blended);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn clip_color(c: [f32; 3]) -> [f32; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    c.map(|v| {
+        let mut v = v;
+        if n < 0.0 {
+            v = l + (v - l) * l / (l - n);
+        }
+        if x > 1.0 {
+            v = l + (v - l) * (1.0 - l) / (x - l);
+        }
+        v
+    })
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    clip_color(c.map(|v| v + d))
+}
+
+fn sat(c: [f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    if x > n {
+        c.map(|v| (v - n) * s / (x - n))
+    } else {
+        [0.0; 3]
+    }
+}
+
+fn blend_channel(mode: BlendMode, b: f32, s: f32) -> f32 {
+    let hard_light = |b: f32, s: f32| {
+        if s <= 0.5 {
+            b * 2.0 * s
+        } else {
+            let s = 2.0 * s - 1.0;
+            b + s - b * s
+        }
+    };
+
+    match mode {
+        BlendMode::Multiply => b * s,
+        BlendMode::Screen => b + s - b * s,
+        BlendMode::Overlay => hard_light(s, b),
+        BlendMode::Darken => b.min(s),
+        BlendMode::Lighten => b.max(s),
+        BlendMode::ColorDodge => {
+            if b == 0.0 {
+                0.0
+            } else if s >= 1.0 {
+                1.0
+            } else {
+                (b / (1.0 - s)).min(1.0)
+            }
+        }
+        BlendMode::ColorBurn => {
+            if b >= 1.0 {
+                1.0
+            } else if s <= 0.0 {
+                0.0
+            } else {
+                1.0 - ((1.0 - b) / s).min(1.0)
+            }
+        }
+        BlendMode::HardLight => hard_light(b, s),
+        BlendMode::SoftLight => {
+            if s <= 0.5 {
+                b - (1.0 - 2.0 * s) * b * (1.0 - b)
+            } else {
+                let d = if b <= 0.25 {
+                    ((16.0 * b - 12.0) * b + 4.0) * b
+                } else {
+                    b.sqrt()
+                };
+                b + (2.0 * s - 1.0) * (d - b)
+            }
+        }
+        BlendMode::Difference => (b - s).abs(),
+        BlendMode::Exclusion => b + s - 2.0 * b * s,
+        BlendMode::Addition => (b + s).min(1.0),
+        BlendMode::Subtract => (b - s).max(0.0),
+        BlendMode::Divide => {
+            if b == 0.0 {
+                0.0
+            } else if b >= s {
+                1.0
+            } else {
+                b / s
+            }
+        }
+        _ => s,
+    }
+}
+
+/// composites `src` over `dst` (both straight alpha), the blend mode decides the color
+/// where both are opaque, like the W3C compositing spec.
+fn blend(mode: BlendMode, dst: [u8; 4], src: [u8; 4]) -> [u8; 4] {
+    let to_f = |c: [u8; 4]| [c[0], c[1], c[2]].map(|v| v as f32 / 255.0);
+    let (b, s) = (to_f(dst), to_f(src));
+    let da = dst[3] as f32 / 255.0;
+    let sa = src[3] as f32 / 255.0;
+
+    let mixed = match mode {
+        BlendMode::Hue => set_lum(set_sat(s, sat(b)), lum(b)),
+        BlendMode::Saturation => set_lum(set_sat(b, sat(s)), lum(b)),
+        BlendMode::Color => set_lum(s, lum(b)),
+        BlendMode::Luminosity => set_lum(b, lum(s)),
+        _ => [0, 1, 2].map(|i| blend_channel(mode, b[i], s[i])),
+    };
+
+    let out_a = sa + da * (1.0 - sa);
+    if out_a <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+
+    let mut out = [0u8; 4];
+    for i in 0..3 {
+        let color = (1.0 - da) * s[i] + da * mixed[i];
+        let v = (color * sa + b[i] * da * (1.0 - sa)) / out_a;
+        out[i] = (v * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    out[3] = (out_a * 255.0).round() as u8;
+    out
+}
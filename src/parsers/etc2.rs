@@ -0,0 +1,378 @@
+//! cpu decoder for the ETC2 / EAC formats every GLES3 device supports, for tools, tests and
+//! devices where the upload fails. ETC1 data decodes as ETC2 rgb.
+
+use super::{CompressedFormat, Image};
+
+/// etc1 intensity modifiers, (small, large) per table.
+const MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// t and h mode distances.
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// 4x4 texels, `[y][x]` rgba.
+type Block = [[[u8; 4]; 4]; 4];
+
+fn bits(block: u64, high: u32, low: u32) -> u32 {
+    ((block >> low) & ((1 << (high - low + 1)) - 1)) as u32
+}
+
+fn extend_4(c: u32) -> i32 {
+    (c * 17) as i32
+}
+
+fn extend_5(c: u32) -> i32 {
+    ((c << 3) | (c >> 2)) as i32
+}
+
+fn extend_6(c: u32) -> i32 {
+    ((c << 2) | (c >> 4)) as i32
+}
+
+fn extend_7(c: u32) -> i32 {
+    ((c << 1) | (c >> 6)) as i32
+}
+
+fn clamp(v: i32) -> u8 {
+    v.clamp(0, 255) as u8
+}
+
+fn offset_color(c: [i32; 3], d: i32) -> [u8; 4] {
+    [clamp(c[0] + d), clamp(c[1] + d), clamp(c[2] + d), 255]
+}
+
+/// 2 bit index of texel (x, y), pixels are stored column by column.
+fn texel_index(block: u64, x: usize, y: usize) -> usize {
+    let i = x * 4 + y;
+    let msb = (block >> (16 + i)) & 1;
+    let lsb = (block >> i) & 1;
+    ((msb << 1) | lsb) as usize
+}
+
+/// one 64 bit etc2 color block, `punchthrough` for the rgb8 a1 formats where bit 33 means opaque.
+fn decode_color_block(block: u64, punchthrough: bool) -> Block {
+    let mut out = [[[0u8; 4]; 4]; 4];
+
+    let diff_or_opaque = bits(block, 33, 33) == 1;
+    let flip = bits(block, 32, 32) == 1;
+    // with punchthrough the differential mode is the only one and bit 33 says if it is opaque.
+    let differential = punchthrough || diff_or_opaque;
+    let transparent_allowed = punchthrough && !diff_or_opaque;
+
+    let paint = |out: &mut Block, colors: [[u8; 4]; 4]| {
+        for (y, row) in out.iter_mut().enumerate() {
+            for (x, texel) in row.iter_mut().enumerate() {
+                let index = texel_index(block, x, y);
+                *texel = if transparent_allowed && index == 2 {
+                    [0, 0, 0, 0]
+                } else {
+                    colors[index]
+                };
+            }
+        }
+    };
+
+    let (base1, base2) = if differential {
+        let r = bits(block, 63, 59) as i32;
+        let g = bits(block, 55, 51) as i32;
+        let b = bits(block, 47, 43) as i32;
+        // 3 bit signed deltas.
+        let dr = ((bits(block, 58, 56) as i32) << 29) >> 29;
+        let dg = ((bits(block, 50, 48) as i32) << 29) >> 29;
+        let db = ((bits(block, 42, 40) as i32) << 29) >> 29;
+
+        if !(0..32).contains(&(r + dr)) {
+            // t mode.
+            let c1 = [
+                extend_4((bits(block, 60, 59) << 2) | bits(block, 57, 56)),
+                extend_4(bits(block, 55, 52)),
+                extend_4(bits(block, 51, 48)),
+            ];
+            let c2 = [
+                extend_4(bits(block, 47, 44)),
+                extend_4(bits(block, 43, 40)),
+                extend_4(bits(block, 39, 36)),
+            ];
+            let d = DISTANCES[((bits(block, 35, 34) << 1) | bits(block, 32, 32)) as usize];
+
+            let colors = [
+                offset_color(c1, 0),
+                offset_color(c2, d),
+                offset_color(c2, 0),
+                offset_color(c2, -d),
+            ];
+            paint(&mut out, colors);
+            return out;
+        }
+
+        if !(0..32).contains(&(g + dg)) {
+            // h mode.
+            let c1 = [
+                extend_4(bits(block, 62, 59)),
+                extend_4((bits(block, 58, 56) << 1) | bits(block, 52, 52)),
+                extend_4((bits(block, 51, 51) << 3) | bits(block, 49, 47)),
+            ];
+            let c2 = [
+                extend_4(bits(block, 46, 43)),
+                extend_4(bits(block, 42, 39)),
+                extend_4(bits(block, 38, 35)),
+            ];
+            let value = |c: [i32; 3]| (c[0] << 16) | (c[1] << 8) | c[2];
+            let index = (bits(block, 34, 34) << 2)
+                | (bits(block, 32, 32) << 1)
+                | (value(c1) >= value(c2)) as u32;
+            let d = DISTANCES[index as usize];
+
+            let colors = [
+                offset_color(c1, d),
+                offset_color(c1, -d),
+                offset_color(c2, d),
+                offset_color(c2, -d),
+            ];
+            paint(&mut out, colors);
+            return out;
+        }
+
+        if !(0..32).contains(&(b + db)) {
+            // planar mode, always opaque.
+            let o = [
+                extend_6(bits(block, 62, 57)),
+                extend_7((bits(block, 56, 56) << 6) | bits(block, 54, 49)),
+                extend_6(
+                    (bits(block, 48, 48) << 5) | (bits(block, 44, 43) << 3) | bits(block, 41, 39),
+                ),
+            ];
+            let h = [
+                extend_6((bits(block, 38, 34) << 1) | bits(block, 32, 32)),
+                extend_7(bits(block, 31, 25)),
+                extend_6(bits(block, 24, 19)),
+            ];
+            let v = [
+                extend_6(bits(block, 18, 13)),
+                extend_7(bits(block, 12, 6)),
+                extend_6(bits(block, 5, 0)),
+            ];
+
+            for (y, row) in out.iter_mut().enumerate() {
+                for (x, texel) in row.iter_mut().enumerate() {
+                    let (x, y) = (x as i32, y as i32);
+                    for c in 0..3 {
+                        texel[c] =
+                            clamp((x * (h[c] - o[c]) + y * (v[c] - o[c]) + 4 * o[c] + 2) >> 2);
+                    }
+                    texel[3] = 255;
+                }
+            }
+            return out;
+        }
+
+        (
+            [extend_5(r as u32), extend_5(g as u32), extend_5(b as u32)],
+            [
+                extend_5((r + dr) as u32),
+                extend_5((g + dg) as u32),
+                extend_5((b + db) as u32),
+            ],
+        )
+    } else {
+        (
+            [
+                extend_4(bits(block, 63, 60)),
+                extend_4(bits(block, 55, 52)),
+                extend_4(bits(block, 47, 44)),
+            ],
+            [
+                extend_4(bits(block, 59, 56)),
+                extend_4(bits(block, 51, 48)),
+                extend_4(bits(block, 43, 40)),
+            ],
+        )
+    };
+
+    let tables = [bits(block, 39, 37) as usize, bits(block, 36, 34) as usize];
+    for (y, row) in out.iter_mut().enumerate() {
+        for (x, texel) in row.iter_mut().enumerate() {
+            let second = if flip { y >= 2 } else { x >= 2 };
+            let (base, table) = if second {
+                (base2, tables[1])
+            } else {
+                (base1, tables[0])
+            };
+
+            let index = texel_index(block, x, y);
+            let [small, large] = MODIFIERS[table];
+            *texel = match index {
+                2 if transparent_allowed => [0, 0, 0, 0],
+                0 if transparent_allowed => offset_color(base, 0),
+                0 => offset_color(base, small),
+                1 => offset_color(base, large),
+                2 => offset_color(base, -small),
+                _ => offset_color(base, -large),
+            };
+        }
+    }
+
+    out
+}
+
+/// one 64 bit eac block as 8 bit values, `[y][x]`.
+fn decode_eac_alpha(block: u64) -> [[u8; 4]; 4] {
+    let base = bits(block, 63, 56) as i32;
+    let multiplier = bits(block, 55, 52) as i32;
+    let table = &EAC_MODIFIERS[bits(block, 51, 48) as usize];
+
+    let mut out = [[0u8; 4]; 4];
+    for (y, row) in out.iter_mut().enumerate() {
+        for (x, value) in row.iter_mut().enumerate() {
+            let shift = 45 - 3 * (x * 4 + y) as u32;
+            let index = bits(block, shift + 2, shift) as usize;
+            *value = clamp(base + table[index] * multiplier);
+        }
+    }
+    out
+}
+
+/// one 64 bit r11 eac block mapped to 8 bits, signed values go from -1.0 -> 1.0 to 0 -> 255.
+fn decode_eac_r11(block: u64, signed: bool) -> [[u8; 4]; 4] {
+    let base = if signed {
+        (bits(block, 63, 56) as u8 as i8).max(-127) as i32
+    } else {
+        bits(block, 63, 56) as i32
+    };
+    let multiplier = bits(block, 55, 52) as i32;
+    let table = &EAC_MODIFIERS[bits(block, 51, 48) as usize];
+
+    let mut out = [[0u8; 4]; 4];
+    for (y, row) in out.iter_mut().enumerate() {
+        for (x, value) in row.iter_mut().enumerate() {
+            let shift = 45 - 3 * (x * 4 + y) as u32;
+            let modifier = table[bits(block, shift + 2, shift) as usize];
+            let modifier = if multiplier == 0 {
+                modifier
+            } else {
+                modifier * multiplier * 8
+            };
+
+            *value = if signed {
+                let v = (base * 8 + modifier).clamp(-1023, 1023);
+                ((v + 1023) * 255 / 2046) as u8
+            } else {
+                let v = (base * 8 + 4 + modifier).clamp(0, 2047);
+                (v * 255 / 2047) as u8
+            };
+        }
+    }
+    out
+}
+
+fn read_block(data: &[u8]) -> u64 {
+    u64::from_be_bytes([
+        data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
+    ])
+}
+
+/// decodes one level of `format` to rgba, r11 goes to red and rg11 to red and green.
+pub fn decode<'a>(
+    format: CompressedFormat,
+    width: i32,
+    height: i32,
+    data: &[u8],
+) -> Result<Image, &'a str> {
+    if width <= 0 || height <= 0 {
+        return Err("Failed to decode ETC2, empty size");
+    }
+    if data.len() < format.level_size(width, height) {
+        return Err("Failed to decode ETC2, not enough data for the size");
+    }
+
+    let blocks_x = (width as usize).div_ceil(4);
+    let block_size = format.block_size();
+
+    let mut image = Image::init_blank(width, height, 4);
+    for (i, chunk) in data
+        .chunks_exact(block_size)
+        .take(format.level_size(width, height) / block_size)
+        .enumerate()
+    {
+        let texels: Block = match format {
+            CompressedFormat::Etc2Rgb8 | CompressedFormat::Etc2Srgb8 => {
+                decode_color_block(read_block(chunk), false)
+            }
+
+            CompressedFormat::Etc2Rgb8A1 | CompressedFormat::Etc2Srgb8A1 => {
+                decode_color_block(read_block(chunk), true)
+            }
+
+            CompressedFormat::Etc2Rgba8 | CompressedFormat::Etc2Srgb8Alpha8 => {
+                let alpha = decode_eac_alpha(read_block(chunk));
+                let mut texels = decode_color_block(read_block(&chunk[8..]), false);
+                for (row, alpha_row) in texels.iter_mut().zip(alpha) {
+                    for (texel, a) in row.iter_mut().zip(alpha_row) {
+                        texel[3] = a;
+                    }
+                }
+                texels
+            }
+
+            CompressedFormat::EacR11 | CompressedFormat::EacR11Signed => {
+                let signed = format == CompressedFormat::EacR11Signed;
+                let red = decode_eac_r11(read_block(chunk), signed);
+                red.map(|row| row.map(|r| [r, 0, 0, 255]))
+            }
+
+            CompressedFormat::EacRg11 | CompressedFormat::EacRg11Signed => {
+                let signed = format == CompressedFormat::EacRg11Signed;
+                let red = decode_eac_r11(read_block(chunk), signed);
+                let green = decode_eac_r11(read_block(&chunk[8..]), signed);
+                let mut texels = [[[0, 0, 0, 255]; 4]; 4];
+                for y in 0..4 {
+                    for x in 0..4 {
+                        texels[y][x][0] = red[y][x];
+                        texels[y][x][1] = green[y][x];
+                    }
+                }
+                texels
+            }
+        };
+
+        let block_x = (i % blocks_x) as i32 * 4;
+        let block_y = (i / blocks_x) as i32 * 4;
+        for (y, row) in texels.iter().enumerate() {
+            for (x, texel) in row.iter().enumerate() {
+                let (px, py) = (block_x + x as i32, block_y + y as i32);
+                if px < width && py < height {
+                    image.pixel_mut(px, py).copy_from_slice(texel);
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
@@ -0,0 +1,330 @@
+//! cpu side image processing on `Image`, everything works on 8 bit, 1 to 4 channels
+//! (gray, gray alpha, rgb, rgba) with tightly packed rows.
+
+use super::{Channel, Image};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeFilter {
+    Nearest,
+    Bilinear,
+    /// averages every source pixel covered by the destination pixel, best for shrinking.
+    Box,
+}
+
+/// expands one pixel of `channels` to rgba.
+fn to_rgba(px: &[u8], channels: i32) -> [u8; 4] {
+    match channels {
+        1 => [px[0], px[0], px[0], 255],
+        2 => [px[0], px[0], px[0], px[1]],
+        3 => [px[0], px[1], px[2], 255],
+        _ => [px[0], px[1], px[2], px[3]],
+    }
+}
+
+/// writes `rgba` into one pixel of `channels`, gray uses the rec. 601 luma.
+fn from_rgba(rgba: [u8; 4], channels: i32, out: &mut [u8]) {
+    let luma = || {
+        let [r, g, b, _] = rgba.map(|v| v as u32);
+        ((r * 299 + g * 587 + b * 114 + 500) / 1000) as u8
+    };
+
+    match channels {
+        1 => out[0] = luma(),
+        2 => {
+            out[0] = luma();
+            out[1] = rgba[3];
+        }
+        3 => out[..3].copy_from_slice(&rgba[..3]),
+        _ => out[..4].copy_from_slice(&rgba),
+    }
+}
+
+/// values in an image of that size, `None` if it is negative or too big for the i32 pixel
+/// offsets.
+fn raw_size(width: i32, height: i32, channels: i32) -> Option<usize> {
+    if width < 0 || height < 0 || channels < 0 {
+        return None;
+    }
+    (width as usize)
+        .checked_mul(height as usize)?
+        .checked_mul(channels as usize)
+        .filter(|&size| size <= i32::MAX as usize)
+}
+
+impl<T: Channel> Image<T> {
+    /// zeroed (transparent black) image, panics on a negative or too big size (`resize`
+    /// returns an error for those instead).
+    pub fn init_blank(width: i32, height: i32, channels: i32) -> Self {
+        assert!(
+            (1..=4).contains(&channels),
+            "Image channels have to be 1 ..= 4"
+        );
+        let size = raw_size(width, height, channels).expect("Image with invalid size");
+
+        Self {
+            raw: vec![T::default(); size],
+            width,
+            height,
+            channels,
+        }
+    }
+
+    fn offset(&self, x: i32, y: i32) -> usize {
+        ((y * self.width + x) * self.channels) as usize
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> &[T] {
+        let offset = self.offset(x, y);
+        &self.raw[offset..offset + self.channels as usize]
+    }
+
+    pub fn pixel_mut(&mut self, x: i32, y: i32) -> &mut [T] {
+        let offset = self.offset(x, y);
+        let channels = self.channels as usize;
+        &mut self.raw[offset..offset + channels]
+    }
+
+    pub fn crop<'a>(&self, x: i32, y: i32, width: i32, height: i32) -> Result<Self, &'a str> {
+        if x < 0 || y < 0 || width <= 0 || height <= 0 {
+            return Err("Failed to crop image, invalid rectangle");
+        }
+        if x + width > self.width || y + height > self.height {
+            return Err("Failed to crop image, rectangle out of bounds");
+        }
+
+        let stride = (width * self.channels) as usize;
+        let mut raw = Vec::with_capacity(stride * height as usize);
+        for row in y..y + height {
+            let offset = self.offset(x, row);
+            raw.extend_from_slice(&self.raw[offset..offset + stride]);
+        }
+
+        Ok(Self {
+            raw,
+            width,
+            height,
+            channels: self.channels,
+        })
+    }
+
+    /// flips the rows in place, for pixels read back from OpenGL (bottom row first).
+    pub fn flip_vertical(&mut self) {
+        let stride = (self.width * self.channels) as usize;
+        let height = self.height as usize;
+        for y in 0..height / 2 {
+            let (top, bottom) = self.raw.split_at_mut((height - 1 - y) * stride);
+            top[y * stride..(y + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+        }
+    }
+
+    pub fn flip_horizontal(&mut self) {
+        let channels = self.channels as usize;
+        let stride = self.width as usize * channels;
+        if stride == 0 {
+            return;
+        }
+
+        for row in self.raw.chunks_exact_mut(stride) {
+            // reversing the bytes reverses the channels too, put them back in order.
+            row.reverse();
+            for px in row.chunks_exact_mut(channels) {
+                px.reverse();
+            }
+        }
+    }
+
+    /// rotated a quarter turn clockwise, call it again for 180 and 270.
+    pub fn rotate90(&self) -> Self {
+        let mut out = Self::init_blank(self.height, self.width, self.channels);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                out.pixel_mut(self.height - 1 - y, x)
+                    .copy_from_slice(self.pixel(x, y));
+            }
+        }
+        out
+    }
+
+    /// calls `f` with (x, y, pixel) for every pixel.
+    pub fn map<F: FnMut(i32, i32, &mut [T])>(&mut self, mut f: F) {
+        let width = self.width.max(1);
+        for (i, px) in self
+            .raw
+            .chunks_exact_mut(self.channels as usize)
+            .enumerate()
+        {
+            let i = i as i32;
+            f(i % width, i / width, px);
+        }
+    }
+}
+
+impl Image {
+    pub fn resize<'a>(
+        &self,
+        width: i32,
+        height: i32,
+        filter: ResizeFilter,
+    ) -> Result<Image, &'a str> {
+        if width <= 0 || height <= 0 || self.width <= 0 || self.height <= 0 {
+            return Err("Failed to resize image, empty size");
+        }
+        if raw_size(width, height, self.channels).is_none() {
+            return Err("Failed to resize image, too big");
+        }
+
+        let mut out = Image::init_blank(width, height, self.channels);
+        let channels = self.channels as usize;
+        let scale_x = self.width as f32 / width as f32;
+        let scale_y = self.height as f32 / height as f32;
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut value = [0.0f32; 4];
+
+                match filter {
+                    ResizeFilter::Nearest => {
+                        let sx = (((x as f32 + 0.5) * scale_x) as i32).min(self.width - 1);
+                        let sy = (((y as f32 + 0.5) * scale_y) as i32).min(self.height - 1);
+                        for (v, &p) in value.iter_mut().zip(self.pixel(sx, sy)) {
+                            *v = p as f32;
+                        }
+                    }
+
+                    ResizeFilter::Bilinear => {
+                        let fx = ((x as f32 + 0.5) * scale_x - 0.5).max(0.0);
+                        let fy = ((y as f32 + 0.5) * scale_y - 0.5).max(0.0);
+                        let x0 = (fx as i32).min(self.width - 1);
+                        let y0 = (fy as i32).min(self.height - 1);
+                        let x1 = (x0 + 1).min(self.width - 1);
+                        let y1 = (y0 + 1).min(self.height - 1);
+                        let tx = fx - x0 as f32;
+                        let ty = fy - y0 as f32;
+
+                        for (c, v) in value.iter_mut().enumerate().take(channels) {
+                            let top = self.pixel(x0, y0)[c] as f32 * (1.0 - tx)
+                                + self.pixel(x1, y0)[c] as f32 * tx;
+                            let bottom = self.pixel(x0, y1)[c] as f32 * (1.0 - tx)
+                                + self.pixel(x1, y1)[c] as f32 * tx;
+                            *v = top * (1.0 - ty) + bottom * ty;
+                        }
+                    }
+
+                    ResizeFilter::Box => {
+                        // source area covered by the destination pixel.
+                        let left = x as f32 * scale_x;
+                        let right = (x + 1) as f32 * scale_x;
+                        let top = y as f32 * scale_y;
+                        let bottom = (y + 1) as f32 * scale_y;
+
+                        let mut total = 0.0;
+                        for sy in (top as i32)..(bottom.ceil() as i32).min(self.height) {
+                            let cover_y =
+                                (bottom.min((sy + 1) as f32) - top.max(sy as f32)).max(0.0);
+                            for sx in (left as i32)..(right.ceil() as i32).min(self.width) {
+                                let cover_x =
+                                    (right.min((sx + 1) as f32) - left.max(sx as f32)).max(0.0);
+                                let weight = cover_x * cover_y;
+                                for (v, &p) in value.iter_mut().zip(self.pixel(sx, sy)) {
+                                    *v += p as f32 * weight;
+                                }
+                                total += weight;
+                            }
+                        }
+                        if total > 0.0 {
+                            value.iter_mut().for_each(|v| *v /= total);
+                        }
+                    }
+                }
+
+                for (o, v) in out.pixel_mut(x, y).iter_mut().zip(value) {
+                    *o = v.round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// draws `src` with its top left corner at (x, y), blending with the alpha of `src`
+    /// when it has one, the parts that fall outside `self` are clipped.
+    pub fn blit(&mut self, src: &Image, x: i32, y: i32) {
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x + src.width).min(self.width);
+        let y1 = (y + src.height).min(self.height);
+
+        for dy in y0..y1 {
+            for dx in x0..x1 {
+                let s = to_rgba(src.pixel(dx - x, dy - y), src.channels);
+                let channels = self.channels;
+                let d = to_rgba(self.pixel(dx, dy), channels);
+
+                let sa = s[3] as u32;
+                let da = d[3] as u32;
+                let out_a = sa * 255 + da * (255 - sa);
+                let mut out = [0u8; 4];
+                for c in 0..3 {
+                    let v = s[c] as u32 * sa * 255 + d[c] as u32 * da * (255 - sa);
+                    out[c] = (v + out_a / 2).checked_div(out_a).unwrap_or(0) as u8;
+                }
+                out[3] = ((out_a + 127) / 255) as u8;
+
+                from_rgba(out, channels, self.pixel_mut(dx, dy));
+            }
+        }
+    }
+
+    /// the same image with 1 (gray), 2 (gray alpha), 3 (rgb) or 4 (rgba) channels.
+    pub fn convert_channels<'a>(&self, channels: i32) -> Result<Image, &'a str> {
+        if !(1..=4).contains(&channels) {
+            return Err("Failed to convert image, channels have to be 1 ..= 4");
+        }
+        if channels == self.channels {
+            return Ok(self.clone());
+        }
+
+        let mut out = Image::init_blank(self.width, self.height, channels);
+        for (src, dst) in self
+            .raw
+            .chunks_exact(self.channels as usize)
+            .zip(out.raw.chunks_exact_mut(channels as usize))
+        {
+            from_rgba(to_rgba(src, self.channels), channels, dst);
+        }
+
+        Ok(out)
+    }
+
+    /// multiplies the colors by alpha, does nothing without an alpha channel.
+    pub fn premultiply_alpha(&mut self) {
+        if self.channels != 2 && self.channels != 4 {
+            return;
+        }
+
+        let channels = self.channels as usize;
+        for px in self.raw.chunks_exact_mut(channels) {
+            let a = px[channels - 1] as u32;
+            for v in px[..channels - 1].iter_mut() {
+                *v = ((*v as u32 * a + 127) / 255) as u8;
+            }
+        }
+    }
+
+    /// rgba copy where every pixel matching `key` (rgb) is fully transparent.
+    pub fn color_key(&self, key: [u8; 3]) -> Image {
+        let mut out = Image::init_blank(self.width, self.height, 4);
+        for (src, dst) in self
+            .raw
+            .chunks_exact(self.channels as usize)
+            .zip(out.raw.chunks_exact_mut(4))
+        {
+            let mut rgba = to_rgba(src, self.channels);
+            if rgba[..3] == key {
+                rgba = [0, 0, 0, 0];
+            }
+            dst.copy_from_slice(&rgba);
+        }
+        out
+    }
+}
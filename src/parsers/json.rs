@@ -0,0 +1,257 @@
+//! minimal json reader for tool exports (sprite atlases, sound banks), no writer.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// keys in file order.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse<'a>(text: &str) -> Result<Self, &'a str> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            cursor: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.cursor != parser.bytes.len() {
+            return Err("Failed to parse json, trailing characters");
+        }
+        Ok(value)
+    }
+
+    /// member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        self.as_f64().map(|n| n as i32)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(o) => Some(o),
+            _ => None,
+        }
+    }
+}
+
+/// deeper documents are rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'t> {
+    bytes: &'t [u8],
+    cursor: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.cursor)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.cursor += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.cursor).copied()
+    }
+
+    fn expect<'a>(&mut self, byte: u8) -> Result<(), &'a str> {
+        if self.peek() != Some(byte) {
+            return Err("Failed to parse json, unexpected character");
+        }
+        self.cursor += 1;
+        Ok(())
+    }
+
+    fn literal<'a>(&mut self, word: &str, value: Json) -> Result<Json, &'a str> {
+        if !self.bytes[self.cursor..].starts_with(word.as_bytes()) {
+            return Err("Failed to parse json, unknown literal");
+        }
+        self.cursor += word.len();
+        Ok(value)
+    }
+
+    fn value<'a>(&mut self, depth: usize) -> Result<Json, &'a str> {
+        if depth > MAX_DEPTH {
+            return Err("Failed to parse json, nested too deep");
+        }
+
+        match self.peek() {
+            Some(b'{') => {
+                self.cursor += 1;
+                let mut members = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.cursor += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err("Failed to parse json, expected a key");
+                    }
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value(depth + 1)?));
+                    match self.peek() {
+                        Some(b',') => self.cursor += 1,
+                        Some(b'}') => {
+                            self.cursor += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err("Failed to parse json, expected ',' or '}'"),
+                    }
+                }
+            }
+
+            Some(b'[') => {
+                self.cursor += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.cursor += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    match self.peek() {
+                        Some(b',') => self.cursor += 1,
+                        Some(b']') => {
+                            self.cursor += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err("Failed to parse json, expected ',' or ']'"),
+                    }
+                }
+            }
+
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err("Failed to parse json, unexpected character"),
+        }
+    }
+
+    fn number<'a>(&mut self) -> Result<Json, &'a str> {
+        let start = self.cursor;
+        while self
+            .bytes
+            .get(self.cursor)
+            .is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.cursor += 1;
+        }
+
+        std::str::from_utf8(&self.bytes[start..self.cursor])
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .map(Json::Number)
+            .ok_or("Failed to parse json, bad number")
+    }
+
+    fn hex4<'a>(&mut self) -> Result<u32, &'a str> {
+        let digits = self
+            .bytes
+            .get(self.cursor..self.cursor + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or("Failed to parse json, bad unicode escape")?;
+        self.cursor += 4;
+        Ok(digits)
+    }
+
+    fn string<'a>(&mut self) -> Result<String, &'a str> {
+        // opening quote.
+        self.cursor += 1;
+        let mut out = Vec::new();
+
+        loop {
+            let Some(&byte) = self.bytes.get(self.cursor) else {
+                return Err("Failed to parse json, unterminated string");
+            };
+            self.cursor += 1;
+
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.bytes.get(self.cursor) else {
+                        return Err("Failed to parse json, unterminated string");
+                    };
+                    self.cursor += 1;
+
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // utf-16 surrogate pair.
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.bytes[self.cursor..].starts_with(b"\\u")
+                            {
+                                self.cursor += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000
+                                    + ((code - 0xD800) << 10)
+                                    + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err("Failed to parse json, bad escape"),
+                    };
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => out.push(byte),
+            }
+        }
+
+        String::from_utf8(out).map_err(|_| "Failed to parse json, invalid utf-8")
+    }
+}
@@ -0,0 +1,385 @@
+//! KTX 1.1 and KTX2 containers holding ETC2 / EAC compressed textures.
+
+use super::{etc2, ByteReader, Image};
+use crate::*;
+
+const KTX1_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// GL_ETC1_RGB8_OES, etc2 rgb decoders read etc1 data.
+const GL_ETC1_RGB8_OES: u32 = 0x8D64;
+
+/// the block compressed formats GLES3 guarantees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressedFormat {
+    Etc2Rgb8,
+    Etc2Srgb8,
+    /// 1 bit alpha.
+    Etc2Rgb8A1,
+    Etc2Srgb8A1,
+    Etc2Rgba8,
+    Etc2Srgb8Alpha8,
+    EacR11,
+    EacR11Signed,
+    EacRg11,
+    EacRg11Signed,
+}
+
+impl CompressedFormat {
+    const ALL: [Self; 10] = [
+        Self::Etc2Rgb8,
+        Self::Etc2Srgb8,
+        Self::Etc2Rgb8A1,
+        Self::Etc2Srgb8A1,
+        Self::Etc2Rgba8,
+        Self::Etc2Srgb8Alpha8,
+        Self::EacR11,
+        Self::EacR11Signed,
+        Self::EacRg11,
+        Self::EacRg11Signed,
+    ];
+
+    pub fn gl_internal_format(&self) -> u32 {
+        use gles_wrapper::gl::*;
+
+        match self {
+            Self::Etc2Rgb8 => GL_COMPRESSED_RGB8_ETC2,
+            Self::Etc2Srgb8 => GL_COMPRESSED_SRGB8_ETC2,
+            Self::Etc2Rgb8A1 => GL_COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            Self::Etc2Srgb8A1 => GL_COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            Self::Etc2Rgba8 => GL_COMPRESSED_RGBA8_ETC2_EAC,
+            Self::Etc2Srgb8Alpha8 => GL_COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
+            Self::EacR11 => GL_COMPRESSED_R11_EAC,
+            Self::EacR11Signed => GL_COMPRESSED_SIGNED_R11_EAC,
+            Self::EacRg11 => GL_COMPRESSED_RG11_EAC,
+            Self::EacRg11Signed => GL_COMPRESSED_SIGNED_RG11_EAC,
+        }
+    }
+
+    /// `VkFormat` value used by KTX2.
+    pub fn vk_format(&self) -> u32 {
+        match self {
+            Self::Etc2Rgb8 => 147,
+            Self::Etc2Srgb8 => 148,
+            Self::Etc2Rgb8A1 => 149,
+            Self::Etc2Srgb8A1 => 150,
+            Self::Etc2Rgba8 => 151,
+            Self::Etc2Srgb8Alpha8 => 152,
+            Self::EacR11 => 153,
+            Self::EacR11Signed => 154,
+            Self::EacRg11 => 155,
+            Self::EacRg11Signed => 156,
+        }
+    }
+
+    pub fn from_gl(internal_format: u32) -> Option<Self> {
+        if internal_format == GL_ETC1_RGB8_OES {
+            return Some(Self::Etc2Rgb8);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|f| f.gl_internal_format() == internal_format)
+    }
+
+    pub fn from_vk(vk_format: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.vk_format() == vk_format)
+    }
+
+    /// bytes per 4x4 block.
+    pub fn block_size(&self) -> usize {
+        match self {
+            Self::Etc2Rgb8
+            | Self::Etc2Srgb8
+            | Self::Etc2Rgb8A1
+            | Self::Etc2Srgb8A1
+            | Self::EacR11
+            | Self::EacR11Signed => 8,
+            _ => 16,
+        }
+    }
+
+    /// bytes of one `width` x `height` image.
+    pub fn level_size(&self, width: i32, height: i32) -> usize {
+        let blocks_x = (width.max(1) as usize).div_ceil(4);
+        let blocks_y = (height.max(1) as usize).div_ceil(4);
+        blocks_x * blocks_y * self.block_size()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KtxLevel {
+    pub width: i32,
+    pub height: i32,
+    /// `layers * faces` compressed images, all the faces of layer 0 first.
+    pub images: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct KtxImage {
+    pub format: CompressedFormat,
+    pub width: i32,
+    pub height: i32,
+    /// 1 for plain textures.
+    pub layers: i32,
+    /// 6 for cubemaps (+x, -x, +y, -y, +z, -z), 1 otherwise.
+    pub faces: i32,
+    /// mip levels, biggest first.
+    pub levels: Vec<KtxLevel>,
+}
+
+impl KtxImage {
+    /// takes in a KTX 1.1 or KTX2 file, the payload has to be ETC2 / EAC.
+    pub fn init<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        if buffer.starts_with(&KTX1_IDENTIFIER) {
+            Self::init_ktx1(buffer)
+        } else if buffer.starts_with(&KTX2_IDENTIFIER) {
+            Self::init_ktx2(buffer)
+        } else {
+            Err("Failed to parse KTX, wrong identifier")
+        }
+    }
+
+    fn init_ktx1<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let mut reader = ByteReader::init(buffer);
+        reader.bytes(KTX1_IDENTIFIER.len())?;
+
+        let swap = match reader.u32_le()? {
+            0x04030201 => false,
+            0x01020304 => true,
+            _ => return Err("Failed to parse KTX, invalid endianness"),
+        };
+        let u32 = |reader: &mut ByteReader| -> Result<u32, &'a str> {
+            let v = reader.u32_le()?;
+            Ok(if swap { v.swap_bytes() } else { v })
+        };
+
+        let gl_type = u32(&mut reader)?;
+        let _gl_type_size = u32(&mut reader)?;
+        let _gl_format = u32(&mut reader)?;
+        let gl_internal_format = u32(&mut reader)?;
+        let _gl_base_internal_format = u32(&mut reader)?;
+        let width = u32(&mut reader)? as i32;
+        let height = u32(&mut reader)? as i32;
+        let depth = u32(&mut reader)?;
+        let array_elements = u32(&mut reader)? as i32;
+        if array_elements < 0 {
+            return Err("Failed to parse KTX, invalid layer count");
+        }
+        let faces = u32(&mut reader)? as i32;
+        let levels_count = u32(&mut reader)?.max(1);
+        let key_value_size = u32(&mut reader)? as usize;
+
+        if gl_type != 0 {
+            return Err("Failed to parse KTX, uncompressed textures are not supported");
+        }
+        let format = CompressedFormat::from_gl(gl_internal_format)
+            .ok_or("Failed to parse KTX, unsupported compressed format")?;
+        Self::check_shape(width, height, depth, faces, levels_count)?;
+
+        reader.bytes(key_value_size)?;
+
+        let layers = array_elements.max(1);
+        let mut levels = Vec::with_capacity(levels_count as usize);
+        for level in 0..levels_count {
+            let (level_width, level_height) = Self::level_dimensions(width, height, level);
+            let image_size = format.level_size(level_width, level_height);
+            let total_size = Self::images_size(image_size, layers, faces)?;
+
+            let level_size = u32(&mut reader)? as usize;
+            // grows with what the buffer holds, the header counts are not trusted.
+            let mut images = Vec::new();
+            if array_elements == 0 && faces == 6 {
+                // non array cubemaps give the size of one face.
+                for _ in 0..faces {
+                    images.push(reader.bytes(level_size)?.to_vec());
+                    reader.seek(reader.position().next_multiple_of(4))?;
+                }
+            } else {
+                if level_size != total_size {
+                    return Err("Failed to parse KTX, level size does not match the format");
+                }
+                let data = reader.bytes(level_size)?;
+                images.extend(data.chunks_exact(image_size).map(|c| c.to_vec()));
+            }
+            reader.seek(reader.position().next_multiple_of(4))?;
+
+            if images.iter().any(|i| i.len() != image_size) {
+                return Err("Failed to parse KTX, level size does not match the format");
+            }
+
+            levels.push(KtxLevel {
+                width: level_width,
+                height: level_height,
+                images,
+            });
+        }
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            layers,
+            faces,
+            levels,
+        })
+    }
+
+    fn init_ktx2<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let mut reader = ByteReader::init(buffer);
+        reader.bytes(KTX2_IDENTIFIER.len())?;
+
+        let vk_format = reader.u32_le()?;
+        let _type_size = reader.u32_le()?;
+        let width = reader.i32_le()?;
+        let height = reader.i32_le()?;
+        let depth = reader.u32_le()?;
+        let layers = reader.i32_le()?;
+        if layers < 0 {
+            return Err("Failed to parse KTX2, invalid layer count");
+        }
+        let layers = layers.max(1);
+        let faces = reader.i32_le()?;
+        let levels_count = reader.u32_le()?.max(1);
+        let supercompression = reader.u32_le()?;
+
+        // data format descriptor, key values and supercompression global data.
+        reader.bytes(4 * 4 + 8 * 2)?;
+
+        let format = CompressedFormat::from_vk(vk_format)
+            .ok_or("Failed to parse KTX2, unsupported compressed format")?;
+        if supercompression != 0 {
+            return Err("Failed to parse KTX2, supercompressed files are not supported");
+        }
+        Self::check_shape(width, height, depth, faces, levels_count)?;
+
+        let mut levels = Vec::with_capacity(levels_count as usize);
+        for level in 0..levels_count {
+            let offset = reader.u64_le()? as usize;
+            let length = reader.u64_le()? as usize;
+            let _uncompressed_length = reader.u64_le()?;
+
+            let (level_width, level_height) = Self::level_dimensions(width, height, level);
+            let image_size = format.level_size(level_width, level_height);
+            let total_size = Self::images_size(image_size, layers, faces)?;
+            if length != total_size {
+                return Err("Failed to parse KTX2, level size does not match the format");
+            }
+
+            let end = offset
+                .checked_add(length)
+                .ok_or("Unexpected end of buffer")?;
+            let data = buffer.get(offset..end).ok_or("Unexpected end of buffer")?;
+            levels.push(KtxLevel {
+                width: level_width,
+                height: level_height,
+                images: data.chunks_exact(image_size).map(|c| c.to_vec()).collect(),
+            });
+        }
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            layers,
+            faces,
+            levels,
+        })
+    }
+
+    fn check_shape<'a>(
+        width: i32,
+        height: i32,
+        depth: u32,
+        faces: i32,
+        levels_count: u32,
+    ) -> Result<(), &'a str> {
+        if width <= 0 || height <= 0 {
+            return Err("Failed to parse KTX, empty size");
+        }
+        if depth > 1 {
+            return Err("Failed to parse KTX, 3D textures are not supported");
+        }
+        if faces != 1 && faces != 6 {
+            return Err("Failed to parse KTX, faces have to be 1 or 6");
+        }
+        if levels_count > 32 || (width.max(height) >> (levels_count - 1)) == 0 {
+            return Err("Failed to parse KTX, too many mip levels");
+        }
+
+        Ok(())
+    }
+
+    /// bytes of a level with `layers * faces` images of `image_size`.
+    fn images_size<'a>(image_size: usize, layers: i32, faces: i32) -> Result<usize, &'a str> {
+        if layers <= 0 || faces <= 0 {
+            return Err("Failed to parse KTX, invalid layer count");
+        }
+        (layers as usize)
+            .checked_mul(faces as usize)
+            .and_then(|count| count.checked_mul(image_size))
+            .ok_or("Failed to parse KTX, level too big")
+    }
+
+    fn level_dimensions(width: i32, height: i32, level: u32) -> (i32, i32) {
+        ((width >> level).max(1), (height >> level).max(1))
+    }
+
+    /// compressed data of one image.
+    pub fn image(&self, level: usize, layer: i32, face: i32) -> Option<&[u8]> {
+        if layer >= self.layers || face >= self.faces {
+            return None;
+        }
+        self.levels
+            .get(level)?
+            .images
+            .get((layer * self.faces + face) as usize)
+            .map(|i| i.as_slice())
+    }
+
+    /// cpu fallback, decodes one image to rgba.
+    pub fn decode<'a>(&self, level: usize, layer: i32, face: i32) -> Result<Image, &'a str> {
+        let data = self
+            .image(level, layer, face)
+            .ok_or("KTX image index out of range")?;
+        let l = &self.levels[level];
+        etc2::decode(self.format, l.width, l.height, data)
+    }
+
+    /// uploads every mip level of layer 0, face 0.
+    pub fn to_texture<'a>(&self) -> Result<Texture, &'a str> {
+        let levels: Vec<(i32, i32, &[u8])> = self
+            .levels
+            .iter()
+            .map(|l| (l.width, l.height, l.images[0].as_slice()))
+            .collect();
+
+        Texture::init_compressed(self.format.gl_internal_format(), &levels)
+    }
+
+    /// uploads every mip level of all the layers (face 0), for array textures.
+    pub fn to_texture_array<'a>(&self) -> Result<TextureArray, &'a str> {
+        let levels: Vec<(i32, i32, Vec<u8>)> = self
+            .levels
+            .iter()
+            .map(|l| {
+                let data = l
+                    .images
+                    .iter()
+                    .step_by(self.faces as usize)
+                    .flatten()
+                    .copied();
+                (l.width, l.height, data.collect())
+            })
+            .collect();
+        let levels: Vec<(i32, i32, &[u8])> = levels
+            .iter()
+            .map(|(w, h, data)| (*w, *h, data.as_slice()))
+            .collect();
+
+        TextureArray::init_compressed(self.format.gl_internal_format(), self.layers, &levels)
+    }
+}
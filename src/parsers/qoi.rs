@@ -0,0 +1,186 @@
+//! the Quite OK Image format (qoi.phoboslab.org), lossless rgb / rgba in pure rust.
+
+use super::Image;
+
+pub(super) const MAGIC: &[u8; 4] = b"qoif";
+const HEADER_SIZE: usize = 14;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+/// same limit as the reference decoder.
+const MAX_PIXELS: usize = 400_000_000;
+
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xC0;
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+const MASK_2: u8 = 0xC0;
+
+fn hash(px: [u8; 4]) -> usize {
+    let [r, g, b, a] = px.map(|v| v as usize);
+    (r * 3 + g * 5 + b * 7 + a * 11) % 64
+}
+
+/// (width, height, channels) from the header.
+pub(super) fn header<'a>(buffer: &[u8]) -> Result<(i32, i32, i32), &'a str> {
+    if buffer.len() < HEADER_SIZE || &buffer[..4] != MAGIC {
+        return Err("Failed to parse qoi, bad header");
+    }
+
+    let be = |at: usize| {
+        u32::from_be_bytes([buffer[at], buffer[at + 1], buffer[at + 2], buffer[at + 3]])
+    };
+    let (width, height, channels) = (be(4) as usize, be(8) as usize, buffer[12]);
+
+    if width == 0 || height == 0 || !matches!(channels, 3 | 4) {
+        return Err("Failed to parse qoi, invalid size or channels");
+    }
+    if width.saturating_mul(height) > MAX_PIXELS {
+        return Err("Failed to parse qoi, image too big");
+    }
+
+    Ok((width as i32, height as i32, channels as i32))
+}
+
+pub(super) fn decode<'a>(buffer: &[u8]) -> Result<Image, &'a str> {
+    let (width, height, channels) = header(buffer)?;
+    let pixels = (width * height) as usize;
+    let mut raw = Vec::with_capacity(pixels * channels as usize);
+
+    let mut index = [[0u8; 4]; 64];
+    let mut px = [0, 0, 0, 255u8];
+    let mut run = 0;
+    let mut cursor = HEADER_SIZE;
+    let data_end = buffer.len().saturating_sub(END_MARKER.len());
+
+    for _ in 0..pixels {
+        if run > 0 {
+            run -= 1;
+        } else {
+            if cursor >= data_end {
+                return Err("Failed to parse qoi, truncated data");
+            }
+            let b1 = buffer[cursor];
+            cursor += 1;
+
+            match b1 {
+                OP_RGB | OP_RGBA => {
+                    let count = if b1 == OP_RGB { 3 } else { 4 };
+                    let bytes = buffer
+                        .get(cursor..cursor + count)
+                        .ok_or("Failed to parse qoi, truncated data")?;
+                    px[..count].copy_from_slice(bytes);
+                    cursor += count;
+                }
+                _ => match b1 & MASK_2 {
+                    OP_INDEX => px = index[b1 as usize],
+                    OP_DIFF => {
+                        px[0] = px[0].wrapping_add(((b1 >> 4) & 3).wrapping_sub(2));
+                        px[1] = px[1].wrapping_add(((b1 >> 2) & 3).wrapping_sub(2));
+                        px[2] = px[2].wrapping_add((b1 & 3).wrapping_sub(2));
+                    }
+                    OP_LUMA => {
+                        let b2 = *buffer
+                            .get(cursor)
+                            .ok_or("Failed to parse qoi, truncated data")?;
+                        cursor += 1;
+                        let vg = (b1 & 0x3F).wrapping_sub(32);
+                        px[0] = px[0].wrapping_add(vg.wrapping_sub(8).wrapping_add(b2 >> 4));
+                        px[1] = px[1].wrapping_add(vg);
+                        px[2] = px[2].wrapping_add(vg.wrapping_sub(8).wrapping_add(b2 & 0x0F));
+                    }
+                    // OP_RUN, this pixel plus `run` more.
+                    _ => run = b1 & 0x3F,
+                },
+            }
+
+            index[hash(px)] = px;
+        }
+
+        raw.extend_from_slice(&px[..channels as usize]);
+    }
+
+    Ok(Image {
+        raw,
+        width,
+        height,
+        channels,
+    })
+}
+
+/// encodes `rows` (tightly packed, 3 or 4 channels) of a `width` x `height` image.
+pub(super) fn encode(rows: &[u8], width: i32, height: i32, channels: i32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + rows.len() + END_MARKER.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(width as u32).to_be_bytes());
+    out.extend_from_slice(&(height as u32).to_be_bytes());
+    out.push(channels as u8);
+    // srgb with linear alpha, informative only.
+    out.push(0);
+
+    let mut index = [[0u8; 4]; 64];
+    let mut prev = [0, 0, 0, 255u8];
+    let mut run = 0u8;
+    let pixels = rows.chunks_exact(channels as usize);
+    let last = pixels.len().saturating_sub(1);
+
+    for (i, chunk) in pixels.enumerate() {
+        let mut px = [0, 0, 0, 255u8];
+        px[..chunk.len()].copy_from_slice(chunk);
+
+        if px == prev {
+            run += 1;
+            if run == 62 || i == last {
+                out.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+
+        if run > 0 {
+            out.push(OP_RUN | (run - 1));
+            run = 0;
+        }
+
+        let slot = hash(px);
+        if index[slot] == px {
+            out.push(OP_INDEX | slot as u8);
+        } else {
+            index[slot] = px;
+
+            if px[3] == prev[3] {
+                let vr = px[0].wrapping_sub(prev[0]) as i8;
+                let vg = px[1].wrapping_sub(prev[1]) as i8;
+                let vb = px[2].wrapping_sub(prev[2]) as i8;
+                let vg_r = vr.wrapping_sub(vg);
+                let vg_b = vb.wrapping_sub(vg);
+
+                if (-2..=1).contains(&vr) && (-2..=1).contains(&vg) && (-2..=1).contains(&vb) {
+                    out.push(
+                        OP_DIFF
+                            | (((vr + 2) as u8) << 4)
+                            | (((vg + 2) as u8) << 2)
+                            | (vb + 2) as u8,
+                    );
+                } else if (-8..=7).contains(&vg_r)
+                    && (-32..=31).contains(&vg)
+                    && (-8..=7).contains(&vg_b)
+                {
+                    out.push(OP_LUMA | (vg + 32) as u8);
+                    out.push((((vg_r + 8) as u8) << 4) | (vg_b + 8) as u8);
+                } else {
+                    out.push(OP_RGB);
+                    out.extend_from_slice(&px[..3]);
+                }
+            } else {
+                out.push(OP_RGBA);
+                out.extend_from_slice(&px);
+            }
+        }
+
+        prev = px;
+    }
+
+    out.extend_from_slice(&END_MARKER);
+    out
+}
@@ -0,0 +1,275 @@
+//! sprite atlas metadata exported by packing tools: TexturePacker json (hash, array and
+//! multi pack) and libGDX `.atlas` (the old indented syntax and the 1.9.12+ one).
+
+use super::Json;
+use crate::SpriteRegion;
+use vector_math::*;
+
+/// one texture of an atlas and the regions packed in it.
+#[derive(Debug, Clone)]
+pub struct SpriteAtlasPage {
+    /// image file name, relative to the atlas file.
+    pub image: String,
+    pub width: i32,
+    pub height: i32,
+    pub regions: Vec<SpriteRegion>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpriteAtlasFile {
+    pub pages: Vec<SpriteAtlasPage>,
+}
+
+fn rect(json: Option<&Json>, keys: [&str; 4]) -> Option<[i32; 4]> {
+    let json = json?;
+    Some([
+        json.get(keys[0])?.as_i32()?,
+        json.get(keys[1])?.as_i32()?,
+        json.get(keys[2])?.as_i32()?,
+        json.get(keys[3])?.as_i32()?,
+    ])
+}
+
+fn texture_packer_region<'a>(name: &str, frame: &Json) -> Result<SpriteRegion, &'a str> {
+    let [x, y, width, height] = rect(frame.get("frame"), ["x", "y", "w", "h"])
+        .ok_or("Failed to parse sprite atlas, frame without a rect")?;
+
+    let mut region = SpriteRegion::init(name, x, y, width, height);
+    if frame.get("rotated").and_then(Json::as_bool) == Some(true) {
+        region.rotation = 90;
+    }
+    if let Some(trim) = frame.get("spriteSourceSize") {
+        region.trim_x = trim.get("x").and_then(Json::as_i32).unwrap_or(0);
+        region.trim_y = trim.get("y").and_then(Json::as_i32).unwrap_or(0);
+    }
+    if let Some(source) = frame.get("sourceSize") {
+        region.source_width = source.get("w").and_then(Json::as_i32).unwrap_or(width);
+        region.source_height = source.get("h").and_then(Json::as_i32).unwrap_or(height);
+    }
+    if let Some(pivot) = frame.get("pivot") {
+        region.pivot = vec2(
+            pivot.get("x").and_then(Json::as_f64).unwrap_or(0.5) as f32,
+            pivot.get("y").and_then(Json::as_f64).unwrap_or(0.5) as f32,
+        );
+    }
+
+    Ok(region)
+}
+
+/// `frames` as an object (hash export) or an array with a "filename" per frame.
+fn texture_packer_regions<'a>(frames: &Json) -> Result<Vec<SpriteRegion>, &'a str> {
+    match frames {
+        Json::Object(members) => members
+            .iter()
+            .map(|(name, frame)| texture_packer_region(name, frame))
+            .collect(),
+        Json::Array(items) => items
+            .iter()
+            .map(|frame| {
+                let name = frame
+                    .get("filename")
+                    .and_then(Json::as_str)
+                    .ok_or("Failed to parse sprite atlas, frame without a filename")?;
+                texture_packer_region(name, frame)
+            })
+            .collect(),
+        _ => Err("Failed to parse sprite atlas, frames is not an object or an array"),
+    }
+}
+
+fn texture_packer_page<'a>(
+    image: Option<&Json>,
+    size: Option<&Json>,
+    frames: &Json,
+) -> Result<SpriteAtlasPage, &'a str> {
+    let size = size.ok_or("Failed to parse sprite atlas, missing texture size")?;
+    Ok(SpriteAtlasPage {
+        image: image.and_then(Json::as_str).unwrap_or_default().to_string(),
+        width: size.get("w").and_then(Json::as_i32).unwrap_or(0),
+        height: size.get("h").and_then(Json::as_i32).unwrap_or(0),
+        regions: texture_packer_regions(frames)?,
+    })
+}
+
+/// "key: a, b" or "key:a,b" split into the key and the trimmed values.
+fn libgdx_field(line: &str) -> Option<(&str, Vec<&str>)> {
+    let (key, values) = line.split_once(':')?;
+    Some((key.trim(), values.split(',').map(str::trim).collect()))
+}
+
+fn libgdx_ints<'a>(values: &[&str], count: usize) -> Result<Vec<i32>, &'a str> {
+    if values.len() < count {
+        return Err("Failed to parse libgdx atlas, missing values");
+    }
+    values[..count]
+        .iter()
+        .map(|v| {
+            v.parse::<i32>()
+                .map_err(|_| "Failed to parse libgdx atlas, bad number")
+        })
+        .collect()
+}
+
+/// libGDX sizes are upright and offsets start at the bottom left, `offsets` is (x, y, orig w, orig h).
+fn libgdx_apply_offsets(region: &mut SpriteRegion, offsets: [i32; 4]) {
+    let [x, y, source_width, source_height] = offsets;
+    region.trim_x = x;
+    region.trim_y = source_height - region.height - y;
+    region.source_width = source_width;
+    region.source_height = source_height;
+}
+
+impl SpriteAtlasFile {
+    pub fn init_texture_packer<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let text = std::str::from_utf8(buffer)
+            .map_err(|_| "Failed to parse sprite atlas, invalid utf-8")?;
+        let json = Json::parse(text)?;
+
+        // multi pack export, one entry per texture.
+        if let Some(textures) = json.get("textures").and_then(Json::as_array) {
+            let pages = textures
+                .iter()
+                .map(|t| {
+                    let frames = t
+                        .get("frames")
+                        .ok_or("Failed to parse sprite atlas, missing frames")?;
+                    texture_packer_page(t.get("image"), t.get("size"), frames)
+                })
+                .collect::<Result<_, _>>()?;
+            return Ok(Self { pages });
+        }
+
+        let frames = json
+            .get("frames")
+            .ok_or("Failed to parse sprite atlas, missing frames")?;
+        let meta = json
+            .get("meta")
+            .ok_or("Failed to parse sprite atlas, missing meta")?;
+        let page = texture_packer_page(meta.get("image"), meta.get("size"), frames)?;
+        Ok(Self { pages: vec![page] })
+    }
+
+    pub fn init_libgdx<'a>(buffer: &[u8]) -> Result<Self, &'a str> {
+        let text = std::str::from_utf8(buffer)
+            .map_err(|_| "Failed to parse libgdx atlas, invalid utf-8")?;
+
+        let mut pages: Vec<SpriteAtlasPage> = Vec::new();
+        // pending old style offset and orig, applied once the region is complete.
+        let mut offset: Option<(i32, i32)> = None;
+        let mut orig: Option<(i32, i32)> = None;
+        let mut new_page = true;
+
+        let finish_region = |pages: &mut Vec<SpriteAtlasPage>,
+                             offset: &mut Option<(i32, i32)>,
+                             orig: &mut Option<(i32, i32)>| {
+            let Some(region) = pages.last_mut().and_then(|p| p.regions.last_mut()) else {
+                return;
+            };
+            if offset.is_some() || orig.is_some() {
+                let (x, y) = offset.take().unwrap_or((0, 0));
+                let (w, h) = orig.take().unwrap_or((region.width, region.height));
+                libgdx_apply_offsets(region, [x, y, w, h]);
+            }
+        };
+
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                new_page = true;
+                continue;
+            }
+
+            match libgdx_field(trimmed) {
+                None if new_page => {
+                    finish_region(&mut pages, &mut offset, &mut orig);
+                    pages.push(SpriteAtlasPage {
+                        image: trimmed.to_string(),
+                        width: 0,
+                        height: 0,
+                        regions: Vec::new(),
+                    });
+                    new_page = false;
+                }
+
+                None => {
+                    finish_region(&mut pages, &mut offset, &mut orig);
+                    let page = pages
+                        .last_mut()
+                        .ok_or("Failed to parse libgdx atlas, region before a page")?;
+                    page.regions.push(SpriteRegion::init(trimmed, 0, 0, 0, 0));
+                }
+
+                Some((key, values)) => {
+                    new_page = false;
+                    let page = pages
+                        .last_mut()
+                        .ok_or("Failed to parse libgdx atlas, field before a page")?;
+
+                    let Some(region) = page.regions.last_mut() else {
+                        if key == "size" {
+                            let size = libgdx_ints(&values, 2)?;
+                            page.width = size[0];
+                            page.height = size[1];
+                        }
+                        continue;
+                    };
+
+                    match key {
+                        "rotate" => {
+                            // counter clockwise degrees, true means 90.
+                            let ccw = match values[0] {
+                                "true" => 90,
+                                "false" => 0,
+                                degrees => degrees
+                                    .parse::<i32>()
+                                    .map_err(|_| "Failed to parse libgdx atlas, bad rotate")?,
+                            };
+                            region.rotation = (360 - ccw.rem_euclid(360)) % 360;
+                        }
+                        "xy" => {
+                            let xy = libgdx_ints(&values, 2)?;
+                            region.x = xy[0];
+                            region.y = xy[1];
+                        }
+                        "size" => {
+                            let size = libgdx_ints(&values, 2)?;
+                            region.width = size[0];
+                            region.height = size[1];
+                            region.source_width = size[0];
+                            region.source_height = size[1];
+                        }
+                        "bounds" => {
+                            let b = libgdx_ints(&values, 4)?;
+                            *region = SpriteRegion {
+                                rotation: region.rotation,
+                                index: region.index,
+                                ..SpriteRegion::init(&region.name, b[0], b[1], b[2], b[3])
+                            };
+                        }
+                        "offsets" => {
+                            let o = libgdx_ints(&values, 4)?;
+                            libgdx_apply_offsets(region, [o[0], o[1], o[2], o[3]]);
+                        }
+                        "orig" => {
+                            let o = libgdx_ints(&values, 2)?;
+                            orig = Some((o[0], o[1]));
+                        }
+                        "offset" => {
+                            let o = libgdx_ints(&values, 2)?;
+                            offset = Some((o[0], o[1]));
+                        }
+                        "index" => region.index = libgdx_ints(&values, 1)?[0],
+                        // split, pad and custom values.
+                        _ => {}
+                    }
+                }
+            }
+        }
+        finish_region(&mut pages, &mut offset, &mut orig);
+
+        if pages.is_empty() {
+            return Err("Failed to parse libgdx atlas, no pages");
+        }
+        Ok(Self { pages })
+    }
+}
@@ -0,0 +1,2827 @@
+#![allow(unused_imports)]
+
+use super::*;
+use parsers::*;
+
+fn packed_char(
+    x0: u16,
+    y0: u16,
+    x1: u16,
+    y1: u16,
+    xadvance: f32,
+) -> c_utils::sys::stbtt_packedchar {
+    c_utils::sys::stbtt_packedchar {
+        x0,
+        y0,
+        x1,
+        y1,
+        xoff: 1.0,
+        yoff: -10.0,
+        xadvance,
+        xoff2: 1.0 + (x1 - x0) as f32,
+        yoff2: -10.0 + (y1 - y0) as f32,
+    }
+}
+
+fn test_font() -> TrueTypeFont {
+    TrueTypeFont {
+        font_size: 16.0,
+        pixels: (0..64).collect(),
+        width: 8,
+        height: 8,
+        ranges: vec![FontRange::init('a', 'c'), FontRange::init('x', 'x')],
+        packed_chars: vec![
+            packed_char(0, 0, 2, 4, 3.0),
+            packed_char(2, 0, 4, 4, 3.5),
+            packed_char(4, 0, 6, 4, 4.0),
+            packed_char(0, 4, 8, 8, 9.0),
+        ],
+        has_glyph: vec![true; 4],
+        info: FontInfo {
+            family: "Test".to_string(),
+            style: "Regular".to_string(),
+            full_name: "Test Regular".to_string(),
+        },
+    }
+}
+
+#[test]
+fn font_glyph_lookup_and_quads() {
+    let font = test_font();
+
+    assert_eq!(font.glyph_index('a'), Some(0));
+    assert_eq!(font.glyph_index('c'), Some(2));
+    assert_eq!(font.glyph_index('x'), Some(3));
+    assert_eq!(font.glyph_index('d'), None);
+    assert_eq!(FontRange::ASCII.count, 95);
+    assert!(FontRange::ASCII.contains('~'));
+
+    let (q, x, y) = font.get_quad_and_next_position('b', 10.0, 20.0);
+    assert_eq!((x, y), (13.5, 20.0));
+    assert_eq!((q.x0, q.y0, q.x1, q.y1), (11.0, 10.0, 13.0, 14.0));
+    assert_eq!((q.s0, q.t0, q.s1, q.t1), (0.25, 0.0, 0.5, 0.5));
+
+    // not baked, nothing to draw and no advance.
+    let (q, x, _) = font.get_quad_and_next_position('z', 10.0, 20.0);
+    assert_eq!(x, 10.0);
+    assert_eq!(q.x0, q.x1);
+}
+
+#[test]
+fn font_baked_round_trip() {
+    let font = test_font();
+    let baked = font.to_baked();
+    let loaded = TrueTypeFont::init_baked(&baked).unwrap();
+
+    assert_eq!(loaded.font_size, font.font_size);
+    assert_eq!((loaded.width, loaded.height), (font.width, font.height));
+    assert_eq!(loaded.ranges, font.ranges);
+    assert_eq!(loaded.pixels, font.pixels);
+    assert_eq!(loaded.packed_chars.len(), font.packed_chars.len());
+    for (a, b) in loaded.packed_chars.iter().zip(font.packed_chars.iter()) {
+        assert_eq!((a.x0, a.y0, a.x1, a.y1), (b.x0, b.y0, b.x1, b.y1));
+        assert_eq!((a.xoff, a.yoff, a.xadvance), (b.xoff, b.yoff, b.xadvance));
+        assert_eq!((a.xoff2, a.yoff2), (b.xoff2, b.yoff2));
+    }
+    assert_eq!(loaded.has_glyph, font.has_glyph);
+    assert_eq!(loaded.info, font.info);
+
+    assert!(TrueTypeFont::init_baked(&baked[..baked.len() - 1]).is_err());
+    assert!(TrueTypeFont::init_baked(b"GGFX").is_err());
+
+    // width at 12, height at 16, the first range at 24.
+    let mut huge = baked.clone();
+    huge[12..20].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0x7F, 0xFF, 0xFF, 0xFF, 0x7F]);
+    assert!(TrueTypeFont::init_baked(&huge).is_err());
+    let mut range = baked.clone();
+    range[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(TrueTypeFont::init_baked(&range).is_err());
+    assert!(!FontRange {
+        first: u32::MAX,
+        count: 2
+    }
+    .contains('a'));
+}
+
+#[test]
+fn font_bake_rejects_invalid_input() {
+    assert!(TrueTypeFont::init(b"definitely not a ttf file", 16).is_err());
+
+    let config = FontBakeConfig {
+        ranges: Vec::new(),
+        ..Default::default()
+    };
+    assert!(TrueTypeFont::init_with_config(b"", &config).is_err());
+}
+
+/// a TrueType font named `family` with one glyph, a 500 x 700 box, for every codepoint in
+/// `first ..= last`.
+fn box_ttf(family: &str, first: u32, last: u32) -> Vec<u8> {
+    let be16 = |v: i32| (v as u16).to_be_bytes();
+    let mut glyph = Vec::new();
+    for v in [1, 0, 0, 500, 700, 3, 0] {
+        glyph.extend(be16(v));
+    }
+    glyph.extend([1u8; 4]);
+    for v in [0, 500, 0, -500, 0, 0, 700, 0] {
+        glyph.extend(be16(v));
+    }
+
+    // format 13: the whole range on glyph 1.
+    let mut cmap = Vec::new();
+    for v in [0, 1, 3, 10] {
+        cmap.extend(be16(v));
+    }
+    cmap.extend(12u32.to_be_bytes());
+    cmap.extend(be16(13));
+    cmap.extend(be16(0));
+    for v in [28, 0, 1, first, last, 1] {
+        cmap.extend(v.to_be_bytes());
+    }
+
+    let mut head = vec![0u8; 54];
+    head[18..20].copy_from_slice(&be16(1000));
+    let mut hhea = vec![0u8; 36];
+    hhea[4..6].copy_from_slice(&be16(800));
+    hhea[6..8].copy_from_slice(&be16(-200));
+    hhea[34..36].copy_from_slice(&be16(2));
+    let mut maxp = vec![0u8; 6];
+    maxp[..4].copy_from_slice(&0x5000u32.to_be_bytes());
+    maxp[4..6].copy_from_slice(&be16(2));
+    let hmtx: Vec<u8> = [600, 0, 600, 0].into_iter().flat_map(be16).collect();
+    let loca: Vec<u8> = [0, 0, glyph.len() as i32 / 2]
+        .into_iter()
+        .flat_map(be16)
+        .collect();
+
+    let family: Vec<u8> = family
+        .encode_utf16()
+        .flat_map(|u| u.to_be_bytes())
+        .collect();
+    let mut name = Vec::new();
+    for v in [0, 1, 18, 3, 1, 0x409, 1, family.len() as i32, 0] {
+        name.extend(be16(v));
+    }
+    name.extend(family);
+
+    let tables: [(&[u8; 4], Vec<u8>); 8] = [
+        (b"cmap", cmap),
+        (b"glyf", glyph),
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"hmtx", hmtx),
+        (b"loca", loca),
+        (b"maxp", maxp),
+        (b"name", name),
+    ];
+    let mut font = vec![0, 1, 0, 0];
+    font.extend(be16(tables.len() as i32));
+    font.extend([0u8; 6]);
+    let mut offset = 12 + tables.len() * 16;
+    for (tag, data) in tables.iter() {
+        font.extend(*tag);
+        font.extend(0u32.to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((data.len() as u32).to_be_bytes());
+        offset += data.len().div_ceil(4) * 4;
+    }
+    for (_, data) in tables {
+        let padded = data.len().div_ceil(4) * 4;
+        font.extend(&data);
+        font.resize(font.len() + padded - data.len(), 0);
+    }
+    font
+}
+
+#[test]
+fn font_atlas_grows_until_glyphs_fit() {
+    let ttf = box_ttf("Box", ' ' as u32, '~' as u32);
+    assert_eq!(TrueTypeFont::face_count(&ttf), 1);
+
+    // 95 boxes of about 32 x 45 pixels need more than the 64 x 64 it starts with.
+    let config = FontBakeConfig {
+        font_size: 64.0,
+        atlas_width: 64,
+        atlas_height: 64,
+        max_atlas_size: 1024,
+        oversample_h: 1,
+        oversample_v: 1,
+        ..Default::default()
+    };
+    let font = TrueTypeFont::init_with_config(&ttf, &config).unwrap();
+    assert!(font.width > 64 && font.height > 64);
+    assert!(font.width <= 1024 && font.height <= 1024);
+    assert_eq!(font.pixels.len(), (font.width * font.height) as usize);
+    assert!(font.has_glyph.iter().all(|&h| h));
+    assert!(font.pixels.iter().any(|&p| p != 0));
+
+    let small = FontBakeConfig {
+        max_atlas_size: 128,
+        ..config.clone()
+    };
+    assert!(TrueTypeFont::init_with_config(&ttf, &small).is_err());
+
+    let empty = FontBakeConfig {
+        atlas_width: 0,
+        ..config
+    };
+    assert!(TrueTypeFont::init_with_config(&ttf, &empty).is_err());
+}
+
+fn codepoints(chars: &[char]) -> Vec<u32> {
+    chars.iter().map(|&c| c as u32).collect()
+}
+
+#[test]
+fn bidi_reorders_mixed_lines() {
+    assert_eq!(text::bidi::paragraph_level(&['a', 'א']), 0);
+    assert_eq!(text::bidi::paragraph_level(&['1', 'א', 'a']), 1);
+
+    let visual: String = text::visual_line("abc אבג").into_iter().collect();
+    assert_eq!(visual, "abc גבא");
+
+    // numbers keep their order inside a right to left paragraph.
+    let visual: String = text::visual_line("אב 123").into_iter().collect();
+    assert_eq!(visual, "123 בא");
+
+    // brackets are mirrored in right to left runs.
+    let visual: String = text::visual_line("א(ב)").into_iter().collect();
+    assert_eq!(visual, "(ב)א");
+
+    let chars: Vec<char> = "ab אב".chars().collect();
+    let (paragraph, levels) = text::bidi::resolve_levels(&chars, Some(1));
+    assert_eq!(paragraph, 1);
+    assert_eq!(levels, vec![2, 2, 1, 1, 1]);
+    assert_eq!(text::bidi::reorder(&levels), vec![4, 3, 2, 0, 1]);
+}
+
+#[test]
+fn arabic_contextual_forms() {
+    use text::arabic::*;
+
+    assert_eq!(joining_type('\u{0628}'), Joining::D);
+    assert_eq!(joining_type('\u{0627}'), Joining::R);
+    assert_eq!(joining_type('\u{0621}'), Joining::U);
+    assert_eq!(joining_type('\u{064E}'), Joining::T);
+
+    // beh yeh teh -> initial, medial, final.
+    let shaped = shape(&['\u{0628}', '\u{064A}', '\u{062A}']);
+    assert_eq!(
+        shaped,
+        vec![Some('\u{FE91}'), Some('\u{FEF4}'), Some('\u{FE96}')]
+    );
+
+    // a fatha between letters does not break the joining.
+    let shaped = shape(&['\u{0628}', '\u{064E}', '\u{062A}']);
+    assert_eq!(
+        shaped,
+        vec![Some('\u{FE91}'), Some('\u{064E}'), Some('\u{FE96}')]
+    );
+
+    // alef only joins to the letter before it.
+    let shaped = shape(&['\u{0627}', '\u{0628}']);
+    assert_eq!(shaped, vec![Some('\u{FE8D}'), Some('\u{FE8F}')]);
+
+    // persian gaf and farsi yeh.
+    let shaped = shape(&['\u{06AF}', '\u{06CC}']);
+    assert_eq!(shaped, vec![Some('\u{FB94}'), Some('\u{FBFD}')]);
+}
+
+#[test]
+fn arabic_lam_alef_ligatures() {
+    let visual = text::visual_line("\u{0644}\u{0627}");
+    assert_eq!(codepoints(&visual), vec![0xFEFB]);
+
+    // seen lam alef meem, the ligature joins to the seen and the meem stands alone.
+    let visual = text::visual_line("\u{0633}\u{0644}\u{0627}\u{0645}");
+    assert_eq!(codepoints(&visual), vec![0xFEE1, 0xFEFC, 0xFEB3]);
+
+    // beh yeh teh in visual order.
+    let visual = text::visual_line("\u{0628}\u{064A}\u{062A}");
+    assert_eq!(codepoints(&visual), vec![0xFE96, 0xFEF4, 0xFE91]);
+}
+
+#[test]
+fn text_layout_glyphs_and_positions() {
+    let font = TrueTypeFont {
+        font_size: 16.0,
+        pixels: vec![0; 64],
+        width: 8,
+        height: 8,
+        ranges: vec![
+            FontRange::init('a', 'b'),
+            FontRange::init('\u{FEFB}', '\u{FEFC}'),
+        ],
+        packed_chars: vec![
+            packed_char(0, 0, 2, 4, 3.0),
+            packed_char(2, 0, 4, 4, 4.0),
+            packed_char(4, 0, 6, 4, 5.0),
+            packed_char(6, 0, 8, 4, 6.0),
+        ],
+        has_glyph: vec![true; 4],
+        info: FontInfo::default(),
+    };
+    let family = FontFamily::init(vec![font]).unwrap();
+
+    // 'c' is not baked and gets skipped.
+    let glyphs = text::layout(&family, "ac \u{0644}\u{0627}b\nb", vec2(10.0, 20.0));
+    let placed: Vec<(usize, f32, f32)> = glyphs.iter().map(|g| (g.glyph, g.x, g.y)).collect();
+    assert_eq!(
+        placed,
+        vec![
+            (0, 10.0, 20.0),
+            (2, 13.0, 20.0),
+            (1, 18.0, 20.0),
+            (1, 10.0, 36.0),
+        ]
+    );
+}
+
+#[test]
+fn font_family_fallback() {
+    let mut primary = test_font();
+    // 'b' is in the baked range but the face has no glyph for it.
+    primary.has_glyph[1] = false;
+    assert_eq!(primary.glyph_index('b'), None);
+
+    let mut fallback = test_font();
+    fallback.ranges = vec![FontRange::init('b', 'e')];
+    fallback.info.style = "Bold".to_string();
+
+    let family = FontFamily::init(vec![primary, fallback]).unwrap();
+    assert_eq!(family.glyph('a'), Some((0, 0)));
+    assert_eq!(family.glyph('b'), Some((1, 0)));
+    assert_eq!(family.glyph('d'), Some((1, 2)));
+    assert_eq!(family.glyph('z'), None);
+    assert_eq!(family.find_face("test", "bold"), Some(1));
+    assert_eq!(family.find_face("test", "italic"), None);
+
+    let glyphs = text::layout(&family, "abx", vec2(0.0, 0.0));
+    let placed: Vec<(usize, usize, f32)> = glyphs.iter().map(|g| (g.face, g.glyph, g.x)).collect();
+    assert_eq!(placed, vec![(0, 0, 0.0), (1, 0, 3.0), (0, 3, 6.0)]);
+
+    assert!(FontFamily::init(Vec::new()).is_err());
+
+    // names are not cut short.
+    let long = "Box ".repeat(100);
+    let info = TrueTypeFont::read_info(&box_ttf(&long, 'a' as u32, 'z' as u32), 0).unwrap();
+    assert_eq!(
+        (info.family.as_str(), info.style.as_str()),
+        (long.as_str(), "")
+    );
+    assert_eq!(TrueTypeFont::face_count(b"not a font at all"), 0);
+    assert!(TrueTypeFont::read_info(b"not a font at all", 0).is_err());
+}
+
+#[test]
+fn text_mesh_groups_faces() {
+    let mut primary = test_font();
+    primary.has_glyph[1] = false;
+    let mut fallback = test_font();
+    fallback.ranges = vec![FontRange::init('b', 'e')];
+    let family = FontFamily::init(vec![primary, fallback]).unwrap();
+
+    // a b a b: the two faces alternate but each one ends up in a single range.
+    let glyphs = text::layout(&family, "abab", vec2(0.0, 0.0));
+    let (vertices, ranges) = text::mesh_vertices(&glyphs);
+    assert_eq!(vertices.len(), 4 * 6 * 4);
+    assert_eq!(
+        ranges,
+        vec![
+            text::FaceRange {
+                face: 0,
+                first: 0,
+                count: 12
+            },
+            text::FaceRange {
+                face: 1,
+                first: 12,
+                count: 12
+            },
+        ]
+    );
+
+    // the second 'a' starts where the first 'b' advanced to.
+    let second_a_x0 = vertices[6 * 4 + 8];
+    assert_eq!(second_a_x0, 6.0 + 1.0);
+
+    let (vertices, ranges) = text::mesh_vertices(&[]);
+    assert!(vertices.is_empty() && ranges.is_empty());
+}
+
+fn test_image(channels: i32) -> Image {
+    let (width, height) = (5, 3);
+    Image {
+        raw: (0..width * height * channels)
+            .map(|i| (i * 7) as u8)
+            .collect(),
+        width,
+        height,
+        channels,
+    }
+}
+
+#[test]
+fn image_encode_round_trip() {
+    for channels in 1..=4 {
+        let image = test_image(channels);
+        // bmp drops the alpha of 2 channel images and writes gray as rgb.
+        let bmp_channels = if channels < 3 { 3 } else { channels };
+        for (encoded, expected) in [
+            (image.encode_png(), channels),
+            (image.encode_bmp(), bmp_channels),
+            (image.encode_tga(), channels),
+        ] {
+            let decoded = Image::init(&encoded.unwrap()).unwrap();
+            assert_eq!(
+                (decoded.width, decoded.height, decoded.channels),
+                (5, 3, expected)
+            );
+            assert_eq!(decoded.raw, image.convert_channels(expected).unwrap().raw);
+        }
+
+        let jpg = image.encode_jpg(90).unwrap();
+        let decoded = Image::init(&jpg).unwrap();
+        assert_eq!((decoded.width, decoded.height), (5, 3));
+
+        let hdr = image.encode_hdr().unwrap();
+        assert!(hdr.starts_with(b"#?RADIANCE"));
+    }
+
+    // png rows are not padded, a 3 channel odd width image is the case the stride got wrong.
+    let image = test_image(3);
+    let mut flipped = Image::init(&image.encode(ImageFormat::Png, true).unwrap()).unwrap();
+    assert_ne!(flipped.raw, image.raw);
+    flipped.flip_vertical();
+    assert_eq!(flipped.raw, image.raw);
+
+    assert!(image.encode_jpg(0).is_err());
+    let broken = Image {
+        raw: vec![0; 3],
+        width: 5,
+        height: 3,
+        channels: 3,
+    };
+    assert!(broken.encode_png().is_err());
+}
+
+/// 2x2 rgba: red, green / blue, white with half alpha.
+fn quad_image() -> Image {
+    Image {
+        raw: vec![
+            255, 0, 0, 255, 0, 255, 0, 255, //
+            0, 0, 255, 255, 255, 255, 255, 128,
+        ],
+        width: 2,
+        height: 2,
+        channels: 4,
+    }
+}
+
+#[test]
+fn image_resize_filters() {
+    let image = quad_image();
+
+    let nearest = image.resize(4, 4, ResizeFilter::Nearest).unwrap();
+    assert_eq!(nearest.pixel(1, 1), image.pixel(0, 0));
+    assert_eq!(nearest.pixel(3, 2), image.pixel(1, 1));
+
+    let shrunk = image.resize(1, 1, ResizeFilter::Box).unwrap();
+    assert_eq!(shrunk.pixel(0, 0), &[128, 128, 128, 223]);
+
+    let bilinear = image.resize(3, 1, ResizeFilter::Bilinear).unwrap();
+    assert_eq!(bilinear.pixel(0, 0), &[128, 0, 128, 255]);
+    assert_eq!(bilinear.pixel(1, 0), &[128, 128, 128, 223]);
+
+    // same size is a copy for every filter.
+    for filter in [
+        ResizeFilter::Nearest,
+        ResizeFilter::Bilinear,
+        ResizeFilter::Box,
+    ] {
+        assert_eq!(image.resize(2, 2, filter).unwrap(), image);
+    }
+    assert!(image.resize(0, 2, ResizeFilter::Box).is_err());
+    assert!(image.resize(i32::MAX, 2, ResizeFilter::Nearest).is_err());
+    assert!(image.resize(65536, 65536, ResizeFilter::Nearest).is_err());
+}
+
+#[test]
+fn image_crop_flip_rotate() {
+    let image = quad_image();
+
+    let crop = image.crop(1, 0, 1, 2).unwrap();
+    assert_eq!(crop.raw, [image.pixel(1, 0), image.pixel(1, 1)].concat());
+    assert!(image.crop(1, 1, 2, 1).is_err());
+
+    let mut flipped = image.clone();
+    flipped.flip_horizontal();
+    assert_eq!(flipped.pixel(0, 0), image.pixel(1, 0));
+    flipped.flip_vertical();
+    assert_eq!(flipped.pixel(0, 0), image.pixel(1, 1));
+
+    let rotated = image.rotate90();
+    assert_eq!(rotated.pixel(1, 0), image.pixel(0, 0));
+    assert_eq!(rotated.pixel(0, 0), image.pixel(0, 1));
+    assert_eq!(rotated.rotate90().rotate90().rotate90(), image);
+
+    let wide = Image::<u8>::init_blank(3, 1, 1).rotate90();
+    assert_eq!((wide.width, wide.height), (1, 3));
+}
+
+#[test]
+fn image_channels_and_alpha() {
+    let image = quad_image();
+
+    let gray = image.convert_channels(1).unwrap();
+    assert_eq!(gray.raw, vec![76, 150, 29, 255]);
+    let rgb = gray.convert_channels(3).unwrap();
+    assert_eq!(rgb.pixel(0, 0), &[76, 76, 76]);
+    assert_eq!(rgb.convert_channels(2).unwrap().pixel(1, 1), &[255, 255]);
+    assert!(image.convert_channels(5).is_err());
+
+    let mut premultiplied = image.clone();
+    premultiplied.premultiply_alpha();
+    assert_eq!(premultiplied.pixel(1, 1), &[128, 128, 128, 128]);
+    assert_eq!(premultiplied.pixel(0, 0), image.pixel(0, 0));
+
+    let keyed = rgb.color_key([76, 76, 76]);
+    assert_eq!(keyed.channels, 4);
+    assert_eq!(keyed.pixel(0, 0), &[0, 0, 0, 0]);
+    assert_eq!(keyed.pixel(1, 0)[3], 255);
+
+    // half white over opaque black, clipped at the right and bottom edges.
+    let mut canvas: Image = Image::init_blank(3, 3, 3);
+    canvas.blit(&image, 2, 2);
+    assert_eq!(canvas.pixel(2, 2), &[255, 0, 0]);
+    canvas.blit(&image, -1, -1);
+    assert_eq!(canvas.pixel(0, 0), &[128, 128, 128]);
+    assert_eq!(canvas.pixel(1, 1), &[0, 0, 0]);
+
+    let mut counted: Image = Image::init_blank(2, 2, 1);
+    counted.map(|x, y, px| px[0] = (y * 2 + x) as u8);
+    assert_eq!(counted.raw, vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn image_16_bit_and_hdr_loading() {
+    // 2x1 gray 16 bit png: 0x1234, 0xfedc.
+    #[rustfmt::skip]
+    let png: [u8; 70] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+        0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x10, 0x00, 0x00, 0x00,
+        0x00, 0x81, 0xd9, 0xfc, 0x15, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x44, 0x41, 0x54, 0x78,
+        0xda, 0x63, 0x10, 0x32, 0xf9, 0x77, 0x07, 0x00, 0x03, 0xc1, 0x02, 0x21, 0xd2, 0xbd,
+        0x55, 0x22, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    let image = Image::init_16(&png).unwrap();
+    assert_eq!((image.width, image.height, image.channels), (2, 1, 1));
+    assert_eq!(image.raw, vec![0x1234, 0xfedc]);
+    // the 8 bit loader keeps the high byte.
+    assert_eq!(Image::init(&png).unwrap().raw, vec![0x12, 0xfe]);
+
+    let floats: Image<f32> = image.convert();
+    assert!((floats.raw[1] - 0xfedc as f32 / 65535.0).abs() < 1e-6);
+    assert_eq!(floats.convert::<u16>(), image);
+
+    let hdr = Image::<f32> {
+        raw: vec![0.5, 1.0, 2.0, 16.0, 0.25, 0.0],
+        width: 2,
+        height: 1,
+        channels: 3,
+    };
+    let loaded = Image::init_hdr(&hdr.encode_hdr().unwrap()).unwrap();
+    assert_eq!((loaded.width, loaded.height, loaded.channels), (2, 1, 3));
+    for (a, b) in loaded.raw.iter().zip(hdr.raw.iter()) {
+        // rgbe keeps 8 bits of mantissa for the brightest channel.
+        assert!((a - b).abs() <= b.max(0.25) / 64.0, "{a} {b}");
+    }
+    assert_eq!(hdr.texture_format(true), Some(TextureFormat::Rgb16F));
+    assert_eq!(
+        loaded.crop(1, 0, 1, 1).unwrap().pixel(0, 0),
+        &loaded.raw[3..6]
+    );
+}
+
+#[test]
+fn animated_gif_frames_and_timing() {
+    // 2x2, red/blue palette, frame 0 checker (50ms), frame 1 all blue (200ms).
+    #[rustfmt::skip]
+    let gif: [u8; 87] = [
+        0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x02, 0x00, 0x02, 0x00, 0x80, 0x00, 0x00, 0xff,
+        0x00, 0x00, 0x00, 0x00, 0xff, 0x21, 0xff, 0x0b, 0x4e, 0x45, 0x54, 0x53, 0x43, 0x41,
+        0x50, 0x45, 0x32, 0x2e, 0x30, 0x03, 0x01, 0x00, 0x00, 0x00, 0x21, 0xf9, 0x04, 0x00,
+        0x05, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00,
+        0x02, 0x03, 0x44, 0x18, 0x14, 0x00, 0x21, 0xf9, 0x04, 0x00, 0x14, 0x00, 0x00, 0x00,
+        0x2c, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x02, 0x03, 0x4c, 0x98,
+        0x14, 0x00, 0x3b,
+    ];
+
+    let animation = AnimatedImage::init(&gif).unwrap();
+    assert_eq!(animation.frames.len(), 2);
+    assert_eq!((animation.width(), animation.height()), (2, 2));
+    assert_eq!(animation.delays, vec![50, 200]);
+    assert_eq!(animation.frames[0].pixel(0, 0), &[255, 0, 0, 255]);
+    assert_eq!(animation.frames[0].pixel(1, 0), &[0, 0, 255, 255]);
+    assert_eq!(animation.frames[1].pixel(0, 0), &[0, 0, 255, 255]);
+
+    assert_eq!(animation.duration(), 250);
+    assert_eq!(animation.frame_at(49, false), 0);
+    assert_eq!(animation.frame_at(50, false), 1);
+    assert_eq!(animation.frame_at(260, true), 0);
+    assert_eq!(animation.frame_at(260, false), 1);
+
+    let strip = animation.to_strip();
+    assert_eq!((strip.width, strip.height), (4, 2));
+    assert_eq!(strip.pixel(2, 0), animation.frames[1].pixel(0, 0));
+    assert_eq!(strip.pixel(1, 1), animation.frames[0].pixel(1, 1));
+    assert_eq!(animation.to_layers().len(), 2 * 2 * 2 * 4);
+
+    assert!(AnimatedImage::init(b"GIF89a").is_err());
+}
+
+#[test]
+fn image_probe_and_catalog() {
+    let image = test_image(3);
+
+    let png = image.encode_png().unwrap();
+    let info = Image::probe(&png).unwrap();
+    assert_eq!(
+        info,
+        ImageInfo {
+            width: 5,
+            height: 3,
+            channels: 3,
+            bit_depth: 8,
+            format: ImageFileFormat::Png,
+        }
+    );
+    // the png header is all that is needed.
+    assert_eq!(Image::probe(&png[..33]).unwrap(), info);
+
+    let tga = Image::probe(&image.encode_tga().unwrap()).unwrap();
+    assert_eq!(tga.format, ImageFileFormat::Tga);
+    let hdr = Image::probe(&image.encode_hdr().unwrap()).unwrap();
+    assert_eq!((hdr.format, hdr.bit_depth), (ImageFileFormat::Hdr, 32));
+    assert!(Image::probe(b"definitely not an image").is_err());
+
+    let mut catalog = assets::ImageCatalog::default();
+    catalog.add("a.png", &png[..64]).unwrap();
+    catalog.add("b.bmp", &image.encode_bmp().unwrap()).unwrap();
+    assert_eq!(
+        catalog.find("b.bmp").unwrap().info.format,
+        ImageFileFormat::Bmp
+    );
+    assert_eq!(catalog.total_area(1), 2 * 7 * 5);
+    assert_eq!(catalog.atlas_size_estimate(1, 1024), Some(16));
+    assert_eq!(catalog.atlas_size_estimate(1, 8), None);
+    assert_eq!(catalog.atlas_size_estimate(1, i32::MAX), Some(16));
+    assert_eq!(catalog.atlas_size_estimate(1, 16), Some(16));
+    // over 2^30 a side, doubling would overflow.
+    assert_eq!(catalog.atlas_size_estimate(600_000_000, i32::MAX), None);
+    assert!(catalog.add("c.txt", b"hello").is_err());
+}
+
+#[test]
+fn atlas_packs_pages_with_extrusion() {
+    let config = atlas::AtlasConfig {
+        page_width: 16,
+        page_height: 16,
+        padding: 1,
+        extrude: 1,
+        max_pages: 4,
+    };
+
+    let mut builder = atlas::AtlasBuilder::init(config.clone());
+    for i in 0..5 {
+        let mut image: Image = Image::init_blank(4, 5, 3);
+        image.map(|x, y, px| px.copy_from_slice(&[i * 40, x as u8, y as u8]));
+        builder.add(&format!("sprite{i}"), &image).unwrap();
+    }
+    assert!(builder.add("sprite0", &test_image(4)).is_err());
+    assert!(builder.add("huge", &Image::init_blank(15, 2, 4)).is_err());
+    assert!(builder.add("empty", &Image::init_blank(0, 4, 4)).is_err());
+    assert!(builder.add("flat", &Image::init_blank(4, 0, 4)).is_err());
+
+    // 7x8 with the border, only two fit in 15x15 per page.
+    let atlas = builder.build().unwrap();
+    assert_eq!(atlas.pages.len(), 3);
+    assert_eq!(atlas.regions.len(), 5);
+
+    let regions: Vec<&atlas::AtlasRegion> = atlas.regions.values().collect();
+    for (i, a) in regions.iter().enumerate() {
+        assert!(a.x >= 2 && a.y >= 2 && a.x + a.width <= 14 && a.y + a.height <= 14);
+        for b in regions.iter().skip(i + 1).filter(|b| b.page == a.page) {
+            let apart = a.x + a.width + 3 <= b.x
+                || b.x + b.width + 3 <= a.x
+                || a.y + a.height + 3 <= b.y
+                || b.y + b.height + 3 <= a.y;
+            assert!(apart, "{a:?} {b:?}");
+        }
+    }
+
+    let r = atlas.region("sprite3").unwrap();
+    let page = &atlas.pages[r.page];
+    assert_eq!(page.pixel(r.x + 2, r.y + 1), &[120, 2, 1, 255]);
+    // extruded corner and edge.
+    assert_eq!(page.pixel(r.x - 1, r.y - 1), &[120, 0, 0, 255]);
+    assert_eq!(page.pixel(r.x + r.width, r.y + 3), &[120, 3, 3, 255]);
+    assert_eq!((r.u0, r.v1), (r.x as f32 / 16.0, (r.y + 5) as f32 / 16.0));
+
+    let mut builder = atlas::AtlasBuilder::init(atlas::AtlasConfig {
+        max_pages: 1,
+        ..config
+    });
+    builder.add("a", &Image::init_blank(10, 10, 4)).unwrap();
+    builder.add("b", &Image::init_blank(10, 10, 4)).unwrap();
+    assert!(builder.build().is_err());
+}
+
+/// 64 bit etc2 / eac block from (high bit, low bit, value) fields, big endian like in the files.
+fn etc_block(fields: &[(u32, u32, u64)]) -> [u8; 8] {
+    let mut block = 0u64;
+    for &(high, low, value) in fields {
+        assert!(value < (1 << (high - low + 1)));
+        block |= value << low;
+    }
+    block.to_be_bytes()
+}
+
+fn decode_block(format: CompressedFormat, data: &[u8]) -> Image {
+    etc2::decode(format, 4, 4, data).unwrap()
+}
+
+#[test]
+fn etc2_color_modes() {
+    // individual: base 8 / 4 (136 / 68), table 0 (+-2, +-8), left and right halves.
+    let block = etc_block(&[
+        (63, 60, 8),
+        (59, 56, 4),
+        (55, 52, 8),
+        (51, 48, 4),
+        (47, 44, 8),
+        (43, 40, 4),
+        // pixel (3, 0) index 1, large positive.
+        (12, 12, 1),
+    ]);
+    let image = decode_block(CompressedFormat::Etc2Rgb8, &block);
+    assert_eq!(image.pixel(0, 0), &[138, 138, 138, 255]);
+    assert_eq!(image.pixel(2, 3), &[70, 70, 70, 255]);
+    assert_eq!(image.pixel(3, 0), &[76, 76, 76, 255]);
+
+    // differential + flip: 16 (132) on top, 16 - 4 (99) at the bottom, table 7 (+-47, +-183).
+    let block = etc_block(&[
+        (63, 59, 16),
+        (58, 56, 0b100),
+        (55, 51, 16),
+        (50, 48, 0b100),
+        (47, 43, 16),
+        (42, 40, 0b100),
+        (39, 37, 7),
+        (36, 34, 7),
+        (33, 33, 1),
+        (32, 32, 1),
+        // pixel (0, 3) index 3, large negative, pixel (1, 0) index 2.
+        (19, 19, 1),
+        (3, 3, 1),
+        (20, 20, 1),
+    ]);
+    let image = decode_block(CompressedFormat::Etc2Rgb8, &block);
+    assert_eq!(image.pixel(0, 0), &[179, 179, 179, 255]);
+    assert_eq!(image.pixel(1, 0), &[85, 85, 85, 255]);
+    assert_eq!(image.pixel(0, 3), &[0, 0, 0, 255]);
+    assert_eq!(image.pixel(3, 2), &[146, 146, 146, 255]);
+
+    // t mode: red 13 overflows with +1, c1 (221, 0, 0), c2 (0, 136, 0), distance 6.
+    let block = etc_block(&[
+        (63, 61, 0b111),
+        (60, 59, 0b11),
+        (58, 56, 0b001),
+        (43, 40, 8),
+        (33, 33, 1),
+        (32, 32, 1),
+        (4, 4, 1),
+        (17, 17, 1),
+        (1, 1, 1),
+    ]);
+    let image = decode_block(CompressedFormat::Etc2Rgb8, &block);
+    assert_eq!(image.pixel(0, 0), &[221, 0, 0, 255]);
+    assert_eq!(image.pixel(1, 0), &[6, 142, 6, 255]);
+    assert_eq!(image.pixel(0, 1), &[0, 130, 0, 255]);
+
+    // planar: blue overflows, origin (130, 0, 105) with red growing along x.
+    let block = etc_block(&[
+        (62, 57, 32),
+        (47, 45, 0b111),
+        (44, 43, 0b11),
+        (41, 39, 0b010),
+        (33, 33, 1),
+        (38, 34, 0b10010),
+        (24, 19, 26),
+        (18, 13, 32),
+        (5, 0, 26),
+    ]);
+    let image = decode_block(CompressedFormat::Etc2Rgb8, &block);
+    assert_eq!(image.pixel(0, 0), &[130, 0, 105, 255]);
+    assert_eq!(image.pixel(3, 0), &[142, 0, 105, 255]);
+    assert_eq!(image.pixel(0, 3), &[130, 0, 105, 255]);
+}
+
+#[test]
+fn etc2_alpha_formats() {
+    // eac: base 100, multiplier 2, table 13, pixel (0, 0) index 7 (+9), the rest index 0 (-1).
+    let alpha = etc_block(&[(63, 56, 100), (55, 52, 2), (51, 48, 13), (47, 45, 7)]);
+    let color = etc_block(&[(63, 60, 15), (59, 56, 15), (55, 52, 0), (51, 48, 0)]);
+    let image = decode_block(CompressedFormat::Etc2Rgba8, &[alpha, color].concat());
+    assert_eq!(image.pixel(0, 0), &[255, 2, 2, 118]);
+    assert_eq!(image.pixel(3, 3), &[255, 2, 2, 98]);
+
+    let red = decode_block(CompressedFormat::EacR11, &alpha);
+    assert_eq!(red.pixel(0, 0), &[(948 * 255 / 2047) as u8, 0, 0, 255]);
+
+    // punchthrough without the opaque bit: index 2 is transparent and index 0 has no modifier.
+    let block = etc_block(&[(63, 59, 16), (55, 51, 16), (47, 43, 16), (16, 16, 1)]);
+    let image = decode_block(CompressedFormat::Etc2Rgb8A1, &block);
+    assert_eq!(image.pixel(0, 0), &[0, 0, 0, 0]);
+    assert_eq!(image.pixel(1, 1), &[132, 132, 132, 255]);
+
+    assert!(etc2::decode(CompressedFormat::Etc2Rgba8, 4, 4, &alpha).is_err());
+}
+
+#[test]
+fn ktx_containers() {
+    let block = etc_block(&[
+        (63, 60, 8),
+        (59, 56, 8),
+        (55, 52, 8),
+        (51, 48, 8),
+        (47, 44, 8),
+        (43, 40, 8),
+    ]);
+    let u32s =
+        |values: &[u32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+
+    // ktx 1.1, 8x4 rgb8 with 2 mip levels and 4 bytes of key values.
+    let mut ktx1 = vec![
+        0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+    ];
+    ktx1.extend(u32s(&[
+        0x04030201, 0, 1, 0, 0x9274, 0x1907, 8, 4, 0, 0, 1, 2, 4,
+    ]));
+    ktx1.extend([1, 2, 3, 4]);
+    ktx1.extend(u32s(&[16]));
+    ktx1.extend([block, block].concat());
+    ktx1.extend(u32s(&[8]));
+    ktx1.extend(block);
+
+    let ktx = KtxImage::init(&ktx1).unwrap();
+    assert_eq!(ktx.format, CompressedFormat::Etc2Rgb8);
+    assert_eq!((ktx.width, ktx.height, ktx.layers, ktx.faces), (8, 4, 1, 1));
+    assert_eq!(ktx.levels.len(), 2);
+    assert_eq!((ktx.levels[1].width, ktx.levels[1].height), (4, 2));
+    assert_eq!(ktx.image(1, 0, 0).unwrap(), &block);
+    let image = ktx.decode(0, 0, 0).unwrap();
+    assert_eq!((image.width, image.height), (8, 4));
+    assert_eq!(image.pixel(7, 3), &[138, 138, 138, 255]);
+    assert_eq!(ktx.decode(1, 0, 0).unwrap().height, 2);
+
+    // truncated level.
+    assert!(KtxImage::init(&ktx1[..ktx1.len() - 1]).is_err());
+    // a negative array size from the header.
+    let mut negative = ktx1.clone();
+    negative[48..52].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(KtxImage::init(&negative).is_err());
+    // a huge array size fails on the level size instead of allocating for it.
+    let mut huge = ktx1.clone();
+    huge[48..52].copy_from_slice(&0x7FFF_FFFFu32.to_le_bytes());
+    assert!(KtxImage::init(&huge).is_err());
+
+    // ktx2, 4x4 rgba8 with 2 layers, one level.
+    let mut ktx2 = vec![
+        0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+    ];
+    ktx2.extend(u32s(&[151, 1, 4, 4, 0, 2, 1, 1, 0]));
+    ktx2.extend(u32s(&[0, 0, 0, 0, 0, 0, 0, 0]));
+    ktx2.extend(u32s(&[104, 0, 32, 0, 32, 0]));
+    let alpha = etc_block(&[(63, 56, 7)]);
+    ktx2.extend([alpha, block, alpha, alpha].concat());
+
+    let ktx = KtxImage::init(&ktx2).unwrap();
+    assert_eq!(ktx.format, CompressedFormat::Etc2Rgba8);
+    assert_eq!(ktx.layers, 2);
+    assert_eq!(
+        ktx.decode(0, 0, 0).unwrap().pixel(0, 0),
+        &[138, 138, 138, 7]
+    );
+    assert!(ktx.image(0, 2, 0).is_none());
+
+    // a level offset that overflows, then a negative layer count.
+    let mut offset = ktx2.clone();
+    offset[80..88].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+    assert!(KtxImage::init(&offset).is_err());
+    let mut negative = ktx2.clone();
+    negative[32..36].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(KtxImage::init(&negative).is_err());
+
+    // zstd supercompression.
+    ktx2[44] = 2;
+    assert!(KtxImage::init(&ktx2).is_err());
+    assert!(KtxImage::init(b"not a ktx").is_err());
+}
+
+fn ase_chunk(chunk_type: u16, data: &[u8]) -> Vec<u8> {
+    let mut chunk = ((data.len() + 6) as u32).to_le_bytes().to_vec();
+    chunk.extend(chunk_type.to_le_bytes());
+    chunk.extend(data);
+    chunk
+}
+
+fn ase_frame(duration: u16, chunks: &[Vec<u8>]) -> Vec<u8> {
+    let data = chunks.concat();
+    let mut frame = ((data.len() + 16) as u32).to_le_bytes().to_vec();
+    frame.extend(0xF1FAu16.to_le_bytes());
+    frame.extend((chunks.len() as u16).to_le_bytes());
+    frame.extend(duration.to_le_bytes());
+    frame.extend([0, 0]);
+    frame.extend((chunks.len() as u32).to_le_bytes());
+    frame.extend(data);
+    frame
+}
+
+fn ase_string(s: &str) -> Vec<u8> {
+    [
+        (s.len() as u16).to_le_bytes().to_vec(),
+        s.as_bytes().to_vec(),
+    ]
+    .concat()
+}
+
+fn ase_layer(name: &str, flags: u16, blend_mode: u16) -> Vec<u8> {
+    let mut data = [flags, 0, 0, 0, 0, blend_mode]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<u8>>();
+    data.extend([255, 0, 0, 0]);
+    data.extend(ase_string(name));
+    ase_chunk(0x2004, &data)
+}
+
+/// cel header (layer, x, y, opacity, type) followed by `rest`.
+fn ase_cel(layer: u16, x: i16, y: i16, cel_type: u16, rest: &[u8]) -> Vec<u8> {
+    let mut data = layer.to_le_bytes().to_vec();
+    data.extend(x.to_le_bytes());
+    data.extend(y.to_le_bytes());
+    data.push(255);
+    data.extend(cel_type.to_le_bytes());
+    data.extend([0; 7]);
+    data.extend(rest);
+    ase_chunk(0x2005, &data)
+}
+
+/// zlib stream made of one stored (uncompressed) deflate block.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let len = data.len() as u16;
+    let mut out = vec![0x78, 0x01, 0x01];
+    out.extend(len.to_le_bytes());
+    out.extend((!len).to_le_bytes());
+    out.extend(data);
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend(((b << 16) | a).to_be_bytes());
+    out
+}
+
+#[test]
+fn aseprite_frames_tags_and_slices() {
+    let orange = [200u8, 100, 50, 255].repeat(4);
+    let gray = [128u8, 128, 128, 255];
+
+    let mut tags = vec![2, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    for (from, to, direction, repeat, name) in
+        [(0u16, 2u16, 2u8, 0u16, "walk"), (1, 1, 0, 2, "hit")]
+    {
+        tags.extend(from.to_le_bytes());
+        tags.extend(to.to_le_bytes());
+        tags.push(direction);
+        tags.extend(repeat.to_le_bytes());
+        tags.extend([0; 10]);
+        tags.extend(ase_string(name));
+    }
+
+    let mut slice = [1u32, 3, 0]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<u8>>();
+    slice.extend(ase_string("button"));
+    for v in [0i32, 0, 0, 2, 2, 1, 1, 0, 0, 1, 1] {
+        slice.extend(v.to_le_bytes());
+    }
+
+    let frames = [
+        ase_frame(
+            50,
+            &[
+                ase_layer("base", 1, 0),
+                ase_layer("shade", 1, 1),
+                ase_layer("hidden", 0, 0),
+                ase_cel(0, 0, 0, 0, &[[2, 0, 2, 0].as_slice(), &orange].concat()),
+                ase_cel(
+                    1,
+                    1,
+                    0,
+                    2,
+                    &[[1, 0, 1, 0].as_slice(), &zlib_stored(&gray)].concat(),
+                ),
+                ase_cel(2, 0, 0, 0, &[1, 0, 1, 0, 255, 0, 0, 255]),
+                ase_chunk(0x2018, &tags),
+                ase_chunk(0x2022, &slice),
+            ],
+        ),
+        ase_frame(0, &[ase_cel(0, 0, 0, 1, &[0, 0])]),
+        ase_frame(30, &[ase_cel(0, 1, 1, 0, &[1, 0, 1, 0, 0, 0, 255, 128])]),
+    ]
+    .concat();
+
+    let mut file = vec![0; 4];
+    file.extend(
+        [0xA5E0u16, 3, 2, 2, 32]
+            .iter()
+            .flat_map(|v| v.to_le_bytes()),
+    );
+    file.extend(1u32.to_le_bytes());
+    file.extend(80u16.to_le_bytes());
+    file.resize(128, 0);
+    file.extend(frames);
+
+    let ase = Aseprite::init(&file).unwrap();
+    assert_eq!((ase.width, ase.height), (2, 2));
+    assert_eq!(ase.durations, [50, 80, 30]);
+    assert_eq!(ase.layers[1].blend_mode, BlendMode::Multiply);
+    assert!(!ase.layer_visible(2));
+
+    // multiply over orange, the hidden layer is left out.
+    assert_eq!(ase.frames[0].pixel(0, 0), &[200, 100, 50, 255]);
+    assert_eq!(ase.frames[0].pixel(1, 0), &[100, 50, 25, 255]);
+    // linked to the base cel of frame 0.
+    assert_eq!(ase.frames[1].pixel(1, 0), &[200, 100, 50, 255]);
+    assert_eq!(ase.frames[2].pixel(0, 0), &[0, 0, 0, 0]);
+    assert_eq!(ase.frames[2].pixel(1, 1), &[0, 0, 255, 128]);
+
+    assert_eq!(ase.tags[0].direction, AnimationDirection::PingPong);
+    assert_eq!(ase.tags[0].frames(), [0, 1, 2, 1]);
+    let key = ase.slices[0].key(2).unwrap();
+    assert_eq!(
+        (key.width, key.center, key.pivot),
+        (2, Some([1, 1, 0, 0]), Some([1, 1]))
+    );
+
+    let (_, _, _, cell_size, columns) = aseprite_grid(&ase);
+    assert_eq!((cell_size, columns), (2, 2));
+    let animations = aseprite_animations(&ase, columns);
+    assert_eq!(animations[0].cells, [(0, 0), (1, 0), (0, 1), (1, 0)]);
+    assert_eq!(animations[0].durations, [50, 80, 30, 80]);
+    assert_eq!(animations[0].cell_at(50), (1, 0));
+    assert_eq!(animations[0].cell_at(240 + 130), (0, 1));
+    assert_eq!(animations[1].cells.len(), 2);
+    assert!(!animations[1].looping);
+
+    file[4] = 0;
+    assert!(Aseprite::init(&file).is_err());
+}
+
+#[test]
+fn aseprite_group_opacity_and_cel_size() {
+    // (type, child level, opacity) at 8, 10 and 18 of the chunk.
+    let layer = |name: &str, group: bool, level: u16, opacity: u8| {
+        let mut chunk = ase_layer(name, 1, 0);
+        chunk[8..10].copy_from_slice(&(group as u16).to_le_bytes());
+        chunk[10..12].copy_from_slice(&level.to_le_bytes());
+        chunk[18] = opacity;
+        chunk
+    };
+    let file = |cel: Vec<u8>| {
+        let frames = ase_frame(
+            100,
+            &[
+                layer("group", true, 0, 128),
+                layer("child", false, 1, 255),
+                layer("top", false, 0, 255),
+                cel,
+            ],
+        );
+        let mut file = vec![0; 4];
+        file.extend(
+            [0xA5E0u16, 1, 2, 2, 32]
+                .iter()
+                .flat_map(|v| v.to_le_bytes()),
+        );
+        file.extend(1u32.to_le_bytes());
+        file.extend(100u16.to_le_bytes());
+        file.resize(128, 0);
+        file.extend(frames);
+        file
+    };
+
+    let white = ase_cel(1, 0, 0, 0, &[1, 0, 1, 0, 255, 255, 255, 255]);
+    let ase = Aseprite::init(&file(white)).unwrap();
+    assert_eq!((ase.layer_opacity(1), ase.layer_opacity(2)), (128, 255));
+    assert_eq!(ase.frames[0].pixel(0, 0), &[255, 255, 255, 128]);
+
+    // 65535 x 65535 rgba is more than a cel can hold.
+    let huge = ase_cel(1, 0, 0, 0, &[0xFF, 0xFF, 0xFF, 0xFF]);
+    assert!(Aseprite::init(&file(huge)).is_err());
+}
+
+#[test]
+fn json_values_and_errors() {
+    let json = Json::parse(r#" {"a": [1, -2.5e1, true, null], "s": "x\"é😀", "o": {}} "#).unwrap();
+    let a = json.get("a").and_then(Json::as_array).unwrap();
+    assert_eq!(a[0].as_i32(), Some(1));
+    assert_eq!(a[1].as_f64(), Some(-25.0));
+    assert_eq!((a[2].as_bool(), &a[3]), (Some(true), &Json::Null));
+    assert_eq!(json.get("s").and_then(Json::as_str), Some("x\"é😀"));
+    assert_eq!(
+        json.get("o").and_then(Json::as_object).map(|o| o.len()),
+        Some(0)
+    );
+
+    for bad in [
+        "",
+        "{",
+        "[1,]",
+        "{\"a\" 1}",
+        "tru",
+        "\"open",
+        "1 2",
+        &"[".repeat(200),
+    ] {
+        assert!(Json::parse(bad).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn sprite_atlas_formats() {
+    let hash = br#"{
+        "frames": {
+            "hero.png": {
+                "frame": {"x": 10, "y": 20, "w": 4, "h": 8}, "rotated": true, "trimmed": true,
+                "spriteSourceSize": {"x": 1, "y": 2, "w": 4, "h": 8},
+                "sourceSize": {"w": 6, "h": 12}, "pivot": {"x": 0.5, "y": 1}
+            }
+        },
+        "meta": {"image": "sheet.png", "size": {"w": 64, "h": 32}}
+    }"#;
+    let atlas = SpriteAtlasFile::init_texture_packer(hash).unwrap();
+    let page = &atlas.pages[0];
+    assert_eq!(
+        (page.image.as_str(), page.width, page.height),
+        ("sheet.png", 64, 32)
+    );
+    let hero = &page.regions[0];
+    assert_eq!(
+        (hero.name.as_str(), hero.x, hero.y, hero.width, hero.height),
+        ("hero.png", 10, 20, 4, 8)
+    );
+    assert_eq!((hero.rotation, hero.trim_x, hero.trim_y), (90, 1, 2));
+    assert_eq!(
+        (hero.source_width, hero.source_height, hero.pivot),
+        (6, 12, vec2(0.5, 1.0))
+    );
+
+    // rotated clockwise: the upright top left is the top right of the 8x4 texture area.
+    let quad = hero.quad(64, 32);
+    assert_eq!(&quad[16..20], &[-2.0, -10.0, 18.0 / 64.0, 20.0 / 32.0]);
+    assert_eq!(&quad[0..4], &[2.0, -2.0, 10.0 / 64.0, 24.0 / 32.0]);
+
+    let array = br#"{"frames": [{"filename": "a", "frame": {"x": 0, "y": 0, "w": 2, "h": 2}},
+                                {"filename": "b", "frame": {"x": 2, "y": 0, "w": 2, "h": 2}}],
+                     "meta": {"size": {"w": 4, "h": 2}}}"#;
+    let atlas = SpriteAtlasFile::init_texture_packer(array).unwrap();
+    let names: Vec<&str> = atlas.pages[0]
+        .regions
+        .iter()
+        .map(|r| r.name.as_str())
+        .collect();
+    assert_eq!(names, ["a", "b"]);
+    assert_eq!(atlas.pages[0].regions[1].quad(4, 2)[4 * 4 + 2], 0.5);
+    assert!(SpriteAtlasFile::init_texture_packer(br#"{"frames": [{"frame": {}}]}"#).is_err());
+
+    let old = b"
+sheet.png
+size: 64, 64
+format: RGBA8888
+filter: Nearest,Nearest
+repeat: none
+walk
+  rotate: true
+  xy: 2, 4
+  size: 10, 6
+  orig: 12, 10
+  offset: 1, 1
+  index: 1
+walk
+  rotate: false
+  xy: 20, 4
+  size: 12, 10
+  orig: 12, 10
+  offset: 0, 0
+  index: 0
+
+second.png
+size: 8,8
+dot
+  xy: 0, 0
+  size: 8, 8
+";
+    let atlas = SpriteAtlasFile::init_libgdx(old).unwrap();
+    assert_eq!(atlas.pages.len(), 2);
+    assert_eq!(
+        (atlas.pages[0].width, atlas.pages[1].image.as_str()),
+        (64, "second.png")
+    );
+    let walk = &atlas.pages[0].regions[0];
+    assert_eq!(
+        (walk.x, walk.y, walk.width, walk.height, walk.rotation),
+        (2, 4, 10, 6, 270)
+    );
+    // offsets start at the bottom: 10 - 6 - 1.
+    assert_eq!(
+        (walk.trim_x, walk.trim_y, walk.source_width, walk.index),
+        (1, 3, 12, 1)
+    );
+    assert_eq!(atlas.pages[1].regions[0].source_height, 8);
+
+    let new = b"sheet.png\nsize:64,64\nfilter:Linear,Linear\nwalk\nbounds:2,4,10,6\noffsets:1,1,12,10\nrotate:90\nindex:3\n";
+    let atlas = SpriteAtlasFile::init_libgdx(new).unwrap();
+    let walk = &atlas.pages[0].regions[0];
+    assert_eq!(
+        (walk.x, walk.width, walk.rotation, walk.trim_y, walk.index),
+        (2, 10, 270, 3, 3)
+    );
+    assert!(SpriteAtlasFile::init_libgdx(b"").is_err());
+    assert!(SpriteAtlasFile::init_libgdx(b"sheet.png\nsize: 64, x\nr\n").is_err());
+}
+
+#[test]
+fn qoi_round_trip_against_png() {
+    // gradients, noise, flat runs longer than 62 pixels and changing alpha.
+    let mut reference: Image = Image::init_blank(70, 9, 4);
+    let mut seed = 7u32;
+    reference.map(|x, y, px| {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        let noise = (seed >> 24) as u8;
+        px.copy_from_slice(&match y {
+            0 => [0, 0, 0, 255],
+            1 | 2 => [x as u8 * 3, 255 - x as u8, 40, 255],
+            3 => [noise, noise / 2, 255 - noise, 255],
+            4 => [10, 20, 30, (x * 3) as u8],
+            _ => [noise, (x * y) as u8, y as u8 * 20, noise / 3],
+        });
+    });
+
+    for channels in [4, 3, 1] {
+        let image = reference.convert_channels(channels).unwrap();
+        let from_png = Image::init(&image.encode_png().unwrap()).unwrap();
+
+        let qoi = from_png.encode_qoi().unwrap();
+        assert_eq!(&qoi[..4], b"qoif");
+        let decoded = Image::init(&qoi).unwrap();
+
+        // gray is stored as rgb.
+        assert_eq!(
+            decoded,
+            from_png.convert_channels(decoded.channels).unwrap()
+        );
+        assert_eq!(Image::probe(&qoi).unwrap().format, ImageFileFormat::Qoi);
+        assert!(Image::init(&qoi[..qoi.len() - 12]).is_err());
+    }
+
+    // run, diff, luma and luma again, as the reference encoder writes them.
+    let image = Image {
+        raw: vec![0, 0, 0, 255, 1, 0, 0, 255, 10, 5, 3, 255, 0, 0, 0, 255],
+        width: 4,
+        height: 1,
+        channels: 4,
+    };
+    let qoi = image.encode_qoi().unwrap();
+    assert_eq!(
+        &qoi[14..qoi.len() - 8],
+        &[0xC0, 0x7A, 0xA5, 0xC6, 0x9B, 0x3A]
+    );
+    assert_eq!(Image::init(&qoi).unwrap(), image);
+
+    let flipped = Image::init(&image.encode(ImageFormat::Qoi, true).unwrap()).unwrap();
+    assert_eq!(flipped, image);
+    assert!(Image::init(b"qoif\0\0\0\0\0\0\0\x01\x03\0").is_err());
+}
+
+fn wav_file(tag: u16, channels: u16, sample_rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+    let mut fmt = Vec::new();
+    fmt.extend(tag.to_le_bytes());
+    fmt.extend(channels.to_le_bytes());
+    fmt.extend(sample_rate.to_le_bytes());
+    fmt.extend((sample_rate * (channels * bits / 8) as u32).to_le_bytes());
+    fmt.extend((channels * bits / 8).to_le_bytes());
+    fmt.extend(bits.to_le_bytes());
+    if tag == 0xFFFE {
+        fmt.extend(22u16.to_le_bytes());
+        fmt.extend(bits.to_le_bytes());
+        fmt.extend(3u32.to_le_bytes());
+        // KSDATAFORMAT_SUBTYPE_PCM.
+        fmt.extend([
+            1, 0, 0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71,
+        ]);
+    }
+
+    let mut body = b"WAVE".to_vec();
+    for (id, chunk) in [
+        (b"fmt ", fmt),
+        (b"LIST", vec![1, 2, 3]),
+        (b"data", data.to_vec()),
+    ] {
+        body.extend(id);
+        body.extend((chunk.len() as u32).to_le_bytes());
+        body.extend(&chunk);
+        if chunk.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+    [
+        b"RIFF".to_vec(),
+        (body.len() as u32).to_le_bytes().to_vec(),
+        body,
+    ]
+    .concat()
+}
+
+/// a silent ogg vorbis stream: 256 sample blocks, a one entry setup and audio packets with the
+/// floors marked unused. the last granule position cuts the stream to `frames`.
+fn vorbis_file(channels: u8, sample_rate: u32, frames: u64) -> Vec<u8> {
+    #[derive(Default)]
+    struct Bits(Vec<u8>, usize);
+    impl Bits {
+        fn put(&mut self, value: u32, count: usize) {
+            for i in 0..count {
+                if self.1.is_multiple_of(8) {
+                    self.0.push(0);
+                }
+                let last = self.0.len() - 1;
+                self.0[last] |= ((value >> i & 1) as u8) << (self.1 % 8);
+                self.1 += 1;
+            }
+        }
+    }
+    let header = |kind: u8| [&[kind][..], b"vorbis"].concat();
+
+    let mut id = header(1);
+    id.extend(0u32.to_le_bytes());
+    id.push(channels);
+    id.extend(sample_rate.to_le_bytes());
+    id.extend([0; 12]);
+    id.extend([0x88, 1]);
+
+    let mut comment = header(3);
+    comment.extend(2u32.to_le_bytes());
+    comment.extend(b"gg");
+    comment.extend(0u32.to_le_bytes());
+    comment.push(1);
+
+    let mut setup = Bits::default();
+    for byte in header(5) {
+        setup.put(byte as u32, 8);
+    }
+    // one codebook, 1 dimension and 2 entries of length 1, no lookup.
+    for (value, count) in [
+        (0, 8),
+        (0x564342, 24),
+        (1, 16),
+        (2, 24),
+        (0, 2),
+        (0, 10),
+        (0, 4),
+    ] {
+        setup.put(value, count);
+    }
+    // time domain, a floor 1 without partitions, a residue 0 with one class and no books.
+    for (value, count) in [(0, 6), (0, 16), (0, 6), (1, 16), (0, 5), (0, 2), (4, 4)] {
+        setup.put(value, count);
+    }
+    for (value, count) in [
+        (0, 6),
+        (0, 16),
+        (0, 24),
+        (256, 24),
+        (31, 24),
+        (0, 6),
+        (0, 8),
+    ] {
+        setup.put(value, count);
+    }
+    setup.put(0, 4);
+    // one mapping and one short block mode.
+    for (value, count) in [
+        (0, 6),
+        (0, 16),
+        (0, 4),
+        (0, 24),
+        (0, 6),
+        (0, 1),
+        (0, 32),
+        (0, 8),
+    ] {
+        setup.put(value, count);
+    }
+    setup.put(1, 1);
+
+    let mut file = Vec::new();
+    let mut page = |flags: u8, granule: u64, packets: &[Vec<u8>]| {
+        let mut bytes = b"OggS\0".to_vec();
+        bytes.push(flags);
+        bytes.extend(granule.to_le_bytes());
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend([0; 4]);
+        let lacing: Vec<u8> = packets
+            .iter()
+            .flat_map(|packet| {
+                let mut lacing = vec![255; packet.len() / 255];
+                lacing.push((packet.len() % 255) as u8);
+                lacing
+            })
+            .collect();
+        bytes.push(lacing.len() as u8);
+        bytes.extend(lacing);
+        bytes.extend(packets.concat());
+        let crc = bytes.iter().fold(0u32, |mut crc, &byte| {
+            crc ^= (byte as u32) << 24;
+            for _ in 0..8 {
+                crc = if crc & 0x8000_0000 != 0 {
+                    crc << 1 ^ 0x04C1_1DB7
+                } else {
+                    crc << 1
+                };
+            }
+            crc
+        });
+        bytes[22..26].copy_from_slice(&crc.to_le_bytes());
+        file.extend(bytes);
+    };
+    page(2, 0, &[id]);
+    page(0, 0, &[comment, setup.0]);
+    // every block after the first one adds 128 frames.
+    let blocks = frames.div_ceil(128) as usize + 1;
+    page(4, frames, &vec![vec![0]; blocks]);
+    file
+}
+
+#[test]
+fn sound_decoding_and_conversion() {
+    use audio::Sound;
+
+    let expected = [0.5, -0.5, -1.0, 0.25];
+    let cases: [(u16, u16, Vec<u8>); 7] = [
+        (1, 8, vec![192, 64, 0, 160]),
+        (
+            1,
+            16,
+            [16384i16, -16384, -32768, 8192]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+        ),
+        (
+            1,
+            24,
+            [0x40_0000i32, -0x40_0000, -0x80_0000, 0x20_0000]
+                .iter()
+                .flat_map(|v| v.to_le_bytes()[..3].to_vec())
+                .collect(),
+        ),
+        (
+            1,
+            32,
+            [1i32 << 30, -(1 << 30), i32::MIN, 1 << 29]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+        ),
+        (
+            3,
+            32,
+            expected
+                .iter()
+                .flat_map(|v: &f32| v.to_le_bytes())
+                .collect(),
+        ),
+        (
+            3,
+            64,
+            expected
+                .iter()
+                .flat_map(|&v| (v as f64).to_le_bytes())
+                .collect(),
+        ),
+        (
+            0xFFFE,
+            16,
+            [16384i16, -16384, -32768, 8192]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+        ),
+    ];
+    for (tag, bits, data) in cases {
+        let sound = Sound::init(&wav_file(tag, 2, 22050, bits, &data)).unwrap();
+        assert_eq!(
+            (sound.channels, sound.sample_rate, sound.frames()),
+            (2, 22050, 2)
+        );
+        assert_eq!(sound.samples, expected, "format {tag} {bits} bits");
+    }
+
+    let stereo = Sound::init_wav(&wav_file(
+        3,
+        2,
+        4,
+        32,
+        &expected
+            .iter()
+            .flat_map(|v: &f32| v.to_le_bytes())
+            .collect::<Vec<u8>>(),
+    ))
+    .unwrap();
+    assert_eq!(stereo.duration(), 0.5);
+    assert_eq!(stereo.convert_channels(1).samples, [0.0, -0.375]);
+    assert_eq!(
+        stereo.convert_channels(1).convert_channels(2).samples,
+        [0.0, 0.0, -0.375, -0.375]
+    );
+    assert_eq!(stereo.to_i16(), [16384, -16384, -32767, 8192]);
+
+    // catmull-rom keeps a ramp a ramp.
+    let ramp = Sound {
+        samples: vec![0.0, 0.2, 0.4, 0.6, 0.8],
+        sample_rate: 1000,
+        channels: 1,
+    };
+    let up = ramp.resample(2000);
+    assert_eq!((up.frames(), up.sample_rate), (10, 2000));
+    for (i, &v) in up.samples.iter().enumerate().take(6).skip(2) {
+        assert!((v - i as f32 * 0.1).abs() < 1e-6, "{i} {v}");
+    }
+    let down = Sound {
+        sample_rate: 44100,
+        ..Sound {
+            samples: vec![0.25; 441 * 2],
+            ..stereo.clone()
+        }
+    }
+    .to_device(48000, 2);
+    assert_eq!(down.frames(), 480);
+    assert!(down.samples.iter().all(|&v| (v - 0.25).abs() < 1e-6));
+
+    assert!(Sound::init(b"RIFF\0\0\0\0WAVEdata\0\0\0\0").is_err());
+    assert!(Sound::init_wav(&wav_file(1, 1, 8000, 12, &[0, 0])).is_err());
+    let ogg = Sound::init(&vorbis_file(2, 22050, 600)).unwrap();
+    assert_eq!(
+        (ogg.channels, ogg.sample_rate, ogg.frames()),
+        (2, 22050, 600)
+    );
+    assert!(ogg.samples.iter().all(|&v| v == 0.0));
+    let mono = Sound::init_ogg(&vorbis_file(1, 8000, 256)).unwrap();
+    assert_eq!(
+        (mono.channels, mono.sample_rate, mono.frames()),
+        (1, 8000, 256)
+    );
+    assert!(Sound::init(b"OggS\0\x02not really vorbis").is_err());
+    assert!(Sound::init(b"ID3").is_err());
+}
+
+#[test]
+fn music_streaming_loops_and_crossfade() {
+    use audio::{MusicPlayer, MusicTrack};
+
+    let float_wav = |values: &[f32]| {
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        wav_file(3, 1, 1000, 32, &data)
+    };
+    let ramp: Vec<f32> = (0..8).map(|i| i as f32 / 8.0).collect();
+    let frames = |values: &[usize]| values.iter().map(|&i| ramp[i]).collect::<Vec<f32>>();
+
+    // intro 0, 1 then 2 .. 4 over and over, sample accurate.
+    let mut track = MusicTrack::init(float_wav(&ramp)).unwrap();
+    assert_eq!(
+        (track.frames(), track.channels(), track.sample_rate()),
+        (8, 1, 1000)
+    );
+    track.set_loop(2, 5);
+    let mut player = MusicPlayer::init(1000, 1);
+    player.play(track);
+    let mut out = vec![0.0; 10];
+    player.mix(&mut out);
+    assert_eq!(out, frames(&[0, 1, 2, 3, 4, 2, 3, 4, 2, 3]));
+
+    // paused players add nothing, resuming picks up where it stopped.
+    player.pause();
+    assert!(player.is_paused());
+    let mut out = vec![0.0; 3];
+    player.mix(&mut out);
+    assert_eq!(out, [0.0; 3]);
+    player.resume();
+    player.mix(&mut out);
+    assert_eq!(out, frames(&[4, 2, 3]));
+
+    // seeking, then the end of a track that does not loop, mono copied to stereo.
+    let mut player = MusicPlayer::init(1000, 2);
+    player.play(MusicTrack::init(float_wav(&ramp)).unwrap());
+    player.seek(0.006).unwrap();
+    assert!((player.position() - 0.006).abs() < 1e-9);
+    let mut out = vec![0.0; 8];
+    player.mix(&mut out);
+    assert_eq!(
+        out,
+        [ramp[6], ramp[6], ramp[7], ramp[7], 0.0, 0.0, 0.0, 0.0]
+    );
+    assert!(!player.is_playing());
+    assert!(player.seek(0.0).is_err());
+
+    // twice the rate, twice the frames, source frames land on the even ones.
+    let mut player = MusicPlayer::init(2000, 1);
+    player.play(MusicTrack::init(float_wav(&ramp)).unwrap());
+    let mut out = vec![0.0; 16];
+    player.mix(&mut out);
+    for (i, v) in ramp.iter().enumerate() {
+        assert_eq!(out[i * 2], *v);
+    }
+    assert!((out[3] - 0.1875).abs() < 1e-6);
+
+    // equal power crossfade from a constant 1.0 to a constant -1.0 over 10 frames.
+    let mut one = MusicTrack::init(float_wav(&[1.0; 64])).unwrap();
+    one.set_loop(0, 64);
+    let mut player = MusicPlayer::init(1000, 1);
+    player.play(one);
+    player.crossfade(MusicTrack::init(float_wav(&[-1.0; 64])).unwrap(), 0.01);
+    let mut out = vec![0.0; 16];
+    player.mix(&mut out);
+    assert_eq!(out[0], 1.0);
+    assert!(out[5].abs() < 1e-6);
+    assert!(out[3] > 0.0 && out[7] < 0.0);
+    assert_eq!(out[10..], [-1.0; 6]);
+
+    // play during a crossfade starts at full volume.
+    player.crossfade(MusicTrack::init(float_wav(&[1.0; 64])).unwrap(), 1.0);
+    player.play(MusicTrack::init(float_wav(&[0.5; 64])).unwrap());
+    let mut out = vec![0.0; 4];
+    player.mix(&mut out);
+    assert_eq!(out, [0.5; 4]);
+
+    // streamed from the disk, chunks bigger than the file.
+    let path = std::env::temp_dir().join("gg_engine_music_test.wav");
+    std::fs::write(&path, float_wav(&ramp)).unwrap();
+    let mut track = MusicTrack::init_file(path.to_str().unwrap()).unwrap();
+    track.set_loop(6, 100);
+    let mut player = MusicPlayer::init(1000, 1);
+    player.play(track);
+    let mut out = vec![0.0; 11];
+    player.mix(&mut out);
+    assert_eq!(out, frames(&[0, 1, 2, 3, 4, 5, 6, 7, 6, 7, 6]));
+    let _ = std::fs::remove_file(&path);
+
+    assert!(MusicTrack::init(b"OggS\0\x02not really vorbis".to_vec()).is_err());
+    assert!(MusicTrack::init(b"ID3".to_vec()).is_err());
+    assert!(MusicTrack::init_file("missing/music.ogg").is_err());
+}
+
+#[test]
+fn mixer_voice_controls() {
+    use audio::{Mixer, SoundHandle, VOICES};
+    use std::sync::Arc;
+
+    // stereo frames of a mono ramp 0.1, 0.2 ..
+    let ramp: Arc<[f32]> = (1..=8).flat_map(|i| [i as f32 / 10.0; 2]).collect();
+    let left = |out: &[f32]| out.iter().step_by(2).copied().collect::<Vec<f32>>();
+    let close = |a: &[f32], b: &[f32]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6)
+    };
+
+    // plays once with `loops` 0, then the channel is free again.
+    let mut mixer = Mixer::init(1000, 2);
+    let handle = mixer.play(ramp.clone(), -1, 0);
+    assert_eq!(handle.channel(), 0);
+    let mut out = vec![0.0; 20];
+    mixer.mix(&mut out);
+    assert!(close(
+        &left(&out),
+        &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.0, 0.0]
+    ));
+    assert!(!mixer.is_playing(handle) && !mixer.is_channel_playing(-1));
+
+    // one extra loop, volume, pan and master volume.
+    let handle = mixer.play(ramp.clone(), 3, 1);
+    mixer.set_volume(handle, 0.5);
+    mixer.set_pan(handle, -0.5);
+    mixer.set_master_volume(0.5);
+    let mut out = vec![0.0; 36];
+    mixer.mix(&mut out);
+    assert!(close(&out[..4], &[0.025, 0.0125, 0.05, 0.025]));
+    assert!(close(&out[28..32], &[0.175, 0.0875, 0.2, 0.1]));
+    assert!(close(&out[32..], &[0.0; 4]));
+    mixer.set_master_volume(1.0);
+
+    // twice the pitch skips every other frame, half of it interpolates.
+    let fast = mixer.play(ramp.clone(), -1, 0);
+    mixer.set_pitch(fast, 2.0);
+    let mut out = vec![0.0; 10];
+    mixer.mix(&mut out);
+    assert!(close(&left(&out), &[0.1, 0.3, 0.5, 0.7, 0.0]));
+    let slow = mixer.play(ramp.clone(), -1, 0);
+    mixer.set_pitch(slow, 0.5);
+    let mut out = vec![0.0; 6];
+    mixer.mix(&mut out);
+    assert!(close(&left(&out), &[0.1, 0.15, 0.2]));
+
+    // paused voices and the global pause hold their position.
+    mixer.pause(slow);
+    let mut out = vec![0.0; 2];
+    mixer.mix(&mut out);
+    assert_eq!(out, [0.0; 2]);
+    mixer.resume(slow);
+    mixer.pause_all();
+    mixer.mix(&mut out);
+    assert_eq!(out, [0.0; 2]);
+    mixer.resume_all();
+    mixer.mix(&mut out);
+    assert!(close(&out, &[0.25, 0.25]));
+
+    // a fade reaches silence and stops the voice, a handle never controls the next sound.
+    mixer.fade_out(slow, 0.004);
+    let mut out = vec![0.0; 10];
+    mixer.mix(&mut out);
+    assert!(close(&left(&out), &[0.3, 0.2625, 0.2, 0.1125, 0.0]));
+    assert!(!mixer.is_playing(slow));
+    let next = mixer.play(ramp.clone(), slow.channel(), 0);
+    assert_eq!(next.channel(), slow.channel());
+    mixer.stop(slow);
+    mixer.set_volume(slow, 0.0);
+    assert!(mixer.is_playing(next));
+    mixer.stop(next);
+    assert!(!mixer.is_channel_playing(next.channel()));
+
+    // every channel taken.
+    for _ in 0..VOICES {
+        assert_ne!(mixer.play(ramp.clone(), -1, -1), SoundHandle::NONE);
+    }
+    assert_eq!(mixer.play(ramp.clone(), -1, 0), SoundHandle::NONE);
+    assert!(!mixer.is_playing(SoundHandle::NONE));
+    mixer.stop_all();
+    assert!(!mixer.is_channel_playing(-1));
+}
+
+#[test]
+fn buses_and_ducking() {
+    use audio::{AudioStream, BusId, DuckRule, Mixer};
+    use std::sync::{Arc, Mutex};
+
+    struct Constant(f32);
+    impl AudioStream for Constant {
+        fn mix(&mut self, out: &mut [f32]) {
+            out.iter_mut().for_each(|o| *o += self.0);
+        }
+        fn is_active(&self) -> bool {
+            true
+        }
+    }
+    let close = |a: &[f32], b: &[f32]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6)
+    };
+    let ones: Arc<[f32]> = Arc::new([1.0; 4]);
+
+    // volumes multiply down the tree, sounds play on sfx unless told otherwise.
+    let mut mixer = Mixer::init(1000, 1);
+    let footsteps = mixer.add_bus("footsteps", BusId::SFX);
+    assert_eq!(mixer.find_bus("footsteps"), Some(footsteps));
+    assert_eq!(mixer.find_bus("voice"), Some(BusId::VOICE));
+    assert_eq!(mixer.find_bus("missing"), None);
+    mixer.set_master_volume(0.5);
+    mixer.set_bus_volume(BusId::SFX, 0.5);
+    mixer.set_bus_volume(footsteps, 0.5);
+    mixer.set_bus_volume(BusId::UI, 0.0);
+    mixer.play(ones.clone(), -1, 0);
+    mixer.play_on(footsteps, ones.clone(), -1, 0);
+    mixer.play_on(BusId::UI, ones.clone(), -1, 0);
+    let mut out = vec![0.0; 2];
+    mixer.mix(&mut out);
+    assert!(close(&out, &[0.375, 0.375]));
+    assert!((mixer.bus_gain(footsteps) - 0.125).abs() < 1e-6);
+    assert_eq!(mixer.bus_volume(footsteps), 0.5);
+
+    // music ducks to 0.25 over 4 frames while a line plays on the voice bus, then comes back
+    // over 8 frames.
+    let mut mixer = Mixer::init(1000, 1);
+    let music: Arc<Mutex<dyn AudioStream>> = Arc::new(Mutex::new(Constant(1.0)));
+    mixer.add_stream(BusId::MUSIC, Arc::downgrade(&music));
+    mixer.add_ducking(DuckRule {
+        trigger: BusId::VOICE,
+        target: BusId::MUSIC,
+        gain: 0.25,
+        attack: 0.004,
+        release: 0.008,
+    });
+    let mut out = vec![0.0; 2];
+    mixer.mix(&mut out);
+    assert_eq!(out, [1.0, 1.0]);
+
+    let line = mixer.play_on(BusId::VOICE, Arc::new([0.0; 6]), -1, 0);
+    let mut out = vec![0.0; 6];
+    mixer.mix(&mut out);
+    assert!(close(&out, &[0.8125, 0.625, 0.4375, 0.25, 0.25, 0.25]));
+    assert!((mixer.bus_gain(BusId::MUSIC) - 0.25).abs() < 1e-6);
+    assert!(!mixer.is_playing(line));
+    let mut out = vec![0.0; 10];
+    mixer.mix(&mut out);
+    assert!(close(&out[..3], &[0.34375, 0.4375, 0.53125]));
+    assert!(close(&out[7..], &[1.0; 3]));
+
+    // a paused voice does not duck, a dropped stream goes away.
+    let line = mixer.play_on(BusId::VOICE, Arc::new([0.0; 6]), -1, -1);
+    mixer.pause(line);
+    let mut out = vec![0.0; 2];
+    mixer.mix(&mut out);
+    assert_eq!(out, [1.0, 1.0]);
+    mixer.clear_ducking();
+    mixer.resume(line);
+    mixer.mix(&mut out);
+    assert_eq!(out, [2.0, 2.0]);
+    drop(music);
+    let mut out = vec![0.0; 2];
+    mixer.mix(&mut out);
+    assert_eq!(out, [0.0, 0.0]);
+}
+
+#[test]
+fn positional_audio() {
+    use audio::{Attenuation, BusId, Listener, Mixer, Spatial};
+    use std::sync::Arc;
+
+    let close = |a: &[f32], b: &[f32]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6)
+    };
+
+    let linear = Spatial::init(Attenuation::Linear, 1.0, 11.0);
+    assert_eq!(linear.gain(0.5), 1.0);
+    assert!((linear.gain(6.0) - 0.5).abs() < 1e-6);
+    assert_eq!(linear.gain(11.0), 0.0);
+    let inverse = Spatial::init(Attenuation::Inverse, 2.0, 100.0);
+    assert!((inverse.gain(4.0) - 0.5).abs() < 1e-6);
+    assert!((inverse.gain(8.0) - 0.25).abs() < 1e-6);
+    assert_eq!(inverse.gain(100.5), 0.0);
+    let exponential = Spatial {
+        rolloff: 2.0,
+        ..Spatial::init(Attenuation::Exponential, 2.0, 100.0)
+    };
+    assert!((exponential.gain(4.0) - 0.25).abs() < 1e-6);
+    assert_eq!(exponential.gain(2.0), 1.0);
+
+    // 2d: only the side matters for the pan, the distance for the gain.
+    let listener = Listener::init_2d(vec2(100.0, 100.0));
+    let (gain, pan) = listener.locate(vec3(50.0, 100.0, 0.0), &linear);
+    assert_eq!((gain, pan), (0.0, -1.0));
+    let (gain, pan) = listener.locate(vec3(100.0, 94.0, 0.0), &linear);
+    assert!((gain - 0.5).abs() < 1e-6 && pan.abs() < 1e-6);
+    assert_eq!(
+        listener.locate(vec3(100.0, 100.0, 0.0), &linear),
+        (1.0, 0.0)
+    );
+
+    // 3d, from the arguments of the view matrix.
+    let listener =
+        Listener::init_look_at(Vec3::default(), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0));
+    let (gain, pan) = listener.locate(vec3(0.0, 0.0, 4.0), &inverse);
+    assert!((gain - 0.5).abs() < 1e-6 && (pan - 1.0).abs() < 1e-6);
+    let (_, pan) = listener.locate(vec3(3.0, 0.0, -3.0), &inverse);
+    assert!((pan + std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+    // a moving source ramps to its new gains over the next mix.
+    let mut mixer = Mixer::init(1000, 2);
+    mixer.set_listener(Listener::init_2d(Vec2::default()));
+    let near = Spatial::init(Attenuation::Linear, 10.0, 20.0);
+    let ones: Arc<[f32]> = Arc::new([1.0; 2]);
+    let handle = mixer.play_at(BusId::SFX, ones, vec3(-10.0, 0.0, 0.0), near, -1);
+    let mut out = vec![0.0; 4];
+    mixer.mix(&mut out);
+    assert!(close(&out, &[1.0, 0.0, 1.0, 0.0]));
+
+    mixer.set_position(handle, vec3(15.0, 0.0, 0.0));
+    let mut out = vec![0.0; 8];
+    mixer.mix(&mut out);
+    assert!(close(
+        &out,
+        &[0.75, 0.125, 0.5, 0.25, 0.25, 0.375, 0.0, 0.5]
+    ));
+    let mut out = vec![0.0; 2];
+    mixer.mix(&mut out);
+    assert!(close(&out, &[0.0, 0.5]));
+
+    // so does a moving listener, past the max distance is silent.
+    mixer.set_listener(Listener::init_2d(vec2(15.0, 0.0)));
+    let mut out = vec![0.0; 2];
+    mixer.mix(&mut out);
+    assert!(close(&out, &[1.0, 1.0]));
+    mixer.set_spatial(handle, Spatial::init(Attenuation::Inverse, 1.0, 5.0));
+    mixer.set_listener(Listener::init_2d(vec2(-15.0, 0.0)));
+    let mut out = vec![0.0; 2];
+    mixer.mix(&mut out);
+    assert!(close(&out, &[0.0, 0.0]));
+    assert!(mixer.is_playing(handle));
+}
+
+#[test]
+fn mixer_outputs() {
+    use audio::{BufferOutput, Mixer, NullOutput, Output};
+    use std::sync::Arc;
+
+    struct FakeDevice;
+    impl Output for FakeDevice {
+        fn is_device(&self) -> bool {
+            true
+        }
+        fn write(&mut self, _samples: &[f32]) {
+            panic!("devices pull the mix");
+        }
+    }
+
+    let buffer = BufferOutput::init();
+    let mut mixer = Mixer::init(1000, 2);
+    mixer.set_output(Box::new(buffer.clone()));
+
+    let a = mixer.play(Arc::new([0.5, 0.5, 0.25, 0.25, 0.125, 0.125]), -1, 0);
+    let b = mixer.play(Arc::new([0.25, -0.25]), -1, 1);
+    mixer.set_pan(a, 1.0);
+    mixer.render(2);
+    mixer.render(2);
+    assert_eq!(buffer.take(), [0.25, 0.25, 0.25, 0.0, 0.0, 0.125, 0.0, 0.0]);
+    assert!(!mixer.is_playing(a) && !mixer.is_playing(b));
+
+    // rendering keeps going with nothing playing, and after the output changed.
+    mixer.render(1);
+    assert_eq!(buffer.samples(), [0.0, 0.0]);
+    let previous = mixer.set_output(Box::new(NullOutput));
+    mixer.render(8);
+    assert_eq!(buffer.samples().len(), 2);
+    assert!(!previous.is_device());
+
+    mixer.set_output(Box::new(FakeDevice));
+    mixer.play(Arc::new([1.0; 2]), -1, 0);
+    mixer.render(4);
+    assert!(mixer.is_channel_playing(-1));
+}
+
+/// held by the tests that play on the engine mixer, so they do not hear each other.
+static ENGINE_MIXER: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[test]
+fn offline_render_to_wav() {
+    use audio::{
+        Audio, BufferOutput, Channels, NullOutput, OfflineRender, Output, Sound, WavEncoding,
+    };
+    use std::io::Cursor;
+    use std::sync::Arc;
+    let _engine = ENGINE_MIXER.lock().unwrap_or_else(|e| e.into_inner());
+
+    // writing and reading back, float is exact and pcm within one step.
+    let sound = Sound {
+        samples: vec![0.5, -0.5, 1.5, -0.25, 0.0, 0.125],
+        sample_rate: 22050,
+        channels: 2,
+    };
+    let float = Sound::init(&sound.encode_wav(WavEncoding::Float32).unwrap()).unwrap();
+    assert_eq!(float.samples, [0.5, -0.5, 1.0, -0.25, 0.0, 0.125]);
+    assert_eq!((float.sample_rate, float.channels), (22050, 2));
+    let pcm = sound.encode_wav(WavEncoding::Pcm16).unwrap();
+    assert_eq!(
+        u32::from_le_bytes([pcm[4], pcm[5], pcm[6], pcm[7]]) as usize,
+        pcm.len() - 8
+    );
+    let pcm = Sound::init(&pcm).unwrap();
+    for (a, b) in pcm.samples.iter().zip(&float.samples) {
+        assert!((a - b).abs() <= 1.0 / 32767.0);
+    }
+
+    // 50ms of a 1000 frame sound at 100 ticks a second, then silence.
+    let (sample_rate, channels) = audio::device_format();
+    let beep = Audio::init_sound(&Sound {
+        samples: vec![0.5; 1000],
+        sample_rate,
+        channels: 1,
+    });
+    let mut render =
+        OfflineRender::init(Cursor::new(Vec::new()), 100, WavEncoding::Float32).unwrap();
+    let handle = beep.play(-1, 0);
+    render.render_seconds(0.05).unwrap();
+    assert!(!handle.is_playing());
+    assert!((render.time() - 0.05).abs() < 1e-9);
+    let out = Sound::init(&render.finish().unwrap().into_inner()).unwrap();
+    assert_eq!(out.frames(), sample_rate as usize / 20);
+    assert_eq!(out.channels, channels);
+    let split = 1000 * channels as usize;
+    assert!(out.samples[..split].iter().all(|&v| v == 0.5));
+    assert!(out.samples[split..].iter().all(|&v| v == 0.0));
+
+    // ticks that do not divide the rate still add up to whole seconds, and the output from
+    // before the render is back after it.
+    let output = BufferOutput::init();
+    let previous = Channels::set_output(Box::new(output.clone()));
+    let mut render = OfflineRender::init(Cursor::new(Vec::new()), 7, WavEncoding::Pcm16).unwrap();
+    for _ in 0..7 {
+        render.tick().unwrap();
+    }
+    assert_eq!(render.time(), 1.0);
+    Channels::render(1);
+    assert!(output.take().is_empty());
+    render.finish().unwrap();
+    Channels::render(1);
+    assert_eq!(output.take().len(), channels as usize);
+    Channels::set_output(previous);
+    assert!(OfflineRender::init(Cursor::new(Vec::new()), 0, WavEncoding::Pcm16).is_err());
+
+    // a device is set aside without closing it and comes back when the render is dropped.
+    struct FakeDevice(Arc<()>);
+    impl Output for FakeDevice {
+        fn is_device(&self) -> bool {
+            true
+        }
+        fn write(&mut self, _samples: &[f32]) {
+            panic!("devices pull the mix");
+        }
+    }
+    let device = Arc::new(());
+    let previous = Channels::set_output(Box::new(FakeDevice(device.clone())));
+    let render = OfflineRender::init(Cursor::new(Vec::new()), 7, WavEncoding::Pcm16).unwrap();
+    assert!(OfflineRender::init(Cursor::new(Vec::new()), 7, WavEncoding::Pcm16).is_err());
+    assert!(!Channels::set_output(Box::new(NullOutput)).is_device());
+    assert_eq!(Arc::strong_count(&device), 2);
+    drop(render);
+    assert!(Channels::set_output(Box::new(FakeDevice(device.clone()))).is_device());
+
+    // a device opened during the render replaces the one set aside, not the render output.
+    let render = OfflineRender::init(Cursor::new(Vec::new()), 7, WavEncoding::Pcm16).unwrap();
+    Channels::open_device();
+    assert_eq!(Arc::strong_count(&device), 1);
+    assert!(!Channels::set_output(Box::new(NullOutput)).is_device());
+    render.finish().unwrap();
+    Channels::set_output(previous);
+}
+
+/// runs `effect` on `input` (mono) and returns the output.
+fn run_effect(effect: &mut dyn audio::Effect, input: &[f32], sample_rate: u32) -> Vec<f32> {
+    let mut buffer = input.to_vec();
+    effect.process(&mut buffer, 1, sample_rate);
+    buffer
+}
+
+fn sine(frequency: f32, sample_rate: u32, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+        .collect()
+}
+
+#[test]
+fn biquad_filters() {
+    use audio::{Biquad, Effect, FilterKind};
+
+    let rate = 48000;
+    let dc = vec![1.0; 4800];
+    let nyquist: Vec<f32> = (0..4800).map(|i| [1.0, -1.0][i % 2]).collect();
+    // the level once the filter settled.
+    let level = |effect: &mut dyn Effect, input: &[f32]| {
+        effect.reset();
+        let out = run_effect(effect, input, rate);
+        out[out.len() - 480..]
+            .iter()
+            .fold(0.0f32, |m, v| m.max(v.abs()))
+    };
+
+    let mut low = Biquad::low_pass(100.0);
+    assert!((level(&mut low, &dc) - 1.0).abs() < 1e-3);
+    assert!(level(&mut low, &nyquist) < 1e-3);
+
+    let mut high = Biquad::high_pass(100.0);
+    assert!(level(&mut high, &dc) < 1e-3);
+    assert!((level(&mut high, &nyquist) - 1.0).abs() < 1e-3);
+
+    let mut band = Biquad::band_pass(1000.0, 1.0);
+    assert!((level(&mut band, &sine(1000.0, rate, 4800)) - 1.0).abs() < 1e-2);
+    assert!(level(&mut band, &dc) < 1e-3);
+    assert!(level(&mut band, &sine(10000.0, rate, 4800)) < 0.2);
+
+    let boost = 10.0f32.powf(6.0 / 20.0);
+    let mut shelf = Biquad::low_shelf(200.0, 6.0);
+    assert!((level(&mut shelf, &dc) - boost).abs() < 1e-2);
+    assert!((level(&mut shelf, &nyquist) - 1.0).abs() < 1e-2);
+    let mut shelf = Biquad::high_shelf(200.0, 6.0);
+    assert!((level(&mut shelf, &dc) - 1.0).abs() < 1e-2);
+    assert!((level(&mut shelf, &nyquist) - boost).abs() < 1e-2);
+
+    // parameters by name, a new frequency moves the cutoff.
+    let mut low = Biquad::init(FilterKind::LowPass, 100.0, 0.7);
+    assert!(low.set_param("frequency", 20000.0));
+    assert!(!low.set_param("room_size", 1.0));
+    assert_eq!(low.param("frequency"), Some(20000.0));
+    assert!(level(&mut low, &sine(1000.0, rate, 4800)) > 0.99);
+
+    // the same input gives the same output after a reset.
+    let input = sine(440.0, rate, 1000);
+    low.reset();
+    let first = run_effect(&mut low, &input, rate);
+    low.reset();
+    assert_eq!(run_effect(&mut low, &input, rate), first);
+}
+
+#[test]
+fn reverb_effect() {
+    use audio::{Effect, Reverb};
+
+    // nothing wet is the dry signal as is.
+    let input = sine(440.0, 44100, 512);
+    let mut dry = Reverb::init(0.5, 0.5, 0.0);
+    assert_eq!(run_effect(&mut dry, &input, 44100), input);
+
+    // an impulse comes back after the shortest comb and decays.
+    let mut reverb = Reverb::init(0.8, 0.2, 1.0);
+    assert!(reverb.set_param("dry", 0.0));
+    let mut buffer = vec![0.0; 44100 * 2];
+    buffer[0] = 1.0;
+    buffer[1] = 1.0;
+    let impulse = buffer.clone();
+    reverb.process(&mut buffer, 2, 44100);
+    assert!(buffer[..1116 * 2].iter().all(|&v| v == 0.0));
+    let energy = |s: &[f32]| s.iter().map(|v| v * v).sum::<f32>();
+    let early = energy(&buffer[..22050]);
+    let late = energy(&buffer[66150..]);
+    assert!(early > 0.0 && late > 0.0 && late < early);
+    // the sides differ with the spread.
+    assert!(buffer.chunks_exact(2).any(|f| f[0] != f[1]));
+
+    reverb.reset();
+    let mut again = impulse;
+    reverb.process(&mut again, 2, 44100);
+    assert_eq!(again, buffer);
+}
+
+#[test]
+fn delay_effect() {
+    use audio::{Delay, Effect};
+
+    // 10 frames at 1000 Hz, every echo half the one before.
+    let mut delay = Delay::init(0.01, 0.5, 1.0);
+    let mut input = vec![0.0; 40];
+    input[0] = 1.0;
+    let out = run_effect(&mut delay, &input, 1000);
+    let mut expected = vec![0.0; 40];
+    expected[0] = 1.0;
+    expected[10] = 1.0;
+    expected[20] = 0.5;
+    expected[30] = 0.25;
+    assert_eq!(out, expected);
+
+    // the mix scales the echoes only.
+    let mut delay = Delay::init(0.005, 0.0, 0.25);
+    let out = run_effect(&mut delay, &input[..12], 1000);
+    assert_eq!(
+        out,
+        [1.0, 0.0, 0.0, 0.0, 0.0, 0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+    );
+    assert_eq!(delay.param("mix"), Some(0.25));
+
+    // a longer time keeps the echoes: the impulse comes back after 20 frames as well.
+    let mut delay = Delay::init(0.01, 0.5, 1.0);
+    let mut out = input.clone();
+    out.resize(45, 0.0);
+    let (first, second) = out.split_at_mut(15);
+    delay.process(first, 1, 1000);
+    assert!(delay.set_param("time", 0.02));
+    delay.process(second, 1, 1000);
+    let mut expected = vec![0.0; 45];
+    expected[0] = 1.0;
+    expected[10] = 1.0;
+    expected[20] = 1.0;
+    expected[30] = 0.5;
+    expected[40] = 0.5;
+    assert_eq!(out, expected);
+
+    // between two frames the echo is spread over both.
+    let mut delay = Delay::init(0.0025, 0.0, 1.0);
+    let out = run_effect(&mut delay, &input[..5], 1000);
+    assert_eq!(out, [1.0, 0.0, 0.5, 0.5, 0.0]);
+}
+
+#[test]
+fn bitcrusher_effect() {
+    use audio::{Bitcrusher, Effect};
+
+    // 2 bits are steps of 0.5.
+    let mut crusher = Bitcrusher::init(2.0, 1.0);
+    let out = run_effect(&mut crusher, &[0.1, 0.3, 0.74, -0.8], 1000);
+    assert_eq!(out, [0.0, 0.5, 0.5, -1.0]);
+
+    // every sample held for 2 frames, stereo frames stay together.
+    let mut crusher = Bitcrusher::init(24.0, 2.0);
+    let mut buffer = vec![0.1, -0.1, 0.9, -0.9, 0.6, -0.6, 0.2, -0.2];
+    crusher.process(&mut buffer, 2, 1000);
+    let expected = [0.1, -0.1, 0.1, -0.1, 0.6, -0.6, 0.6, -0.6];
+    assert!(buffer
+        .iter()
+        .zip(expected)
+        .all(|(a, b)| (a - b).abs() < 1e-6));
+}
+
+#[test]
+fn compressor_and_limiter() {
+    use audio::{Compressor, Effect};
+
+    // 6 dB over the threshold at 2:1 comes out 3 dB over, quiet parts are left alone.
+    let mut compressor = Compressor::init(-6.0, 2.0, 0.0, 0.0);
+    let out = run_effect(&mut compressor, &[1.0, 0.25], 1000);
+    assert!((out[0] - 10.0f32.powf(-3.0 / 20.0)).abs() < 1e-4);
+    assert!((out[1] - 0.25).abs() < 1e-6);
+
+    // nothing passes the ceiling of a limiter.
+    let ceiling = 10.0f32.powf(-6.0 / 20.0);
+    let mut limiter = Compressor::limiter(-6.0);
+    let out = run_effect(&mut limiter, &[1.0, 2.0, -4.0], 1000);
+    assert!(out.iter().all(|v| (v.abs() - ceiling).abs() < 1e-4));
+
+    // the attack lets the first frames through, the envelope then follows.
+    let mut slow = Compressor::init(-20.0, 4.0, 0.01, 0.1);
+    let out = run_effect(&mut slow, &[1.0; 100], 1000);
+    assert!(out[0] > out[50] && out[50] > out[99]);
+
+    // makeup gain.
+    let mut makeup = Compressor::init(0.0, 1.0, 0.0, 0.0);
+    assert!(makeup.set_param("makeup_db", 6.0));
+    let out = run_effect(&mut makeup, &[0.5], 1000);
+    assert!((out[0] - 0.5 * 10.0f32.powf(6.0 / 20.0)).abs() < 1e-4);
+}
+
+#[test]
+fn effect_chains_and_automation() {
+    use audio::{Bitcrusher, BusId, Compressor, Mixer};
+    use std::sync::Arc;
+
+    // the makeup of the sfx bus ramps to -6 dB over 64 frames, 32 frames at a time.
+    let mut mixer = Mixer::init(1000, 1);
+    let chain = mixer.bus_effects(BusId::SFX).unwrap();
+    let gain = chain.push(Box::new(Compressor::init(0.0, 1.0, 0.0, 0.0)));
+    let half = 20.0 * 0.5f32.log10();
+    assert!(chain.automate(gain, "makeup_db", half, 0.064));
+    assert!(!chain.automate(gain, "missing", 0.0, 1.0));
+    assert!(!chain.automate(7, "makeup_db", 0.0, 1.0));
+    mixer.play(Arc::new([1.0; 200]), -1, 0);
+    let mut out = vec![0.0; 96];
+    mixer.mix(&mut out);
+    assert!((out[0] - 1.0).abs() < 1e-4);
+    assert!((out[40] - 0.5f32.sqrt()).abs() < 1e-4);
+    assert!((out[80] - 0.5).abs() < 1e-4);
+    let chain = mixer.bus_effects(BusId::SFX).unwrap();
+    assert!((chain.param(gain, "makeup_db").unwrap() - half).abs() < 1e-6);
+
+    // a voice crushed to 1 bit goes silent, the other one plays on.
+    let mut mixer = Mixer::init(1000, 1);
+    let crushed = mixer.play(Arc::new([0.4; 8]), -1, 0);
+    mixer.play(Arc::new([0.25; 8]), -1, 0);
+    mixer
+        .voice_effects(crushed)
+        .unwrap()
+        .push(Box::new(Bitcrusher::init(1.0, 1.0)));
+    let mut out = vec![0.0; 4];
+    mixer.mix(&mut out);
+    assert_eq!(out, [0.25; 4]);
+
+    // a limiter on master holds two full voices at 0 dB, after the bus volumes.
+    let mut mixer = Mixer::init(1000, 1);
+    mixer
+        .bus_effects(BusId::MASTER)
+        .unwrap()
+        .push(Box::new(Compressor::limiter(0.0)));
+    mixer.play(Arc::new([1.0; 8]), -1, 0);
+    mixer.play(Arc::new([1.0; 8]), -1, 0);
+    let mut out = vec![0.0; 4];
+    mixer.mix(&mut out);
+    assert!(out.iter().all(|v| (v - 1.0).abs() < 1e-4));
+    mixer.set_master_volume(0.5);
+    let mut out = vec![0.0; 4];
+    mixer.mix(&mut out);
+    assert!(out.iter().all(|v| (v - 0.5).abs() < 1e-4));
+}
+
+#[test]
+fn sound_events_and_banks() {
+    use audio::{Audio, BufferOutput, BusId, Channels, Sound, SoundBank, SoundHandle, StealPolicy};
+    let _engine = ENGINE_MIXER.lock().unwrap_or_else(|e| e.into_inner());
+
+    let (sample_rate, _) = audio::device_format();
+    let loads = std::cell::RefCell::new(Vec::new());
+    // a second of a constant level per file.
+    let mut load = |name: &str| {
+        loads.borrow_mut().push(name.to_string());
+        let level = match name {
+            "a.wav" => 0.25,
+            "b.wav" => 0.5,
+            _ => return Err("missing"),
+        };
+        Ok(Audio::init_sound(&Sound {
+            samples: vec![level; sample_rate as usize],
+            sample_rate,
+            channels: 1,
+        }))
+    };
+    let json = br#"{
+        "step": { "sounds": ["a.wav", "b.wav", "a.wav"], "volume": [0.5, 1.0],
+                  "pitch": [0.9, 1.1], "cooldown": 0.1, "max_instances": 2 },
+        "shot": { "sounds": ["b.wav"], "max_instances": 2, "steal": "quietest", "bus": "ui" },
+        "once": { "sounds": ["a.wav"], "volume": 0.5, "max_instances": 1, "steal": "none" }
+    }"#;
+    let mut bank = SoundBank::init_json(json, &mut load).unwrap();
+    assert_eq!(*loads.borrow(), ["a.wav", "b.wav"]);
+    let step = bank.event("step").unwrap();
+    assert_eq!(
+        (step.sounds.len(), step.volume, step.pitch),
+        (3, (0.5, 1.0), (0.9, 1.1))
+    );
+    assert_eq!((step.max_instances, step.steal), (2, StealPolicy::Oldest));
+    let shot = bank.event_by_hash(one_at_a_time_hash("shot")).unwrap();
+    assert_eq!((shot.steal, shot.bus), (StealPolicy::Quietest, BusId::UI));
+    assert_eq!(bank.event("once").unwrap().volume, (0.5, 0.5));
+    assert!(bank.event("missing").is_none());
+    let output = BufferOutput::init();
+    let previous = Channels::set_output(Box::new(output.clone()));
+
+    for bad in [
+        &br#"[]"#[..],
+        br#"{ "x": { "volume": 1 } }"#,
+        br#"{ "x": { "sounds": ["c.wav"] } }"#,
+        br#"{ "x": { "sounds": ["a.wav"], "steal": "newest" } }"#,
+        br#"{ "x": { "sounds": ["a.wav"], "bus": "nowhere" } }"#,
+        br#"{ "x": { "sounds": ["a.wav"], "pitch": [1] } }"#,
+    ] {
+        assert!(SoundBank::init_json(bad, &mut load).is_err());
+    }
+
+    // the cooldown holds the event until the clock passed it.
+    let first = bank.play("step");
+    assert_ne!(first, SoundHandle::NONE);
+    assert_eq!(bank.play("step"), SoundHandle::NONE);
+    bank.update(0.1);
+    let second = bank.play("step");
+    assert_ne!(second, SoundHandle::NONE);
+    assert_eq!(bank.instances("step"), 2);
+
+    // a third instance steals the oldest, which fades out.
+    bank.update(0.1);
+    let third = bank.play("step");
+    assert_ne!(third, SoundHandle::NONE);
+    assert_eq!(bank.instances("step"), 2);
+    Channels::render(sample_rate as usize / 100);
+    assert!(!first.is_playing());
+    assert!(second.is_playing() && third.is_playing());
+
+    // the quietest goes first.
+    let shot = |bank: &mut SoundBank, volume: f32| {
+        bank.event_mut("shot").unwrap().volume = (volume, volume);
+        bank.play("shot")
+    };
+    let loud = shot(&mut bank, 0.3);
+    let quiet = shot(&mut bank, 0.1);
+    let louder = shot(&mut bank, 0.5);
+    Channels::render(sample_rate as usize / 100);
+    assert!(loud.is_playing() && louder.is_playing());
+    assert!(!quiet.is_playing());
+
+    // no stealing, the new one is dropped, triggered by hash.
+    let once = bank.play_by_hash(one_at_a_time_hash("once"));
+    assert_ne!(once, SoundHandle::NONE);
+    assert_eq!(bank.play("once"), SoundHandle::NONE);
+    once.stop();
+    assert_ne!(bank.play_at("once", vec3(1.0, 0.0, 0.0)), SoundHandle::NONE);
+    assert_eq!(bank.play("missing"), SoundHandle::NONE);
+    Channels::stop_all();
+
+    // nothing is stolen while every voice is busy.
+    bank.update(1.0);
+    let first = bank.play("step");
+    bank.update(1.0);
+    let second = bank.play("step");
+    let filler = load("a.wav").unwrap();
+    while filler.play(-1, 0) != SoundHandle::NONE {}
+    bank.update(1.0);
+    assert_eq!(bank.play("step"), SoundHandle::NONE);
+    assert_eq!(bank.instances("step"), 2);
+    Channels::render(sample_rate as usize / 100);
+    assert!(first.is_playing() && second.is_playing());
+    Channels::stop_all();
+
+    // the same seed picks the same volumes, always in range.
+    output.take();
+    let mut levels = |seed: u32| {
+        let mut bank = SoundBank::init_json(json, &mut load).unwrap();
+        bank.set_seed(seed);
+        (0..8)
+            .map(|_| {
+                bank.update(1.0);
+                let handle = bank.play("step");
+                Channels::render(1);
+                handle.stop();
+                output.take()[0]
+            })
+            .collect::<Vec<f32>>()
+    };
+    let first = levels(7);
+    assert_eq!(levels(7), first);
+    assert_ne!(levels(8), first);
+    for level in first {
+        // a.wav or b.wav at 0.5 to 1.0.
+        assert!((0.125..=0.25).contains(&level) || (0.25..=0.5).contains(&level));
+    }
+    Channels::set_output(previous);
+}
+
+#[test]
+fn audio_init_and_device_selection() {
+    use audio::{AudioConfig, Channels, Sound};
+    let _engine = ENGINE_MIXER.lock().unwrap_or_else(|e| e.into_inner());
+
+    // the format is chosen before anything is loaded, a running mixer keeps its own.
+    audio::shutdown();
+    let config = AudioConfig {
+        sample_rate: 22050,
+        channels: 1,
+        buffer_frames: 512,
+        ..AudioConfig::default()
+    };
+    assert!(audio::init(config.clone()).is_ok());
+    assert_eq!(audio::device_format(), (22050, 1));
+    assert!(audio::init(AudioConfig::default()).is_err());
+    assert_eq!(audio::config(), config);
+    let beep = audio::Audio::init_sound(&Sound {
+        samples: vec![0.5; 100],
+        sample_rate: 44100,
+        channels: 2,
+    });
+    // looping, so that a device pulling the mix cannot finish it before the asserts.
+    let handle = beep.play(-1, -1);
+    assert!(handle.is_playing());
+
+    // another device keeps the mixer and what plays on it.
+    audio::select_device(Some("USB headset"));
+    assert_eq!(audio::config().device.as_deref(), Some("USB headset"));
+    assert!(handle.is_playing());
+    audio::select_device(None);
+    assert_eq!(audio::config().device, None);
+    assert!(audio::devices().iter().all(|name| !name.is_empty()));
+
+    // shutting down stops everything, the next use starts again with the config.
+    audio::shutdown();
+    assert!(!handle.is_playing());
+    assert!(!Channels::any_playing());
+    assert_eq!(audio::device_format(), (22050, 1));
+    audio::shutdown();
+    assert!(audio::init(AudioConfig::default()).is_ok());
+    assert_eq!(
+        audio::device_format(),
+        (audio::DEFAULT_FREQUENCY, audio::DEFAULT_CHANNELS)
+    );
+}
+
+/// (row, channel, period, sample, effect, param).
+type ModCell = (usize, usize, u16, u8, u8, u8);
+
+/// a 4 channel "M.K." module, samples are (data, loop start, loop length).
+fn mod_file(samples: &[(&[i8], usize, usize)], orders: &[u8], patterns: &[&[ModCell]]) -> Vec<u8> {
+    let mut b = vec![0u8; 1084];
+    b[..4].copy_from_slice(b"test");
+    for (i, (data, loop_start, loop_length)) in samples.iter().enumerate() {
+        let h = 20 + i * 30;
+        b[h + 22..h + 24].copy_from_slice(&(data.len() as u16 / 2).to_be_bytes());
+        b[h + 25] = 64;
+        b[h + 26..h + 28].copy_from_slice(&(*loop_start as u16 / 2).to_be_bytes());
+        b[h + 28..h + 30].copy_from_slice(&(*loop_length as u16 / 2).max(1).to_be_bytes());
+    }
+    b[950] = orders.len() as u8;
+    b[951] = 127;
+    b[952..952 + orders.len()].copy_from_slice(orders);
+    b[1080..1084].copy_from_slice(b"M.K.");
+    for pattern in patterns {
+        let mut p = vec![0u8; 64 * 4 * 4];
+        for &(row, channel, period, sample, effect, param) in pattern.iter() {
+            let c = (row * 4 + channel) * 4;
+            p[c] = (sample & 0xF0) | (period >> 8) as u8;
+            p[c + 1] = period as u8;
+            p[c + 2] = (sample << 4) | effect;
+            p[c + 3] = param;
+        }
+        b.extend(p);
+    }
+    for (data, ..) in samples {
+        b.extend(data.iter().map(|&v| v as u8));
+    }
+    b
+}
+
+/// the largest value of `channel` in frames `from .. to` of stereo `out`.
+fn stereo_peak(out: &[f32], channel: usize, from: usize, to: usize) -> f32 {
+    (from..to)
+        .map(|f| out[f * 2 + channel].abs())
+        .fold(0.0, f32::max)
+}
+
+/// the tone of a square wave in `channel`, from its sign changes.
+fn stereo_frequency(out: &[f32], channel: usize, from: usize, to: usize, rate: f32) -> f32 {
+    let changes = (from + 1..to)
+        .filter(|&f| (out[f * 2 + channel] > 0.0) != (out[(f - 1) * 2 + channel] > 0.0))
+        .count();
+    changes as f32 / 2.0 / ((to - from) as f32 / rate)
+}
+
+#[test]
+fn tracker_mod_playback() {
+    use audio::{Module, ModuleFormat, ModulePlayer, MusicDecoder, MusicPlayer, MusicTrack};
+
+    let square: Vec<i8> = (0..32).map(|i| if i < 16 { 64 } else { -64 }).collect();
+    let one_shot = [64i8; 64];
+    let samples: &[(&[i8], usize, usize)] = &[(&square, 0, 32), (&one_shot, 0, 0)];
+    // C-4, C-5 an octave up, C20 halves the volume, EC3 cuts it after 3 ticks, then a sample
+    // that does not loop on the second channel.
+    let song = mod_file(
+        samples,
+        &[0],
+        &[&[
+            (0, 0, 428, 1, 0, 0),
+            (16, 0, 214, 1, 0, 0),
+            (32, 0, 0, 0, 0xC, 0x20),
+            (48, 0, 0, 0, 0xE, 0xC3),
+            (56, 1, 428, 2, 0, 0),
+        ]],
+    );
+    assert!(Module::init(&[0; 2000]).is_err());
+    let module = Module::init(&song).unwrap();
+    assert_eq!(
+        (module.format, module.channels(), module.song_length()),
+        (ModuleFormat::Mod, 4, 1)
+    );
+
+    // speed 6 at 125 bpm: 960 frames a tick, 5760 a row.
+    let rate = 48000;
+    let row = 5760;
+    let mut player = ModulePlayer::init(module, rate);
+    assert_eq!((player.frames(), player.channels()), (64 * row as u64, 2));
+    assert!(!player.is_looping());
+    let mut out = vec![0.0; 64 * row * 2 + 100];
+    assert_eq!(player.read(&mut out), 64 * row);
+    assert_eq!(player.read(&mut out), 0);
+
+    // amiga C-4 plays the 32 frame square at 8287 Hz.
+    let c4 = stereo_frequency(&out, 0, row, 16 * row, rate as f32);
+    assert!((c4 - 258.97).abs() < 2.0, "{c4}");
+    let c5 = stereo_frequency(&out, 0, 17 * row, 32 * row, rate as f32);
+    assert!((c5 - 2.0 * 258.97).abs() < 4.0, "{c5}");
+
+    let full = stereo_peak(&out, 0, 20 * row, 32 * row);
+    let half = stereo_peak(&out, 0, 36 * row, 48 * row);
+    assert!((half / full - 0.5).abs() < 0.02);
+    assert!(stereo_peak(&out, 0, 48 * row, 48 * row + 2880) > 0.0);
+    assert_eq!(stereo_peak(&out, 0, 48 * row + 2880, 56 * row), 0.0);
+
+    // 64 frames at 8287 Hz are 371 frames at 48 kHz, then nothing.
+    let start = 56 * row;
+    assert!(stereo_peak(&out, 1, start, start + 360) > 0.0);
+    assert_eq!(stereo_peak(&out, 1, start + 380, 64 * row), 0.0);
+
+    // F03 at 3 ticks a row, D00 breaks to the next order after 4 rows, B01 jumps back to it:
+    // the song loops from the second order.
+    let song = mod_file(
+        samples,
+        &[0, 1],
+        &[
+            &[(0, 0, 428, 1, 0xF, 3), (3, 0, 0, 0, 0xD, 0)],
+            &[(1, 0, 0, 0, 0xB, 1)],
+        ],
+    );
+    let track = MusicTrack::init(song.clone()).unwrap();
+    assert_eq!((track.frames(), track.sample_rate()), (17280, rate));
+    assert!(track.looping);
+    assert_eq!((track.loop_start, track.loop_end), (11520, 17280));
+
+    let mut music = MusicPlayer::init(rate, 2);
+    music.play(track);
+    let mut out = vec![0.0; (17280 + 5760) * 2];
+    music.mix(&mut out);
+    for f in 0..5760 {
+        for c in 0..2 {
+            let (looped, first) = (out[(17280 + f) * 2 + c], out[(11520 + f) * 2 + c]);
+            assert!((looped - first).abs() < 1e-4);
+        }
+    }
+
+    // seeking lands on the frames a straight render has.
+    let mut player = ModulePlayer::init(Module::init(&song).unwrap(), rate);
+    assert_eq!(player.loop_start(), 11520);
+    let mut straight = vec![0.0; 17280 * 2];
+    player.read(&mut straight);
+    for frame in [0, 3000, 12000, 17000] {
+        player.seek(frame).unwrap();
+        let mut out = vec![0.0; 200];
+        assert_eq!(player.read(&mut out), 100.min(17280 - frame as usize));
+        for (i, v) in out
+            .iter()
+            .enumerate()
+            .take((17280 - frame as usize).min(100) * 2)
+        {
+            assert!((v - straight[frame as usize * 2 + i]).abs() < 1e-4);
+        }
+    }
+
+    // a note on an empty sample slot, retriggered with E91, plays nothing.
+    let song = mod_file(
+        &[(&square, 0, 32), (&[], 0, 0)],
+        &[0],
+        &[&[(0, 0, 428, 2, 0xE, 0x91), (1, 1, 428, 1, 0, 0)]],
+    );
+    let mut player = ModulePlayer::init(Module::init(&song).unwrap(), rate);
+    let mut out = vec![0.0; 2 * row * 2];
+    assert_eq!(player.read(&mut out), 2 * row);
+    assert_eq!(stereo_peak(&out, 0, 0, row), 0.0);
+    assert!(stereo_peak(&out, 1, row, 2 * row) > 0.0);
+}
+
+/// a looped sample with a volume envelope: (sample, points, sustain point, fadeout).
+type XmInstrument<'a> = (&'a [i8], &'a [(u16, u16)], Option<u8>, u16);
+
+/// a one pattern XM with linear frequencies, cells are (row, channel, [note, instrument,
+/// volume, effect, param]).
+fn xm_file(
+    channels: usize,
+    rows: usize,
+    instruments: &[XmInstrument],
+    cells: &[(usize, usize, [u8; 5])],
+) -> Vec<u8> {
+    let u16le = |v: usize| (v as u16).to_le_bytes();
+    let mut b = b"Extended Module: ".to_vec();
+    b.extend([0u8; 20]);
+    b.push(0x1A);
+    b.extend([0u8; 20]);
+    b.extend(u16le(0x0104));
+    b.extend(276u32.to_le_bytes());
+    for v in [1, 0, channels, 1, instruments.len(), 1, 6, 125] {
+        b.extend(u16le(v));
+    }
+    b.extend([0u8; 256]);
+
+    let mut data = vec![0u8; rows * channels * 5];
+    for &(row, channel, cell) in cells {
+        let at = (row * channels + channel) * 5;
+        data[at..at + 5].copy_from_slice(&cell);
+    }
+    b.extend(9u32.to_le_bytes());
+    b.push(0);
+    b.extend(u16le(rows));
+    b.extend(u16le(data.len()));
+    b.extend(data);
+
+    for (sample, envelope, sustain, fadeout) in instruments {
+        let mut h = vec![0u8; 263];
+        h[..4].copy_from_slice(&263u32.to_le_bytes());
+        h[27..29].copy_from_slice(&u16le(1));
+        h[29..33].copy_from_slice(&40u32.to_le_bytes());
+        for (i, &(x, y)) in envelope.iter().enumerate() {
+            h[129 + i * 4..131 + i * 4].copy_from_slice(&x.to_le_bytes());
+            h[131 + i * 4..133 + i * 4].copy_from_slice(&y.to_le_bytes());
+        }
+        h[225] = envelope.len() as u8;
+        h[227] = sustain.unwrap_or(0);
+        h[233] = if sustain.is_some() { 3 } else { 1 };
+        h[239..241].copy_from_slice(&fadeout.to_le_bytes());
+        b.extend(h);
+
+        let mut s = vec![0u8; 40];
+        s[..4].copy_from_slice(&(sample.len() as u32).to_le_bytes());
+        s[8..12].copy_from_slice(&(sample.len() as u32).to_le_bytes());
+        s[12] = 64;
+        s[14] = 1;
+        s[15] = 128;
+        b.extend(s);
+        let mut old = 0i8;
+        for &v in sample.iter() {
+            b.push(v.wrapping_sub(old) as u8);
+            old = v;
+        }
+    }
+    b
+}
+
+#[test]
+fn tracker_xm_envelopes_and_effects() {
+    use audio::{Module, ModuleFormat, ModulePlayer, MusicDecoder};
+
+    let square: Vec<i8> = (0..32).map(|i| if i < 16 { 64 } else { -64 }).collect();
+    let decay: &[(u16, u16)] = &[(0, 64), (10, 0)];
+    let held: &[(u16, u16)] = &[(0, 64), (1, 64)];
+    // panned apart: the first channel decays over 10 ticks, the second holds until its key
+    // off at row 8 then fades out over 8 ticks, at half volume from row 4.
+    let song = xm_file(
+        2,
+        16,
+        &[(&square, decay, None, 0), (&square, held, Some(0), 4096)],
+        &[
+            (0, 0, [49, 1, 0, 0x08, 0x00]),
+            (0, 1, [49, 2, 0, 0x08, 0xFF]),
+            (4, 1, [0, 0, 0x30, 0, 0]),
+            (8, 1, [97, 0, 0, 0, 0]),
+        ],
+    );
+    let module = Module::init(&song).unwrap();
+    assert_eq!((module.format, module.channels()), (ModuleFormat::Xm, 2));
+
+    let (rate, tick, row) = (48000, 960, 5760);
+    let mut player = ModulePlayer::init(module, rate);
+    assert_eq!(player.frames(), 16 * row as u64);
+    let mut out = vec![0.0; 16 * row * 2];
+    assert_eq!(player.read(&mut out), 16 * row);
+
+    // the envelope at tick 5 is half of tick 0, nothing once it reached 0.
+    let first = stereo_peak(&out, 0, 0, tick);
+    let fifth = stereo_peak(&out, 0, 5 * tick, 6 * tick);
+    assert!(first > 0.0 && (fifth / first - 0.5).abs() < 0.02);
+    // the other channel is panned hard right, all but nothing leaks.
+    assert!(stereo_peak(&out, 0, 11 * tick, 16 * row) < 1e-6);
+
+    // linear C-4 plays the square at 8363 Hz, held by the sustain.
+    let c4 = stereo_frequency(&out, 1, row, 4 * row, rate as f32);
+    assert!((c4 - 261.34).abs() < 2.0, "{c4}");
+    let full = stereo_peak(&out, 1, row, 4 * row);
+    let half = stereo_peak(&out, 1, 4 * row + tick, 8 * row);
+    assert!((half / full - 0.5).abs() < 0.02);
+    assert!((stereo_peak(&out, 1, 3 * row, 4 * row) - full).abs() < 1e-4);
+    let fading = stereo_peak(&out, 1, 8 * row + 4 * tick, 8 * row + 5 * tick);
+    assert!(fading > 0.0 && fading < half);
+    assert_eq!(stereo_peak(&out, 1, 10 * row, 16 * row), 0.0);
+}
+
+#[test]
+fn tracker_s3m_playback() {
+    use audio::{Module, ModuleFormat, ModulePlayer, MusicDecoder};
+
+    let mut b = vec![0u8; 112];
+    b[28] = 0x1A;
+    b[29] = 16;
+    b[32..34].copy_from_slice(&2u16.to_le_bytes());
+    b[34..36].copy_from_slice(&1u16.to_le_bytes());
+    b[36..38].copy_from_slice(&1u16.to_le_bytes());
+    b[42..44].copy_from_slice(&2u16.to_le_bytes());
+    b[44..48].copy_from_slice(b"SCRM");
+    (b[48], b[49], b[50], b[51]) = (64, 6, 125, 0xB0);
+    b[64..96].fill(255);
+    (b[64], b[65]) = (0, 8);
+    (b[96], b[97]) = (0, 255);
+    b[98..100].copy_from_slice(&7u16.to_le_bytes());
+    b[100..102].copy_from_slice(&14u16.to_le_bytes());
+
+    // an unsigned square at 16726 Hz, an octave over the 8363 default.
+    let mut sample = vec![0u8; 80];
+    sample[0] = 1;
+    sample[14..16].copy_from_slice(&12u16.to_le_bytes());
+    sample[16..20].copy_from_slice(&32u32.to_le_bytes());
+    sample[24..28].copy_from_slice(&32u32.to_le_bytes());
+    (sample[28], sample[31]) = (64, 1);
+    sample[32..36].copy_from_slice(&16726u32.to_le_bytes());
+    sample[76..80].copy_from_slice(b"SCRS");
+    b.extend(sample);
+    b.extend((0..32).map(|i| if i < 16 { 192u8 } else { 64 }));
+
+    // C-4 on the left channel at volume 32, A03 for 3 ticks a row.
+    let mut pattern = vec![0xE0, 0x40, 1, 32, 1, 3, 0];
+    pattern.extend([0u8; 63]);
+    b.extend(((pattern.len() + 2) as u16).to_le_bytes());
+    b.extend(pattern);
+
+    let module = Module::init(&b).unwrap();
+    assert_eq!(
+        (module.format, module.channels(), module.song_length()),
+        (ModuleFormat::S3m, 2, 1)
+    );
+    let (rate, row) = (48000, 2880);
+    let mut player = ModulePlayer::init(module, rate);
+    assert_eq!(player.frames(), 64 * row as u64);
+    let mut out = vec![0.0; 64 * row * 2];
+    assert_eq!(player.read(&mut out), 64 * row);
+
+    let c4 = stereo_frequency(&out, 0, 0, 16 * row, rate as f32);
+    assert!((c4 - 522.69).abs() < 4.0, "{c4}");
+    // half the sample, half volume, the mix headroom of 4 channels, panned 0x33.
+    let left = 0.5 * 0.5 * 0.25 * (0x33 as f32 / 255.0 * std::f32::consts::FRAC_PI_2).cos();
+    assert!((stereo_peak(&out, 0, 0, 16 * row) - left).abs() < 1e-3);
+    assert!(stereo_peak(&out, 1, 0, 16 * row) < left);
+}
@@ -0,0 +1,176 @@
+//! Arabic contextual shaping, maps letters to their presentation forms
+//! (isolated, final, initial, medial) and builds the lam-alef ligatures.
+//!
+//! works on logical order, run it before reordering the line.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Joining {
+    /// does not join.
+    U,
+    /// joins with the char before it only.
+    R,
+    /// joins on both sides.
+    D,
+    /// forces joining on both sides without changing its own form (tatweel, zwj).
+    C,
+    /// marks, skipped when looking for neighbours.
+    T,
+}
+
+/// (letter, isolated, final, initial, medial), 0 when the form does not exist.
+#[rustfmt::skip]
+const FORMS: &[(u32, u32, u32, u32, u32)] = &[
+    (0x0621, 0xFE80, 0,      0,      0     ), // hamza
+    (0x0622, 0xFE81, 0xFE82, 0,      0     ), // alef with madda above
+    (0x0623, 0xFE83, 0xFE84, 0,      0     ), // alef with hamza above
+    (0x0624, 0xFE85, 0xFE86, 0,      0     ), // waw with hamza above
+    (0x0625, 0xFE87, 0xFE88, 0,      0     ), // alef with hamza below
+    (0x0626, 0xFE89, 0xFE8A, 0xFE8B, 0xFE8C), // yeh with hamza above
+    (0x0627, 0xFE8D, 0xFE8E, 0,      0     ), // alef
+    (0x0628, 0xFE8F, 0xFE90, 0xFE91, 0xFE92), // beh
+    (0x0629, 0xFE93, 0xFE94, 0,      0     ), // teh marbuta
+    (0x062A, 0xFE95, 0xFE96, 0xFE97, 0xFE98), // teh
+    (0x062B, 0xFE99, 0xFE9A, 0xFE9B, 0xFE9C), // theh
+    (0x062C, 0xFE9D, 0xFE9E, 0xFE9F, 0xFEA0), // jeem
+    (0x062D, 0xFEA1, 0xFEA2, 0xFEA3, 0xFEA4), // hah
+    (0x062E, 0xFEA5, 0xFEA6, 0xFEA7, 0xFEA8), // khah
+    (0x062F, 0xFEA9, 0xFEAA, 0,      0     ), // dal
+    (0x0630, 0xFEAB, 0xFEAC, 0,      0     ), // thal
+    (0x0631, 0xFEAD, 0xFEAE, 0,      0     ), // reh
+    (0x0632, 0xFEAF, 0xFEB0, 0,      0     ), // zain
+    (0x0633, 0xFEB1, 0xFEB2, 0xFEB3, 0xFEB4), // seen
+    (0x0634, 0xFEB5, 0xFEB6, 0xFEB7, 0xFEB8), // sheen
+    (0x0635, 0xFEB9, 0xFEBA, 0xFEBB, 0xFEBC), // sad
+    (0x0636, 0xFEBD, 0xFEBE, 0xFEBF, 0xFEC0), // dad
+    (0x0637, 0xFEC1, 0xFEC2, 0xFEC3, 0xFEC4), // tah
+    (0x0638, 0xFEC5, 0xFEC6, 0xFEC7, 0xFEC8), // zah
+    (0x0639, 0xFEC9, 0xFECA, 0xFECB, 0xFECC), // ain
+    (0x063A, 0xFECD, 0xFECE, 0xFECF, 0xFED0), // ghain
+    (0x0641, 0xFED1, 0xFED2, 0xFED3, 0xFED4), // feh
+    (0x0642, 0xFED5, 0xFED6, 0xFED7, 0xFED8), // qaf
+    (0x0643, 0xFED9, 0xFEDA, 0xFEDB, 0xFEDC), // kaf
+    (0x0644, 0xFEDD, 0xFEDE, 0xFEDF, 0xFEE0), // lam
+    (0x0645, 0xFEE1, 0xFEE2, 0xFEE3, 0xFEE4), // meem
+    (0x0646, 0xFEE5, 0xFEE6, 0xFEE7, 0xFEE8), // noon
+    (0x0647, 0xFEE9, 0xFEEA, 0xFEEB, 0xFEEC), // heh
+    (0x0648, 0xFEED, 0xFEEE, 0,      0     ), // waw
+    (0x0649, 0xFEEF, 0xFEF0, 0xFBE8, 0xFBE9), // alef maksura
+    (0x064A, 0xFEF1, 0xFEF2, 0xFEF3, 0xFEF4), // yeh
+    (0x0671, 0xFB50, 0xFB51, 0,      0     ), // alef wasla
+    (0x0679, 0xFB66, 0xFB67, 0xFB68, 0xFB69), // tteh
+    (0x067E, 0xFB56, 0xFB57, 0xFB58, 0xFB59), // peh
+    (0x0686, 0xFB7A, 0xFB7B, 0xFB7C, 0xFB7D), // tcheh
+    (0x0688, 0xFB88, 0xFB89, 0,      0     ), // ddal
+    (0x0691, 0xFB8C, 0xFB8D, 0,      0     ), // rreh
+    (0x0698, 0xFB8A, 0xFB8B, 0,      0     ), // jeh
+    (0x06A9, 0xFB8E, 0xFB8F, 0xFB90, 0xFB91), // keheh
+    (0x06AF, 0xFB92, 0xFB93, 0xFB94, 0xFB95), // gaf
+    (0x06BE, 0xFBAA, 0xFBAB, 0xFBAC, 0xFBAD), // heh doachashmee
+    (0x06C0, 0xFBA4, 0xFBA5, 0,      0     ), // heh with yeh above
+    (0x06C1, 0xFBA6, 0xFBA7, 0xFBA8, 0xFBA9), // heh goal
+    (0x06CC, 0xFBFC, 0xFBFD, 0xFBFE, 0xFBFF), // farsi yeh
+    (0x06D2, 0xFBAE, 0xFBAF, 0,      0     ), // yeh barree
+];
+
+/// (alef, isolated ligature, final ligature) for lam followed by that alef.
+const LAM_ALEF: &[(u32, u32, u32)] = &[
+    (0x0622, 0xFEF5, 0xFEF6),
+    (0x0623, 0xFEF7, 0xFEF8),
+    (0x0625, 0xFEF9, 0xFEFA),
+    (0x0627, 0xFEFB, 0xFEFC),
+];
+
+const LAM: char = '\u{0644}';
+
+fn forms(c: char) -> Option<&'static (u32, u32, u32, u32, u32)> {
+    FORMS
+        .binary_search_by_key(&(c as u32), |f| f.0)
+        .ok()
+        .map(|index| &FORMS[index])
+}
+
+pub fn joining_type(c: char) -> Joining {
+    if let Some(&(_, _, fin, ini, _)) = forms(c) {
+        return match (fin, ini) {
+            (0, _) => Joining::U,
+            (_, 0) => Joining::R,
+            _ => Joining::D,
+        };
+    }
+
+    match c as u32 {
+        0x0640 | 0x200D => Joining::C,
+        0x0610..=0x061A
+        | 0x064B..=0x065F
+        | 0x0670
+        | 0x06D6..=0x06DC
+        | 0x06DF..=0x06E4
+        | 0x06E7..=0x06E8
+        | 0x06EA..=0x06ED => Joining::T,
+        _ => Joining::U,
+    }
+}
+
+fn joins_forward(j: Joining) -> bool {
+    matches!(j, Joining::D | Joining::C)
+}
+
+fn joins_backward(j: Joining) -> bool {
+    matches!(j, Joining::R | Joining::D | Joining::C)
+}
+
+fn to_char(code: u32) -> char {
+    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// shapes one line in logical order, the result has the same length as `chars`,
+/// an alef merged into a lam-alef ligature becomes None.
+pub fn shape(chars: &[char]) -> Vec<Option<char>> {
+    let joining: Vec<Joining> = chars.iter().map(|&c| joining_type(c)).collect();
+
+    let previous = |i: usize| (0..i).rev().find(|&k| joining[k] != Joining::T);
+    let next = |i: usize| (i + 1..chars.len()).find(|&k| joining[k] != Joining::T);
+
+    let mut shaped: Vec<Option<char>> = chars.iter().map(|&c| Some(c)).collect();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let Some(&(_, isolated, fin, ini, med)) = forms(chars[i]) else {
+            i += 1;
+            continue;
+        };
+
+        let joins_previous =
+            joins_backward(joining[i]) && previous(i).is_some_and(|p| joins_forward(joining[p]));
+
+        if chars[i] == LAM && i + 1 < chars.len() {
+            if let Some(&(_, lig_isolated, lig_final)) =
+                LAM_ALEF.iter().find(|l| l.0 == chars[i + 1] as u32)
+            {
+                shaped[i] = Some(to_char(if joins_previous {
+                    lig_final
+                } else {
+                    lig_isolated
+                }));
+                shaped[i + 1] = None;
+                i += 2;
+                continue;
+            }
+        }
+
+        let joins_next =
+            joins_forward(joining[i]) && next(i).is_some_and(|n| joins_backward(joining[n]));
+
+        let form = match (joins_previous, joins_next) {
+            (true, true) => med,
+            (true, false) => fin,
+            (false, true) => ini,
+            (false, false) => isolated,
+        };
+        shaped[i] = Some(to_char(if form != 0 { form } else { isolated }));
+
+        i += 1;
+    }
+
+    shaped
+}
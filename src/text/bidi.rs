@@ -0,0 +1,847 @@
+//! Unicode bidirectional algorithm (UAX #9) for a single paragraph.
+//!
+//! the class table covers latin, hebrew, arabic and the common punctuation, anything else is
+//! treated as left to right.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BidiClass {
+    L,
+    R,
+    AL,
+    EN,
+    ES,
+    ET,
+    AN,
+    CS,
+    NSM,
+    BN,
+    B,
+    S,
+    WS,
+    ON,
+    LRE,
+    LRO,
+    RLE,
+    RLO,
+    PDF,
+    LRI,
+    RLI,
+    FSI,
+    PDI,
+}
+
+use BidiClass::*;
+
+const MAX_DEPTH: u8 = 125;
+
+/// (first, last, class) sorted and non overlapping.
+#[rustfmt::skip]
+const BIDI_CLASS_TABLE: &[(u32, u32, BidiClass)] = &[
+    (0x0000, 0x0008, BN),
+    (0x0009, 0x0009, S),
+    (0x000A, 0x000A, B),
+    (0x000B, 0x000B, S),
+    (0x000C, 0x000C, WS),
+    (0x000D, 0x000D, B),
+    (0x000E, 0x001B, BN),
+    (0x001C, 0x001E, B),
+    (0x001F, 0x001F, S),
+    (0x0020, 0x0020, WS),
+    (0x0021, 0x0022, ON),
+    (0x0023, 0x0025, ET),
+    (0x0026, 0x002A, ON),
+    (0x002B, 0x002B, ES),
+    (0x002C, 0x002C, CS),
+    (0x002D, 0x002D, ES),
+    (0x002E, 0x002F, CS),
+    (0x0030, 0x0039, EN),
+    (0x003A, 0x003A, CS),
+    (0x003B, 0x0040, ON),
+    (0x005B, 0x0060, ON),
+    (0x007B, 0x007E, ON),
+    (0x007F, 0x0084, BN),
+    (0x0085, 0x0085, B),
+    (0x0086, 0x009F, BN),
+    (0x00A0, 0x00A0, CS),
+    (0x00A1, 0x00A1, ON),
+    (0x00A2, 0x00A5, ET),
+    (0x00A6, 0x00A9, ON),
+    (0x00AB, 0x00AC, ON),
+    (0x00AD, 0x00AD, BN),
+    (0x00AE, 0x00AF, ON),
+    (0x00B0, 0x00B1, ET),
+    (0x00B2, 0x00B3, EN),
+    (0x00B4, 0x00B4, ON),
+    (0x00B6, 0x00B8, ON),
+    (0x00B9, 0x00B9, EN),
+    (0x00BB, 0x00BF, ON),
+    (0x00D7, 0x00D7, ON),
+    (0x00F7, 0x00F7, ON),
+    (0x0300, 0x036F, NSM),
+    (0x0483, 0x0489, NSM),
+    (0x0590, 0x0590, R),
+    (0x0591, 0x05BD, NSM),
+    (0x05BE, 0x05BE, R),
+    (0x05BF, 0x05BF, NSM),
+    (0x05C0, 0x05C0, R),
+    (0x05C1, 0x05C2, NSM),
+    (0x05C3, 0x05C3, R),
+    (0x05C4, 0x05C5, NSM),
+    (0x05C6, 0x05C6, R),
+    (0x05C7, 0x05C7, NSM),
+    (0x05C8, 0x05FF, R),
+    (0x0600, 0x0605, AN),
+    (0x0606, 0x0608, AL),
+    (0x0609, 0x060A, ET),
+    (0x060B, 0x060B, AL),
+    (0x060C, 0x060C, CS),
+    (0x060D, 0x060F, AL),
+    (0x0610, 0x061A, NSM),
+    (0x061B, 0x064A, AL),
+    (0x064B, 0x065F, NSM),
+    (0x0660, 0x0669, AN),
+    (0x066A, 0x066A, ET),
+    (0x066B, 0x066C, AN),
+    (0x066D, 0x066F, AL),
+    (0x0670, 0x0670, NSM),
+    (0x0671, 0x06D5, AL),
+    (0x06D6, 0x06DC, NSM),
+    (0x06DD, 0x06DD, AN),
+    (0x06DE, 0x06DE, AL),
+    (0x06DF, 0x06E4, NSM),
+    (0x06E5, 0x06E6, AL),
+    (0x06E7, 0x06E8, NSM),
+    (0x06E9, 0x06E9, AL),
+    (0x06EA, 0x06ED, NSM),
+    (0x06EE, 0x06EF, AL),
+    (0x06F0, 0x06F9, EN),
+    (0x06FA, 0x0710, AL),
+    (0x0711, 0x0711, NSM),
+    (0x0712, 0x072F, AL),
+    (0x0730, 0x074A, NSM),
+    (0x074B, 0x07A5, AL),
+    (0x07A6, 0x07B0, NSM),
+    (0x07B1, 0x07BF, AL),
+    (0x07C0, 0x07EA, R),
+    (0x07EB, 0x07F3, NSM),
+    (0x07F4, 0x085F, R),
+    (0x0860, 0x08D2, AL),
+    (0x08D3, 0x08E1, NSM),
+    (0x08E2, 0x08E2, AN),
+    (0x08E3, 0x08FF, NSM),
+    (0x1680, 0x1680, WS),
+    (0x2000, 0x200A, WS),
+    (0x200B, 0x200D, BN),
+    (0x200E, 0x200E, L),
+    (0x200F, 0x200F, R),
+    (0x2010, 0x2027, ON),
+    (0x2028, 0x2028, WS),
+    (0x2029, 0x2029, B),
+    (0x202A, 0x202A, LRE),
+    (0x202B, 0x202B, RLE),
+    (0x202C, 0x202C, PDF),
+    (0x202D, 0x202D, LRO),
+    (0x202E, 0x202E, RLO),
+    (0x202F, 0x202F, CS),
+    (0x2030, 0x2034, ET),
+    (0x2035, 0x2043, ON),
+    (0x2044, 0x2044, CS),
+    (0x2045, 0x205E, ON),
+    (0x205F, 0x205F, WS),
+    (0x2060, 0x2065, BN),
+    (0x2066, 0x2066, LRI),
+    (0x2067, 0x2067, RLI),
+    (0x2068, 0x2068, FSI),
+    (0x2069, 0x2069, PDI),
+    (0x2070, 0x2070, EN),
+    (0x2074, 0x2079, EN),
+    (0x207A, 0x207B, ES),
+    (0x2080, 0x2089, EN),
+    (0x208A, 0x208B, ES),
+    (0x20A0, 0x20CF, ET),
+    (0x20D0, 0x20F0, NSM),
+    (0x2190, 0x2211, ON),
+    (0x2212, 0x2212, ES),
+    (0x2213, 0x2213, ET),
+    (0x2214, 0x2BFF, ON),
+    (0x3000, 0x3000, WS),
+    (0x3001, 0x3003, ON),
+    (0xFB1D, 0xFB28, R),
+    (0xFB29, 0xFB29, ES),
+    (0xFB2A, 0xFB4F, R),
+    (0xFB50, 0xFDCF, AL),
+    (0xFDF0, 0xFDFF, AL),
+    (0xFE00, 0xFE0F, NSM),
+    (0xFE10, 0xFE19, ON),
+    (0xFE20, 0xFE2F, NSM),
+    (0xFE30, 0xFE4F, ON),
+    (0xFE50, 0xFE50, CS),
+    (0xFE51, 0xFE51, ON),
+    (0xFE52, 0xFE52, CS),
+    (0xFE54, 0xFE54, ON),
+    (0xFE55, 0xFE55, CS),
+    (0xFE56, 0xFE61, ON),
+    (0xFE62, 0xFE63, ES),
+    (0xFE64, 0xFE66, ON),
+    (0xFE68, 0xFE68, ON),
+    (0xFE6A, 0xFE6B, ON),
+    (0xFE70, 0xFEFE, AL),
+    (0xFEFF, 0xFEFF, BN),
+    (0xFF01, 0xFF02, ON),
+    (0xFF06, 0xFF0A, ON),
+    (0xFF0B, 0xFF0B, ES),
+    (0xFF0C, 0xFF0C, CS),
+    (0xFF0D, 0xFF0D, ES),
+    (0xFF0E, 0xFF0F, CS),
+    (0xFF10, 0xFF19, EN),
+    (0xFF1A, 0xFF1A, CS),
+    (0xFF1B, 0xFF20, ON),
+    (0x10800, 0x10FFF, R),
+    (0x1E800, 0x1EDFF, R),
+    (0x1EE00, 0x1EEFF, AL),
+];
+
+pub fn bidi_class(c: char) -> BidiClass {
+    let c = c as u32;
+    match BIDI_CLASS_TABLE.binary_search_by(|&(first, last, _)| {
+        if last < c {
+            std::cmp::Ordering::Less
+        } else if first > c {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }) {
+        Ok(index) => BIDI_CLASS_TABLE[index].2,
+        Err(_) => L,
+    }
+}
+
+/// the mirrored glyph drawn for `c` in a right to left run (rule L4).
+pub fn mirror(c: char) -> char {
+    match c {
+        '(' => ')',
+        ')' => '(',
+        '<' => '>',
+        '>' => '<',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        '«' => '»',
+        '»' => '«',
+        '‹' => '›',
+        '›' => '‹',
+        '≤' => '≥',
+        '≥' => '≤',
+        _ => c,
+    }
+}
+
+fn is_isolate_initiator(class: BidiClass) -> bool {
+    matches!(class, LRI | RLI | FSI)
+}
+
+fn is_removed_by_x9(class: BidiClass) -> bool {
+    matches!(class, RLE | LRE | RLO | LRO | PDF | BN)
+}
+
+fn is_neutral_or_isolate(class: BidiClass) -> bool {
+    matches!(class, B | S | WS | ON | LRI | RLI | FSI | PDI)
+}
+
+fn direction_of_level(level: u8) -> BidiClass {
+    if level & 1 == 0 {
+        L
+    } else {
+        R
+    }
+}
+
+/// for every isolate initiator the index of its matching PDI (BD9).
+fn match_isolates(classes: &[BidiClass]) -> Vec<Option<usize>> {
+    let mut matching = vec![None; classes.len()];
+    let mut stack: Vec<usize> = Vec::new();
+
+    for (i, &class) in classes.iter().enumerate() {
+        match class {
+            LRI | RLI | FSI => stack.push(i),
+            PDI => {
+                if let Some(initiator) = stack.pop() {
+                    matching[initiator] = Some(i);
+                }
+            }
+            B => stack.clear(),
+            _ => {}
+        }
+    }
+
+    matching
+}
+
+/// level of the first strong char in `start..end`, skipping isolates (rules P2, P3).
+fn first_strong_level(
+    classes: &[BidiClass],
+    matching_pdi: &[Option<usize>],
+    start: usize,
+    end: usize,
+) -> Option<u8> {
+    let mut i = start;
+    while i < end {
+        match classes[i] {
+            L => return Some(0),
+            R | AL => return Some(1),
+            LRI | RLI | FSI => match matching_pdi[i] {
+                Some(pdi) => i = pdi,
+                None => return None,
+            },
+            B => return None,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// the direction a paragraph would get from its first strong char, 0 (ltr) when there is none.
+pub fn paragraph_level(chars: &[char]) -> u8 {
+    let classes: Vec<BidiClass> = chars.iter().map(|&c| bidi_class(c)).collect();
+    let matching_pdi = match_isolates(&classes);
+    first_strong_level(&classes, &matching_pdi, 0, classes.len()).unwrap_or(0)
+}
+
+#[derive(Clone, Copy)]
+struct DirectionalStatus {
+    level: u8,
+    override_class: Option<BidiClass>,
+    isolate: bool,
+}
+
+/// resolves the embedding level of every char of one paragraph, `base_level` forces the
+/// paragraph direction (0 ltr, 1 rtl) otherwise it comes from the first strong char.
+///
+/// returns (paragraph_level, levels).
+pub fn resolve_levels(chars: &[char], base_level: Option<u8>) -> (u8, Vec<u8>) {
+    let original: Vec<BidiClass> = chars.iter().map(|&c| bidi_class(c)).collect();
+    let matching_pdi = match_isolates(&original);
+    let paragraph = base_level.map(|l| l & 1).unwrap_or_else(|| {
+        first_strong_level(&original, &matching_pdi, 0, original.len()).unwrap_or(0)
+    });
+
+    let mut classes = original.clone();
+    let mut levels = vec![paragraph; chars.len()];
+
+    resolve_explicit(
+        &original,
+        &matching_pdi,
+        paragraph,
+        &mut classes,
+        &mut levels,
+    );
+
+    for sequence in isolating_run_sequences(&original, &matching_pdi, paragraph, &levels) {
+        resolve_weak(&sequence, &mut classes);
+        resolve_brackets(&sequence, chars, &original, &mut classes);
+        resolve_neutral(&sequence, &mut classes);
+        resolve_implicit(&sequence, &classes, &mut levels);
+    }
+
+    // chars removed by X9 take the level of the char before them, they are invisible anyway.
+    for i in 0..levels.len() {
+        if is_removed_by_x9(original[i]) {
+            levels[i] = if i == 0 { paragraph } else { levels[i - 1] };
+        }
+    }
+
+    // L1
+    let mut trailing = true;
+    for i in (0..levels.len()).rev() {
+        match original[i] {
+            S | B => {
+                levels[i] = paragraph;
+                trailing = true;
+            }
+            WS | LRI | RLI | FSI | PDI => {
+                if trailing {
+                    levels[i] = paragraph;
+                }
+            }
+            class if is_removed_by_x9(class) => {
+                if trailing {
+                    levels[i] = paragraph;
+                }
+            }
+            _ => trailing = false,
+        }
+    }
+
+    (paragraph, levels)
+}
+
+/// rules X1 -> X8.
+fn resolve_explicit(
+    original: &[BidiClass],
+    matching_pdi: &[Option<usize>],
+    paragraph: u8,
+    classes: &mut [BidiClass],
+    levels: &mut [u8],
+) {
+    let mut stack = vec![DirectionalStatus {
+        level: paragraph,
+        override_class: None,
+        isolate: false,
+    }];
+    let mut overflow_isolates = 0usize;
+    let mut overflow_embeddings = 0usize;
+    let mut valid_isolates = 0usize;
+
+    for i in 0..original.len() {
+        let last = *stack.last().unwrap();
+
+        match original[i] {
+            RLE | LRE | RLO | LRO => {
+                let rtl = matches!(original[i], RLE | RLO);
+                let level = if rtl {
+                    (last.level + 1) | 1
+                } else {
+                    (last.level + 2) & !1
+                };
+
+                if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    stack.push(DirectionalStatus {
+                        level,
+                        override_class: match original[i] {
+                            RLO => Some(R),
+                            LRO => Some(L),
+                            _ => None,
+                        },
+                        isolate: false,
+                    });
+                } else if overflow_isolates == 0 {
+                    overflow_embeddings += 1;
+                }
+                levels[i] = last.level;
+            }
+
+            RLI | LRI | FSI => {
+                levels[i] = last.level;
+                if let Some(class) = last.override_class {
+                    classes[i] = class;
+                }
+
+                let rtl = match original[i] {
+                    RLI => true,
+                    LRI => false,
+                    _ => {
+                        let end = matching_pdi[i].unwrap_or(original.len());
+                        first_strong_level(original, matching_pdi, i + 1, end) == Some(1)
+                    }
+                };
+                let level = if rtl {
+                    (last.level + 1) | 1
+                } else {
+                    (last.level + 2) & !1
+                };
+
+                if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    valid_isolates += 1;
+                    stack.push(DirectionalStatus {
+                        level,
+                        override_class: None,
+                        isolate: true,
+                    });
+                } else {
+                    overflow_isolates += 1;
+                }
+            }
+
+            PDI => {
+                if overflow_isolates > 0 {
+                    overflow_isolates -= 1;
+                } else if valid_isolates > 0 {
+                    overflow_embeddings = 0;
+                    while !stack.last().unwrap().isolate {
+                        stack.pop();
+                    }
+                    stack.pop();
+                    valid_isolates -= 1;
+                }
+
+                let last = *stack.last().unwrap();
+                levels[i] = last.level;
+                if let Some(class) = last.override_class {
+                    classes[i] = class;
+                }
+            }
+
+            PDF => {
+                if overflow_isolates > 0 {
+                    // nothing
+                } else if overflow_embeddings > 0 {
+                    overflow_embeddings -= 1;
+                } else if !last.isolate && stack.len() >= 2 {
+                    stack.pop();
+                }
+                levels[i] = last.level;
+            }
+
+            B => levels[i] = paragraph,
+
+            BN => levels[i] = last.level,
+
+            _ => {
+                levels[i] = last.level;
+                if let Some(class) = last.override_class {
+                    classes[i] = class;
+                }
+            }
+        }
+    }
+}
+
+/// chars of one isolating run sequence (BD13) with its start/end of sequence types.
+struct Sequence {
+    indices: Vec<usize>,
+    level: u8,
+    sos: BidiClass,
+    eos: BidiClass,
+}
+
+/// rule X10.
+fn isolating_run_sequences(
+    original: &[BidiClass],
+    matching_pdi: &[Option<usize>],
+    paragraph: u8,
+    levels: &[u8],
+) -> Vec<Sequence> {
+    // level runs, ignoring the chars removed by X9.
+    let mut runs: Vec<Vec<usize>> = Vec::new();
+    let mut run_level = None;
+    for i in 0..original.len() {
+        if is_removed_by_x9(original[i]) {
+            continue;
+        }
+        if run_level == Some(levels[i]) {
+            runs.last_mut().unwrap().push(i);
+        } else {
+            runs.push(vec![i]);
+            run_level = Some(levels[i]);
+        }
+    }
+
+    let mut run_of = vec![usize::MAX; original.len()];
+    for (r, run) in runs.iter().enumerate() {
+        for &i in run.iter() {
+            run_of[i] = r;
+        }
+    }
+
+    let is_matched_pdi: Vec<bool> = {
+        let mut matched = vec![false; original.len()];
+        for pdi in matching_pdi.iter().flatten() {
+            matched[*pdi] = true;
+        }
+        matched
+    };
+
+    let mut sequences = Vec::new();
+    for run in runs.iter() {
+        if is_matched_pdi[run[0]] {
+            continue;
+        }
+
+        let mut indices = run.clone();
+        loop {
+            let last = *indices.last().unwrap();
+            match (is_isolate_initiator(original[last]), matching_pdi[last]) {
+                (true, Some(pdi)) if run_of[pdi] != usize::MAX => {
+                    indices.extend_from_slice(&runs[run_of[pdi]]);
+                }
+                _ => break,
+            }
+        }
+
+        let first = indices[0];
+        let last = *indices.last().unwrap();
+        let level = levels[first];
+
+        let before = (0..first)
+            .rev()
+            .find(|&i| !is_removed_by_x9(original[i]))
+            .map(|i| levels[i])
+            .unwrap_or(paragraph);
+        let after = if is_isolate_initiator(original[last]) {
+            paragraph
+        } else {
+            (last + 1..original.len())
+                .find(|&i| !is_removed_by_x9(original[i]))
+                .map(|i| levels[i])
+                .unwrap_or(paragraph)
+        };
+
+        sequences.push(Sequence {
+            indices,
+            level,
+            sos: direction_of_level(level.max(before)),
+            eos: direction_of_level(level.max(after)),
+        });
+    }
+
+    sequences
+}
+
+/// rules W1 -> W7.
+fn resolve_weak(sequence: &Sequence, classes: &mut [BidiClass]) {
+    let indices = &sequence.indices;
+
+    // W1
+    for k in 0..indices.len() {
+        if classes[indices[k]] == NSM {
+            classes[indices[k]] = if k == 0 {
+                sequence.sos
+            } else {
+                match classes[indices[k - 1]] {
+                    LRI | RLI | FSI | PDI => ON,
+                    class => class,
+                }
+            };
+        }
+    }
+
+    // W2, W3
+    let mut last_strong = sequence.sos;
+    for &i in indices.iter() {
+        match classes[i] {
+            L | R => last_strong = classes[i],
+            AL => {
+                last_strong = AL;
+                classes[i] = R;
+            }
+            EN if last_strong == AL => classes[i] = AN,
+            _ => {}
+        }
+    }
+
+    // W4
+    for k in 1..indices.len().saturating_sub(1) {
+        let (prev, current, next) = (
+            classes[indices[k - 1]],
+            classes[indices[k]],
+            classes[indices[k + 1]],
+        );
+        if current == ES && prev == EN && next == EN {
+            classes[indices[k]] = EN;
+        } else if current == CS && prev == next && matches!(prev, EN | AN) {
+            classes[indices[k]] = prev;
+        }
+    }
+
+    // W5
+    let mut k = 0;
+    while k < indices.len() {
+        if classes[indices[k]] != ET {
+            k += 1;
+            continue;
+        }
+
+        let start = k;
+        while k < indices.len() && classes[indices[k]] == ET {
+            k += 1;
+        }
+
+        let touches_en = (start > 0 && classes[indices[start - 1]] == EN)
+            || (k < indices.len() && classes[indices[k]] == EN);
+        if touches_en {
+            for &i in indices[start..k].iter() {
+                classes[i] = EN;
+            }
+        }
+    }
+
+    // W6
+    for &i in indices.iter() {
+        if matches!(classes[i], ES | ET | CS) {
+            classes[i] = ON;
+        }
+    }
+
+    // W7
+    let mut last_strong = sequence.sos;
+    for &i in indices.iter() {
+        match classes[i] {
+            L | R => last_strong = classes[i],
+            EN if last_strong == L => classes[i] = L,
+            _ => {}
+        }
+    }
+}
+
+fn strong_direction(class: BidiClass) -> Option<BidiClass> {
+    match class {
+        L => Some(L),
+        R | AL | EN | AN => Some(R),
+        _ => None,
+    }
+}
+
+/// rule N0, paired brackets take the direction of what they enclose.
+fn resolve_brackets(
+    sequence: &Sequence,
+    chars: &[char],
+    original: &[BidiClass],
+    classes: &mut [BidiClass],
+) {
+    let indices = &sequence.indices;
+
+    // BD16
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    let mut stack: Vec<(char, usize)> = Vec::new();
+    for (k, &i) in indices.iter().enumerate() {
+        if classes[i] != ON {
+            continue;
+        }
+
+        match chars[i] {
+            '(' | '[' | '{' => {
+                if stack.len() == 63 {
+                    break;
+                }
+                stack.push((mirror(chars[i]), k));
+            }
+            ')' | ']' | '}' => {
+                if let Some(position) = stack.iter().rposition(|&(closing, _)| closing == chars[i])
+                {
+                    pairs.push((stack[position].1, k));
+                    stack.truncate(position);
+                }
+            }
+            _ => {}
+        }
+    }
+    pairs.sort();
+
+    let embedding = direction_of_level(sequence.level);
+    for (open, close) in pairs {
+        let mut found_embedding = false;
+        let mut found_opposite = false;
+        for &i in indices[open + 1..close].iter() {
+            match strong_direction(classes[i]) {
+                Some(d) if d == embedding => found_embedding = true,
+                Some(_) => found_opposite = true,
+                None => {}
+            }
+        }
+
+        let direction = if found_embedding {
+            embedding
+        } else if found_opposite {
+            let before = indices[..open]
+                .iter()
+                .rev()
+                .find_map(|&i| strong_direction(classes[i]))
+                .unwrap_or(sequence.sos);
+            if before != embedding {
+                before
+            } else {
+                embedding
+            }
+        } else {
+            continue;
+        };
+
+        for k in [open, close] {
+            classes[indices[k]] = direction;
+            for &i in indices[k + 1..].iter() {
+                if original[i] != NSM {
+                    break;
+                }
+                classes[i] = direction;
+            }
+        }
+    }
+}
+
+/// rules N1, N2.
+fn resolve_neutral(sequence: &Sequence, classes: &mut [BidiClass]) {
+    let indices = &sequence.indices;
+    let embedding = direction_of_level(sequence.level);
+
+    let mut k = 0;
+    while k < indices.len() {
+        if !is_neutral_or_isolate(classes[indices[k]]) {
+            k += 1;
+            continue;
+        }
+
+        let start = k;
+        while k < indices.len() && is_neutral_or_isolate(classes[indices[k]]) {
+            k += 1;
+        }
+
+        let before = if start == 0 {
+            sequence.sos
+        } else {
+            strong_direction(classes[indices[start - 1]]).unwrap_or(sequence.sos)
+        };
+        let after = if k == indices.len() {
+            sequence.eos
+        } else {
+            strong_direction(classes[indices[k]]).unwrap_or(sequence.eos)
+        };
+
+        let direction = if before == after { before } else { embedding };
+        for &i in indices[start..k].iter() {
+            classes[i] = direction;
+        }
+    }
+}
+
+/// rules I1, I2.
+fn resolve_implicit(sequence: &Sequence, classes: &[BidiClass], levels: &mut [u8]) {
+    for &i in sequence.indices.iter() {
+        if levels[i] & 1 == 0 {
+            match classes[i] {
+                R => levels[i] += 1,
+                AN | EN => levels[i] += 2,
+                _ => {}
+            }
+        } else if matches!(classes[i], L | EN | AN) {
+            levels[i] += 1;
+        }
+    }
+}
+
+/// visual order of a line (rule L2), `result[visual_position] = logical_index`.
+pub fn reorder(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+
+    let highest = levels.iter().copied().max().unwrap_or(0);
+    let lowest_odd = levels
+        .iter()
+        .copied()
+        .filter(|l| l & 1 == 1)
+        .min()
+        .unwrap_or(highest + 1);
+
+    let mut level = highest;
+    while level >= lowest_odd && level > 0 {
+        let mut k = 0;
+        while k < order.len() {
+            if levels[order[k]] < level {
+                k += 1;
+                continue;
+            }
+
+            let start = k;
+            while k < order.len() && levels[order[k]] >= level {
+                k += 1;
+            }
+            order[start..k].reverse();
+        }
+
+        level -= 1;
+    }
+
+    order
+}